@@ -0,0 +1,82 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rusoto_s3::{ListObjectsV2Request, S3Client, S3};
+use s3_signer::S3Configuration;
+use std::{convert::TryFrom, net::SocketAddr};
+use tokio::runtime::Runtime;
+use warp::Filter;
+
+const CONCURRENT_REQUESTS: &[usize] = &[1, 16];
+
+const EMPTY_LISTING: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult><Name>bucket</Name><KeyCount>0</KeyCount><IsTruncated>false</IsTruncated></ListBucketResult>"#;
+
+/// Local storage answering every request with an empty listing.
+fn start_storage(runtime: &Runtime) -> SocketAddr {
+  let storage =
+    warp::any().map(|| warp::reply::with_header(EMPTY_LISTING, "content-type", "application/xml"));
+  let (address, server) =
+    runtime.block_on(async { warp::serve(storage).bind_ephemeral(([127, 0, 0, 1], 0)) });
+  runtime.spawn(server);
+  address
+}
+
+fn configuration(address: SocketAddr) -> S3Configuration {
+  S3Configuration::new_with_hostname(
+    "access",
+    "secret",
+    "us-east-1",
+    &format!("http://{}", address),
+  )
+  .unwrap()
+}
+
+async fn list_objects(client: S3Client) {
+  client
+    .list_objects_v2(ListObjectsV2Request {
+      bucket: "bucket".to_string(),
+      ..Default::default()
+    })
+    .await
+    .unwrap();
+}
+
+/// Lists objects with the client shared by the configuration, as the signer does, and with a
+/// client built for each request.
+fn bench_s3_client(c: &mut Criterion) {
+  let runtime = Runtime::new().unwrap();
+  let address = start_storage(&runtime);
+  let shared_configuration = configuration(address);
+
+  let mut group = c.benchmark_group("list_objects");
+  // Building a client loads the system certificates, which takes tens of milliseconds.
+  group.sample_size(10);
+  for concurrent_requests in CONCURRENT_REQUESTS {
+    group.bench_with_input(
+      BenchmarkId::new("shared_client", concurrent_requests),
+      concurrent_requests,
+      |b, concurrent_requests| {
+        b.iter(|| {
+          runtime
+            .block_on(futures::future::join_all((0..*concurrent_requests).map(
+              |_| list_objects(S3Client::try_from(&shared_configuration).unwrap()),
+            )))
+        })
+      },
+    );
+    group.bench_with_input(
+      BenchmarkId::new("client_per_request", concurrent_requests),
+      concurrent_requests,
+      |b, concurrent_requests| {
+        b.iter(|| {
+          runtime.block_on(futures::future::join_all((0..*concurrent_requests).map(
+            |_| list_objects(S3Client::try_from(&configuration(address)).unwrap()),
+          )))
+        })
+      },
+    );
+  }
+  group.finish();
+}
+
+criterion_group!(benches, bench_s3_client);
+criterion_main!(benches);
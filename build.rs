@@ -1,5 +1,22 @@
 extern crate built;
 
+use std::process::Command;
+
 fn main() {
   built::write_built_file().expect("Failed to acquire build-time information");
+
+  // Commit of the build, given by the environment when built outside of the repository, e.g. in a
+  // Docker build context without `.git`.
+  let git_sha = std::env::var("S3_SIGNER_GIT_SHA").ok().or_else(|| {
+    Command::new("git")
+      .args(["rev-parse", "HEAD"])
+      .output()
+      .ok()
+      .filter(|output| output.status.success())
+      .and_then(|output| String::from_utf8(output.stdout).ok())
+      .map(|git_sha| git_sha.trim().to_string())
+  });
+  if let Some(git_sha) = git_sha {
+    println!("cargo:rustc-env=S3_SIGNER_GIT_SHA={}", git_sha);
+  }
 }
@@ -0,0 +1,101 @@
+use std::{
+  fmt::{Display, Formatter},
+  str::FromStr,
+};
+
+/// Header negotiating the API version of unversioned paths, and echoing the served version
+pub const API_VERSION_HEADER: &str = "api-version";
+
+/// Versions of the API, each one served under its own path segment, e.g. `/api/v1/limits`.
+///
+/// Breaking changes of the DTOs ship under a new version, while the previous ones keep being
+/// served for the deployed clients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiVersion {
+  V1,
+}
+
+impl ApiVersion {
+  /// Versions currently served, oldest first
+  pub const ALL: [ApiVersion; 1] = [ApiVersion::V1];
+
+  /// Version served on unversioned paths when no `Api-Version` header is sent, so that clients
+  /// predating versioning keep working.
+  pub const DEFAULT: ApiVersion = ApiVersion::V1;
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ApiVersion::V1 => "v1",
+    }
+  }
+}
+
+impl Display for ApiVersion {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for ApiVersion {
+  type Err = String;
+
+  /// Parses `v1` or `1`, case-insensitively.
+  fn from_str(version: &str) -> Result<Self, Self::Err> {
+    let version = version.trim();
+    let number = version
+      .strip_prefix('v')
+      .or_else(|| version.strip_prefix('V'))
+      .unwrap_or(version);
+
+    ApiVersion::ALL
+      .iter()
+      .find(|api_version| api_version.as_str()[1..] == *number)
+      .copied()
+      .ok_or_else(|| format!("unsupported API version {:?}", version))
+  }
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::Error;
+  use warp::{Filter, Rejection, Reply};
+
+  /// Serves the routes of an API version under its path segment, e.g. `/v1/limits`, and on the
+  /// unversioned paths when negotiated through the `Api-Version` header (or by default).
+  ///
+  /// Responses carry the served version in the `Api-Version` header.
+  pub(crate) fn versioned<F, R>(
+    version: ApiVersion,
+    routes: F,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone
+  where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+  {
+    warp::path(version.as_str())
+      .or(negotiated(version))
+      .unify()
+      .and(routes)
+      .map(move |reply: R| warp::reply::with_header(reply, API_VERSION_HEADER, version.as_str()))
+  }
+
+  fn negotiated(version: ApiVersion) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>(API_VERSION_HEADER)
+      .and_then(move |requested: Option<String>| async move {
+        let requested = match requested {
+          Some(requested) => requested
+            .parse::<ApiVersion>()
+            .map_err(|error| warp::reject::custom(Error::InvalidParameter(error)))?,
+          None => ApiVersion::DEFAULT,
+        };
+
+        if requested == version {
+          Ok(())
+        } else {
+          Err(warp::reject::not_found())
+        }
+      })
+      .untuple_one()
+  }
+}
@@ -0,0 +1,305 @@
+use crate::{Error, S3Configuration};
+use hyper_tls::HttpsConnector;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::{
+  sync::{Arc, RwLock},
+  time::{Duration, Instant},
+};
+use warp::{
+  http::Uri,
+  hyper::{body, header::AUTHORIZATION, Client},
+  Filter, Rejection,
+};
+
+const BEARER_PREFIX: &str = "Bearer ";
+const MIN_JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Claims of a validated bearer token.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Claims {
+  pub sub: Option<String>,
+  pub iss: Option<String>,
+  pub exp: Option<u64>,
+  /// Every other claim of the token
+  #[serde(flatten)]
+  pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Validates `Authorization: Bearer` JSON Web Tokens against the keys published at a JWKS URL.
+///
+/// Keys are fetched lazily and fetched again when a token is signed by an unknown key, at most
+/// once per minute and by one request at a time, within 10 seconds.
+///
+/// Tokens must be signed with the algorithm of their key: the `alg` of the JWK, else the one of
+/// [`JwtValidator::with_algorithm`], RS256 by default. The algorithm of the token header is
+/// checked against it rather than trusted.
+#[derive(Clone, Debug)]
+pub struct JwtValidator {
+  jwks_url: Uri,
+  issuer: Option<String>,
+  audience: Option<String>,
+  algorithm: Algorithm,
+  jwks: Arc<RwLock<Option<(JwkSet, Instant)>>>,
+  /// Held while fetching the keys, so that concurrent refreshes share the fetch in flight
+  refresh: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl JwtValidator {
+  pub fn new(
+    jwks_url: &str,
+    issuer: Option<String>,
+    audience: Option<String>,
+  ) -> Result<Self, warp::http::uri::InvalidUri> {
+    Ok(Self {
+      jwks_url: jwks_url.parse()?,
+      issuer,
+      audience,
+      algorithm: Algorithm::RS256,
+      jwks: Arc::new(RwLock::new(None)),
+      refresh: Arc::new(tokio::sync::Mutex::new(())),
+    })
+  }
+
+  /// Sets the algorithm of the keys published without `alg`.
+  pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+    self.algorithm = algorithm;
+    self
+  }
+
+  pub async fn validate(&self, token: &str) -> Result<Claims, Error> {
+    let header = decode_header(token).map_err(unauthorized)?;
+    let kid = header
+      .kid
+      .ok_or_else(|| unauthorized("missing key ID in token header"))?;
+
+    let (decoding_key, algorithm) = match self.decoding_key(&kid)? {
+      Some(decoding_key) => decoding_key,
+      None => {
+        self.refresh_jwks().await?;
+        self
+          .decoding_key(&kid)?
+          .ok_or_else(|| unauthorized(format!("unknown key ID {:?}", kid)))?
+      }
+    };
+
+    if header.alg != algorithm {
+      return Err(unauthorized(format!(
+        "token signed with {:?}, expected {:?} for key ID {:?}",
+        header.alg, algorithm, kid
+      )));
+    }
+
+    let mut validation = Validation::new(algorithm);
+    if let Some(issuer) = &self.issuer {
+      validation.set_issuer(&[issuer]);
+    }
+    if let Some(audience) = &self.audience {
+      validation.set_audience(&[audience]);
+    }
+
+    decode::<Claims>(token, &decoding_key, &validation)
+      .map(|token_data| token_data.claims)
+      .map_err(unauthorized)
+  }
+
+  /// Returns the key of the given ID, along with its algorithm.
+  fn decoding_key(&self, kid: &str) -> Result<Option<(DecodingKey, Algorithm)>, Error> {
+    let jwks = self.jwks.read().unwrap_or_else(|error| error.into_inner());
+
+    jwks
+      .as_ref()
+      .and_then(|(jwks, _)| jwks.find(kid))
+      .map(|jwk| {
+        let algorithm = jwk.common.algorithm.unwrap_or(self.algorithm);
+        DecodingKey::from_jwk(jwk)
+          .map(|decoding_key| (decoding_key, algorithm))
+          .map_err(unauthorized)
+      })
+      .transpose()
+  }
+
+  /// Fetches the keys again, unless they were fetched less than a minute ago.
+  ///
+  /// Concurrent refreshes wait for the fetch in flight, and use its keys once fetched.
+  pub(crate) async fn refresh_jwks(&self) -> Result<(), Error> {
+    let _refresh = self.refresh.lock().await;
+    if self.recently_fetched() {
+      return Ok(());
+    }
+
+    log::info!("Fetch JWKS: url={}", self.jwks_url);
+    let jwks = tokio::time::timeout(JWKS_FETCH_TIMEOUT, self.fetch_jwks())
+      .await
+      .map_err(|_| {
+        jwks_error(format!(
+          "no response within {} seconds",
+          JWKS_FETCH_TIMEOUT.as_secs()
+        ))
+      })??;
+
+    *self.jwks.write().unwrap_or_else(|error| error.into_inner()) = Some((jwks, Instant::now()));
+
+    Ok(())
+  }
+
+  fn recently_fetched(&self) -> bool {
+    self
+      .jwks
+      .read()
+      .unwrap_or_else(|error| error.into_inner())
+      .as_ref()
+      .map(|(_, fetched_at)| fetched_at.elapsed() < MIN_JWKS_REFRESH_INTERVAL)
+      .unwrap_or_default()
+  }
+
+  async fn fetch_jwks(&self) -> Result<JwkSet, Error> {
+    let client = Client::builder().build::<_, warp::hyper::Body>(HttpsConnector::new());
+    let response = client
+      .get(self.jwks_url.clone())
+      .await
+      .map_err(|error| jwks_error(error.to_string()))?;
+
+    if !response.status().is_success() {
+      return Err(jwks_error(format!("status {}", response.status())));
+    }
+
+    let bytes = body::to_bytes(response.into_body())
+      .await
+      .map_err(|error| jwks_error(error.to_string()))?;
+    serde_json::from_slice(&bytes).map_err(|error| jwks_error(error.to_string()))
+  }
+}
+
+/// Extracts the claims of the bearer token of the request.
+///
+/// Requests are rejected when a JWT validator is configured and the token is missing or invalid.
+/// Without validator, no claims are extracted.
+pub fn claims(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (Option<Claims>,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+
+  warp::header::optional::<String>(AUTHORIZATION.as_str()).and_then(
+    move |authorization: Option<String>| {
+      let s3_configuration = s3_configuration.current();
+      async move {
+        validate_authorization(&s3_configuration, authorization.as_deref())
+          .await
+          .map_err(warp::reject::custom)
+      }
+    },
+  )
+}
+
+/// Validates the `Authorization` header of a request, like [`claims`] does.
+pub async fn validate_authorization(
+  s3_configuration: &S3Configuration,
+  authorization: Option<&str>,
+) -> Result<Option<Claims>, Error> {
+  let jwt_validator = match s3_configuration.jwt_validator() {
+    Some(jwt_validator) => jwt_validator,
+    None => return Ok(None),
+  };
+
+  let token = authorization
+    .and_then(|authorization| authorization.strip_prefix(BEARER_PREFIX))
+    .ok_or_else(|| unauthorized("missing bearer token"))?;
+
+  jwt_validator.validate(token.trim()).await.map(Some)
+}
+
+fn unauthorized<E: ToString>(error: E) -> Error {
+  Error::Unauthorized(error.to_string())
+}
+
+fn jwks_error(error: String) -> Error {
+  Error::JwksError(error)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use jsonwebtoken::{encode, EncodingKey, Header};
+
+  const SECRET: &[u8] = b"secret";
+
+  fn validator(alg: Option<&str>) -> JwtValidator {
+    let mut jwk = serde_json::json!({ "kty": "oct", "kid": "key", "k": base64::encode(SECRET) });
+    if let Some(alg) = alg {
+      jwk["alg"] = alg.into();
+    }
+    let jwks = serde_json::from_value(serde_json::json!({ "keys": [jwk] })).unwrap();
+
+    let validator = JwtValidator::new("https://sso.example.com/jwks.json", None, None).unwrap();
+    *validator.jwks.write().unwrap() = Some((jwks, Instant::now()));
+    validator
+  }
+
+  fn token(algorithm: Algorithm) -> String {
+    let header = Header {
+      kid: Some("key".to_string()),
+      ..Header::new(algorithm)
+    };
+    let claims = serde_json::json!({ "sub": "alice", "exp": 4_102_444_800u64 });
+    encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+  }
+
+  #[tokio::test]
+  async fn tokens_signed_with_the_algorithm_of_their_key_are_accepted() {
+    let claims = validator(Some("HS256"))
+      .validate(&token(Algorithm::HS256))
+      .await
+      .unwrap();
+    assert_eq!(claims.sub.as_deref(), Some("alice"));
+  }
+
+  #[tokio::test]
+  async fn the_algorithm_of_the_token_header_is_not_trusted() {
+    let error = validator(Some("HS256"))
+      .validate(&token(Algorithm::HS512))
+      .await
+      .unwrap_err();
+    assert!(matches!(error, Error::Unauthorized(_)), "{:?}", error);
+  }
+
+  #[tokio::test]
+  async fn keys_without_algorithm_use_the_configured_one() {
+    let error = validator(None)
+      .validate(&token(Algorithm::HS256))
+      .await
+      .unwrap_err();
+    assert!(matches!(error, Error::Unauthorized(_)), "{:?}", error);
+
+    let claims = validator(None)
+      .with_algorithm(Algorithm::HS256)
+      .validate(&token(Algorithm::HS256))
+      .await
+      .unwrap();
+    assert_eq!(claims.sub.as_deref(), Some("alice"));
+  }
+
+  #[tokio::test]
+  async fn concurrent_refreshes_share_one_fetch() {
+    let fetches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counted_fetches = fetches.clone();
+    let jwks_route = warp::path("jwks.json").and_then(move || {
+      let fetches = counted_fetches.clone();
+      async move {
+        fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok::<_, Rejection>(warp::reply::json(&serde_json::json!({ "keys": [] })))
+      }
+    });
+    let (address, server) = warp::serve(jwks_route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let validator =
+      JwtValidator::new(&format!("http://{}/jwks.json", address), None, None).unwrap();
+    let results = futures::future::join_all((0..5).map(|_| validator.refresh_jwks())).await;
+
+    assert!(results.iter().all(Result::is_ok), "{:?}", results);
+    assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+  }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+/// Result of a batch operation, reporting the outcome of every item
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[cfg_attr(
+  feature = "server",
+  aliases(
+    AbortUploadsBatchResult = BatchResult<crate::multipart_upload::AbortUploadItem>,
+    UploadManifestResult = BatchResult<crate::objects::ManifestUpload>
+  )
+)]
+pub struct BatchResult<T> {
+  /// Number of items which succeeded
+  pub succeeded: usize,
+  /// Number of items which failed
+  pub failed: usize,
+  /// Outcome of every item, in the order of the request
+  #[cfg_attr(feature = "server", schema(inline))]
+  pub items: Vec<BatchItemResult<T>>,
+}
+
+/// Outcome of an item of a batch operation
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct BatchItemResult<T> {
+  /// Index of the item in the request
+  pub index: usize,
+  pub status: BatchItemStatus,
+  /// Result of the item, when it succeeded
+  #[cfg_attr(feature = "server", schema(value_type = Option<Object>))]
+  pub result: Option<T>,
+  /// Error of the item, when it failed
+  pub error: Option<BatchItemError>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+  Succeeded,
+  Failed,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct BatchItemError {
+  /// Error code, as returned by S3 when available (e.g. `NoSuchUpload`)
+  pub code: String,
+  pub message: String,
+}
+
+impl<T> BatchResult<T> {
+  pub fn new(items: Vec<BatchItemResult<T>>) -> Self {
+    let failed = items
+      .iter()
+      .filter(|item| item.status == BatchItemStatus::Failed)
+      .count();
+
+    Self {
+      succeeded: items.len() - failed,
+      failed,
+      items,
+    }
+  }
+}
+
+impl<T> BatchItemResult<T> {
+  pub fn succeeded(index: usize, result: T) -> Self {
+    Self {
+      index,
+      status: BatchItemStatus::Succeeded,
+      result: Some(result),
+      error: None,
+    }
+  }
+
+  pub fn failed(index: usize, code: &str, message: &str) -> Self {
+    Self {
+      index,
+      status: BatchItemStatus::Failed,
+      result: None,
+      error: Some(BatchItemError {
+        code: code.to_string(),
+        message: message.to_string(),
+      }),
+    }
+  }
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use rusoto_core::RusotoError;
+  use std::fmt::Debug;
+
+  /// Extracts an error code from an S3 error, preferring the code returned by S3.
+  pub(crate) fn error_code<E: Debug>(error: &RusotoError<E>) -> String {
+    match error {
+      RusotoError::Service(service_error) => format!("{:?}", service_error)
+        .split('(')
+        .next()
+        .unwrap_or_default()
+        .to_string(),
+      RusotoError::Unknown(response) => {
+        let body = String::from_utf8_lossy(&response.body);
+        body
+          .split_once("<Code>")
+          .and_then(|(_, remaining)| remaining.split_once("</Code>"))
+          .map(|(code, _)| code.to_string())
+          .unwrap_or_else(|| format!("Http{}", response.status.as_u16()))
+      }
+      RusotoError::HttpDispatch(_) => "HttpDispatch".to_string(),
+      RusotoError::Credentials(_) => "Credentials".to_string(),
+      RusotoError::Validation(_) => "Validation".to_string(),
+      RusotoError::ParseError(_) => "ParseError".to_string(),
+      RusotoError::Blocking => "Blocking".to_string(),
+    }
+  }
+}
@@ -0,0 +1,52 @@
+use lambda_runtime::{service_fn, LambdaEvent};
+use s3_signer::{
+  lambda::{self, ApiGatewayRequest},
+  request_id::RequestIdLogger,
+  settings::{Settings, DEFAULT_BASE_PATH},
+  Signer,
+};
+use simple_logger::SimpleLogger;
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_runtime::Error> {
+  let settings = Settings::load().map_err(|error| error.to_string())?;
+
+  let log_level = match settings.verbose {
+    0 => log::LevelFilter::Error,
+    1 => log::LevelFilter::Warn,
+    2 => log::LevelFilter::Info,
+    3 => log::LevelFilter::Debug,
+    _ => log::LevelFilter::Trace,
+  };
+
+  log::set_boxed_logger(Box::new(RequestIdLogger::new(
+    SimpleLogger::new().with_level(log_level),
+  )))?;
+  log::set_max_level(log_level);
+
+  s3_signer::telemetry::init_tracing(settings.otel_endpoint.as_deref())?;
+
+  let s3_configuration = settings
+    .s3_configuration(None)
+    .map_err(|error| error.to_string())?;
+  let signer = Signer::builder()
+    .configuration(s3_configuration)
+    .base_path(settings.base_path.as_deref().unwrap_or(DEFAULT_BASE_PATH))
+    .cors(settings.cors())
+    .build()
+    .map_err(|error| error.to_string())?;
+  let service = lambda::service(&signer);
+
+  let result = lambda_runtime::run(service_fn(move |event: LambdaEvent<ApiGatewayRequest>| {
+    let service = service.clone();
+    async move {
+      lambda::handle(service, event.payload)
+        .await
+        .map_err(lambda_runtime::Error::from)
+    }
+  }))
+  .await;
+
+  s3_signer::telemetry::shutdown_tracing();
+  result
+}
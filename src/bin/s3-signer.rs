@@ -1,10 +1,29 @@
-use clap::Parser;
-use s3_signer::S3Configuration;
+use s3_signer::{
+  multipart_upload,
+  policy::{self, Identity},
+  presign::{self, CredentialsExpired, Partition, PresignOptions, Region},
+  request_id::{with_request_id, RequestIdLogger},
+  settings::{
+    Command, ConfigurationError, LsCommand, OpenApiFormat, PresignCommand, PresignMethod, Settings,
+    DEFAULT_BASE_PATH, DEFAULT_PORT,
+  },
+  ApiVersion, ContractValidator, Error, S3Configuration, Scheduler, Signer,
+};
 use simple_logger::SimpleLogger;
-use std::convert::Infallible;
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::{
+  net::TcpListener,
+  signal::unix::{signal, SignalKind},
+};
+use tokio_native_tls::TlsAcceptor;
 use utoipa::OpenApi;
 use warp::{
-  hyper::{header::ACCESS_CONTROL_ALLOW_METHODS, Body, StatusCode},
+  hyper::{
+    server::{accept, conn::AddrStream},
+    service::make_service_fn,
+    HeaderMap, Server,
+  },
+  path::Peek,
   Filter, Rejection, Reply,
 };
 
@@ -12,56 +31,52 @@ pub mod built_info {
   include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
-/// S3 Signer for AWS and other S3 compatible storage systems
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
-struct Args {
-  /// Sets the AWS Access Key ID
-  #[clap(
-    long,
-    value_parser,
-    name = "aws-access-key-id",
-    env = "AWS_ACCESS_KEY_ID"
-  )]
-  aws_access_key_id: String,
-
-  /// Sets the AWS Secret Access Key
-  #[clap(
-    long,
-    value_parser,
-    name = "aws-secret-access-key",
-    env = "AWS_SECRET_ACCESS_KEY"
-  )]
-  aws_secret_access_key: String,
-
-  /// Sets the AWS Region
-  #[clap(
-    long,
-    value_parser,
-    name = "aws-region",
-    env = "AWS_REGION",
-    default_value = "us-east-1"
-  )]
-  aws_region: String,
-
-  /// Sets the AWS Hostname (required for non-AWS S3 endpoint)
-  #[clap(short, long, value_parser, env = "AWS_HOSTNAME")]
-  aws_hostname: Option<String>,
-
-  /// Sets the port number to server the signer
-  #[clap(short, long, value_parser, env = "PORT", default_value_t = 8000)]
-  port: u16,
-
-  /// Sets the level of verbosity
-  #[clap(short, long, parse(from_occurrences))]
-  verbose: usize,
-}
-
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-  let args = Args::parse();
+  let settings =
+    Settings::load().unwrap_or_else(|error| exit_on_error(ConfigurationError::Settings(error)));
 
-  let log_level = match args.verbose {
+  if let Some(command) = &settings.command {
+    let s3_configuration = || {
+      settings.s3_configuration(None).unwrap_or_else(|error| {
+        if let Some(Command::Check) = &settings.command {
+          eprintln!("Configuration: FAILED");
+        }
+        exit_on_error(error)
+      })
+    };
+    match command {
+      Command::Presign(command) => match presign(&s3_configuration(), command).await {
+        Ok(url) => println!("{}", url),
+        Err(error) => {
+          eprintln!("{}", error);
+          std::process::exit(1);
+        }
+      },
+      Command::Ls(command) => {
+        if let Err(error) = ls(&s3_configuration(), command).await {
+          eprintln!("{}", error);
+          std::process::exit(1);
+        }
+      }
+      Command::Check => {
+        if !check(&s3_configuration()).await {
+          std::process::exit(1);
+        }
+      }
+      Command::OpenApi(command) => {
+        let base_path = command
+          .base_path
+          .as_deref()
+          .or(settings.base_path.as_deref())
+          .unwrap_or(DEFAULT_BASE_PATH);
+        println!("{}", open_api(command.format, base_path)?);
+      }
+    }
+    return Ok(());
+  }
+
+  let log_level = match settings.verbose {
     0 => log::LevelFilter::Error,
     1 => log::LevelFilter::Warn,
     2 => log::LevelFilter::Info,
@@ -69,39 +84,427 @@ async fn main() -> std::io::Result<()> {
     _ => log::LevelFilter::Trace,
   };
 
-  SimpleLogger::new().with_level(log_level).init().unwrap();
+  log::set_boxed_logger(Box::new(RequestIdLogger::new(
+    SimpleLogger::new().with_level(log_level),
+  )))
+  .unwrap();
+  log::set_max_level(log_level);
 
-  let s3_configuration = if let Some(aws_hostname) = args.aws_hostname {
-    S3Configuration::new_with_hostname(
-      &args.aws_access_key_id,
-      &args.aws_secret_access_key,
-      &args.aws_region,
-      &aws_hostname,
-    )
-  } else {
-    S3Configuration::new(
-      &args.aws_access_key_id,
-      &args.aws_secret_access_key,
-      &args.aws_region,
+  s3_signer::telemetry::init_tracing(settings.otel_endpoint.as_deref())
+    .map_err(std::io::Error::other)?;
+
+  let mock_hostname = start_mock_s3(&settings)?;
+  let settings = with_mock_storage(settings, mock_hostname.as_deref());
+  let s3_configuration = settings
+    .s3_configuration(None)
+    .unwrap_or_else(|error| exit_on_error(error))
+    .reloadable();
+  let signer = Signer::builder()
+    .configuration(s3_configuration.clone())
+    .base_path(settings.base_path.as_deref().unwrap_or(DEFAULT_BASE_PATH))
+    .cors(settings.cors())
+    .build()
+    .unwrap_or_else(|error| exit_on_error(error));
+
+  if let Some(days) = settings.ensure_lifecycle {
+    tokio::spawn(ensure_lifecycle(
+      s3_configuration.current(),
+      settings.lifecycle_buckets(),
+      days,
+    ));
+  }
+
+  start_grpc(&settings, &s3_configuration);
+
+  let scheduler = {
+    let s3_configuration = s3_configuration.clone();
+    let mock_hostname = mock_hostname.clone();
+    settings
+      .scheduler()
+      .unwrap_or_else(|error| exit_on_error(error))
+      .with_credentials_refresh(move || {
+        try_reload(&s3_configuration, mock_hostname.as_deref()).map_err(|error| error.to_string())
+      })
+  };
+  scheduler.start(&s3_configuration);
+
+  let tls_acceptor = match (settings.tls_cert, settings.tls_key) {
+    (Some(tls_cert), Some(tls_key)) => {
+      let certificate = std::fs::read(tls_cert)?;
+      let key = std::fs::read(tls_key)?;
+      let tls_acceptor = s3_signer::tls_acceptor(&certificate, &key)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+      Some(tls_acceptor)
+    }
+    (Some(_), None) => exit_on_error(ConfigurationError::Missing("tls-key")),
+    (None, Some(_)) => exit_on_error(ConfigurationError::Missing("tls-cert")),
+    (None, None) => None,
+  };
+
+  let docs = match (settings.enable_docs.unwrap_or(true), settings.docs_auth) {
+    (false, _) => Docs::Disabled,
+    (true, false) => Docs::Public,
+    (true, true) => Docs::Authenticated,
+  };
+  let has_api_keys = s3_configuration
+    .policies()
+    .is_some_and(|policies| policies.iter().any(|policy| policy.api_key.is_some()));
+  if docs == Docs::Authenticated && s3_configuration.jwt_validator().is_none() && !has_api_keys {
+    exit_on_error(ConfigurationError::Missing("jwks-url or --policy-file"));
+  }
+
+  tokio::spawn(reload_on_hangup(
+    s3_configuration.clone(),
+    mock_hostname.clone(),
+  ));
+  if let Some(config) = settings.config {
+    tokio::spawn(reload_on_change(
+      s3_configuration.clone(),
+      config,
+      mock_hostname,
+    ));
+  }
+
+  let result = start(
+    &signer,
+    &scheduler,
+    settings.port.unwrap_or(DEFAULT_PORT),
+    settings.strict_open_api,
+    tls_acceptor,
+    settings.request_timeout.map(Duration::from_secs),
+    docs,
+  )
+  .await;
+
+  s3_signer::telemetry::shutdown_tracing();
+  result
+}
+
+fn exit_on_error(error: ConfigurationError) -> ! {
+  eprintln!("{}", error);
+  std::process::exit(1)
+}
+
+/// Serves the in-memory storage of `--mock` on a local port, returning its hostname.
+#[cfg(feature = "mock-s3")]
+fn start_mock_s3(settings: &Settings) -> std::io::Result<Option<String>> {
+  if !settings.mock {
+    return Ok(None);
+  }
+
+  let (address, server) = s3_signer::MockS3::default()
+    .serve(([127, 0, 0, 1], 0))
+    .map_err(std::io::Error::other)?;
+  tokio::spawn(server);
+  log::warn!(
+    "Serving an in-memory S3 storage at http://{}, its objects are lost on exit",
+    address
+  );
+  Ok(Some(format!("http://{}", address)))
+}
+
+#[cfg(not(feature = "mock-s3"))]
+fn start_mock_s3(_settings: &Settings) -> std::io::Result<Option<String>> {
+  Ok(None)
+}
+
+/// Serves the gRPC interface of the signer in the background, with `--grpc-port`.
+#[cfg(feature = "grpc")]
+fn start_grpc(settings: &Settings, s3_configuration: &S3Configuration) {
+  if let Some(grpc_port) = settings.grpc_port {
+    let s3_configuration = s3_configuration.clone();
+    tokio::spawn(async move {
+      if let Err(error) =
+        s3_signer::grpc::serve(&s3_configuration, ([0, 0, 0, 0], grpc_port).into()).await
+      {
+        log::error!("gRPC server: {}", error);
+      }
+    });
+  }
+}
+
+#[cfg(not(feature = "grpc"))]
+fn start_grpc(_settings: &Settings, _s3_configuration: &S3Configuration) {}
+
+/// Points the settings at the in-memory storage of `--mock`, when served.
+fn with_mock_storage(settings: Settings, mock_hostname: Option<&str>) -> Settings {
+  match mock_hostname {
+    Some(mock_hostname) => settings.with_mock_storage(mock_hostname),
+    None => settings,
+  }
+}
+
+/// Pre-signs the URL of the `presign` command, with the credentials and region of its bucket.
+async fn presign(
+  s3_configuration: &S3Configuration,
+  command: &PresignCommand,
+) -> Result<String, CredentialsExpired> {
+  let s3_configuration = s3_configuration.for_bucket(&command.bucket);
+  let configuration = s3_configuration
+    .presign_configuration(&command.bucket)
+    .await;
+  let options = PresignOptions::from(&s3_configuration)
+    .with_transfer_acceleration(s3_configuration.transfer_acceleration());
+  let options = match command.expires_in {
+    Some(expires_in) => options.with_expiration(Duration::from_secs(expires_in)),
+    None => options,
+  };
+
+  let presign_object = match command.method {
+    PresignMethod::Get => presign::get_object,
+    PresignMethod::Put => presign::put_object,
+    PresignMethod::Delete => presign::delete_object,
+  };
+  presign_object(&configuration, &command.bucket, &command.key, &options)
+}
+
+/// Prints the objects of the `ls` command, page by page: their modification date, size and key,
+/// relative to the listed prefix.
+async fn ls(s3_configuration: &S3Configuration, command: &LsCommand) -> Result<(), String> {
+  let (bucket, prefix) = command
+    .location
+    .strip_prefix("s3://")
+    .map(|location| location.split_once('/').unwrap_or((location, "")))
+    .filter(|(bucket, _)| !bucket.is_empty())
+    .ok_or_else(|| {
+      format!(
+        "Invalid location {}, expected s3://bucket/prefix",
+        command.location
+      )
+    })?;
+
+  let mut continuation_token = None;
+  loop {
+    let (objects, next_continuation_token) = s3_signer::objects::list_objects_page(
+      s3_configuration,
+      bucket,
+      prefix,
+      command.recursive,
+      continuation_token,
     )
-    .unwrap()
+    .await
+    .map_err(|error| error.to_string())?;
+
+    for object in objects {
+      let path = object.key.strip_prefix(prefix).unwrap_or(&object.key);
+      if object.is_dir {
+        println!("{:>19} {:>12} {}", "", "PRE", path);
+      } else {
+        let last_modified = object
+          .last_modified
+          .map(|last_modified| {
+            chrono::DateTime::parse_from_rfc3339(&last_modified)
+              .map(|date_time| date_time.format("%Y-%m-%d %H:%M:%S").to_string())
+              .unwrap_or(last_modified)
+          })
+          .unwrap_or_default();
+        println!(
+          "{:>19} {:>12} {}",
+          last_modified,
+          object.size.unwrap_or_default(),
+          path
+        );
+      }
+    }
+
+    match next_continuation_token {
+      Some(next_continuation_token) => continuation_token = Some(next_continuation_token),
+      None => return Ok(()),
+    }
+  }
+}
+
+/// Reports the configuration and the probes of the storage of the `check` command, returning
+/// whether they all succeeded.
+async fn check(s3_configuration: &S3Configuration) -> bool {
+  let access_key_id = s3_configuration.access_key_id();
+  println!("Configuration: ok");
+  if access_key_id.is_empty() || s3_configuration.secret_access_key().is_empty() {
+    println!(
+      "Credentials: FAILED, empty --aws-access-key-id or --aws-secret-access-key (or their environment variables)"
+    );
+    return false;
+  }
+  println!(
+    "Credentials: access key {}...",
+    access_key_id.get(..4).unwrap_or(access_key_id)
+  );
+  match s3_configuration.region() {
+    Region::Custom { name, endpoint } => println!("Storage: {} ({})", endpoint, name),
+    region => println!(
+      "Storage: AWS ({}, {} partition)",
+      region.name(),
+      Partition::of(region).name()
+    ),
+  }
+
+  let mut succeeded = true;
+  for result in s3_signer::health::check(s3_configuration).await {
+    match result.error {
+      Some(error) => {
+        succeeded = false;
+        println!("{}: FAILED, {}", result.probe, error);
+      }
+      None => println!("{}: ok", result.probe),
+    }
+  }
+  succeeded
+}
+
+/// Installs the lifecycle rule aborting the incomplete multipart uploads of `--ensure-lifecycle`
+/// on the buckets, logging the failures rather than exiting.
+async fn ensure_lifecycle(s3_configuration: Arc<S3Configuration>, buckets: Vec<String>, days: i64) {
+  if buckets.is_empty() {
+    log::warn!("--ensure-lifecycle requires --allowed-buckets without wildcards or --uppy-bucket");
+  }
+
+  for bucket in buckets {
+    match multipart_upload::ensure_lifecycle_rule(&s3_configuration, &bucket, days).await {
+      Ok(_rule) => log::info!(
+        "Incomplete multipart uploads of bucket {} are aborted after {} days",
+        bucket,
+        days
+      ),
+      Err(error) => log::error!(
+        "Cannot install the lifecycle rule of bucket {}: {}",
+        bucket,
+        error
+      ),
+    }
+  }
+}
+
+/// Reloads the configuration when the signer receives SIGHUP.
+async fn reload_on_hangup(s3_configuration: S3Configuration, mock_hostname: Option<String>) {
+  let mut hangups = match signal(SignalKind::hangup()) {
+    Ok(hangups) => hangups,
+    Err(error) => {
+      log::error!("Cannot handle SIGHUP: {}", error);
+      return;
+    }
+  };
+
+  while hangups.recv().await.is_some() {
+    reload(&s3_configuration, mock_hostname.as_deref());
+  }
+}
+
+/// Reloads the configuration when the configuration file is modified.
+async fn reload_on_change(
+  s3_configuration: S3Configuration,
+  config: String,
+  mock_hostname: Option<String>,
+) {
+  let modified_at = |config: &str| {
+    std::fs::metadata(config)
+      .and_then(|metadata| metadata.modified())
+      .ok()
   };
 
-  start(&s3_configuration, args.port).await;
+  let mut last_modified_at = modified_at(&config);
+  loop {
+    tokio::time::sleep(CONFIG_POLL_INTERVAL).await;
+
+    let current_modified_at = modified_at(&config);
+    if current_modified_at != last_modified_at {
+      last_modified_at = current_modified_at;
+      reload(&s3_configuration, mock_hostname.as_deref());
+    }
+  }
+}
+
+/// Swaps the served configuration for the one of the current settings, unless they are invalid.
+///
+/// The download links issued so far, the jobs, the concurrency limit and listing cache unless
+/// reconfigured, the usage of the quotas, and the in-memory storage of `--mock`, are kept. The port, base path, TLS, CORS,
+/// OpenAPI validation, request timeout, documentation, scheduled tasks, logging and tracing
+/// settings are only read at startup.
+fn reload(s3_configuration: &S3Configuration, mock_hostname: Option<&str>) {
+  match try_reload(s3_configuration, mock_hostname) {
+    Ok(()) => log::info!("Configuration reloaded"),
+    Err(error) => log::error!("Configuration not reloaded: {}", error),
+  }
+}
 
+/// Swaps the served configuration like [`reload`], answering whether the settings are invalid.
+fn try_reload(
+  s3_configuration: &S3Configuration,
+  mock_hostname: Option<&str>,
+) -> Result<(), ConfigurationError> {
+  let download_links = s3_configuration.current().download_links().cloned();
+  let reloaded = Settings::load()
+    .map_err(ConfigurationError::Settings)
+    .and_then(|settings| {
+      with_mock_storage(settings, mock_hostname).s3_configuration(download_links)
+    })?;
+  s3_configuration.reload(reloaded);
   Ok(())
 }
 
-const API_ROOT_PATH: &str = "api";
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+async fn start(
+  signer: &Signer,
+  scheduler: &Scheduler,
+  port: u16,
+  strict_open_api: bool,
+  tls_acceptor: Option<TlsAcceptor>,
+  request_timeout: Option<Duration>,
+  docs: Docs,
+) -> std::io::Result<()> {
+  let base_path = signer.base_path();
+  let s3_configuration = signer.s3_configuration().current();
+  let open_api_doc = s3_signer::insert_security_schemes(
+    s3_signer::insert_open_api_at(ApiDoc::openapi(), base_path),
+    &s3_configuration,
+  );
+  let contract_validator = ContractValidator::new(open_api_doc.clone(), strict_open_api);
+  let metrics = s3_signer::metrics::instrument(&open_api_doc);
+
+  let routes = with_request_id(s3_signer::recover_errors(
+    contract_validator.requests().and(
+      root(docs)
+        .or(s3_signer::health_routes(signer.s3_configuration()))
+        .or(s3_signer::metrics_route())
+        .or(s3_signer::scheduled_tasks_route(
+          scheduler,
+          signer.s3_configuration(),
+        ))
+        .or(signer.routes())
+        .or(docs_access(docs, signer.s3_configuration()).and(doc(
+          open_api_doc,
+          base_path,
+          &s3_configuration,
+        ))),
+    ),
+  ))
+  .with(contract_validator.responses())
+  .with(metrics)
+  .with(s3_signer::telemetry::trace_requests());
 
-async fn start(s3_configuration: &S3Configuration, port: u16) {
-  let routes = root()
-    .or(options())
-    .or(warp::path(API_ROOT_PATH).and(s3_signer::routes(s3_configuration)))
-    .or(doc())
-    .recover(handle_rejection);
+  // Served by hyper rather than `warp::serve`, to answer the requests timing out.
+  let service = move |remote_address| {
+    let service = s3_signer::with_request_timeout(routes.clone(), remote_address, request_timeout);
+    async move { Ok::<_, Infallible>(service) }
+  };
 
-  warp::serve(routes).run(([0, 0, 0, 0], port)).await;
+  if let Some(tls_acceptor) = tls_acceptor {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    Server::builder(accept::from_stream(s3_signer::tls_incoming(
+      listener,
+      tls_acceptor,
+    )))
+    .serve(make_service_fn(|_connection| service(None)))
+    .await
+  } else {
+    Server::try_bind(&([0, 0, 0, 0], port).into())
+      .map_err(std::io::Error::other)?
+      .serve(make_service_fn(|connection: &AddrStream| {
+        service(Some(connection.remote_addr()))
+      }))
+      .await
+  }
+  .map_err(std::io::Error::other)
 }
 
 #[derive(OpenApi)]
@@ -113,6 +516,19 @@ async fn start(s3_configuration: &S3Configuration, port: u16) {
 )]
 struct ApiDoc;
 
+/// OpenAPI document of the server served under `base_path`, as printed by the `openapi` command.
+///
+/// Both security schemes are documented, whatever the configuration requires.
+fn open_api(format: OpenApiFormat, base_path: &str) -> std::io::Result<String> {
+  let open_api_doc = s3_signer::insert_open_api_at(ApiDoc::openapi(), base_path);
+  match format {
+    OpenApiFormat::Json => {
+      serde_json::to_string_pretty(&open_api_doc).map_err(std::io::Error::other)
+    }
+    OpenApiFormat::Yaml => serde_yaml::to_string(&open_api_doc).map_err(std::io::Error::other),
+  }
+}
+
 /// Root path
 #[utoipa::path(
   get,
@@ -120,45 +536,121 @@ struct ApiDoc;
   tag = "Server",
   responses((status = 200, description = "Server information"))
 )]
-fn root() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-  warp::path::end().and(warp::get()).map(|| {
-    format!(
-      "S3 Signer (version {})\nAPI documentation on: /swagger-ui/",
-      built_info::PKG_VERSION
-    )
+fn root(docs: Docs) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  warp::path::end().and(warp::get()).map(move || {
+    if docs == Docs::Disabled {
+      format!("S3 Signer (version {})", built_info::PKG_VERSION)
+    } else {
+      format!(
+        "S3 Signer (version {})\nAPI documentation on: /swagger-ui/",
+        built_info::PKG_VERSION
+      )
+    }
   })
 }
 
-fn options() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-  warp::options().map(|| {
-    s3_signer::request_builder()
-      .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, OPTIONS, POST, PUT")
-      .body(Body::empty())
-      .unwrap()
-  })
+/// First path segments of the OpenAPI documents and the Swagger UI.
+const DOCS_SEGMENTS: &[&str] = &["api-doc.json", "api-doc", "swagger-ui"];
+
+/// Access to the OpenAPI documents and the Swagger UI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Docs {
+  Disabled,
+  Public,
+  /// Requires a bearer token or an API key of the policies
+  Authenticated,
 }
 
-fn doc() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-  let open_api_doc = s3_signer::insert_open_api_at(ApiDoc::openapi(), API_ROOT_PATH);
+/// Lets the requests to the documentation through, depending on its access.
+fn docs_access(
+  docs: Docs,
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  warp::path::peek()
+    .and(warp::header::headers_cloned())
+    .and(warp::any().map(move || s3_configuration.current()))
+    .and_then(
+      move |path: Peek, headers: HeaderMap, s3_configuration: Arc<S3Configuration>| async move {
+        let is_docs_path = path
+          .segments()
+          .next()
+          .is_some_and(|segment| DOCS_SEGMENTS.contains(&segment));
+        match docs {
+          _ if !is_docs_path => Err(warp::reject::not_found()),
+          Docs::Disabled => Err(warp::reject::not_found()),
+          Docs::Public => Ok(()),
+          Docs::Authenticated => {
+            let identity = Identity::from_headers(&s3_configuration, &headers)
+              .await
+              .map_err(warp::reject::custom)?;
+            if policy::is_authenticated(&s3_configuration, &identity) {
+              Ok(())
+            } else {
+              Err(warp::reject::custom(Error::Unauthorized(
+                "the documentation requires a bearer token or an API key".to_string(),
+              )))
+            }
+          }
+        }
+      },
+    )
+    .untuple_one()
+}
 
+/// Serves the OpenAPI document of all the versions at `/api-doc.json`, and the one of each version
+/// at e.g. `/api-doc/v1.json`, documenting the authentication of the configuration.
+fn doc(
+  open_api_doc: utoipa::openapi::OpenApi,
+  base_path: &str,
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
   let api_doc = warp::path("api-doc.json")
     .and(warp::get())
     .map(move || warp::reply::json(&open_api_doc));
 
-  let swagger = s3_signer::swagger_route("swagger-ui", "api-doc.json");
+  let versioned_open_api_docs = Arc::new(
+    ApiVersion::ALL
+      .iter()
+      .map(|version| {
+        (
+          format!("{}.json", version),
+          s3_signer::insert_security_schemes(
+            s3_signer::insert_versioned_open_api_at(ApiDoc::openapi(), base_path, *version),
+            s3_configuration,
+          ),
+        )
+      })
+      .collect::<Vec<_>>(),
+  );
+  let versioned_api_doc =
+    warp::path!("api-doc" / String)
+      .and(warp::get())
+      .and_then(move |file_name: String| {
+        let versioned_open_api_docs = versioned_open_api_docs.clone();
+        async move {
+          versioned_open_api_docs
+            .iter()
+            .find(|(versioned_file_name, _)| *versioned_file_name == file_name)
+            .map(|(_, open_api_doc)| warp::reply::json(open_api_doc))
+            .ok_or_else(warp::reject::not_found)
+        }
+      });
 
-  api_doc.or(swagger)
-}
-
-async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
-  if err.is_not_found() {
-    return Ok(StatusCode::NOT_FOUND.into_response());
-  }
+  let open_api_routes = std::iter::once("api-doc.json".to_string())
+    .chain(
+      ApiVersion::ALL
+        .iter()
+        .map(|version| format!("api-doc/{}.json", version)),
+    )
+    .collect::<Vec<_>>();
+  let swagger = s3_signer::swagger_route(
+    "swagger-ui",
+    &open_api_routes
+      .iter()
+      .map(String::as_str)
+      .collect::<Vec<_>>(),
+  );
 
-  if let Some(error) = err.find::<s3_signer::Error>() {
-    log::error!("{}", error);
-  } else {
-    log::error!("Unhandled rejection: {:?}", err);
-  }
-  Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+  api_doc.or(versioned_api_doc).or(swagger)
 }
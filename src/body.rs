@@ -0,0 +1,11 @@
+use crate::S3Configuration;
+use serde::de::DeserializeOwned;
+use warp::{Filter, Rejection};
+
+/// Deserializes the JSON body of the requests into `T`, like `warp::body::json`, but rejecting
+/// the bodies larger than the maximum size of the configuration before reading them.
+pub(crate) fn json<T: DeserializeOwned + Send>(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+  warp::body::content_length_limit(s3_configuration.max_json_body_size()).and(warp::body::json())
+}
@@ -0,0 +1,90 @@
+use crate::{metrics, presign::Partition, telemetry, S3Configuration};
+use rusoto_s3::{GetBucketLocationRequest, S3Client, S3};
+use rusoto_signature::Region;
+use std::{
+  collections::HashMap,
+  convert::TryFrom,
+  str::FromStr,
+  sync::{Arc, RwLock},
+};
+use tracing::Instrument;
+
+/// Regions of the buckets, looked up with GetBucketLocation the first time a URL is pre-signed for
+/// them, so that buckets of other regions than the configured one get valid signatures.
+///
+/// Lookups are kept for the lifetime of the cache, buckets seldom moving. Failed lookups (e.g.
+/// without the `s3:GetBucketLocation` permission) fall back on the configured region, and are
+/// retried on the next URLs.
+#[derive(Clone, Debug, Default)]
+pub struct BucketRegions {
+  regions: Arc<RwLock<HashMap<String, Region>>>,
+}
+
+impl BucketRegions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Returns the region of the bucket, looking it up with the bucket configuration unless cached.
+  pub(crate) async fn region(&self, s3_configuration: &S3Configuration, bucket: &str) -> Region {
+    if let Some(region) = self
+      .regions
+      .read()
+      .unwrap_or_else(|error| error.into_inner())
+      .get(bucket)
+    {
+      return region.clone();
+    }
+
+    match lookup(s3_configuration, bucket).await {
+      Ok(region) => {
+        log::info!("Bucket region: bucket={}, region={}", bucket, region.name());
+        self
+          .regions
+          .write()
+          .unwrap_or_else(|error| error.into_inner())
+          .insert(bucket.to_string(), region.clone());
+        region
+      }
+      Err(error) => {
+        log::warn!(
+          "Cannot detect the region of bucket {}, signing for {}: {}",
+          bucket,
+          s3_configuration.region().name(),
+          error
+        );
+        s3_configuration.region().clone()
+      }
+    }
+  }
+}
+
+async fn lookup(s3_configuration: &S3Configuration, bucket: &str) -> Result<Region, String> {
+  let client = S3Client::try_from(s3_configuration).map_err(|error| error.to_string())?;
+  let output = client
+    .get_bucket_location(GetBucketLocationRequest {
+      bucket: bucket.to_string(),
+      ..Default::default()
+    })
+    .instrument(telemetry::s3_span("GetBucketLocation"))
+    .await
+    .map_err(metrics::count_s3_error("GetBucketLocation"))
+    .map_err(|error| error.to_string())?;
+
+  // Buckets of the default region of the partition, e.g. us-east-1, have no location constraint,
+  // and the ones of eu-west-1 may have the legacy `EU` one.
+  let default_region = Partition::of(s3_configuration.region()).default_region();
+  let name = match output.location_constraint.as_deref() {
+    None | Some("") => default_region.name(),
+    Some("EU") => "eu-west-1",
+    Some(name) => name,
+  };
+
+  match s3_configuration.region() {
+    Region::Custom { endpoint, .. } => Ok(Region::Custom {
+      name: name.to_string(),
+      endpoint: endpoint.clone(),
+    }),
+    _ => Region::from_str(name).map_err(|error| error.to_string()),
+  }
+}
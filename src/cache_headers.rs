@@ -0,0 +1,41 @@
+//! `Cache-Control` and `Expires` metadata of uploaded objects, which S3 (and the CDNs in front of
+//! it) answer along with the objects.
+
+use crate::Error;
+
+const CACHE_CONTROL_HEADER: &str = "Cache-Control";
+const EXPIRES_HEADER: &str = "Expires";
+
+/// Checks that the metadata can be sent as headers, `expires` being an HTTP date, e.g.
+/// `Wed, 21 Oct 2026 07:28:00 GMT`.
+pub(crate) fn check_parameters(
+  cache_control: &Option<String>,
+  expires: &Option<String>,
+) -> Result<(), Error> {
+  if let Some(cache_control) = cache_control {
+    if cache_control.is_empty() || cache_control.chars().any(|c| c.is_control()) {
+      return Err(Error::InvalidParameter(
+        "cache_control must be a valid Cache-Control header".to_string(),
+      ));
+    }
+  }
+  if let Some(expires) = expires {
+    chrono::DateTime::parse_from_rfc2822(expires)
+      .map_err(|_| Error::InvalidParameter("expires must be an HTTP date".to_string()))?;
+  }
+
+  Ok(())
+}
+
+/// Headers to sign so that S3 stores the metadata along with the object.
+pub(crate) fn headers(
+  cache_control: &Option<String>,
+  expires: &Option<String>,
+) -> Vec<(&'static str, String)> {
+  cache_control
+    .clone()
+    .map(|cache_control| (CACHE_CONTROL_HEADER, cache_control))
+    .into_iter()
+    .chain(expires.clone().map(|expires| (EXPIRES_HEADER, expires)))
+    .collect()
+}
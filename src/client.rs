@@ -0,0 +1,233 @@
+//! Typed HTTP client of the signer API, for Rust services consuming it.
+
+use crate::{
+  api_version::ApiVersion,
+  multipart_upload::{
+    AbortOrCompleteUploadBody, AbortOrCompleteUploadQueryParameters, CompletedUploadPart,
+    CreateUploadQueryParameters, CreateUploadResponse, PartUploadQueryParameters,
+    PartUploadResponse,
+  },
+  objects::{ListObjectsQueryParameters, ListObjectsResponse, SignQueryParameters},
+};
+use reqwest::{
+  header::{LOCATION, RETRY_AFTER},
+  redirect, Client, RequestBuilder, Response, StatusCode, Url,
+};
+use serde::de::DeserializeOwned;
+use std::fmt::{Debug, Display, Formatter};
+
+pub enum ClientError {
+  BadRequest,
+  Forbidden,
+  InvalidBaseUrl(String),
+  InvalidRedirection(String),
+  NotFound,
+  RequestError(reqwest::Error),
+  Unauthorized,
+  Unavailable(Option<u64>),
+  UnexpectedStatus(StatusCode),
+}
+
+impl Debug for ClientError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ClientError::BadRequest => write!(f, "Bad request"),
+      ClientError::Forbidden => write!(f, "Forbidden"),
+      ClientError::InvalidBaseUrl(error) => write!(f, "Invalid base URL: {:?}", error),
+      ClientError::InvalidRedirection(error) => write!(f, "Invalid redirection: {:?}", error),
+      ClientError::NotFound => write!(f, "Not found"),
+      ClientError::RequestError(error) => write!(f, "Request: {:?}", error),
+      ClientError::Unauthorized => write!(f, "Unauthorized"),
+      ClientError::Unavailable(Some(retry_after)) => {
+        write!(f, "Unavailable: retry after {}s", retry_after)
+      }
+      ClientError::Unavailable(None) => write!(f, "Unavailable"),
+      ClientError::UnexpectedStatus(status) => write!(f, "Unexpected status: {}", status),
+    }
+  }
+}
+
+impl Display for ClientError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{:?}", self)
+  }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+  fn from(error: reqwest::Error) -> Self {
+    ClientError::RequestError(error)
+  }
+}
+
+/// Client of the API served at a base URL, e.g. `https://signer.example.com/api`.
+///
+/// Requests are sent to the paths of [`ApiVersion::V1`], so that the responses keep matching
+/// the DTOs of this crate when the server moves to a newer default version.
+#[derive(Clone, Debug)]
+pub struct S3SignerClient {
+  client: Client,
+  base_url: Url,
+  bearer_token: Option<String>,
+}
+
+impl S3SignerClient {
+  pub fn new(base_url: &str) -> Result<Self, ClientError> {
+    // Redirections are the pre-signed URLs returned by the signer, not to be followed.
+    let client = Client::builder()
+      .redirect(redirect::Policy::none())
+      .build()?;
+    Self::with_client(client, base_url)
+  }
+
+  /// Uses a configured `reqwest` client, e.g. with timeouts. It must not follow redirections.
+  pub fn with_client(client: Client, base_url: &str) -> Result<Self, ClientError> {
+    let mut base_url =
+      Url::parse(base_url).map_err(|error| ClientError::InvalidBaseUrl(error.to_string()))?;
+    if base_url.cannot_be_a_base() {
+      return Err(ClientError::InvalidBaseUrl(base_url.to_string()));
+    }
+    if !base_url.path().ends_with('/') {
+      base_url.set_path(&format!("{}/", base_url.path()));
+    }
+
+    Ok(Self {
+      client,
+      base_url,
+      bearer_token: None,
+    })
+  }
+
+  /// Authenticates the requests with a bearer token, when the signer validates JWTs.
+  pub fn with_bearer_token(mut self, bearer_token: &str) -> Self {
+    self.bearer_token = Some(bearer_token.to_string());
+    self
+  }
+
+  pub async fn list_objects(
+    &self,
+    parameters: &ListObjectsQueryParameters,
+  ) -> Result<ListObjectsResponse, ClientError> {
+    let request = self.client.get(self.url("objects")?).query(parameters);
+    self.send_json(request).await
+  }
+
+  /// Pre-signed URL of the object, or download link when the signer issues them.
+  pub async fn get_object_url(&self, parameters: &SignQueryParameters) -> Result<Url, ClientError> {
+    let url = self.url("object")?;
+    let response = self
+      .send(self.client.get(url.clone()).query(parameters))
+      .await?;
+    if response.status() != StatusCode::FOUND {
+      return Err(ClientError::UnexpectedStatus(response.status()));
+    }
+
+    let location = response
+      .headers()
+      .get(LOCATION)
+      .ok_or_else(|| ClientError::InvalidRedirection("missing Location header".to_string()))?
+      .to_str()
+      .map_err(|error| ClientError::InvalidRedirection(error.to_string()))?;
+
+    // Download links are relative to the requested URL.
+    url
+      .join(location)
+      .map_err(|error| ClientError::InvalidRedirection(error.to_string()))
+  }
+
+  pub async fn create_upload(
+    &self,
+    parameters: &CreateUploadQueryParameters,
+  ) -> Result<CreateUploadResponse, ClientError> {
+    let request = self
+      .client
+      .post(self.url("multipart-upload")?)
+      .query(parameters);
+    self.send_json(request).await
+  }
+
+  /// Pre-signed URL to upload the part `part_number` (from 1) of a multipart upload.
+  pub async fn part_url(
+    &self,
+    upload_id: &str,
+    part_number: i64,
+    parameters: &PartUploadQueryParameters,
+  ) -> Result<PartUploadResponse, ClientError> {
+    let mut url = self.url("multipart-upload")?;
+    url
+      .path_segments_mut()
+      .map_err(|_| ClientError::InvalidBaseUrl(self.base_url.to_string()))?
+      .extend(&[upload_id, "part", &part_number.to_string()]);
+
+    self.send_json(self.client.get(url).query(parameters)).await
+  }
+
+  /// Completes a multipart upload with the ETags of its uploaded parts.
+  pub async fn complete_upload(
+    &self,
+    upload_id: &str,
+    parameters: &AbortOrCompleteUploadQueryParameters,
+    parts: Vec<CompletedUploadPart>,
+  ) -> Result<(), ClientError> {
+    let mut url = self.url("multipart-upload")?;
+    url
+      .path_segments_mut()
+      .map_err(|_| ClientError::InvalidBaseUrl(self.base_url.to_string()))?
+      .push(upload_id);
+
+    let request = self
+      .client
+      .post(url)
+      .query(parameters)
+      .json(&AbortOrCompleteUploadBody::Complete { parts });
+    self.send(request).await.map(|_response| ())
+  }
+
+  fn url(&self, path: &str) -> Result<Url, ClientError> {
+    self
+      .base_url
+      .join(&format!("{}/{}", ApiVersion::V1, path))
+      .map_err(|error| ClientError::InvalidBaseUrl(error.to_string()))
+  }
+
+  async fn send_json<T: DeserializeOwned>(
+    &self,
+    request: RequestBuilder,
+  ) -> Result<T, ClientError> {
+    let response = self.send(request).await?;
+    if response.status() != StatusCode::OK {
+      return Err(ClientError::UnexpectedStatus(response.status()));
+    }
+
+    Ok(response.json().await?)
+  }
+
+  async fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+    let request = match &self.bearer_token {
+      Some(bearer_token) => request.bearer_auth(bearer_token),
+      None => request,
+    };
+
+    let response = request.send().await?;
+    let status = response.status();
+    if status.is_success() || status.is_redirection() {
+      return Ok(response);
+    }
+
+    Err(match status {
+      StatusCode::BAD_REQUEST => ClientError::BadRequest,
+      StatusCode::UNAUTHORIZED => ClientError::Unauthorized,
+      StatusCode::FORBIDDEN => ClientError::Forbidden,
+      StatusCode::NOT_FOUND => ClientError::NotFound,
+      StatusCode::SERVICE_UNAVAILABLE => ClientError::Unavailable(
+        response
+          .headers()
+          .get(RETRY_AFTER)
+          .and_then(|value| value.to_str().ok())
+          .and_then(|value| value.parse().ok()),
+      ),
+      status => ClientError::UnexpectedStatus(status),
+    })
+  }
+}
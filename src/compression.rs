@@ -0,0 +1,129 @@
+use crate::Error;
+use flate2::{write::GzEncoder, Compression};
+use std::io::Write;
+use warp::{
+  hyper::{
+    self,
+    header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, VARY},
+    Body, Response,
+  },
+  Filter, Rejection, Reply,
+};
+
+/// Bodies smaller than this are not worth compressing.
+const MIN_COMPRESSED_LENGTH: usize = 1024;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW_SIZE: u32 = 22;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+  Brotli,
+  Gzip,
+}
+
+impl Encoding {
+  fn as_str(&self) -> &'static str {
+    match self {
+      Encoding::Brotli => "br",
+      Encoding::Gzip => "gzip",
+    }
+  }
+
+  fn compress(&self, content: &[u8]) -> std::io::Result<Vec<u8>> {
+    match self {
+      Encoding::Brotli => {
+        let mut writer = brotli::CompressorWriter::new(
+          vec![],
+          BROTLI_BUFFER_SIZE,
+          BROTLI_QUALITY,
+          BROTLI_WINDOW_SIZE,
+        );
+        writer.write_all(content)?;
+        Ok(writer.into_inner())
+      }
+      Encoding::Gzip => {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(content)?;
+        encoder.finish()
+      }
+    }
+  }
+}
+
+/// Compresses the responses of the filter with brotli or gzip, as accepted by the client.
+pub(crate) fn compressed<F, R>(
+  filter: F,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone
+where
+  F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+  R: Reply + Send,
+{
+  warp::header::optional::<String>(ACCEPT_ENCODING.as_str())
+    .and(filter)
+    .and_then(|accept_encoding: Option<String>, reply: R| async move {
+      let encoding = accept_encoding.as_deref().and_then(preferred_encoding);
+      compress(reply.into_response(), encoding)
+        .await
+        .map_err(warp::reject::custom)
+    })
+}
+
+async fn compress(
+  response: Response<Body>,
+  encoding: Option<Encoding>,
+) -> Result<Response<Body>, Error> {
+  let (mut parts, body) = response.into_parts();
+  parts
+    .headers
+    .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+  let encoding = match encoding {
+    Some(encoding) if !parts.headers.contains_key(CONTENT_ENCODING) => encoding,
+    _ => return Ok(Response::from_parts(parts, body)),
+  };
+
+  let content = hyper::body::to_bytes(body)
+    .await
+    .map_err(|error| Error::CompressionError(error.to_string()))?;
+  if content.len() < MIN_COMPRESSED_LENGTH {
+    return Ok(Response::from_parts(parts, content.into()));
+  }
+
+  let compressed = encoding
+    .compress(&content)
+    .map_err(|error| Error::CompressionError(error.to_string()))?;
+
+  parts.headers.remove(CONTENT_LENGTH);
+  parts.headers.insert(
+    CONTENT_ENCODING,
+    HeaderValue::from_static(encoding.as_str()),
+  );
+  Ok(Response::from_parts(parts, compressed.into()))
+}
+
+/// Picks the supported encoding with the highest quality value, preferring brotli on ties.
+fn preferred_encoding(accept_encoding: &str) -> Option<Encoding> {
+  accept_encoding
+    .split(',')
+    .filter_map(|item| {
+      let mut parameters = item.split(';').map(str::trim);
+      let encoding = match parameters.next()?.to_ascii_lowercase().as_str() {
+        "br" => Encoding::Brotli,
+        "gzip" | "x-gzip" => Encoding::Gzip,
+        _ => return None,
+      };
+      let quality = parameters
+        .find_map(|parameter| parameter.strip_prefix("q="))
+        .map_or(Some(1.0), |quality| quality.parse::<f32>().ok())?;
+
+      (quality > 0.0).then_some((encoding, quality))
+    })
+    .max_by(|(encoding, quality), (other_encoding, other_quality)| {
+      quality
+        .partial_cmp(other_quality)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| (*encoding == Encoding::Brotli).cmp(&(*other_encoding == Encoding::Brotli)))
+    })
+    .map(|(encoding, _)| encoding)
+}
@@ -0,0 +1,53 @@
+use crate::{Error, S3Configuration};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits the number of concurrent S3 control-plane calls (listings and multipart uploads).
+///
+/// Requests beyond the limit are rejected rather than queued, and clients are asked to retry
+/// after `retry_after`.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimit {
+  semaphore: Arc<Semaphore>,
+  max_concurrent_calls: usize,
+  retry_after: Duration,
+}
+
+impl ConcurrencyLimit {
+  pub fn new(max_concurrent_calls: usize, retry_after: Duration) -> Self {
+    Self {
+      semaphore: Arc::new(Semaphore::new(max_concurrent_calls)),
+      max_concurrent_calls,
+      retry_after,
+    }
+  }
+
+  /// Returns this limit, with the calls in flight, unless `other` allows another number of calls.
+  pub(crate) fn reloaded(&self, other: ConcurrencyLimit) -> ConcurrencyLimit {
+    if other.max_concurrent_calls == self.max_concurrent_calls {
+      ConcurrencyLimit {
+        retry_after: other.retry_after,
+        ..self.clone()
+      }
+    } else {
+      other
+    }
+  }
+}
+
+/// Reserves a slot for an S3 control-plane call, released when the permit is dropped.
+pub(crate) fn acquire(
+  s3_configuration: &S3Configuration,
+) -> Result<Option<OwnedSemaphorePermit>, Error> {
+  let concurrency_limit = match s3_configuration.concurrency_limit() {
+    Some(concurrency_limit) => concurrency_limit,
+    None => return Ok(None),
+  };
+
+  concurrency_limit
+    .semaphore
+    .clone()
+    .try_acquire_owned()
+    .map(Some)
+    .map_err(|_| Error::ConcurrencyLimitReached(concurrency_limit.retry_after.as_secs().max(1)))
+}
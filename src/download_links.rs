@@ -0,0 +1,310 @@
+use crate::{
+  metrics,
+  policy::{self, Identity, Operation},
+  request_builder, request_timeout, telemetry, to_ok_json_response, Error, S3Configuration,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use rusoto_s3::{GetObjectRequest, S3Client, S3};
+use std::{
+  collections::HashMap,
+  convert::TryFrom,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+use tracing::Instrument;
+use warp::{
+  hyper::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    Body, Response, StatusCode,
+  },
+  Filter, Rejection, Reply,
+};
+
+const TOKEN_LENGTH: usize = 32;
+
+/// Opaque download links proxying objects, which unlike pre-signed URLs can be revoked.
+///
+/// Links are kept in memory: they can be downloaded `max_downloads` times (unlimited when 0)
+/// until they are revoked or expire.
+#[derive(Clone, Debug)]
+pub struct DownloadLinks {
+  max_downloads: u32,
+  expiration: Duration,
+  links: Arc<Mutex<HashMap<String, DownloadLink>>>,
+}
+
+#[derive(Clone, Debug)]
+struct DownloadLink {
+  bucket: String,
+  key: String,
+  created_at: Instant,
+  downloads: u32,
+  revoked: bool,
+}
+
+impl DownloadLinks {
+  pub fn new(max_downloads: u32, expiration: Duration) -> Self {
+    Self {
+      max_downloads,
+      expiration,
+      links: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+
+  /// Issues a new link to the object and returns its token.
+  pub fn issue(&self, bucket: &str, key: &str) -> String {
+    let token: String = rand::thread_rng()
+      .sample_iter(&Alphanumeric)
+      .take(TOKEN_LENGTH)
+      .map(char::from)
+      .collect();
+
+    let mut links = self.links.lock().unwrap_or_else(|error| error.into_inner());
+    let expiration = self.expiration;
+    links.retain(|_, link| link.created_at.elapsed() < expiration);
+    links.insert(
+      token.clone(),
+      DownloadLink {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        created_at: Instant::now(),
+        downloads: 0,
+        revoked: false,
+      },
+    );
+
+    token
+  }
+
+  /// Counts a download of the link, returning the linked object if it can still be downloaded.
+  ///
+  /// The download is counted before the object is got, for concurrent downloads not to exceed
+  /// `max_downloads`, and [released](DownloadLinks::release) if it fails.
+  fn consume(&self, token: &str) -> Option<(String, String)> {
+    let mut links = self.links.lock().unwrap_or_else(|error| error.into_inner());
+    let link = links.get_mut(token)?;
+
+    if link.revoked
+      || link.created_at.elapsed() >= self.expiration
+      || (self.max_downloads != 0 && link.downloads >= self.max_downloads)
+    {
+      return None;
+    }
+
+    link.downloads += 1;
+    Some((link.bucket.clone(), link.key.clone()))
+  }
+
+  /// Gives back the download counted by [`DownloadLinks::consume`], the object not being sent.
+  fn release(&self, token: &str) {
+    let mut links = self.links.lock().unwrap_or_else(|error| error.into_inner());
+    if let Some(link) = links.get_mut(token) {
+      link.downloads = link.downloads.saturating_sub(1);
+    }
+  }
+
+  fn get(&self, token: &str) -> Option<DownloadLink> {
+    let links = self.links.lock().unwrap_or_else(|error| error.into_inner());
+    links.get(token).cloned()
+  }
+
+  fn revoke(&self, token: &str) {
+    let mut links = self.links.lock().unwrap_or_else(|error| error.into_inner());
+    if let Some(link) = links.get_mut(token) {
+      link.revoked = true;
+    }
+  }
+}
+
+/// Download object through a link
+#[utoipa::path(
+  get,
+  path = "/d/{token}",
+  tag = "Objects",
+  responses(
+    (status = 200, description = "Streams the linked object"),
+    (status = 404, description = "Unknown, revoked, expired or exhausted link"),
+  ),
+  params(
+    ("token" = String, Path, description = "Token of the download link"),
+  ),
+)]
+pub(crate) fn download_route(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("d" / String)
+    .and(warp::get())
+    .and(request_timeout::client_address())
+    // Without the endpoint override, the route being unauthenticated.
+    .and(warp::any().map(move || s3_configuration.current()))
+    .and_then(
+      |token: String, requester: Option<String>, s3_configuration: Arc<S3Configuration>| async move {
+        download(&s3_configuration, &token, &requester.unwrap_or_default())
+          .await
+          .map_err(warp::reject::custom)
+      },
+    )
+}
+
+/// Revoke a download link
+#[utoipa::path(
+  delete,
+  path = "/d/{token}",
+  tag = "Objects",
+  responses(
+    (status = 200, description = "Successfully revoked the link"),
+    (status = 404, description = "Unknown link"),
+  ),
+  params(
+    ("token" = String, Path, description = "Token of the download link"),
+  ),
+  security(("bearer_token" = []), ("api_key" = [])),
+)]
+pub(crate) fn revoke_route(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("d" / String)
+    .and(warp::delete())
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
+    .and_then(
+      |token: String, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+        revoke(&s3_configuration, &identity, &token)
+          .await
+          .map_err(warp::reject::custom)
+      },
+    )
+}
+
+/// Streams the object of a download link, on behalf of `requester` (logged).
+#[tracing::instrument(skip_all)]
+pub async fn download(
+  s3_configuration: &S3Configuration,
+  token: &str,
+  requester: &str,
+) -> Result<Response<Body>, Error> {
+  let download_links = s3_configuration
+    .download_links()
+    .ok_or_else(link_not_found)?;
+  let (bucket, key) = download_links.consume(token).ok_or_else(link_not_found)?;
+
+  log::info!(
+    "Download link accessed: bucket={}, key={}, requester={}",
+    bucket,
+    key,
+    requester
+  );
+
+  let s3_configuration = s3_configuration.for_bucket(&bucket);
+  let client = S3Client::try_from(&s3_configuration)?;
+  let output = client
+    .get_object(GetObjectRequest {
+      bucket,
+      key,
+      ..Default::default()
+    })
+    .instrument(telemetry::s3_span("GetObject"))
+    .await
+    .map_err(metrics::count_s3_error("GetObject"))
+    .map_err(Error::GetObjectError)
+    .inspect_err(|_| download_links.release(token))?;
+
+  let mut response = request_builder().status(StatusCode::OK);
+  if let Some(content_type) = output.content_type {
+    response = response.header(CONTENT_TYPE, content_type);
+  }
+  if let Some(content_length) = output.content_length {
+    response = response.header(CONTENT_LENGTH, content_length);
+  }
+
+  response
+    .body(
+      output
+        .body
+        .map(Body::wrap_stream)
+        .unwrap_or_else(Body::empty),
+    )
+    .map_err(Error::HttpError)
+}
+
+/// Revokes a download link, provided that `identity` can read its object.
+#[tracing::instrument(skip_all)]
+pub async fn revoke(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  token: &str,
+) -> Result<Response<Body>, Error> {
+  let download_links = s3_configuration
+    .download_links()
+    .ok_or_else(link_not_found)?;
+  let link = download_links.get(token).ok_or_else(link_not_found)?;
+
+  policy::authorize(
+    s3_configuration,
+    identity,
+    Operation::Read,
+    &link.bucket,
+    &link.key,
+  )?;
+
+  log::info!(
+    "Revoke download link: bucket={}, key={}",
+    link.bucket,
+    link.key
+  );
+  download_links.revoke(token);
+
+  to_ok_json_response(&())
+}
+
+/// Error of the unknown links, which does not echo their secret token.
+fn link_not_found() -> Error {
+  Error::NotFound("unknown, revoked, expired or exhausted download link".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn links_are_downloaded_at_most_max_downloads_times() {
+    let download_links = DownloadLinks::new(2, Duration::from_secs(60));
+    let token = download_links.issue("bucket", "a.txt");
+    assert!(download_links.consume(&token).is_some());
+    assert!(download_links.consume(&token).is_some());
+    assert!(download_links.consume(&token).is_none());
+
+    download_links.release(&token);
+    assert!(download_links.consume(&token).is_some());
+  }
+
+  #[tokio::test]
+  async fn failed_downloads_are_not_counted() {
+    let download_links = DownloadLinks::new(1, Duration::from_secs(60));
+    let token = download_links.issue("bucket", "a.txt");
+    // Nothing listens on the discard port.
+    let s3_configuration =
+      S3Configuration::new_with_hostname("access", "secret", "us-east-1", "http://127.0.0.1:9")
+        .unwrap()
+        .with_download_links(download_links.clone());
+
+    assert!(download(&s3_configuration, &token, "client").await.is_err());
+    assert!(download_links.consume(&token).is_some());
+  }
+
+  #[tokio::test]
+  async fn unknown_links_do_not_echo_their_token() {
+    let s3_configuration =
+      S3Configuration::new_with_hostname("access", "secret", "us-east-1", "http://127.0.0.1:9")
+        .unwrap()
+        .with_download_links(DownloadLinks::new(1, Duration::from_secs(60)));
+
+    let error = download(&s3_configuration, "secret-token", "client")
+      .await
+      .unwrap_err();
+    assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+    assert!(!error.to_string().contains("secret-token"), "{}", error);
+  }
+}
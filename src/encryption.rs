@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+/// Server-side encryption applied by S3 to uploaded objects
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub enum ServerSideEncryption {
+  /// SSE-S3, keys managed by S3
+  #[serde(rename = "AES256")]
+  Aes256,
+  /// SSE-KMS, keys managed by AWS KMS
+  #[serde(rename = "aws:kms")]
+  AwsKms,
+}
+
+impl ServerSideEncryption {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ServerSideEncryption::Aes256 => "AES256",
+      ServerSideEncryption::AwsKms => "aws:kms",
+    }
+  }
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::ServerSideEncryption;
+  use crate::Error;
+  use md5::{Digest, Md5};
+  use std::fmt::{Debug, Formatter};
+  use warp::{Filter, Rejection};
+
+  const SERVER_SIDE_ENCRYPTION_HEADER: &str = "x-amz-server-side-encryption";
+  const KMS_KEY_ID_HEADER: &str = "x-amz-server-side-encryption-aws-kms-key-id";
+  const CUSTOMER_ALGORITHM_HEADER: &str = "x-amz-server-side-encryption-customer-algorithm";
+  pub const CUSTOMER_KEY_HEADER: &str = "x-amz-server-side-encryption-customer-key";
+  const CUSTOMER_KEY_MD5_HEADER: &str = "x-amz-server-side-encryption-customer-key-MD5";
+  const CUSTOMER_ALGORITHM: &str = "AES256";
+  const CUSTOMER_KEY_LENGTH: usize = 32;
+
+  /// Customer-provided encryption key (SSE-C), which is never logged.
+  #[derive(Clone)]
+  pub struct CustomerKey {
+    key: String,
+    key_md5: String,
+  }
+
+  impl Debug for CustomerKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      write!(f, "CustomerKey(<redacted>)")
+    }
+  }
+
+  impl CustomerKey {
+    /// Reads the base64-encoded key sent in the `x-amz-server-side-encryption-customer-key`
+    /// header of the signing request.
+    pub fn from_header(key: &str) -> Result<Self, Error> {
+      let decoded_key = base64::decode(key)
+        .ok()
+        .filter(|decoded_key| decoded_key.len() == CUSTOMER_KEY_LENGTH)
+        .ok_or_else(|| {
+          Error::InvalidParameter(format!(
+            "{} must be a base64-encoded {}-byte key",
+            CUSTOMER_KEY_HEADER, CUSTOMER_KEY_LENGTH
+          ))
+        })?;
+
+      Ok(CustomerKey {
+        key: key.to_string(),
+        key_md5: base64::encode(Md5::digest(&decoded_key)),
+      })
+    }
+
+    pub(crate) fn algorithm(&self) -> String {
+      CUSTOMER_ALGORITHM.to_string()
+    }
+
+    pub(crate) fn key(&self) -> String {
+      self.key.clone()
+    }
+
+    pub(crate) fn key_md5(&self) -> String {
+      self.key_md5.clone()
+    }
+
+    /// Headers to sign so that S3 encrypts or decrypts the object with the customer key.
+    pub(crate) fn headers(&self) -> Vec<(&'static str, String)> {
+      vec![
+        (CUSTOMER_ALGORITHM_HEADER, self.algorithm()),
+        (CUSTOMER_KEY_HEADER, self.key()),
+        (CUSTOMER_KEY_MD5_HEADER, self.key_md5()),
+      ]
+    }
+  }
+
+  /// Extracts the optional customer-provided key, sent base64-encoded in the
+  /// `x-amz-server-side-encryption-customer-key` header of the signing request.
+  pub(crate) fn customer_key(
+  ) -> impl Filter<Extract = (Option<CustomerKey>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(CUSTOMER_KEY_HEADER).and_then(
+      |key: Option<String>| async move {
+        key
+          .map(|key| CustomerKey::from_header(&key))
+          .transpose()
+          .map_err(warp::reject::custom)
+      },
+    )
+  }
+
+  /// Checks that a KMS key is only provided along with SSE-KMS encryption.
+  pub(crate) fn check_parameters(
+    sse: Option<ServerSideEncryption>,
+    kms_key_id: &Option<String>,
+  ) -> Result<(), Error> {
+    if kms_key_id.is_some() && sse != Some(ServerSideEncryption::AwsKms) {
+      return Err(Error::InvalidParameter(
+        "kms_key_id requires sse=aws:kms".to_string(),
+      ));
+    }
+
+    Ok(())
+  }
+
+  /// Headers to sign so that S3 encrypts the object as requested.
+  pub(crate) fn headers(
+    sse: Option<ServerSideEncryption>,
+    kms_key_id: &Option<String>,
+  ) -> Vec<(&'static str, String)> {
+    sse
+      .map(|sse| (SERVER_SIDE_ENCRYPTION_HEADER, sse.as_str().to_string()))
+      .into_iter()
+      .chain(
+        kms_key_id
+          .clone()
+          .map(|kms_key_id| (KMS_KEY_ID_HEADER, kms_key_id)),
+      )
+      .collect()
+  }
+}
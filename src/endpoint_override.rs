@@ -0,0 +1,39 @@
+//! Per-request S3 endpoint, with `--allow-endpoint-override`: the `X-S3-Endpoint` header
+//! of a request, e.g. `http://minio-42:9000`, points its calls and pre-signed URLs at another
+//! storage, so that a single signer can serve the ephemeral MinIO instances of test runs.
+//!
+//! Only the hosts matching the patterns of `--allow-endpoint-override` can be targeted, the signer
+//! sending them requests signed with its own credentials. The header is rejected otherwise, rather
+//! than ignored, so that a request meant for a test storage never reaches the configured one.
+
+use crate::{Error, S3Configuration};
+use std::sync::Arc;
+use warp::{filters::BoxedFilter, Filter};
+
+const ENDPOINT_OVERRIDE_HEADER: &str = "x-s3-endpoint";
+
+/// Extracts the configuration to serve the request with: the current one, targeting the endpoint
+/// of the `X-S3-Endpoint` header if any.
+pub(crate) fn current(s3_configuration: &S3Configuration) -> BoxedFilter<(Arc<S3Configuration>,)> {
+  let s3_configuration = s3_configuration.clone();
+  warp::header::optional::<String>(ENDPOINT_OVERRIDE_HEADER)
+    .and_then(move |endpoint: Option<String>| {
+      let s3_configuration = s3_configuration.current();
+      async move {
+        match endpoint {
+          None => Ok(s3_configuration),
+          Some(_) if s3_configuration.endpoint_overrides().is_empty() => {
+            Err(warp::reject::custom(Error::Forbidden(
+              "the X-S3-Endpoint header requires --allow-endpoint-override".to_string(),
+            )))
+          }
+          Some(endpoint) => s3_configuration
+            .with_endpoint(&endpoint)
+            .map(Arc::new)
+            .map_err(warp::reject::custom),
+        }
+      }
+    })
+    // Boxed, being part of every route, to keep the stack of their futures shallow.
+    .boxed()
+}
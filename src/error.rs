@@ -1,36 +1,119 @@
-use rusoto_core::{request::TlsError, RusotoError};
+use crate::{
+  batch::server::error_code, request_id::current_request_id, retry, to_json_response, ErrorBody,
+};
+use rusoto_core::RusotoError;
 use rusoto_s3::{
   AbortMultipartUploadError, CompleteMultipartUploadError, CreateMultipartUploadError,
-  ListObjectsV2Error,
+  DeleteObjectError, GetBucketAccelerateConfigurationError, GetBucketLifecycleConfigurationError,
+  GetObjectError, HeadObjectError, ListMultipartUploadsError, ListObjectsV2Error, ListPartsError,
+  PutBucketLifecycleConfigurationError, PutObjectError, SelectObjectContentError, UploadPartError,
 };
 use std::fmt::{Debug, Display, Formatter};
-use warp::{http::uri::InvalidUri, reject::Reject};
+use warp::{
+  http::uri::InvalidUri,
+  hyper::{header::RETRY_AFTER, Body, Response, StatusCode},
+  reject::Reject,
+};
+
+const INTERNAL_ERROR_MESSAGE: &str = "Internal server error";
 
 pub enum Error {
+  ArchiveError(String),
+  CompressionError(String),
+  ConcurrencyLimitReached(u64),
+  Conflict(String),
+  ContractViolation(String),
+  CredentialsExpired(String),
+  DeleteObjectError(RusotoError<DeleteObjectError>),
+  EntityTooSmall(PartError),
+  Forbidden(String),
+  GetObjectError(RusotoError<GetObjectError>),
+  HeadObjectError(RusotoError<HeadObjectError>),
   HttpError(warp::http::Error),
+  InvalidParameter(String),
+  InvalidPart(PartError),
+  InvalidPartOrder(String),
+  JobStoreError(String),
   JsonError(serde_json::Error),
+  JwksError(String),
+  LambdaError(String),
+  LifecycleConfigurationError(RusotoError<GetBucketLifecycleConfigurationError>),
+  LifecycleUpdateError(RusotoError<PutBucketLifecycleConfigurationError>),
+  MediaError(String),
+  MetricsError(String),
   ListObjectsError(RusotoError<ListObjectsV2Error>),
+  ListPartsError(RusotoError<ListPartsError>),
+  ListUploadsError(RusotoError<ListMultipartUploadsError>),
   MultipartUploadError(String),
+  NotFound(String),
   MultipartUploadAbortionError(RusotoError<AbortMultipartUploadError>),
   MultipartUploadCompletionError(RusotoError<CompleteMultipartUploadError>),
   MultipartUploadCreationError(RusotoError<CreateMultipartUploadError>),
-  S3ConnectionError(TlsError),
+  NoSuchUpload(String),
+  /// Error of GetObjectAttributes, parsed as the ones of GetObject
+  ObjectAttributesError(RusotoError<GetObjectError>),
+  ObjectLockError(String),
+  PutObjectError(RusotoError<PutObjectError>),
+  QuotaExceeded(String),
+  S3ConnectionError(native_tls::Error),
+  SelectError(String),
+  SelectObjectContentError(RusotoError<SelectObjectContentError>),
   SignatureError(String),
+  SiteError(String),
+  TransferAccelerationDisabled(String),
+  TransferAccelerationError(RusotoError<GetBucketAccelerateConfigurationError>),
+  Unauthorized(String),
+  UploadError(String),
+  UploadPartError(RusotoError<UploadPartError>),
   UriError(InvalidUri),
 }
 
+/// Part of a multipart upload rejected by S3 on completion.
+#[derive(Debug)]
+pub struct PartError {
+  pub part_number: Option<i64>,
+  pub message: String,
+}
+
 impl Debug for Error {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
     match self {
+      Error::ArchiveError(error) => write!(f, "Archive: {:?}", error),
+      Error::CompressionError(error) => write!(f, "Compression: {:?}", error),
+      Error::ConcurrencyLimitReached(retry_after) => {
+        write!(f, "Concurrency limit reached: retry after {}s", retry_after)
+      }
+      Error::Conflict(error) => write!(f, "Conflict: {:?}", error),
+      Error::ContractViolation(error) => write!(f, "Contract violation: {:?}", error),
+      Error::CredentialsExpired(error) => write!(f, "Credentials expired: {:?}", error),
+      Error::DeleteObjectError(error) => write!(f, "Delete object: {:?}", error),
+      Error::EntityTooSmall(error) => write!(f, "Entity too small: {:?}", error),
+      Error::Forbidden(error) => write!(f, "Forbidden: {:?}", error),
+      Error::GetObjectError(error) => write!(f, "Get object: {:?}", error),
+      Error::HeadObjectError(error) => write!(f, "Head object: {:?}", error),
       Error::HttpError(error) => {
         write!(f, "HTTP: {:?}", error)
       }
+      Error::InvalidParameter(error) => write!(f, "Invalid parameter: {:?}", error),
+      Error::InvalidPart(error) => write!(f, "Invalid part: {:?}", error),
+      Error::InvalidPartOrder(error) => write!(f, "Invalid part order: {:?}", error),
+      Error::JobStoreError(error) => write!(f, "Job store: {:?}", error),
       Error::JsonError(error) => {
         write!(f, "JSON: {:?}", error)
       }
+      Error::JwksError(error) => write!(f, "JWKS: {:?}", error),
+      Error::LambdaError(error) => write!(f, "Lambda: {:?}", error),
+      Error::LifecycleConfigurationError(error) => {
+        write!(f, "Lifecycle configuration: {:?}", error)
+      }
+      Error::LifecycleUpdateError(error) => write!(f, "Lifecycle update: {:?}", error),
       Error::ListObjectsError(error) => {
         write!(f, "Objects listing: {:?}", error)
       }
+      Error::ListPartsError(error) => write!(f, "Parts listing: {:?}", error),
+      Error::ListUploadsError(error) => write!(f, "Uploads listing: {:?}", error),
+      Error::MediaError(error) => write!(f, "Media: {:?}", error),
+      Error::MetricsError(error) => write!(f, "Metrics: {:?}", error),
       Error::MultipartUploadAbortionError(error) => {
         write!(f, "Multipart upload abortion: {:?}", error)
       }
@@ -41,8 +124,30 @@ impl Debug for Error {
         write!(f, "Multipart upload creation: {:?}", error)
       }
       Error::MultipartUploadError(error) => write!(f, "Multipart upload: {:?}", error),
+      Error::NoSuchUpload(error) => write!(f, "No such upload: {:?}", error),
+      Error::NotFound(error) => write!(f, "Not found: {:?}", error),
+      Error::ObjectAttributesError(error) => write!(f, "Object attributes: {:?}", error),
+      Error::ObjectLockError(error) => write!(f, "Object Lock: {:?}", error),
+      Error::PutObjectError(error) => write!(f, "Put object: {:?}", error),
+      Error::QuotaExceeded(error) => write!(f, "Quota exceeded: {:?}", error),
       Error::S3ConnectionError(error) => write!(f, "Cannot create S3 client: {:?}", error),
+      Error::SelectError(error) => write!(f, "Select: {:?}", error),
+      Error::SelectObjectContentError(error) => write!(f, "Select object content: {:?}", error),
       Error::SignatureError(error) => write!(f, "Signature: {:?}", error),
+      Error::SiteError(error) => write!(f, "Site: {:?}", error),
+      Error::TransferAccelerationDisabled(bucket) => {
+        write!(
+          f,
+          "Transfer acceleration is not enabled on bucket {:?}",
+          bucket
+        )
+      }
+      Error::TransferAccelerationError(error) => {
+        write!(f, "Transfer acceleration: {:?}", error)
+      }
+      Error::Unauthorized(error) => write!(f, "Unauthorized: {:?}", error),
+      Error::UploadError(error) => write!(f, "Upload: {:?}", error),
+      Error::UploadPartError(error) => write!(f, "Upload part: {:?}", error),
       Error::UriError(error) => {
         write!(f, "URI: {:?}", error)
       }
@@ -58,4 +163,278 @@ impl Display for Error {
 
 impl std::error::Error for Error {}
 
+impl From<crate::presign::CredentialsExpired> for Error {
+  fn from(error: crate::presign::CredentialsExpired) -> Self {
+    Error::CredentialsExpired(error.to_string())
+  }
+}
+
+impl Error {
+  /// Status code of the responses to the requests failing with this error.
+  pub fn status_code(&self) -> StatusCode {
+    if let Some((_code, status_code, _message)) = self.s3_error() {
+      return status_code;
+    }
+
+    match self {
+      Error::ContractViolation(_)
+      | Error::EntityTooSmall(_)
+      | Error::InvalidParameter(_)
+      | Error::InvalidPart(_)
+      | Error::InvalidPartOrder(_) => StatusCode::BAD_REQUEST,
+      Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+      Error::Forbidden(_) | Error::QuotaExceeded(_) => StatusCode::FORBIDDEN,
+      Error::Conflict(_) => StatusCode::CONFLICT,
+      Error::NoSuchUpload(_) | Error::NotFound(_) => StatusCode::NOT_FOUND,
+      Error::ConcurrencyLimitReached(_) | Error::CredentialsExpired(_) => {
+        StatusCode::SERVICE_UNAVAILABLE
+      }
+      _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+
+  /// Code of the error in the response bodies, the one returned by S3 when available.
+  pub fn code(&self) -> String {
+    if let Some((code, _status_code, _message)) = self.s3_error() {
+      return code;
+    }
+
+    match self {
+      Error::ConcurrencyLimitReached(_) => "ConcurrencyLimitReached",
+      Error::Conflict(_) => "Conflict",
+      Error::ContractViolation(_) => "ContractViolation",
+      Error::CredentialsExpired(_) => "CredentialsExpired",
+      Error::EntityTooSmall(_) => "EntityTooSmall",
+      Error::Forbidden(_) => "Forbidden",
+      Error::InvalidParameter(_) => "InvalidParameter",
+      Error::InvalidPart(_) => "InvalidPart",
+      Error::InvalidPartOrder(_) => "InvalidPartOrder",
+      Error::NoSuchUpload(_) => "NoSuchUpload",
+      Error::NotFound(_) => "NotFound",
+      Error::QuotaExceeded(_) => "QuotaExceeded",
+      Error::TransferAccelerationDisabled(_) => "TransferAccelerationDisabled",
+      Error::Unauthorized(_) => "Unauthorized",
+      _ => "InternalError",
+    }
+    .to_string()
+  }
+
+  /// Logs the error and turns it into a JSON [`ErrorBody`] response, with a `Retry-After` header
+  /// when the request can be retried.
+  ///
+  /// The details of the internal errors are only logged.
+  pub fn to_response(&self) -> Response<Body> {
+    let status_code = self.status_code();
+    if self.is_internal() {
+      log::error!("{}", self);
+    } else {
+      log::warn!("{}", self);
+    }
+
+    let mut response = error_response(
+      status_code,
+      &self.code(),
+      &self.public_message(),
+      self.part_number(),
+    );
+    if let Error::ConcurrencyLimitReached(retry_after) = self {
+      response
+        .headers_mut()
+        .insert(RETRY_AFTER, (*retry_after).into());
+    }
+    response
+  }
+
+  /// Message of the error answered to clients, hiding the details of the internal errors.
+  pub(crate) fn public_message(&self) -> String {
+    if self.is_internal() {
+      INTERNAL_ERROR_MESSAGE.to_string()
+    } else {
+      self.message()
+    }
+  }
+
+  fn is_internal(&self) -> bool {
+    let status_code = self.status_code();
+    status_code.is_server_error()
+      && status_code != StatusCode::SERVICE_UNAVAILABLE
+      && status_code != StatusCode::GATEWAY_TIMEOUT
+  }
+
+  fn message(&self) -> String {
+    if let Some((_code, _status_code, message)) = self.s3_error() {
+      return message;
+    }
+
+    match self {
+      Error::Conflict(message)
+      | Error::ContractViolation(message)
+      | Error::CredentialsExpired(message)
+      | Error::Forbidden(message)
+      | Error::InvalidParameter(message)
+      | Error::InvalidPartOrder(message)
+      | Error::NoSuchUpload(message)
+      | Error::NotFound(message)
+      | Error::QuotaExceeded(message)
+      | Error::Unauthorized(message) => message.clone(),
+      Error::EntityTooSmall(error) | Error::InvalidPart(error) => error.message.clone(),
+      Error::ConcurrencyLimitReached(retry_after) => format!("retry after {}s", retry_after),
+      error => error.to_string(),
+    }
+  }
+
+  fn part_number(&self) -> Option<i64> {
+    match self {
+      Error::EntityTooSmall(error) | Error::InvalidPart(error) => error.part_number,
+      _ => None,
+    }
+  }
+
+  /// Returns whether the storage could not be reached or answered with a server error, so that
+  /// another storage may answer the request.
+  pub(crate) fn is_storage_unavailable(&self) -> bool {
+    match self {
+      Error::GetObjectError(error) => is_storage_unavailable(error),
+      Error::HeadObjectError(error) => is_storage_unavailable(error),
+      Error::LifecycleConfigurationError(error) => is_storage_unavailable(error),
+      Error::LifecycleUpdateError(error) => is_storage_unavailable(error),
+      Error::ListObjectsError(error) => is_storage_unavailable(error),
+      Error::ListPartsError(error) => is_storage_unavailable(error),
+      Error::ListUploadsError(error) => is_storage_unavailable(error),
+      Error::MultipartUploadAbortionError(error) => is_storage_unavailable(error),
+      Error::MultipartUploadCompletionError(error) => is_storage_unavailable(error),
+      Error::MultipartUploadCreationError(error) => is_storage_unavailable(error),
+      Error::ObjectAttributesError(error) => is_storage_unavailable(error),
+      Error::PutObjectError(error) => is_storage_unavailable(error),
+      Error::SelectObjectContentError(error) => is_storage_unavailable(error),
+      Error::TransferAccelerationError(error) => is_storage_unavailable(error),
+      Error::UploadPartError(error) => is_storage_unavailable(error),
+      _ => false,
+    }
+  }
+
+  fn s3_error(&self) -> Option<(String, StatusCode, String)> {
+    match self {
+      Error::DeleteObjectError(error) => Some(s3_error(error)),
+      Error::GetObjectError(error) => Some(s3_error(error)),
+      Error::HeadObjectError(error) => Some(s3_error(error)),
+      Error::LifecycleConfigurationError(error) => Some(s3_error(error)),
+      Error::LifecycleUpdateError(error) => Some(s3_error(error)),
+      Error::ListObjectsError(error) => Some(s3_error(error)),
+      Error::ListPartsError(error) => Some(s3_error(error)),
+      Error::ListUploadsError(error) => Some(s3_error(error)),
+      Error::MultipartUploadAbortionError(error) => Some(s3_error(error)),
+      Error::MultipartUploadCompletionError(error) => Some(s3_error(error)),
+      Error::MultipartUploadCreationError(error) => Some(s3_error(error)),
+      Error::ObjectAttributesError(error) => Some(s3_error(error)),
+      Error::PutObjectError(error) => Some(s3_error(error)),
+      Error::SelectObjectContentError(error) => Some(s3_error(error)),
+      Error::TransferAccelerationError(error) => Some(s3_error(error)),
+      Error::UploadPartError(error) => Some(s3_error(error)),
+      _ => None,
+    }
+  }
+}
+
+fn is_storage_unavailable<E>(error: &RusotoError<E>) -> bool {
+  match error {
+    RusotoError::HttpDispatch(_) => true,
+    RusotoError::Unknown(response) => response.status.is_server_error(),
+    _ => false,
+  }
+}
+
+/// Code, status code and message of the responses to the requests failing with an S3 error.
+fn s3_error<E: std::error::Error + 'static>(
+  error: &RusotoError<E>,
+) -> (String, StatusCode, String) {
+  if retry::is_timeout(error) {
+    return (
+      "GatewayTimeout".to_string(),
+      StatusCode::GATEWAY_TIMEOUT,
+      error.to_string(),
+    );
+  }
+
+  let code = error_code(error);
+  let status_code = match code.as_str() {
+    "NoSuchBucket" | "NoSuchKey" | "NoSuchUpload" => StatusCode::NOT_FOUND,
+    "AccessDenied" => StatusCode::FORBIDDEN,
+    "EntityTooSmall" | "InvalidPart" | "InvalidPartOrder" => StatusCode::BAD_REQUEST,
+    "InvalidRange" => StatusCode::RANGE_NOT_SATISFIABLE,
+    _ => match error {
+      // Responses without body, e.g. to HEAD requests
+      RusotoError::Unknown(response)
+        if response.status == StatusCode::FORBIDDEN || response.status == StatusCode::NOT_FOUND =>
+      {
+        response.status
+      }
+      _ => StatusCode::INTERNAL_SERVER_ERROR,
+    },
+  };
+
+  let message = match error {
+    RusotoError::Unknown(response) => {
+      xml_element(&String::from_utf8_lossy(&response.body), "Message")
+        .unwrap_or_else(|| response.status.to_string())
+    }
+    error => error.to_string(),
+  };
+
+  (code, status_code, message)
+}
+
+/// Typed error of a failed multipart upload completion.
+///
+/// S3 does not declare the errors of `CompleteMultipartUpload`, so they are read from the XML
+/// body of the response, along with the offending part number.
+pub(crate) fn completion_error(error: RusotoError<CompleteMultipartUploadError>) -> Error {
+  let body = match &error {
+    RusotoError::Unknown(response) => String::from_utf8_lossy(&response.body).to_string(),
+    _ => return Error::MultipartUploadCompletionError(error),
+  };
+  let message = xml_element(&body, "Message").unwrap_or_default();
+  let part_error = || PartError {
+    part_number: xml_element(&body, "PartNumber").and_then(|part_number| part_number.parse().ok()),
+    message: message.clone(),
+  };
+
+  match error_code(&error).as_str() {
+    "EntityTooSmall" => Error::EntityTooSmall(part_error()),
+    "InvalidPart" => Error::InvalidPart(part_error()),
+    "InvalidPartOrder" => Error::InvalidPartOrder(message),
+    "NoSuchUpload" => Error::NoSuchUpload(message),
+    _ => Error::MultipartUploadCompletionError(error),
+  }
+}
+
+/// Text of the first `name` element of an S3 XML error body.
+fn xml_element(body: &str, name: &str) -> Option<String> {
+  body
+    .split_once(&format!("<{}>", name))
+    .and_then(|(_, remaining)| remaining.split_once(&format!("</{}>", name)))
+    .map(|(text, _)| text.to_string())
+}
+
+/// JSON [`ErrorBody`] response, identified by the current request identifier.
+pub(crate) fn error_response(
+  status_code: StatusCode,
+  code: &str,
+  message: &str,
+  part_number: Option<i64>,
+) -> Response<Body> {
+  let error_body = ErrorBody {
+    code: code.to_string(),
+    message: message.to_string(),
+    part_number,
+    request_id: current_request_id(),
+  };
+
+  to_json_response(status_code, &error_body).unwrap_or_else(|_| {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status_code;
+    response
+  })
+}
+
 impl Reject for Error {}
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+/// Body of the error responses
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ErrorBody {
+  /// Error code, as returned by S3 when available (e.g. `NoSuchKey`)
+  pub code: String,
+  pub message: String,
+  /// Number of the offending part, when completing a multipart upload fails because of a part
+  pub part_number: Option<i64>,
+  /// Identifier of the failed request, as returned in the `X-Request-Id` header
+  pub request_id: Option<String>,
+}
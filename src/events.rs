@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EventsQueryParameters {
+  pub bucket: Option<String>,
+  pub prefix: Option<String>,
+}
+
+/// Step of the lifecycle of a multipart upload, the name of its server-sent events.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum UploadEventKind {
+  Created,
+  PartSigned,
+  Completed,
+  Aborted,
+}
+
+impl UploadEventKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      UploadEventKind::Created => "created",
+      UploadEventKind::PartSigned => "part_signed",
+      UploadEventKind::Completed => "completed",
+      UploadEventKind::Aborted => "aborted",
+    }
+  }
+}
+
+/// Data of the server-sent events of the upload lifecycle.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct UploadEvent {
+  pub kind: UploadEventKind,
+  pub bucket: String,
+  pub key: String,
+  pub upload_id: String,
+  /// Number of the signed part (`part_signed` only)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub part_number: Option<i64>,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    objects::key,
+    policy::{self, Identity, Operation},
+    query, Error, S3Configuration,
+  };
+  use futures::{stream, Stream};
+  use std::{
+    convert::Infallible,
+    sync::{Arc, OnceLock},
+  };
+  use tokio::sync::broadcast::{self, error::RecvError, Receiver, Sender};
+  use warp::{
+    hyper::{Body, Response},
+    sse::Event,
+    Filter, Rejection, Reply,
+  };
+
+  /// Events kept for the subscribers lagging behind, which skip the older ones
+  const EVENTS_CAPACITY: usize = 1024;
+
+  fn events() -> &'static Sender<UploadEvent> {
+    static EVENTS: OnceLock<Sender<UploadEvent>> = OnceLock::new();
+    EVENTS.get_or_init(|| broadcast::channel(EVENTS_CAPACITY).0)
+  }
+
+  /// Publishes an event of the upload lifecycle to the subscribers, if any.
+  pub(crate) fn publish(
+    kind: UploadEventKind,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    part_number: Option<i64>,
+  ) {
+    // Fails when nobody is subscribed
+    let _ = events().send(UploadEvent {
+      kind,
+      bucket: bucket.to_string(),
+      key: key.to_string(),
+      upload_id: upload_id.to_string(),
+      part_number,
+    });
+  }
+
+  /// Upload events
+  #[utoipa::path(
+    get,
+    path = "/events",
+    tag = "Multipart upload",
+    responses(
+      (
+        status = 200,
+        description = "Streams the events of the multipart uploads as server-sent events, named after their kind",
+        content_type = "text/event-stream",
+        body = UploadEvent
+      ),
+    ),
+    params(
+      ("bucket" = Option<String>, Query, description = "Name of the bucket of the uploads"),
+      ("prefix" = Option<String>, Query, description = "Prefix of the keys of the uploads")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("events")
+      .and(warp::get())
+      .and(query::parameters::<EventsQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: EventsQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          upload_events(&s3_configuration, &identity, parameters)
+            .await
+            .map_err(warp::reject::custom)
+        },
+      )
+  }
+
+  /// Streams the events of the multipart uploads of a bucket and prefix (all by default) as
+  /// server-sent events, on behalf of `identity`.
+  ///
+  /// Only the events of the objects that `identity` can read are sent.
+  pub async fn upload_events(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: EventsQueryParameters,
+  ) -> Result<Response<Body>, Error> {
+    identity.scope_prefix(&mut parameters.prefix);
+    let prefix = parameters.prefix.unwrap_or_default();
+    key::server::validate_prefix(s3_configuration, &prefix)?;
+    if let Some(bucket) = &parameters.bucket {
+      policy::authorize(s3_configuration, identity, Operation::Read, bucket, &prefix)?;
+    }
+    log::info!(
+      "Subscribe to upload events: bucket={:?}, prefix={}",
+      parameters.bucket,
+      prefix
+    );
+
+    let events = subscribe(
+      s3_configuration.clone(),
+      identity.clone(),
+      parameters.bucket,
+      prefix,
+    );
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response())
+  }
+
+  fn subscribe(
+    s3_configuration: S3Configuration,
+    identity: Identity,
+    bucket: Option<String>,
+    prefix: String,
+  ) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(
+      events().subscribe(),
+      move |mut receiver: Receiver<UploadEvent>| {
+        let s3_configuration = s3_configuration.clone();
+        let identity = identity.clone();
+        let bucket = bucket.clone();
+        let prefix = prefix.clone();
+        async move {
+          loop {
+            let event = match receiver.recv().await {
+              Ok(event) => event,
+              Err(RecvError::Lagged(skipped)) => {
+                log::warn!("Upload events subscriber lagging: skipped={}", skipped);
+                continue;
+              }
+              Err(RecvError::Closed) => return None,
+            };
+
+            let is_watched = bucket.as_ref().is_none_or(|bucket| *bucket == event.bucket)
+              && event.key.starts_with(&prefix)
+              && policy::is_allowed(
+                // Follows the reloads of the policies
+                &s3_configuration.current(),
+                &identity,
+                Operation::Read,
+                &event.bucket,
+                &event.key,
+              );
+            if !is_watched {
+              continue;
+            }
+
+            match Event::default()
+              .event(event.kind.as_str())
+              .json_data(&event)
+            {
+              Ok(sse_event) => return Some((Ok(sse_event), receiver)),
+              Err(error) => log::error!("Cannot serialize upload event: {}", error),
+            }
+          }
+        }
+      },
+    )
+  }
+}
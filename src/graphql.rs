@@ -0,0 +1,372 @@
+//! GraphQL endpoint of the signer, `POST /graphql`, over the same [`handlers`](crate::handlers)
+//! as the REST API: queries browsing the buckets and their objects, and mutations pre-signing
+//! URLs and driving multipart uploads.
+//!
+//! The errors of the handlers are answered in the `errors` of the response, with their code in
+//! the `code` extension.
+
+use crate::{
+  body, handlers,
+  multipart_upload::{
+    normalize_etag, AbortOrCompleteUploadBody, AbortOrCompleteUploadQueryParameters,
+    CompletedUploadPart, CreateUploadQueryParameters, CreateUploadResponse,
+    PartUploadQueryParameters,
+  },
+  objects::{
+    CreateQueryParameters, ListObjectsQueryParameters, Object, PresignedUrlResponse,
+    SignQueryParameters,
+  },
+  policy::{self, Identity, Operation},
+  query, to_ok_json_response, Error, S3Configuration,
+};
+use async_graphql::{
+  Context, EmptySubscription, ErrorExtensions, InputObject, Object as GraphQLObject, Schema,
+  SimpleObject,
+};
+use std::sync::Arc;
+use warp::{
+  filters::BoxedFilter,
+  hyper::{Body, Response},
+  Filter,
+};
+
+/// Objects of a page, when unspecified
+const DEFAULT_PAGE_SIZE: usize = 100;
+/// Objects of a page, bounding the size of the responses
+const MAX_PAGE_SIZE: usize = 1_000;
+/// Depth of the queries, bounding the work of a single request
+const MAX_QUERY_DEPTH: usize = 8;
+
+type SignerSchema = Schema<Query, Mutation, EmptySubscription>;
+
+/// Executes the GraphQL requests, on behalf of the identity of the request.
+pub(crate) fn route(s3_configuration: &S3Configuration) -> BoxedFilter<(Response<Body>,)> {
+  let schema = Schema::build(Query, Mutation, EmptySubscription)
+    .limit_depth(MAX_QUERY_DEPTH)
+    .finish();
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("graphql")
+    .and(warp::post())
+    .and(body::json::<async_graphql::Request>(&s3_configuration))
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
+    .and_then(
+      move |request: async_graphql::Request,
+            identity: Identity,
+            s3_configuration: Arc<S3Configuration>| {
+        let schema: SignerSchema = schema.clone();
+        async move {
+          let response = schema
+            .execute(request.data(s3_configuration).data(identity))
+            .await;
+          to_ok_json_response(&response).map_err(warp::reject::custom)
+        }
+      },
+    )
+    .boxed()
+}
+
+pub(crate) struct Query;
+
+#[GraphQLObject]
+impl Query {
+  /// Buckets set by the configuration which the caller may read.
+  async fn buckets(&self, context: &Context<'_>) -> Vec<String> {
+    let (s3_configuration, identity) = request_data(context);
+    s3_configuration
+      .configured_buckets()
+      .into_iter()
+      .filter(|bucket| policy::is_allowed(s3_configuration, identity, Operation::Read, bucket, ""))
+      .collect()
+  }
+
+  /// Objects and folders under a prefix of a bucket, a page at a time: `after` is the
+  /// `endCursor` of the previous page.
+  async fn objects(
+    &self,
+    context: &Context<'_>,
+    bucket: String,
+    prefix: Option<String>,
+    first: Option<usize>,
+    after: Option<String>,
+    filter: Option<ObjectFilter>,
+  ) -> async_graphql::Result<ObjectPage> {
+    let (s3_configuration, identity) = request_data(context);
+    required(&[("bucket", &bucket)])?;
+    let first = first.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&first) {
+      return Err(to_graphql_error(&Error::InvalidParameter(format!(
+        "first must be between 1 and {}",
+        MAX_PAGE_SIZE
+      ))));
+    }
+    let listing = handlers::list_objects(
+      s3_configuration,
+      identity,
+      ListObjectsQueryParameters { bucket, prefix },
+    )
+    .await
+    .map_err(|error| to_graphql_error(&error))?;
+
+    let filter = filter.unwrap_or_default();
+    let mut objects = listing.body.objects;
+    objects.retain(|object| filter.matches(object));
+    let total_count = objects.len();
+    let start = match after {
+      Some(after) => objects
+        .iter()
+        .position(|object| object.path == after)
+        .map_or(objects.len(), |position| position + 1),
+      None => 0,
+    };
+    let nodes: Vec<Object> = objects.into_iter().skip(start).take(first).collect();
+    let has_next_page = start + nodes.len() < total_count;
+    Ok(ObjectPage {
+      page_info: PageInfo {
+        has_next_page,
+        end_cursor: nodes.last().map(|object| object.path.clone()),
+      },
+      total_count,
+      nodes,
+    })
+  }
+}
+
+/// Filters of the listed objects.
+#[derive(Default, InputObject)]
+pub(crate) struct ObjectFilter {
+  /// Only the folders, or only the objects
+  is_dir: Option<bool>,
+  /// Only the paths ending with this suffix, e.g. `.jpg`
+  suffix: Option<String>,
+}
+
+impl ObjectFilter {
+  fn matches(&self, object: &Object) -> bool {
+    self.is_dir.is_none_or(|is_dir| object.is_dir == is_dir)
+      && self
+        .suffix
+        .as_deref()
+        .is_none_or(|suffix| object.path.ends_with(suffix))
+  }
+}
+
+/// Page of the objects under a prefix.
+#[derive(SimpleObject)]
+pub(crate) struct ObjectPage {
+  nodes: Vec<Object>,
+  page_info: PageInfo,
+  /// Objects matching the filter, over all the pages
+  total_count: usize,
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct PageInfo {
+  has_next_page: bool,
+  /// Cursor of the last object of the page
+  end_cursor: Option<String>,
+}
+
+pub(crate) struct Mutation;
+
+#[GraphQLObject]
+impl Mutation {
+  /// Pre-signs the URL to get an object.
+  async fn presign_get(
+    &self,
+    context: &Context<'_>,
+    bucket: String,
+    path: String,
+    accelerate: Option<bool>,
+  ) -> async_graphql::Result<String> {
+    let (s3_configuration, identity) = request_data(context);
+    required(&[("bucket", &bucket), ("path", &path)])?;
+    handlers::presign_get_object(
+      s3_configuration,
+      identity,
+      sign_parameters(bucket, path, accelerate),
+      None,
+    )
+    .await
+    .map_err(|error| to_graphql_error(&error))
+  }
+
+  /// Pre-signs the URL to upload an object.
+  async fn presign_put(
+    &self,
+    context: &Context<'_>,
+    bucket: String,
+    path: String,
+    accelerate: Option<bool>,
+  ) -> async_graphql::Result<PresignedUrlResponse> {
+    let (s3_configuration, identity) = request_data(context);
+    required(&[("bucket", &bucket), ("path", &path)])?;
+    handlers::create_object(
+      s3_configuration,
+      identity,
+      CreateQueryParameters::from(sign_parameters(bucket, path, accelerate)),
+      None,
+    )
+    .await
+    .map_err(|error| to_graphql_error(&error))
+  }
+
+  /// Creates a multipart upload.
+  async fn create_upload(
+    &self,
+    context: &Context<'_>,
+    bucket: String,
+    path: String,
+    content_type: Option<String>,
+  ) -> async_graphql::Result<CreateUploadResponse> {
+    let (s3_configuration, identity) = request_data(context);
+    required(&[("bucket", &bucket), ("path", &path)])?;
+    handlers::create_multipart_upload(
+      s3_configuration,
+      identity,
+      CreateUploadQueryParameters {
+        bucket,
+        path,
+        sse: None,
+        kms_key_id: None,
+        content_type,
+        cache_control: None,
+        expires: None,
+      },
+    )
+    .await
+    .map(|served| served.body)
+    .map_err(|error| to_graphql_error(&error))
+  }
+
+  /// Pre-signs the URL to upload a part of a multipart upload.
+  async fn part_url(
+    &self,
+    context: &Context<'_>,
+    bucket: String,
+    path: String,
+    upload_id: String,
+    part_number: i64,
+    accelerate: Option<bool>,
+  ) -> async_graphql::Result<String> {
+    let (s3_configuration, identity) = request_data(context);
+    required(&[
+      ("bucket", &bucket),
+      ("path", &path),
+      ("uploadId", &upload_id),
+    ])?;
+    handlers::part_upload_url(
+      s3_configuration,
+      identity,
+      upload_id,
+      part_number,
+      PartUploadQueryParameters {
+        bucket,
+        path,
+        accelerate,
+      },
+    )
+    .await
+    .map(|response| response.presigned_url)
+    .map_err(|error| to_graphql_error(&error))
+  }
+
+  /// Completes a multipart upload with its uploaded parts.
+  async fn complete_upload(
+    &self,
+    context: &Context<'_>,
+    bucket: String,
+    path: String,
+    upload_id: String,
+    parts: Vec<CompletedUploadPart>,
+  ) -> async_graphql::Result<bool> {
+    let (s3_configuration, identity) = request_data(context);
+    required(&[
+      ("bucket", &bucket),
+      ("path", &path),
+      ("uploadId", &upload_id),
+    ])?;
+    let parts = parts
+      .into_iter()
+      .map(|part| CompletedUploadPart {
+        number: part.number,
+        etag: normalize_etag(&part.etag),
+      })
+      .collect();
+    handlers::abort_or_complete_multipart_upload(
+      s3_configuration,
+      identity,
+      upload_id,
+      AbortOrCompleteUploadQueryParameters { bucket, path },
+      AbortOrCompleteUploadBody::Complete { parts },
+    )
+    .await
+    .map(|_| true)
+    .map_err(|error| to_graphql_error(&error))
+  }
+
+  /// Aborts a multipart upload.
+  async fn abort_upload(
+    &self,
+    context: &Context<'_>,
+    bucket: String,
+    path: String,
+    upload_id: String,
+  ) -> async_graphql::Result<bool> {
+    let (s3_configuration, identity) = request_data(context);
+    required(&[
+      ("bucket", &bucket),
+      ("path", &path),
+      ("uploadId", &upload_id),
+    ])?;
+    handlers::abort_or_complete_multipart_upload(
+      s3_configuration,
+      identity,
+      upload_id,
+      AbortOrCompleteUploadQueryParameters { bucket, path },
+      AbortOrCompleteUploadBody::Abort,
+    )
+    .await
+    .map(|_| true)
+    .map_err(|error| to_graphql_error(&error))
+  }
+}
+
+/// Configuration and identity of the request, set by the [`route`].
+fn request_data<'a>(context: &Context<'a>) -> (&'a S3Configuration, &'a Identity) {
+  (
+    context.data_unchecked::<S3Configuration>(),
+    context.data_unchecked::<Identity>(),
+  )
+}
+
+/// Rejects the empty arguments, as the REST API does.
+fn required(arguments: &[(&str, &str)]) -> async_graphql::Result<()> {
+  arguments
+    .iter()
+    .try_for_each(|(name, value)| query::required(name, value))
+    .map_err(|error| to_graphql_error(&error))
+}
+
+fn sign_parameters(bucket: String, path: String, accelerate: Option<bool>) -> SignQueryParameters {
+  SignQueryParameters {
+    bucket,
+    path,
+    accelerate,
+    proxy: None,
+  }
+}
+
+/// Logs the error and turns it into a GraphQL error with its code, hiding the details of the
+/// internal errors as in the REST responses.
+fn to_graphql_error(error: &Error) -> async_graphql::Error {
+  if error.status_code().is_server_error() {
+    log::error!("{}", error);
+  } else {
+    log::warn!("{}", error);
+  }
+  let code = error.code();
+  async_graphql::Error::new(error.public_message()).extend_with(|_, extensions| {
+    extensions.set("code", code.as_str());
+  })
+}
@@ -0,0 +1,487 @@
+//! gRPC interface of the signer, mirroring the main endpoints of the HTTP API over the same
+//! [`handlers`](crate::handlers).
+//!
+//! The service `s3signer.v1.Signer` is described by `proto/signer.proto`. Its messages are
+//! declared here rather than generated, to build without `protoc`. The callers authenticate with
+//! the same `authorization` and `x-api-key` metadata as the HTTP headers, and the errors are
+//! answered with the gRPC status matching their HTTP status code.
+
+use crate::{
+  handlers,
+  multipart_upload::{
+    normalize_etag, AbortOrCompleteUploadBody, AbortOrCompleteUploadQueryParameters,
+    CompletedUploadPart, CreateUploadQueryParameters, PartUploadQueryParameters,
+  },
+  objects::{CreateQueryParameters, ListObjectsQueryParameters, SignQueryParameters},
+  policy::Identity,
+  query::required,
+  Error, S3Configuration,
+};
+use futures::future::BoxFuture;
+use std::{
+  convert::Infallible,
+  future::Future,
+  net::SocketAddr,
+  sync::Arc,
+  task::{Context, Poll},
+};
+use tonic::{
+  body::BoxBody,
+  codec::ProstCodec,
+  server::{Grpc, NamedService, UnaryService},
+  Code, Status,
+};
+use tower_service::Service;
+use warp::hyper::{self, service::make_service_fn, Body, Server, StatusCode};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListObjectsRequest {
+  #[prost(string, tag = "1")]
+  pub bucket: String,
+  #[prost(string, optional, tag = "2")]
+  pub prefix: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListObjectsResponse {
+  #[prost(message, repeated, tag = "1")]
+  pub objects: Vec<Object>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Object {
+  /// Path of the object, or of the folder, relative to the prefix
+  #[prost(string, tag = "1")]
+  pub path: String,
+  #[prost(bool, tag = "2")]
+  pub is_dir: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PresignRequest {
+  #[prost(string, tag = "1")]
+  pub bucket: String,
+  #[prost(string, tag = "2")]
+  pub path: String,
+  /// Sign against the S3 Transfer Acceleration endpoint
+  #[prost(bool, optional, tag = "3")]
+  pub accelerate: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PresignedUrl {
+  #[prost(string, tag = "1")]
+  pub url: String,
+  /// Names of the signed headers the request must be sent with
+  #[prost(string, repeated, tag = "2")]
+  pub headers: Vec<String>,
+  /// Key of the object, when generated from the key template of the bucket
+  #[prost(string, optional, tag = "3")]
+  pub key: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CreateUploadRequest {
+  #[prost(string, tag = "1")]
+  pub bucket: String,
+  #[prost(string, tag = "2")]
+  pub path: String,
+  #[prost(string, optional, tag = "3")]
+  pub content_type: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CreateUploadResponse {
+  #[prost(string, tag = "1")]
+  pub upload_id: String,
+  /// Key of the object, when generated from the key template of the bucket
+  #[prost(string, optional, tag = "2")]
+  pub key: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompleteUploadRequest {
+  #[prost(string, tag = "1")]
+  pub bucket: String,
+  #[prost(string, tag = "2")]
+  pub path: String,
+  #[prost(string, tag = "3")]
+  pub upload_id: String,
+  #[prost(message, repeated, tag = "4")]
+  pub parts: Vec<CompletedPart>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompletedPart {
+  #[prost(int64, tag = "1")]
+  pub number: i64,
+  /// ETag of the part upload response, with or without its quotes
+  #[prost(string, tag = "2")]
+  pub etag: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompleteUploadResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AbortUploadRequest {
+  #[prost(string, tag = "1")]
+  pub bucket: String,
+  #[prost(string, tag = "2")]
+  pub path: String,
+  #[prost(string, tag = "3")]
+  pub upload_id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AbortUploadResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PartUrlRequest {
+  #[prost(string, tag = "1")]
+  pub bucket: String,
+  #[prost(string, tag = "2")]
+  pub path: String,
+  #[prost(string, tag = "3")]
+  pub upload_id: String,
+  #[prost(int64, tag = "4")]
+  pub part_number: i64,
+  /// Sign against the S3 Transfer Acceleration endpoint
+  #[prost(bool, optional, tag = "5")]
+  pub accelerate: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PartUrlResponse {
+  #[prost(string, tag = "1")]
+  pub presigned_url: String,
+}
+
+/// The `s3signer.v1.Signer` service, to be served over HTTP/2, e.g. by [`serve`].
+#[derive(Clone)]
+pub struct GrpcSigner {
+  s3_configuration: S3Configuration,
+}
+
+impl GrpcSigner {
+  pub fn new(s3_configuration: &S3Configuration) -> Self {
+    Self {
+      s3_configuration: s3_configuration.clone(),
+    }
+  }
+}
+
+impl NamedService for GrpcSigner {
+  const NAME: &'static str = "s3signer.v1.Signer";
+}
+
+impl Service<hyper::Request<Body>> for GrpcSigner {
+  type Response = hyper::Response<BoxBody>;
+  type Error = Infallible;
+  type Future = BoxFuture<'static, Result<Self::Response, Infallible>>;
+
+  fn poll_ready(&mut self, _context: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn call(&mut self, request: hyper::Request<Body>) -> Self::Future {
+    let s3_configuration = self.s3_configuration.current();
+    Box::pin(async move {
+      let response = match request.uri().path() {
+        "/s3signer.v1.Signer/ListObjects" => unary(s3_configuration, request, list_objects).await,
+        "/s3signer.v1.Signer/PresignGet" => unary(s3_configuration, request, presign_get).await,
+        "/s3signer.v1.Signer/PresignPut" => unary(s3_configuration, request, presign_put).await,
+        "/s3signer.v1.Signer/CreateUpload" => unary(s3_configuration, request, create_upload).await,
+        "/s3signer.v1.Signer/CompleteUpload" => {
+          unary(s3_configuration, request, complete_upload).await
+        }
+        "/s3signer.v1.Signer/AbortUpload" => unary(s3_configuration, request, abort_upload).await,
+        "/s3signer.v1.Signer/PartUrl" => unary(s3_configuration, request, part_url).await,
+        path => Status::unimplemented(format!("unknown method {}", path)).to_http(),
+      };
+      Ok(response)
+    })
+  }
+}
+
+/// Serves the [`GrpcSigner`] over HTTP/2 on `address`.
+pub async fn serve(
+  s3_configuration: &S3Configuration,
+  address: SocketAddr,
+) -> Result<(), hyper::Error> {
+  let grpc_signer = GrpcSigner::new(s3_configuration);
+  Server::try_bind(&address)?
+    .http2_only(true)
+    .serve(make_service_fn(move |_connection| {
+      let grpc_signer = grpc_signer.clone();
+      async move { Ok::<_, Infallible>(grpc_signer) }
+    }))
+    .await
+}
+
+/// Decodes the request of a unary method, and encodes the response of its `rpc`.
+async fn unary<M, R, F, Fut>(
+  s3_configuration: Arc<S3Configuration>,
+  request: hyper::Request<Body>,
+  rpc: F,
+) -> hyper::Response<BoxBody>
+where
+  M: prost::Message + Default + Send + 'static,
+  R: prost::Message + Send + 'static,
+  F: Fn(Arc<S3Configuration>, tonic::Request<M>) -> Fut + Send + 'static,
+  Fut: Future<Output = Result<R, Error>> + Send + 'static,
+{
+  Grpc::new(ProstCodec::<R, M>::default())
+    .unary(
+      Unary {
+        s3_configuration,
+        rpc,
+      },
+      request,
+    )
+    .await
+}
+
+struct Unary<F> {
+  s3_configuration: Arc<S3Configuration>,
+  rpc: F,
+}
+
+impl<M, R, F, Fut> UnaryService<M> for Unary<F>
+where
+  F: Fn(Arc<S3Configuration>, tonic::Request<M>) -> Fut,
+  Fut: Future<Output = Result<R, Error>> + Send + 'static,
+{
+  type Response = R;
+  type Future = BoxFuture<'static, Result<tonic::Response<R>, Status>>;
+
+  fn call(&mut self, request: tonic::Request<M>) -> Self::Future {
+    let response = (self.rpc)(self.s3_configuration.clone(), request);
+    Box::pin(async move {
+      response
+        .await
+        .map(tonic::Response::new)
+        .map_err(|error| to_status(&error))
+    })
+  }
+}
+
+async fn list_objects(
+  s3_configuration: Arc<S3Configuration>,
+  request: tonic::Request<ListObjectsRequest>,
+) -> Result<ListObjectsResponse, Error> {
+  let identity = identity(&s3_configuration, &request).await?;
+  let ListObjectsRequest { bucket, prefix } = request.into_inner();
+  required("bucket", &bucket)?;
+  let listing = handlers::list_objects(
+    &s3_configuration,
+    &identity,
+    ListObjectsQueryParameters { bucket, prefix },
+  )
+  .await?;
+  Ok(ListObjectsResponse {
+    objects: listing
+      .body
+      .objects
+      .into_iter()
+      .map(|object| Object {
+        path: object.path,
+        is_dir: object.is_dir,
+      })
+      .collect(),
+  })
+}
+
+async fn presign_get(
+  s3_configuration: Arc<S3Configuration>,
+  request: tonic::Request<PresignRequest>,
+) -> Result<PresignedUrl, Error> {
+  let identity = identity(&s3_configuration, &request).await?;
+  let parameters = sign_parameters(request.into_inner())?;
+  let url = handlers::presign_get_object(&s3_configuration, &identity, parameters, None).await?;
+  Ok(PresignedUrl {
+    url,
+    headers: Vec::new(),
+    key: None,
+  })
+}
+
+async fn presign_put(
+  s3_configuration: Arc<S3Configuration>,
+  request: tonic::Request<PresignRequest>,
+) -> Result<PresignedUrl, Error> {
+  let identity = identity(&s3_configuration, &request).await?;
+  let parameters = CreateQueryParameters::from(sign_parameters(request.into_inner())?);
+  let presigned_url =
+    handlers::create_object(&s3_configuration, &identity, parameters, None).await?;
+  Ok(PresignedUrl {
+    url: presigned_url.url,
+    headers: presigned_url.headers,
+    key: presigned_url.key,
+  })
+}
+
+async fn create_upload(
+  s3_configuration: Arc<S3Configuration>,
+  request: tonic::Request<CreateUploadRequest>,
+) -> Result<CreateUploadResponse, Error> {
+  let identity = identity(&s3_configuration, &request).await?;
+  let CreateUploadRequest {
+    bucket,
+    path,
+    content_type,
+  } = request.into_inner();
+  required("bucket", &bucket)?;
+  required("path", &path)?;
+  let served = handlers::create_multipart_upload(
+    &s3_configuration,
+    &identity,
+    CreateUploadQueryParameters {
+      bucket,
+      path,
+      sse: None,
+      kms_key_id: None,
+      content_type,
+      cache_control: None,
+      expires: None,
+    },
+  )
+  .await?;
+  Ok(CreateUploadResponse {
+    upload_id: served.body.upload_id,
+    key: served.body.key,
+  })
+}
+
+async fn complete_upload(
+  s3_configuration: Arc<S3Configuration>,
+  request: tonic::Request<CompleteUploadRequest>,
+) -> Result<CompleteUploadResponse, Error> {
+  let identity = identity(&s3_configuration, &request).await?;
+  let CompleteUploadRequest {
+    bucket,
+    path,
+    upload_id,
+    parts,
+  } = request.into_inner();
+  required("bucket", &bucket)?;
+  required("path", &path)?;
+  required("upload_id", &upload_id)?;
+  let parts = parts
+    .into_iter()
+    .map(|part| CompletedUploadPart {
+      number: part.number,
+      etag: normalize_etag(&part.etag),
+    })
+    .collect();
+  handlers::abort_or_complete_multipart_upload(
+    &s3_configuration,
+    &identity,
+    upload_id,
+    AbortOrCompleteUploadQueryParameters { bucket, path },
+    AbortOrCompleteUploadBody::Complete { parts },
+  )
+  .await?;
+  Ok(CompleteUploadResponse {})
+}
+
+async fn abort_upload(
+  s3_configuration: Arc<S3Configuration>,
+  request: tonic::Request<AbortUploadRequest>,
+) -> Result<AbortUploadResponse, Error> {
+  let identity = identity(&s3_configuration, &request).await?;
+  let AbortUploadRequest {
+    bucket,
+    path,
+    upload_id,
+  } = request.into_inner();
+  required("bucket", &bucket)?;
+  required("path", &path)?;
+  required("upload_id", &upload_id)?;
+  handlers::abort_or_complete_multipart_upload(
+    &s3_configuration,
+    &identity,
+    upload_id,
+    AbortOrCompleteUploadQueryParameters { bucket, path },
+    AbortOrCompleteUploadBody::Abort,
+  )
+  .await?;
+  Ok(AbortUploadResponse {})
+}
+
+async fn part_url(
+  s3_configuration: Arc<S3Configuration>,
+  request: tonic::Request<PartUrlRequest>,
+) -> Result<PartUrlResponse, Error> {
+  let identity = identity(&s3_configuration, &request).await?;
+  let PartUrlRequest {
+    bucket,
+    path,
+    upload_id,
+    part_number,
+    accelerate,
+  } = request.into_inner();
+  required("bucket", &bucket)?;
+  required("path", &path)?;
+  required("upload_id", &upload_id)?;
+  let response = handlers::part_upload_url(
+    &s3_configuration,
+    &identity,
+    upload_id,
+    part_number,
+    PartUploadQueryParameters {
+      bucket,
+      path,
+      accelerate,
+    },
+  )
+  .await?;
+  Ok(PartUrlResponse {
+    presigned_url: response.presigned_url,
+  })
+}
+
+/// Identity of the caller, from the metadata of the request.
+async fn identity<M>(
+  s3_configuration: &S3Configuration,
+  request: &tonic::Request<M>,
+) -> Result<Identity, Error> {
+  Identity::from_headers(s3_configuration, &request.metadata().clone().into_headers()).await
+}
+
+fn sign_parameters(request: PresignRequest) -> Result<SignQueryParameters, Error> {
+  required("bucket", &request.bucket)?;
+  required("path", &request.path)?;
+  Ok(SignQueryParameters {
+    bucket: request.bucket,
+    path: request.path,
+    accelerate: request.accelerate,
+    proxy: None,
+  })
+}
+
+/// Logs the error and turns it into the gRPC status matching its HTTP status code.
+///
+/// The details of the internal errors are only logged, as in the HTTP responses.
+fn to_status(error: &Error) -> Status {
+  let status_code = error.status_code();
+  let code = match status_code {
+    StatusCode::BAD_REQUEST => Code::InvalidArgument,
+    StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+    StatusCode::FORBIDDEN => Code::PermissionDenied,
+    StatusCode::NOT_FOUND => Code::NotFound,
+    StatusCode::CONFLICT => Code::AlreadyExists,
+    StatusCode::PRECONDITION_FAILED => Code::FailedPrecondition,
+    StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+    StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+    StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+    status_code if status_code.is_client_error() => Code::InvalidArgument,
+    _ => Code::Internal,
+  };
+  if code == Code::Internal {
+    log::error!("{}", error);
+  } else {
+    log::warn!("{}", error);
+  }
+  Status::new(code, error.public_message())
+}
@@ -0,0 +1,55 @@
+//! Handlers of the signer API, independent of the web framework serving them.
+//!
+//! Each handler takes the configuration, the [`Identity`] of the caller (see
+//! [`Identity::from_headers`]) and the parameters of the request, and returns its result or an
+//! [`Error`](crate::Error), which [`Error::to_response`](crate::Error::to_response) turns into a
+//! response. The routes of the signer are thin warp adapters over these handlers.
+//!
+//! The JSON handlers return the typed body of their response, e.g. a [`LimitsResponse`], to be
+//! asserted on without parsing a response. The handlers calling S3 wrap it in a [`Served`], along
+//! with the endpoint that answered. The handlers streaming their body, or redirecting, still
+//! return the response.
+//!
+//! To mount the whole API in another server instead, see [`service`](crate::service).
+
+pub use crate::{
+  download_links::{download, revoke},
+  encryption::server::{CustomerKey, CUSTOMER_KEY_HEADER},
+  events::server::upload_events,
+  health::server::{liveness, readiness},
+  jobs::{get_job, submit_archive, submit_inventory, submit_prefix_delete, submit_upload_cleanup},
+  limits::{server::limits, LimitsResponse},
+  media::server::{dash_manifest, hls_playlist},
+  metrics::metrics_response,
+  multipart_upload::{
+    abort_batch::server::{abort_key_multipart_uploads, abort_multipart_uploads},
+    abort_or_complete::server::abort_or_complete_multipart_upload,
+    create::server::create_multipart_upload,
+    lifecycle::server::put_lifecycle_rule,
+    part_upload_url::server::{part_upload_url, part_upload_urls},
+    plan::server::plan_multipart_upload,
+    Served,
+  },
+  objects::{
+    archive::server::archive_objects,
+    attributes::server::get_object_attributes,
+    content::server::put_object_content,
+    create::create_object,
+    dedup::server::find_duplicate,
+    get::{get_object, presign_get_object},
+    list::server::{list_objects, stream_objects, Listing},
+    lock::server::{
+      get_object_legal_hold, get_object_retention, put_object_legal_hold, put_object_retention,
+    },
+    manifest::server::sign_upload_manifest,
+    select::server::select_object,
+    share::server::share_object,
+    site::server::site,
+    verify::server::verify_object,
+  },
+  policy::Identity,
+  uppy::server::{
+    uppy_abort_multipart_upload, uppy_complete_multipart_upload, uppy_create_multipart_upload,
+    uppy_list_parts, uppy_part_url,
+  },
+};
@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+  Ok,
+  Unavailable,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct HealthResponse {
+  pub status: HealthStatus,
+  /// Reason of the unavailability
+  pub error: Option<String>,
+}
+
+#[cfg(feature = "server")]
+pub use server::{check, CheckResult};
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{telemetry, to_json_response, Error, S3Configuration};
+  use rusoto_core::RusotoError;
+  use rusoto_s3::{HeadBucketRequest, ListObjectsV2Request, S3Client, S3};
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{
+    hyper::{Body, Response, StatusCode},
+    Filter, Rejection, Reply,
+  };
+
+  /// Liveness probe
+  #[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "Health",
+    responses(
+      (
+        status = 200,
+        description = "The signer is up",
+        content_type = "application/json",
+        body = HealthResponse
+      ),
+    ),
+  )]
+  pub(crate) fn liveness_route() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone
+  {
+    warp::path!("healthz")
+      .and(warp::get())
+      .and_then(|| async { liveness().map_err(warp::reject::custom) })
+  }
+
+  /// Answers the liveness probe.
+  pub fn liveness() -> Result<Response<Body>, Error> {
+    to_json_response(
+      StatusCode::OK,
+      &HealthResponse {
+        status: HealthStatus::Ok,
+        error: None,
+      },
+    )
+  }
+
+  /// Readiness probe
+  #[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "Health",
+    responses(
+      (
+        status = 200,
+        description = "The signer is ready to serve requests",
+        content_type = "application/json",
+        body = HealthResponse
+      ),
+      (
+        status = 503,
+        description = "The health check bucket cannot be reached",
+        content_type = "application/json",
+        body = HealthResponse
+      ),
+    ),
+  )]
+  pub(crate) fn readiness_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("readyz")
+      .and(warp::get())
+      .and(warp::any().map(move || s3_configuration.current()))
+      .and_then(|s3_configuration: Arc<S3Configuration>| async move {
+        readiness(&s3_configuration)
+          .await
+          .map_err(warp::reject::custom)
+      })
+  }
+
+  /// Answers the readiness probe, checking that the health check bucket (if any) can be reached.
+  #[tracing::instrument(skip_all)]
+  pub async fn readiness(s3_configuration: &S3Configuration) -> Result<Response<Body>, Error> {
+    let error = match s3_configuration.health_check_bucket() {
+      Some(bucket) => check_bucket(&s3_configuration.for_bucket(bucket), bucket)
+        .await
+        .err(),
+      None => None,
+    };
+
+    if let Some(error) = &error {
+      log::warn!("Readiness check: {}", error);
+    }
+
+    let (status_code, status) = if error.is_some() {
+      (StatusCode::SERVICE_UNAVAILABLE, HealthStatus::Unavailable)
+    } else {
+      (StatusCode::OK, HealthStatus::Ok)
+    };
+
+    to_json_response(status_code, &HealthResponse { status, error })
+  }
+
+  async fn check_bucket(s3_configuration: &S3Configuration, bucket: &str) -> Result<(), String> {
+    let client = S3Client::try_from(s3_configuration).map_err(|error| error.to_string())?;
+
+    client
+      .head_bucket(HeadBucketRequest {
+        bucket: bucket.to_string(),
+        ..Default::default()
+      })
+      .instrument(telemetry::s3_span("HeadBucket"))
+      .await
+      .map_err(|error| format!("bucket {}: {}", bucket, error))
+  }
+
+  /// Outcome of a probe of [`check`].
+  #[derive(Debug)]
+  pub struct CheckResult {
+    /// Probe, e.g. `HeadBucket media`
+    pub probe: String,
+    /// Reason of the failure, with a hint on the setting to fix
+    pub error: Option<String>,
+  }
+
+  /// Checks that the storage can be reached with the configuration: the configured buckets (see
+  /// [`S3Configuration::configured_buckets`]) are probed with HeadBucket and ListObjectsV2, with
+  /// their own configuration, or the buckets are listed when none is configured.
+  pub async fn check(s3_configuration: &S3Configuration) -> Vec<CheckResult> {
+    let buckets = s3_configuration.configured_buckets();
+    if buckets.is_empty() {
+      let result = match S3Client::try_from(s3_configuration) {
+        Ok(client) => client
+          .list_buckets()
+          .await
+          .map(|_| ())
+          .map_err(|error| error_hint(&error)),
+        Err(error) => Err(error.to_string()),
+      };
+      return vec![CheckResult {
+        probe: "ListBuckets".to_string(),
+        error: result.err(),
+      }];
+    }
+
+    let mut results = vec![];
+    for bucket in buckets {
+      let bucket_configuration = s3_configuration.for_bucket(&bucket);
+      let client = match S3Client::try_from(&bucket_configuration) {
+        Ok(client) => client,
+        Err(error) => {
+          results.push(CheckResult {
+            probe: format!("S3 client of {}", bucket),
+            error: Some(error.to_string()),
+          });
+          continue;
+        }
+      };
+
+      let head_bucket = client
+        .head_bucket(HeadBucketRequest {
+          bucket: bucket.clone(),
+          ..Default::default()
+        })
+        .await
+        .map_err(|error| error_hint(&error));
+      results.push(CheckResult {
+        probe: format!("HeadBucket {}", bucket),
+        error: head_bucket.err(),
+      });
+
+      let list_objects = client
+        .list_objects_v2(ListObjectsV2Request {
+          bucket: bucket.clone(),
+          max_keys: Some(1),
+          ..Default::default()
+        })
+        .await
+        .map_err(|error| error_hint(&error));
+      results.push(CheckResult {
+        probe: format!("ListObjectsV2 {}", bucket),
+        error: list_objects.err(),
+      });
+    }
+    results
+  }
+
+  /// Describes an S3 error with the settings to check.
+  fn error_hint<E: std::error::Error + 'static>(error: &RusotoError<E>) -> String {
+    match error {
+      RusotoError::HttpDispatch(error) => format!(
+        "cannot reach the storage ({}), check --aws-hostname and the network",
+        error
+      ),
+      RusotoError::Credentials(error) => format!(
+        "invalid credentials ({}), check --aws-access-key-id and --aws-secret-access-key",
+        error
+      ),
+      RusotoError::Unknown(response) => match response.status.as_u16() {
+        301 => "the bucket is in another region (301), check --aws-region or its --bucket-configuration".to_string(),
+        403 => "access denied (403), check --aws-access-key-id, --aws-secret-access-key and the bucket policy".to_string(),
+        404 => "no such bucket (404), check its name and --aws-hostname".to_string(),
+        status => format!("unexpected response ({}): {}", status, response.body_as_str()),
+      },
+      error => error.to_string(),
+    }
+  }
+}
@@ -0,0 +1,161 @@
+//! HAL envelope of the listing and pre-signing responses, with `--hypermedia`: the responses
+//! carry `_links` to the related requests, for generic hypermedia clients to navigate the API
+//! without building its URLs.
+//!
+//! The links to the signer are relative to the request URL, like the download links, so that they
+//! are followed under the same root path and API version.
+
+use crate::{
+  accepts_json,
+  objects::{Object, PresignedUrlResponse},
+  request_builder, to_presigned_response, Error, S3Configuration,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use warp::hyper::{header::CONTENT_TYPE, Body, Response, StatusCode};
+
+pub(crate) const HAL_CONTENT_TYPE: &str = "application/hal+json";
+
+#[derive(Serialize)]
+struct Link {
+  href: String,
+}
+
+type Links = BTreeMap<&'static str, Link>;
+
+/// Resource of a HAL document: its body, along with its links.
+#[derive(Serialize)]
+struct Resource<'a, T> {
+  #[serde(flatten)]
+  body: &'a T,
+  #[serde(rename = "_links")]
+  links: Links,
+}
+
+#[derive(Serialize)]
+struct Listing<'a> {
+  #[serde(rename = "_links")]
+  links: Links,
+  #[serde(rename = "_embedded")]
+  embedded: EmbeddedObjects<'a>,
+}
+
+#[derive(Serialize)]
+struct EmbeddedObjects<'a> {
+  objects: Vec<Resource<'a, Object>>,
+}
+
+/// Answers the objects listed under a prefix, as a HAL document with the links to get each object
+/// and to list each folder when the configuration asks for it, as a JSON array otherwise.
+pub(crate) fn listing_response(
+  s3_configuration: &S3Configuration,
+  bucket: &str,
+  prefix: Option<&str>,
+  objects: &[Object],
+) -> Result<Response<Body>, Error> {
+  if !s3_configuration.hypermedia() {
+    return crate::to_ok_json_response(objects);
+  }
+
+  let prefix = prefix.unwrap_or_default();
+  let objects = objects
+    .iter()
+    .map(|object| {
+      let key = format!("{}{}", prefix, object.path);
+      let link = if object.is_dir {
+        ("objects", listing_link(bucket, &key)?)
+      } else {
+        ("download", object_link(bucket, &key)?)
+      };
+      Ok(Resource {
+        body: object,
+        links: Links::from([link]),
+      })
+    })
+    .collect::<Result<_, Error>>()?;
+  to_hal_response(&Listing {
+    links: Links::from([("self", listing_link(bucket, prefix)?)]),
+    embedded: EmbeddedObjects { objects },
+  })
+}
+
+/// Answers the pre-signed URL to get an object like [`to_presigned_response`], as a HAL document
+/// linking to it when the configuration asks for it.
+pub(crate) fn download_response(
+  s3_configuration: &S3Configuration,
+  presigned_url: &PresignedUrlResponse,
+  accept: Option<&str>,
+  bucket: &str,
+  key: &str,
+) -> Result<Response<Body>, Error> {
+  if !s3_configuration.hypermedia() || !accepts_json(accept) {
+    return to_presigned_response(presigned_url, accept);
+  }
+
+  to_hal_response(&Resource {
+    body: presigned_url,
+    links: Links::from([
+      ("self", object_link(bucket, key)?),
+      (
+        "download",
+        Link {
+          href: presigned_url.url.clone(),
+        },
+      ),
+    ]),
+  })
+}
+
+/// Answers the pre-signed URL to upload an object like [`to_presigned_response`], as a HAL
+/// document linking to it, and to the download of the object, when the configuration asks for it.
+pub(crate) fn upload_response(
+  s3_configuration: &S3Configuration,
+  presigned_url: &PresignedUrlResponse,
+  accept: Option<&str>,
+  bucket: &str,
+  key: &str,
+) -> Result<Response<Body>, Error> {
+  if !s3_configuration.hypermedia() || !accepts_json(accept) {
+    return to_presigned_response(presigned_url, accept);
+  }
+
+  to_hal_response(&Resource {
+    body: presigned_url,
+    links: Links::from([
+      (
+        "upload",
+        Link {
+          href: presigned_url.url.clone(),
+        },
+      ),
+      ("download", object_link(bucket, key)?),
+    ]),
+  })
+}
+
+/// Link to the pre-signed URL of an object, `GET /object`.
+fn object_link(bucket: &str, key: &str) -> Result<Link, Error> {
+  link("object", &[("bucket", bucket), ("path", key)])
+}
+
+/// Link to the listing of a folder, `GET /objects`.
+fn listing_link(bucket: &str, prefix: &str) -> Result<Link, Error> {
+  link("objects", &[("bucket", bucket), ("prefix", prefix)])
+}
+
+fn link(path: &str, parameters: &[(&str, &str)]) -> Result<Link, Error> {
+  let query = serde_urlencoded::to_string(parameters)
+    .map_err(|error| Error::InvalidParameter(error.to_string()))?;
+  Ok(Link {
+    href: format!("{}?{}", path, query),
+  })
+}
+
+fn to_hal_response<T: Serialize>(document: &T) -> Result<Response<Body>, Error> {
+  let json = serde_json::to_string(document).map_err(Error::JsonError)?;
+  request_builder()
+    .header(CONTENT_TYPE, HAL_CONTENT_TYPE)
+    .status(StatusCode::OK)
+    .body(json.into())
+    .map_err(Error::HttpError)
+}
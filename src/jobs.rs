@@ -0,0 +1,790 @@
+//! Background jobs running the operations too long to answer within a request, e.g. deleting
+//! every object under a prefix: their submission answers `202 Accepted` with the pending job, whose
+//! status and result are then polled with `GET /jobs/{id}`.
+//!
+//! The jobs are kept in a [`JobStore`], in memory by default, or in Redis with the `redis-jobs`
+//! feature to poll them from any signer. They run in the signer which accepted them, and are lost,
+//! still running, when it stops. Finished jobs are kept for a day.
+
+use crate::{
+  batch::BatchResult,
+  body, json_reply, listing_cache, metrics,
+  multipart_upload::{
+    abort_batch::server::{abort_multipart_uploads, list_stale_uploads},
+    lifecycle::DEFAULT_ABORT_INCOMPLETE_UPLOADS_DAYS,
+    AbortUploadItem, AbortUploadsBody,
+  },
+  objects::{
+    archive::server::{authorize_archive, store_archive},
+    key, list_objects_page, ArchiveBody,
+  },
+  policy::{self, Identity, Operation},
+  query, quotas, retry, telemetry, to_json_response, Error, S3Configuration,
+};
+use chrono::{SecondsFormat, Utc};
+use futures::{StreamExt, TryStreamExt};
+use rand::{distributions::Alphanumeric, Rng};
+use rusoto_s3::{DeleteObjectRequest, S3Client, S3};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  convert::TryFrom,
+  fmt::{Debug, Formatter},
+  future::Future,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+use tracing::Instrument;
+use warp::{
+  filters::BoxedFilter,
+  hyper::{Body, Response, StatusCode},
+  Filter, Rejection, Reply,
+};
+
+const JOB_ID_LENGTH: usize = 32;
+/// Lifetime of the finished jobs in their store
+const JOB_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+/// Objects deleted at once by a prefix deletion
+const DELETE_CONCURRENCY: usize = 8;
+
+/// Operation run by a job.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobKind {
+  PrefixDelete,
+  Inventory,
+  Archive,
+  UploadCleanup,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+  Pending,
+  Running,
+  Succeeded,
+  Failed,
+}
+
+/// Operation run in the background, polled until it succeeded or failed.
+#[derive(Clone, Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct Job {
+  pub id: String,
+  pub kind: JobKind,
+  pub status: JobStatus,
+  /// Subject, or digest of the API key, of the caller who submitted the job, the only one to
+  /// see it
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub owner: Option<String>,
+  /// Submission date, RFC 3339
+  pub created_at: String,
+  /// End date, RFC 3339, once succeeded or failed
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub finished_at: Option<String>,
+  /// Result of the job once succeeded, depending on its kind
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[schema(value_type = Option<Object>)]
+  pub result: Option<serde_json::Value>,
+  /// Error of the job once failed
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<JobError>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct JobError {
+  /// Error code, as answered by the synchronous requests (e.g. `NoSuchBucket`)
+  pub code: String,
+  pub message: String,
+}
+
+/// Objects under a prefix, to delete or to take the inventory of.
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct PrefixJobBody {
+  pub bucket: String,
+  pub prefix: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct ArchiveJobBody {
+  pub bucket: String,
+  /// Keys of the objects to archive, named after their key in the archive
+  pub keys: Option<Vec<String>>,
+  /// Prefix of the objects to archive, named after their path relative to the prefix
+  pub prefix: Option<String>,
+  /// Key of the archive, stored in the same bucket
+  pub destination: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct UploadCleanupJobBody {
+  pub bucket: String,
+  /// Prefix of the keys of the uploads, the whole bucket by default
+  pub prefix: Option<String>,
+  /// Age of the uploads to abort, in days, 7 by default
+  pub older_than_days: Option<i64>,
+}
+
+/// Result of a prefix deletion job.
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct PrefixDeleteResult {
+  /// Number of deleted objects
+  pub deleted: usize,
+}
+
+/// Result of an inventory job.
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct InventoryResult {
+  /// Number of objects under the prefix
+  pub objects: usize,
+  /// Total size of the objects, in bytes
+  pub bytes: u64,
+}
+
+/// Result of an archive job.
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct ArchiveResult {
+  /// Key of the stored archive
+  pub key: String,
+  /// Size of the archive, in bytes
+  pub bytes: u64,
+}
+
+/// Storage of the jobs, shared by the signers answering their status.
+///
+/// Its calls may block, e.g. on the network: the signer makes them on the threads dedicated to
+/// blocking operations, off the async workers.
+pub trait JobStore: Send + Sync {
+  fn get(&self, id: &str) -> Result<Option<Job>, Error>;
+  /// Inserts or updates a job.
+  fn put(&self, job: &Job) -> Result<(), Error>;
+}
+
+/// Store keeping the jobs in memory, lost on restart.
+#[derive(Default)]
+pub struct MemoryJobStore {
+  jobs: Mutex<HashMap<String, (Instant, Job)>>,
+}
+
+impl JobStore for MemoryJobStore {
+  fn get(&self, id: &str) -> Result<Option<Job>, Error> {
+    let jobs = self.jobs.lock().unwrap_or_else(|error| error.into_inner());
+    Ok(jobs.get(id).map(|(_updated_at, job)| job.clone()))
+  }
+
+  fn put(&self, job: &Job) -> Result<(), Error> {
+    let mut jobs = self.jobs.lock().unwrap_or_else(|error| error.into_inner());
+    jobs.retain(|_, (updated_at, job)| {
+      job.finished_at.is_none() || updated_at.elapsed() < JOB_RETENTION
+    });
+    jobs.insert(job.id.clone(), (Instant::now(), job.clone()));
+    Ok(())
+  }
+}
+
+/// Store keeping the jobs in Redis, as JSON expiring a day after their last update.
+///
+/// Connections are reused between calls, a few of them being kept open.
+#[cfg(feature = "redis-jobs")]
+pub struct RedisJobStore {
+  client: redis::Client,
+  idle_connections: Mutex<Vec<redis::Connection>>,
+}
+
+#[cfg(feature = "redis-jobs")]
+impl RedisJobStore {
+  /// Connection, read and write timeout of the requests to Redis
+  const TIMEOUT: Duration = Duration::from_secs(5);
+  /// Connections kept open between calls
+  const MAX_IDLE_CONNECTIONS: usize = 8;
+
+  /// Store of the Redis server at `url`, e.g. `redis://localhost:6379/0`.
+  pub fn new(url: &str) -> Result<Self, redis::RedisError> {
+    redis::Client::open(url).map(|client| Self {
+      client,
+      idle_connections: Mutex::default(),
+    })
+  }
+
+  /// Runs a command on an idle connection, or on a new one, kept open for the next calls unless
+  /// broken.
+  fn with_connection<T>(
+    &self,
+    command: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>,
+  ) -> Result<T, Error> {
+    let idle_connection = self
+      .idle_connections
+      .lock()
+      .unwrap_or_else(|error| error.into_inner())
+      .pop();
+    let mut connection = match idle_connection {
+      Some(connection) => connection,
+      None => self.connect()?,
+    };
+
+    let result = command(&mut connection);
+    if redis::ConnectionLike::is_open(&connection)
+      && !result.as_ref().is_err_and(|error| error.is_io_error())
+    {
+      let mut idle_connections = self
+        .idle_connections
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+      if idle_connections.len() < Self::MAX_IDLE_CONNECTIONS {
+        idle_connections.push(connection);
+      }
+    }
+    result.map_err(|error| Error::JobStoreError(error.to_string()))
+  }
+
+  fn connect(&self) -> Result<redis::Connection, Error> {
+    let connection = self
+      .client
+      .get_connection_with_timeout(Self::TIMEOUT)
+      .map_err(|error| Error::JobStoreError(error.to_string()))?;
+    connection
+      .set_read_timeout(Some(Self::TIMEOUT))
+      .and_then(|()| connection.set_write_timeout(Some(Self::TIMEOUT)))
+      .map_err(|error| Error::JobStoreError(error.to_string()))?;
+    Ok(connection)
+  }
+
+  fn key(id: &str) -> String {
+    format!("s3-signer:job:{}", id)
+  }
+}
+
+#[cfg(feature = "redis-jobs")]
+impl JobStore for RedisJobStore {
+  fn get(&self, id: &str) -> Result<Option<Job>, Error> {
+    let json: Option<String> =
+      self.with_connection(|connection| redis::Commands::get(connection, Self::key(id)))?;
+    json
+      .map(|json| serde_json::from_str(&json).map_err(Error::JsonError))
+      .transpose()
+  }
+
+  fn put(&self, job: &Job) -> Result<(), Error> {
+    let json = serde_json::to_string(job).map_err(Error::JsonError)?;
+    self.with_connection(|connection| {
+      redis::Commands::set_ex(
+        connection,
+        Self::key(&job.id),
+        json,
+        JOB_RETENTION.as_secs() as usize,
+      )
+    })
+  }
+}
+
+/// Background jobs of the signer, kept in a [`JobStore`].
+#[derive(Clone)]
+pub struct Jobs {
+  store: Arc<dyn JobStore>,
+}
+
+impl Debug for Jobs {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Jobs").finish_non_exhaustive()
+  }
+}
+
+impl Default for Jobs {
+  /// Jobs kept in memory.
+  fn default() -> Self {
+    Self::new(Arc::new(MemoryJobStore::default()))
+  }
+}
+
+impl Jobs {
+  pub fn new(store: Arc<dyn JobStore>) -> Self {
+    Self { store }
+  }
+
+  /// Stores a pending job of `identity`, then runs `work` in the background, storing its outcome.
+  async fn spawn<F, T>(&self, kind: JobKind, identity: &Identity, work: F) -> Result<Job, Error>
+  where
+    F: Future<Output = Result<T, Error>> + Send + 'static,
+    T: Serialize,
+  {
+    let id: String = rand::thread_rng()
+      .sample_iter(&Alphanumeric)
+      .take(JOB_ID_LENGTH)
+      .map(char::from)
+      .collect();
+    let job = Job {
+      id,
+      kind,
+      status: JobStatus::Pending,
+      owner: identity.owner(),
+      created_at: now(),
+      finished_at: None,
+      result: None,
+      error: None,
+    };
+    put(&self.store, job.clone()).await?;
+    log::info!("Job submitted: id={}, kind={:?}", job.id, kind);
+
+    let store = self.store.clone();
+    let mut running = job.clone();
+    tokio::spawn(
+      async move {
+        running.status = JobStatus::Running;
+        update(&store, &running).await;
+
+        let outcome = work
+          .await
+          .and_then(|result| serde_json::to_value(result).map_err(Error::JsonError));
+        match outcome {
+          Ok(result) => {
+            running.status = JobStatus::Succeeded;
+            running.result = Some(result);
+          }
+          Err(error) => {
+            log::error!("Job failed: id={}, {}", running.id, error);
+            running.status = JobStatus::Failed;
+            running.error = Some(JobError {
+              code: error.code(),
+              message: error.public_message(),
+            });
+          }
+        }
+        running.finished_at = Some(now());
+        update(&store, &running).await;
+        log::info!(
+          "Job finished: id={}, status={:?}",
+          running.id,
+          running.status
+        );
+      }
+      .instrument(tracing::info_span!("job", kind = ?kind)),
+    );
+    Ok(job)
+  }
+
+  /// Job `id` of `identity`: the jobs of the other callers are not found.
+  async fn get(&self, identity: &Identity, id: &str) -> Result<Job, Error> {
+    let job_id = id.to_string();
+    blocking(&self.store, move |store| store.get(&job_id))
+      .await?
+      .filter(|job| job.owner == identity.owner())
+      .ok_or_else(|| Error::NotFound(format!("no job {}", id)))
+  }
+}
+
+/// Runs a call to the store on the blocking threads.
+async fn blocking<T: Send + 'static>(
+  store: &Arc<dyn JobStore>,
+  call: impl FnOnce(&dyn JobStore) -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+  let store = store.clone();
+  tokio::task::spawn_blocking(move || call(store.as_ref()))
+    .await
+    .map_err(|error| Error::JobStoreError(error.to_string()))?
+}
+
+async fn put(store: &Arc<dyn JobStore>, job: Job) -> Result<(), Error> {
+  blocking(store, move |store| store.put(&job)).await
+}
+
+/// Stores the progress of a running job, which goes on when it cannot be stored.
+async fn update(store: &Arc<dyn JobStore>, job: &Job) {
+  if let Err(error) = put(store, job.clone()).await {
+    log::error!("Cannot store job: id={}, {}", job.id, error);
+  }
+}
+
+fn now() -> String {
+  Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub(crate) fn routes(s3_configuration: &S3Configuration) -> BoxedFilter<(Response<Body>,)> {
+  // Boxed, like the routes they are mounted with, to keep the stack of their futures shallow.
+  get_route(s3_configuration)
+    .or(prefix_delete_route(s3_configuration))
+    .or(inventory_route(s3_configuration))
+    .or(archive_route(s3_configuration))
+    .or(upload_cleanup_route(s3_configuration))
+    .map(Reply::into_response)
+    .boxed()
+}
+
+/// Get a job
+#[utoipa::path(
+  get,
+  path = "/jobs/{id}",
+  tag = "Jobs",
+  responses(
+    (status = 200, description = "Returns the status of the job, and its result once finished", content_type = "application/json", body = Job),
+    (status = 404, description = "Unknown or expired job, or job of another caller"),
+  ),
+  params(
+    ("id" = String, Path, description = "ID of the job"),
+  ),
+  security(("bearer_token" = []), ("api_key" = [])),
+)]
+pub(crate) fn get_route(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("jobs" / String)
+    .and(warp::get())
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
+    .and_then(
+      |id: String, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+        json_reply(get_job(&s3_configuration, &identity, &id).await)
+      },
+    )
+}
+
+/// Delete the objects under a prefix
+#[utoipa::path(
+  post,
+  path = "/jobs/prefix-delete",
+  tag = "Jobs",
+  request_body(content = PrefixJobBody, content_type = "application/json"),
+  responses(
+    (status = 202, description = "Returns the pending job, resulting in a PrefixDeleteResult", content_type = "application/json", body = Job),
+  ),
+  security(("bearer_token" = []), ("api_key" = [])),
+)]
+pub(crate) fn prefix_delete_route(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("jobs" / "prefix-delete")
+    .and(warp::post())
+    .and(body::json::<PrefixJobBody>(&s3_configuration))
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
+    .and_then(
+      |body: PrefixJobBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+        accepted(submit_prefix_delete(&s3_configuration, &identity, body).await)
+      },
+    )
+}
+
+/// Take the inventory of the objects under a prefix
+#[utoipa::path(
+  post,
+  path = "/jobs/inventory",
+  tag = "Jobs",
+  request_body(content = PrefixJobBody, content_type = "application/json"),
+  responses(
+    (status = 202, description = "Returns the pending job, resulting in an InventoryResult", content_type = "application/json", body = Job),
+  ),
+  security(("bearer_token" = []), ("api_key" = [])),
+)]
+pub(crate) fn inventory_route(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("jobs" / "inventory")
+    .and(warp::post())
+    .and(body::json::<PrefixJobBody>(&s3_configuration))
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
+    .and_then(
+      |body: PrefixJobBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+        accepted(submit_inventory(&s3_configuration, &identity, body).await)
+      },
+    )
+}
+
+/// Store objects as a ZIP archive
+#[utoipa::path(
+  post,
+  path = "/jobs/archive",
+  tag = "Jobs",
+  request_body(content = ArchiveJobBody, content_type = "application/json"),
+  responses(
+    (status = 202, description = "Returns the pending job, resulting in an ArchiveResult", content_type = "application/json", body = Job),
+  ),
+  security(("bearer_token" = []), ("api_key" = [])),
+)]
+pub(crate) fn archive_route(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("jobs" / "archive")
+    .and(warp::post())
+    .and(body::json::<ArchiveJobBody>(&s3_configuration))
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
+    .and_then(
+      |body: ArchiveJobBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+        accepted(submit_archive(&s3_configuration, &identity, body).await)
+      },
+    )
+}
+
+/// Abort the stale multipart uploads
+#[utoipa::path(
+  post,
+  path = "/jobs/upload-cleanup",
+  tag = "Jobs",
+  request_body(content = UploadCleanupJobBody, content_type = "application/json"),
+  responses(
+    (status = 202, description = "Returns the pending job, resulting in an AbortUploadsBatchResult", content_type = "application/json", body = Job),
+  ),
+  security(("bearer_token" = []), ("api_key" = [])),
+)]
+pub(crate) fn upload_cleanup_route(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("jobs" / "upload-cleanup")
+    .and(warp::post())
+    .and(body::json::<UploadCleanupJobBody>(&s3_configuration))
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
+    .and_then(
+      |body: UploadCleanupJobBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+        accepted(submit_upload_cleanup(&s3_configuration, &identity, body).await)
+      },
+    )
+}
+
+/// Answers a submitted job with `202 Accepted`.
+fn accepted(job: Result<Job, Error>) -> Result<Response<Body>, Rejection> {
+  job
+    .and_then(|job| to_json_response(StatusCode::ACCEPTED, &job))
+    .map_err(warp::reject::custom)
+}
+
+/// Job `id` submitted by `identity`.
+pub async fn get_job(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  id: &str,
+) -> Result<Job, Error> {
+  s3_configuration.jobs().get(identity, id).await
+}
+
+/// Deletes every object under a prefix in the background, on behalf of `identity`.
+pub async fn submit_prefix_delete(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  mut body: PrefixJobBody,
+) -> Result<Job, Error> {
+  // An empty prefix would empty the bucket.
+  query::required("prefix", &body.prefix)?;
+  identity.scope(&mut body.prefix);
+  key::server::validate_prefix(s3_configuration, &body.prefix)?;
+  policy::authorize(
+    s3_configuration,
+    identity,
+    Operation::Delete,
+    &body.bucket,
+    &body.prefix,
+  )?;
+
+  let job_configuration = s3_configuration.clone();
+  s3_configuration
+    .jobs()
+    .spawn(JobKind::PrefixDelete, identity, async move {
+      delete_prefix(&job_configuration, &body.bucket, &body.prefix).await
+    })
+    .await
+}
+
+/// Counts the objects under a prefix, and their size, in the background, on behalf of
+/// `identity`.
+pub async fn submit_inventory(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  mut body: PrefixJobBody,
+) -> Result<Job, Error> {
+  identity.scope(&mut body.prefix);
+  key::server::validate_prefix(s3_configuration, &body.prefix)?;
+  policy::authorize(
+    s3_configuration,
+    identity,
+    Operation::Read,
+    &body.bucket,
+    &body.prefix,
+  )?;
+
+  let job_configuration = s3_configuration.clone();
+  s3_configuration
+    .jobs()
+    .spawn(JobKind::Inventory, identity, async move {
+      take_inventory(&job_configuration, &body.bucket, &body.prefix).await
+    })
+    .await
+}
+
+/// Stores objects, given by their keys or their prefix, as a ZIP archive in the background, on
+/// behalf of `identity`.
+pub async fn submit_archive(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  body: ArchiveJobBody,
+) -> Result<Job, Error> {
+  let ArchiveJobBody {
+    bucket,
+    keys,
+    prefix,
+    mut destination,
+  } = body;
+  let mut archive = ArchiveBody {
+    bucket,
+    keys,
+    prefix,
+  };
+  authorize_archive(s3_configuration, identity, &mut archive)?;
+  identity.scope(&mut destination);
+  key::server::validate_key(s3_configuration, &destination)?;
+  policy::authorize(
+    s3_configuration,
+    identity,
+    Operation::Write,
+    &archive.bucket,
+    &destination,
+  )?;
+  quotas::check(s3_configuration, &archive.bucket, &destination)?;
+
+  let job_configuration = s3_configuration.clone();
+  s3_configuration
+    .jobs()
+    .spawn(JobKind::Archive, identity, async move {
+      let bucket = archive.bucket.clone();
+      let bytes = store_archive(&job_configuration, archive, &destination).await?;
+      listing_cache::invalidate(&job_configuration, &bucket, &destination);
+      quotas::account_upload(&job_configuration, &bucket, &destination).await;
+      Ok(ArchiveResult {
+        key: destination,
+        bytes,
+      })
+    })
+    .await
+}
+
+/// Aborts the multipart uploads under a prefix initiated some days ago in the background, on
+/// behalf of `identity`, like a lifecycle rule would.
+pub async fn submit_upload_cleanup(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  mut body: UploadCleanupJobBody,
+) -> Result<Job, Error> {
+  let days = body
+    .older_than_days
+    .unwrap_or(DEFAULT_ABORT_INCOMPLETE_UPLOADS_DAYS);
+  if days < 0 {
+    return Err(Error::InvalidParameter(
+      "older_than_days must not be negative".to_string(),
+    ));
+  }
+  identity.scope_prefix(&mut body.prefix);
+  let prefix = body.prefix.unwrap_or_default();
+  key::server::validate_prefix(s3_configuration, &prefix)?;
+  policy::authorize(
+    s3_configuration,
+    identity,
+    Operation::Multipart,
+    &body.bucket,
+    &prefix,
+  )?;
+
+  let bucket = body.bucket;
+  let job_configuration = s3_configuration.clone();
+  let job_identity = identity.clone();
+  s3_configuration
+    .jobs()
+    .spawn(JobKind::UploadCleanup, identity, async move {
+      let initiated_before = Utc::now() - chrono::Duration::days(days);
+      let uploads =
+        list_stale_uploads(&job_configuration, &bucket, &prefix, initiated_before).await?;
+      let result: BatchResult<AbortUploadItem> = abort_multipart_uploads(
+        &job_configuration,
+        &job_identity,
+        AbortUploadsBody { uploads },
+      )
+      .await;
+      Ok(result)
+    })
+    .await
+}
+
+/// Deletes the objects under a prefix a listing page at a time, returning how many were deleted.
+async fn delete_prefix(
+  s3_configuration: &S3Configuration,
+  bucket: &str,
+  prefix: &str,
+) -> Result<PrefixDeleteResult, Error> {
+  let client = S3Client::try_from(&s3_configuration.for_bucket(bucket))?;
+  let mut deleted = 0;
+  let mut continuation_token = None;
+  loop {
+    let (objects, next_continuation_token) =
+      list_objects_page(s3_configuration, bucket, prefix, true, continuation_token).await?;
+    deleted += futures::stream::iter(objects)
+      .map(|object| delete_object(s3_configuration, &client, bucket, object.key))
+      .buffer_unordered(DELETE_CONCURRENCY)
+      .try_fold(0, |deleted, ()| async move { Ok(deleted + 1) })
+      .await?;
+
+    continuation_token = next_continuation_token;
+    if continuation_token.is_none() {
+      break;
+    }
+  }
+  log::info!(
+    "Deleted prefix: bucket={}, prefix={}, count={}",
+    bucket,
+    prefix,
+    deleted
+  );
+  Ok(PrefixDeleteResult { deleted })
+}
+
+async fn delete_object(
+  s3_configuration: &S3Configuration,
+  client: &S3Client,
+  bucket: &str,
+  key: String,
+) -> Result<(), Error> {
+  let request = DeleteObjectRequest {
+    bucket: bucket.to_string(),
+    key: key.clone(),
+    ..Default::default()
+  };
+  retry::s3_call(s3_configuration, "DeleteObject", || {
+    client
+      .delete_object(request.clone())
+      .instrument(telemetry::s3_span("DeleteObject"))
+  })
+  .await
+  .map_err(metrics::count_s3_error("DeleteObject"))
+  .map_err(Error::DeleteObjectError)?;
+  listing_cache::invalidate(s3_configuration, bucket, &key);
+  Ok(())
+}
+
+/// Counts the objects under a prefix, and sums their size, a listing page at a time.
+async fn take_inventory(
+  s3_configuration: &S3Configuration,
+  bucket: &str,
+  prefix: &str,
+) -> Result<InventoryResult, Error> {
+  let mut inventory = InventoryResult {
+    objects: 0,
+    bytes: 0,
+  };
+  let mut continuation_token = None;
+  loop {
+    let (objects, next_continuation_token) =
+      list_objects_page(s3_configuration, bucket, prefix, true, continuation_token).await?;
+    inventory.objects += objects.len();
+    inventory.bytes += objects
+      .iter()
+      .map(|object| object.size.unwrap_or_default().max(0) as u64)
+      .sum::<u64>();
+
+    continuation_token = next_continuation_token;
+    if continuation_token.is_none() {
+      break;
+    }
+  }
+  Ok(inventory)
+}
@@ -0,0 +1,168 @@
+use crate::{
+  metrics,
+  policy::{self, Identity, Operation},
+  retry, telemetry, Error, S3Configuration,
+};
+use chrono::Utc;
+use rusoto_core::RusotoError;
+use rusoto_s3::{HeadObjectError, HeadObjectRequest, S3Client, S3};
+use std::{
+  convert::TryFrom,
+  fmt::{Display, Formatter},
+  str::FromStr,
+};
+use tracing::Instrument;
+use warp::hyper::StatusCode;
+
+/// Placeholders of the key templates
+const PLACEHOLDERS: &[&str] = &["{date}", "{uuid}", "{filename}"];
+/// Keys rendered before giving up on an upload colliding with existing objects
+const MAX_ATTEMPTS: u32 = 10;
+
+/// Template of the keys generated on upload, e.g. `uploads/{date}/{uuid}/{filename}`, so that
+/// clients cannot choose the keys of their objects.
+///
+/// `{date}` is the current UTC date (`2024-01-31`), `{uuid}` a random UUID, and `{filename}` the
+/// last segment of the path requested by the client.
+#[derive(Clone, Debug)]
+pub struct KeyTemplate(String);
+
+impl FromStr for KeyTemplate {
+  type Err = String;
+
+  fn from_str(template: &str) -> Result<Self, Self::Err> {
+    let mut remaining = PLACEHOLDERS
+      .iter()
+      .fold(template.to_string(), |remaining, placeholder| {
+        remaining.replace(placeholder, "")
+      });
+    remaining.retain(|c| c == '{' || c == '}');
+    if !remaining.is_empty() {
+      return Err(format!(
+        "{:?} has placeholders other than {}",
+        template,
+        PLACEHOLDERS.join(", ")
+      ));
+    }
+    if template.is_empty() || template.starts_with('/') {
+      return Err(format!("{:?} is not a relative key", template));
+    }
+    Ok(Self(template.to_string()))
+  }
+}
+
+impl Display for KeyTemplate {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl KeyTemplate {
+  /// Renders a key for the upload of `filename`, suffixed with the number of the `attempt` after
+  /// the first one, e.g. `report-1.pdf`.
+  fn render(&self, filename: &str, attempt: u32) -> String {
+    let filename = match (attempt, filename.rsplit_once('.')) {
+      (0, _) => filename.to_string(),
+      (_, Some((stem, extension))) if !stem.is_empty() => {
+        format!("{}-{}.{}", stem, attempt, extension)
+      }
+      _ => format!("{}-{}", filename, attempt),
+    };
+    self
+      .0
+      .replace("{date}", &Utc::now().format("%Y-%m-%d").to_string())
+      .replace("{uuid}", &uuid())
+      .replace("{filename}", &filename)
+  }
+}
+
+/// Generates the key of an object uploaded to a bucket with a key template, from the `path`
+/// requested by the client, on behalf of `identity`.
+///
+/// The rendered keys colliding with an existing object are rendered again. Returns `None` when
+/// the bucket has no key template, the path being the key.
+pub(crate) async fn generate_key(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  operation: Operation,
+  bucket: &str,
+  path: &str,
+) -> Result<Option<String>, Error> {
+  let s3_configuration = s3_configuration.for_bucket(bucket);
+  let key_template = match s3_configuration.key_template() {
+    Some(key_template) => key_template,
+    None => return Ok(None),
+  };
+  let filename = path.rsplit('/').next().unwrap_or_default();
+  if filename.is_empty() || filename == "." || filename == ".." {
+    return Err(Error::InvalidParameter(format!(
+      "no file name in path {:?}",
+      path
+    )));
+  }
+
+  let client = S3Client::try_from(&s3_configuration)?;
+  for attempt in 0..MAX_ATTEMPTS {
+    let mut key = key_template.render(filename, attempt);
+    identity.scope(&mut key);
+    crate::objects::key::server::validate_key(&s3_configuration, &key)?;
+    policy::authorize(&s3_configuration, identity, operation, bucket, &key)?;
+    if !exists(&s3_configuration, &client, bucket, &key).await? {
+      log::info!(
+        "Generated key: bucket={}, key={}, template={}",
+        bucket,
+        key,
+        key_template
+      );
+      return Ok(Some(key));
+    }
+  }
+
+  Err(Error::Conflict(format!(
+    "no free key for {:?} after {} attempts",
+    filename, MAX_ATTEMPTS
+  )))
+}
+
+async fn exists(
+  s3_configuration: &S3Configuration,
+  client: &S3Client,
+  bucket: &str,
+  key: &str,
+) -> Result<bool, Error> {
+  let request = HeadObjectRequest {
+    bucket: bucket.to_string(),
+    key: key.to_string(),
+    ..Default::default()
+  };
+  match retry::s3_call(s3_configuration, "HeadObject", || {
+    client
+      .head_object(request.clone())
+      .instrument(telemetry::s3_span("HeadObject"))
+  })
+  .await
+  {
+    Ok(_) => Ok(true),
+    Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(false),
+    // Answers to HEAD requests have no error body
+    Err(RusotoError::Unknown(response)) if response.status == StatusCode::NOT_FOUND => Ok(false),
+    Err(error) => {
+      metrics::s3_error("HeadObject");
+      Err(Error::HeadObjectError(error))
+    }
+  }
+}
+
+/// Random (version 4) UUID.
+fn uuid() -> String {
+  let bits = (rand::random::<u128>() & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+  let hex = format!("{:032x}", bits);
+  format!(
+    "{}-{}-{}-{}-{}",
+    &hex[..8],
+    &hex[8..12],
+    &hex[12..16],
+    &hex[16..20],
+    &hex[20..]
+  )
+}
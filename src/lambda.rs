@@ -0,0 +1,479 @@
+//! Adapter of the signer routes to the events of AWS Lambda, to run the signer serverlessly.
+//!
+//! Requests are received from API Gateway REST APIs (payload format 1.0), HTTP APIs (payload
+//! format 2.0) or Lambda function URLs, and answered with the matching response payload.
+
+use crate::{health_routes, recover_errors, Error, Signer};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, convert::Infallible, future::Future};
+use tower_service::Service;
+use warp::{
+  hyper::{
+    body,
+    header::{CONTENT_ENCODING, COOKIE},
+    Body, Request, Response,
+  },
+  Filter, Reply,
+};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Request event of API Gateway or of a Lambda function URL.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ApiGatewayRequest {
+  V2(ApiGatewayV2Request),
+  V1(ApiGatewayV1Request),
+}
+
+/// Request of an API Gateway HTTP API or of a Lambda function URL (payload format 2.0).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiGatewayV2Request {
+  pub raw_path: String,
+  #[serde(default)]
+  pub raw_query_string: String,
+  #[serde(default)]
+  pub headers: HashMap<String, String>,
+  #[serde(default)]
+  pub cookies: Vec<String>,
+  pub request_context: ApiGatewayV2RequestContext,
+  pub body: Option<String>,
+  #[serde(default)]
+  pub is_base64_encoded: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiGatewayV2RequestContext {
+  pub http: ApiGatewayV2Http,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiGatewayV2Http {
+  pub method: String,
+  pub source_ip: Option<String>,
+}
+
+/// Request of an API Gateway REST API (payload format 1.0).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiGatewayV1Request {
+  pub http_method: String,
+  pub path: String,
+  pub multi_value_query_string_parameters: Option<HashMap<String, Vec<String>>>,
+  pub multi_value_headers: Option<HashMap<String, Vec<String>>>,
+  pub headers: Option<HashMap<String, String>>,
+  #[serde(default)]
+  pub request_context: ApiGatewayV1RequestContext,
+  pub body: Option<String>,
+  #[serde(default)]
+  pub is_base64_encoded: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ApiGatewayV1RequestContext {
+  #[serde(default)]
+  pub identity: ApiGatewayV1Identity,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiGatewayV1Identity {
+  pub source_ip: Option<String>,
+}
+
+/// Response payload, understood by both payload formats.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiGatewayResponse {
+  pub status_code: u16,
+  pub headers: HashMap<String, String>,
+  pub body: String,
+  pub is_base64_encoded: bool,
+}
+
+impl ApiGatewayRequest {
+  /// Converts the event into the HTTP request it describes.
+  ///
+  /// The source IP of the event is forwarded in the `X-Forwarded-For` header, unless already set.
+  pub fn into_http_request(self) -> Result<Request<Body>, Error> {
+    let (method, uri, headers, source_ip, body, is_base64_encoded) = match self {
+      ApiGatewayRequest::V2(request) => {
+        let uri = if request.raw_query_string.is_empty() {
+          request.raw_path
+        } else {
+          format!("{}?{}", request.raw_path, request.raw_query_string)
+        };
+
+        let mut headers = request.headers.into_iter().collect::<Vec<_>>();
+        if !request.cookies.is_empty() {
+          headers.push((COOKIE.to_string(), request.cookies.join("; ")));
+        }
+
+        (
+          request.request_context.http.method,
+          uri,
+          headers,
+          request.request_context.http.source_ip,
+          request.body,
+          request.is_base64_encoded,
+        )
+      }
+      ApiGatewayRequest::V1(request) => {
+        let query = request
+          .multi_value_query_string_parameters
+          .unwrap_or_default()
+          .into_iter()
+          .flat_map(|(name, values)| values.into_iter().map(move |value| (name.clone(), value)))
+          .collect::<Vec<_>>();
+        let uri = if query.is_empty() {
+          request.path
+        } else {
+          let query = serde_urlencoded::to_string(&query)
+            .map_err(|error| Error::LambdaError(error.to_string()))?;
+          format!("{}?{}", request.path, query)
+        };
+
+        let headers = match request.multi_value_headers {
+          Some(multi_value_headers) => multi_value_headers
+            .into_iter()
+            .flat_map(|(name, values)| values.into_iter().map(move |value| (name.clone(), value)))
+            .collect(),
+          None => request
+            .headers
+            .unwrap_or_default()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        };
+
+        (
+          request.http_method,
+          uri,
+          headers,
+          request.request_context.identity.source_ip,
+          request.body,
+          request.is_base64_encoded,
+        )
+      }
+    };
+
+    let body = match body {
+      Some(body) if is_base64_encoded => {
+        base64::decode(body).map_err(|error| Error::LambdaError(error.to_string()))?
+      }
+      Some(body) => body.into_bytes(),
+      None => vec![],
+    };
+
+    let mut builder = Request::builder().method(method.as_str()).uri(uri);
+    let forwarded = headers
+      .iter()
+      .any(|(name, _)| name.eq_ignore_ascii_case(X_FORWARDED_FOR));
+    for (name, value) in headers {
+      builder = builder.header(name, value);
+    }
+    if let (false, Some(source_ip)) = (forwarded, source_ip) {
+      builder = builder.header(X_FORWARDED_FOR, source_ip);
+    }
+
+    builder.body(Body::from(body)).map_err(Error::HttpError)
+  }
+}
+
+impl ApiGatewayResponse {
+  /// Buffers the HTTP response into a response payload, base64-encoding binary or compressed
+  /// bodies.
+  pub async fn from_http_response(response: Response<Body>) -> Result<Self, Error> {
+    let (parts, body) = response.into_parts();
+    let bytes = body::to_bytes(body)
+      .await
+      .map_err(|error| Error::LambdaError(error.to_string()))?;
+
+    let mut headers = HashMap::new();
+    for (name, value) in &parts.headers {
+      if let Ok(value) = value.to_str() {
+        headers
+          .entry(name.to_string())
+          .and_modify(|values: &mut String| {
+            values.push_str(", ");
+            values.push_str(value);
+          })
+          .or_insert_with(|| value.to_string());
+      }
+    }
+
+    let text = if parts.headers.contains_key(CONTENT_ENCODING) {
+      None
+    } else {
+      String::from_utf8(bytes.to_vec()).ok()
+    };
+
+    Ok(match text {
+      Some(body) => ApiGatewayResponse {
+        status_code: parts.status.as_u16(),
+        headers,
+        body,
+        is_base64_encoded: false,
+      },
+      None => ApiGatewayResponse {
+        status_code: parts.status.as_u16(),
+        headers,
+        body: base64::encode(&bytes),
+        is_base64_encoded: true,
+      },
+    })
+  }
+}
+
+/// Serves the probes, and the routes of the signer, to the Lambda events.
+pub fn service(
+  signer: &Signer,
+) -> impl Service<
+  Request<Body>,
+  Response = Response<Body>,
+  Error = Infallible,
+  Future = impl Future<Output = Result<Response<Body>, Infallible>> + Send,
+> + Clone
+     + Send {
+  // Boxed to keep the type of the filter, and of the futures of the runtime, shallow.
+  let routes = health_routes(signer.s3_configuration())
+    .or(signer.routes())
+    .map(Reply::into_response)
+    .boxed();
+
+  warp::service(recover_errors(routes).with(crate::telemetry::trace_requests()))
+}
+
+/// Answers a Lambda event with the given service, e.g. [`service`].
+pub async fn handle<S>(
+  mut service: S,
+  request: ApiGatewayRequest,
+) -> Result<ApiGatewayResponse, Error>
+where
+  S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+  let request = request.into_http_request()?;
+  let response = match service.call(request).await {
+    Ok(response) => response,
+    Err(infallible) => match infallible {},
+  };
+
+  ApiGatewayResponse::from_http_response(response).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use warp::hyper::{header::CONTENT_TYPE, Method, StatusCode};
+
+  async fn body_of(request: Request<Body>) -> Vec<u8> {
+    body::to_bytes(request.into_body()).await.unwrap().to_vec()
+  }
+
+  fn event(json: &str) -> ApiGatewayRequest {
+    serde_json::from_str(json).unwrap()
+  }
+
+  #[tokio::test]
+  async fn http_api_event() {
+    let request = event(
+      r#"{
+        "version": "2.0",
+        "routeKey": "$default",
+        "rawPath": "/api/objects/media/folder/file.txt",
+        "rawQueryString": "expires_in=60&download=true",
+        "cookies": ["session=abc", "theme=dark"],
+        "headers": {
+          "authorization": "Bearer token",
+          "content-type": "application/json"
+        },
+        "queryStringParameters": { "expires_in": "60", "download": "true" },
+        "requestContext": {
+          "accountId": "123456789012",
+          "apiId": "api-id",
+          "domainName": "id.execute-api.us-east-1.amazonaws.com",
+          "http": {
+            "method": "POST",
+            "path": "/api/objects/media/folder/file.txt",
+            "protocol": "HTTP/1.1",
+            "sourceIp": "192.0.2.1",
+            "userAgent": "agent"
+          },
+          "requestId": "id",
+          "stage": "$default",
+          "time": "12/Mar/2020:19:03:58 +0000",
+          "timeEpoch": 1583348638390
+        },
+        "body": "eyJhIjoxfQ==",
+        "isBase64Encoded": true
+      }"#,
+    );
+    assert!(matches!(request, ApiGatewayRequest::V2(_)));
+
+    let request = request.into_http_request().unwrap();
+    assert_eq!(request.method(), Method::POST);
+    assert_eq!(
+      request.uri(),
+      "/api/objects/media/folder/file.txt?expires_in=60&download=true"
+    );
+    assert_eq!(request.headers()["authorization"], "Bearer token");
+    assert_eq!(request.headers()[CONTENT_TYPE], "application/json");
+    assert_eq!(request.headers()[COOKIE], "session=abc; theme=dark");
+    assert_eq!(request.headers()[X_FORWARDED_FOR], "192.0.2.1");
+    assert_eq!(body_of(request).await, br#"{"a":1}"#);
+  }
+
+  #[tokio::test]
+  async fn function_url_event() {
+    let request = event(
+      r#"{
+        "version": "2.0",
+        "routeKey": "$default",
+        "rawPath": "/api/version",
+        "rawQueryString": "",
+        "headers": {
+          "x-forwarded-for": "198.51.100.7",
+          "host": "abc.lambda-url.us-east-1.on.aws"
+        },
+        "requestContext": {
+          "accountId": "anonymous",
+          "apiId": "abc",
+          "domainName": "abc.lambda-url.us-east-1.on.aws",
+          "domainPrefix": "abc",
+          "http": {
+            "method": "GET",
+            "path": "/api/version",
+            "protocol": "HTTP/1.1",
+            "sourceIp": "203.0.113.9",
+            "userAgent": "agent"
+          },
+          "requestId": "id",
+          "routeKey": "$default",
+          "stage": "$default",
+          "time": "12/Mar/2020:19:03:58 +0000",
+          "timeEpoch": 1583348638390
+        },
+        "isBase64Encoded": false
+      }"#,
+    );
+
+    let request = request.into_http_request().unwrap();
+    assert_eq!(request.method(), Method::GET);
+    assert_eq!(request.uri(), "/api/version");
+    assert!(request.headers().get(COOKIE).is_none());
+    // Kept as forwarded by the function URL
+    assert_eq!(request.headers()[X_FORWARDED_FOR], "198.51.100.7");
+    assert!(body_of(request).await.is_empty());
+  }
+
+  #[tokio::test]
+  async fn rest_api_event() {
+    let request = event(
+      r#"{
+        "resource": "/{proxy+}",
+        "path": "/api/buckets/media/objects",
+        "httpMethod": "GET",
+        "headers": { "Accept": "application/json" },
+        "multiValueHeaders": {
+          "Accept": ["application/json"],
+          "X-Custom": ["a", "b"]
+        },
+        "queryStringParameters": { "prefix": "folder/" },
+        "multiValueQueryStringParameters": { "prefix": ["folder/a b&c"] },
+        "pathParameters": { "proxy": "api/buckets/media/objects" },
+        "stageVariables": null,
+        "requestContext": {
+          "accountId": "123456789012",
+          "resourceId": "id",
+          "stage": "prod",
+          "requestId": "id",
+          "identity": { "sourceIp": "192.0.2.2", "userAgent": "agent" },
+          "resourcePath": "/{proxy+}",
+          "httpMethod": "GET",
+          "apiId": "api-id"
+        },
+        "body": null,
+        "isBase64Encoded": false
+      }"#,
+    );
+    assert!(matches!(request, ApiGatewayRequest::V1(_)));
+
+    let request = request.into_http_request().unwrap();
+    assert_eq!(request.method(), Method::GET);
+    assert_eq!(
+      request.uri(),
+      "/api/buckets/media/objects?prefix=folder%2Fa+b%26c"
+    );
+    assert_eq!(request.headers()["accept"], "application/json");
+    let custom = request
+      .headers()
+      .get_all("x-custom")
+      .iter()
+      .collect::<Vec<_>>();
+    assert_eq!(custom, ["a", "b"]);
+    assert_eq!(request.headers()[X_FORWARDED_FOR], "192.0.2.2");
+    assert!(body_of(request).await.is_empty());
+  }
+
+  #[tokio::test]
+  async fn text_responses_are_kept_as_is() {
+    let response = Response::builder()
+      .status(StatusCode::CREATED)
+      .header(CONTENT_TYPE, "application/json")
+      .header("vary", "origin")
+      .header("vary", "accept")
+      .body(Body::from(r#"{"url":"https://example.com"}"#))
+      .unwrap();
+
+    let response = ApiGatewayResponse::from_http_response(response)
+      .await
+      .unwrap();
+    assert_eq!(response.status_code, 201);
+    assert_eq!(response.headers["content-type"], "application/json");
+    assert_eq!(response.headers["vary"], "origin, accept");
+    assert_eq!(response.body, r#"{"url":"https://example.com"}"#);
+    assert!(!response.is_base64_encoded);
+  }
+
+  #[tokio::test]
+  async fn binary_and_compressed_responses_are_base64_encoded() {
+    let binary = Response::new(Body::from(vec![0, 159, 146, 150]));
+    let response = ApiGatewayResponse::from_http_response(binary)
+      .await
+      .unwrap();
+    assert_eq!(response.body, base64::encode([0, 159, 146, 150]));
+    assert!(response.is_base64_encoded);
+
+    let compressed = Response::builder()
+      .header(CONTENT_ENCODING, "gzip")
+      .body(Body::from("text"))
+      .unwrap();
+    let response = ApiGatewayResponse::from_http_response(compressed)
+      .await
+      .unwrap();
+    assert_eq!(response.body, base64::encode("text"));
+    assert!(response.is_base64_encoded);
+  }
+
+  #[tokio::test]
+  async fn events_are_answered_by_the_service() {
+    let service = warp::service(
+      warp::path!("api" / "echo" / String).map(|name: String| format!("hello {}", name)),
+    );
+    let response = handle(
+      service,
+      event(
+        r#"{
+          "rawPath": "/api/echo/lambda",
+          "requestContext": { "http": { "method": "GET" } }
+        }"#,
+      ),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body, "hello lambda");
+  }
+}
@@ -1,32 +1,302 @@
+// `Error` embeds the Rusoto errors as is, the handlers returning it by value.
+#![allow(clippy::result_large_err)]
+
+pub mod api_version;
+#[cfg(feature = "server")]
+pub mod authentication;
+pub mod batch;
+#[cfg(feature = "server")]
+mod body;
+#[cfg(feature = "server")]
+mod bucket_regions;
+#[cfg(feature = "server")]
+mod cache_headers;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "server")]
+mod compression;
+#[cfg(feature = "server")]
+mod concurrency_limit;
+#[cfg(feature = "server")]
+mod download_links;
+pub mod encryption;
+#[cfg(feature = "server")]
+mod endpoint_override;
 #[cfg(feature = "server")]
 mod error;
+pub mod error_body;
+pub mod events;
+#[cfg(feature = "graphql")]
+mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "server")]
+pub mod handlers;
+pub mod health;
+#[cfg(feature = "server")]
+mod hypermedia;
+#[cfg(feature = "server")]
+pub mod jobs;
+#[cfg(feature = "server")]
+mod key_template;
+#[cfg(feature = "lambda")]
+pub mod lambda;
+pub mod limits;
+#[cfg(feature = "server")]
+mod listing_cache;
+pub mod media;
+#[cfg(feature = "server")]
+pub mod metrics;
+#[cfg(feature = "mock-s3")]
+mod mock_s3;
 pub mod multipart_upload;
 pub mod objects;
 #[cfg(feature = "server")]
 mod open_api;
 #[cfg(feature = "server")]
+pub mod policy;
+#[cfg(feature = "presign")]
+pub mod presign;
+#[cfg(feature = "server")]
+mod query;
+#[cfg(feature = "server")]
+mod quotas;
+#[cfg(feature = "server")]
+pub mod request_id;
+#[cfg(feature = "server")]
+mod request_timeout;
+#[cfg(feature = "server")]
+mod retry;
+#[cfg(feature = "server")]
 mod s3_configuration;
+#[cfg(feature = "server")]
+pub mod scheduler;
+#[cfg(feature = "server")]
+pub mod settings;
+#[cfg(feature = "server")]
+mod signer;
+#[cfg(feature = "server")]
+pub mod telemetry;
+#[cfg(feature = "server")]
+mod tls;
+#[cfg(feature = "server")]
+mod transfer_acceleration;
+pub mod uppy;
+#[cfg(feature = "server")]
+mod version;
+#[cfg(feature = "server")]
+mod zip;
 
+#[cfg(feature = "client")]
+pub use client::{ClientError, S3SignerClient};
+pub use error_body::ErrorBody;
+#[cfg(feature = "mock-s3")]
+pub use mock_s3::MockS3;
 #[cfg(feature = "server")]
 pub use server::*;
 
 #[cfg(feature = "server")]
 mod server {
-  pub use crate::{error::Error, open_api::*, s3_configuration::S3Configuration};
+  pub use crate::{
+    api_version::ApiVersion,
+    authentication::{Claims, JwtValidator},
+    bucket_regions::BucketRegions,
+    concurrency_limit::ConcurrencyLimit,
+    download_links::DownloadLinks,
+    error::{Error, PartError},
+    jobs::{JobStore, Jobs, MemoryJobStore},
+    key_template::KeyTemplate,
+    listing_cache::ListingCache,
+    open_api::*,
+    policy::{Policy, Tenancy},
+    quotas::{MemoryQuotaStore, Quota, QuotaStore, Quotas},
+    request_timeout::with_request_timeout,
+    retry::RetryPolicy,
+    s3_configuration::{BucketConfiguration, S3Configuration, S3ConfigurationError, AWS_REGIONS},
+    scheduler::{ScheduledTask, Scheduler},
+    signer::{Cors, Signer, SignerBuilder},
+    tls::{tls_acceptor, tls_incoming},
+  };
 
   use serde::Serialize;
+  use std::{convert::Infallible, future::Future};
+  use tower_service::Service;
   use warp::{
+    body::BodyDeserializeError,
+    filters::BoxedFilter,
     hyper::{
       header::{ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE, LOCATION},
-      Body, Response, StatusCode,
+      Body, Request, Response, StatusCode,
     },
-    Filter, Rejection, Reply,
+    reject, Filter, Rejection, Reply,
   };
 
+  /// Routes of every API version, e.g. `/v1/limits`, the unversioned paths being negotiated
+  /// through the `Api-Version` header.
   pub fn routes(
     s3_configuration: &S3Configuration,
   ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    crate::multipart_upload::routes(s3_configuration).or(crate::objects::routes(s3_configuration))
+    crate::request_id::with_request_id(crate::version::route().or(
+      crate::api_version::server::versioned(ApiVersion::V1, v1_routes(s3_configuration)),
+    ))
+  }
+
+  fn v1_routes(s3_configuration: &S3Configuration) -> BoxedFilter<(Response<Body>,)> {
+    // Boxed to keep the type of the filter, and the stack of its futures, shallow.
+    let routes = crate::multipart_upload::routes(s3_configuration)
+      .or(crate::objects::routes(s3_configuration))
+      .or(crate::limits::server::route(s3_configuration))
+      .or(crate::media::server::route(s3_configuration))
+      .or(crate::media::server::dash_route(s3_configuration))
+      .or(crate::download_links::download_route(s3_configuration))
+      .or(crate::download_links::revoke_route(s3_configuration))
+      .or(crate::uppy::server::routes(s3_configuration))
+      .or(crate::events::server::route(s3_configuration))
+      .or(crate::jobs::routes(s3_configuration))
+      .map(Reply::into_response)
+      .boxed();
+    #[cfg(feature = "graphql")]
+    let routes = routes
+      .or(crate::graphql::route(s3_configuration))
+      .unify()
+      .boxed();
+    routes
+  }
+
+  /// Serves the [`routes`] as a `tower::Service`, e.g. to nest the signer in an axum or hyper
+  /// application instead of running a second server.
+  ///
+  /// Rejected requests are answered by [`handle_rejection`].
+  pub fn service(
+    s3_configuration: &S3Configuration,
+  ) -> impl Service<
+    Request<Body>,
+    Response = Response<Body>,
+    Error = Infallible,
+    Future = impl Future<Output = Result<Response<Body>, Infallible>> + Send,
+  > + Clone
+       + Send {
+    warp::service(recover_errors(routes(s3_configuration)))
+  }
+
+  /// Answers the requests rejected by the filter with an [`ErrorBody`], e.g. to serve the
+  /// [`routes`] on their own.
+  pub fn recover_errors<F, R>(
+    filter: F,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone
+  where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+  {
+    filter.recover(handle_rejection)
+  }
+
+  /// Answers the requests rejected by the [`routes`] with an [`ErrorBody`], and the status code
+  /// of their error.
+  pub async fn handle_rejection(rejection: Rejection) -> Result<Response<Body>, Infallible> {
+    if let Some(error) = rejection.find::<Error>() {
+      return Ok(error.to_response());
+    }
+
+    let (status_code, code, message) = if rejection.is_not_found() {
+      (StatusCode::NOT_FOUND, "NotFound", "not found".to_string())
+    } else if let Some(error) = rejection.find::<reject::InvalidQuery>() {
+      (
+        StatusCode::BAD_REQUEST,
+        "InvalidParameter",
+        error.to_string(),
+      )
+    } else if let Some(error) = rejection.find::<reject::MissingHeader>() {
+      (
+        StatusCode::BAD_REQUEST,
+        "InvalidParameter",
+        error.to_string(),
+      )
+    } else if let Some(error) = rejection.find::<reject::InvalidHeader>() {
+      (
+        StatusCode::BAD_REQUEST,
+        "InvalidParameter",
+        error.to_string(),
+      )
+    } else if let Some(error) = rejection.find::<BodyDeserializeError>() {
+      (
+        StatusCode::BAD_REQUEST,
+        "InvalidParameter",
+        error.to_string(),
+      )
+    } else if let Some(error) = rejection.find::<reject::LengthRequired>() {
+      (
+        StatusCode::LENGTH_REQUIRED,
+        "LengthRequired",
+        error.to_string(),
+      )
+    } else if let Some(error) = rejection.find::<reject::PayloadTooLarge>() {
+      (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "PayloadTooLarge",
+        error.to_string(),
+      )
+    } else if let Some(error) = rejection.find::<reject::UnsupportedMediaType>() {
+      (
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "UnsupportedMediaType",
+        error.to_string(),
+      )
+    } else if let Some(error) = rejection.find::<reject::MethodNotAllowed>() {
+      (
+        StatusCode::METHOD_NOT_ALLOWED,
+        "MethodNotAllowed",
+        error.to_string(),
+      )
+    } else {
+      log::error!("Unhandled rejection: {:?}", rejection);
+      (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "InternalError",
+        "Internal server error".to_string(),
+      )
+    };
+
+    if status_code != StatusCode::NOT_FOUND {
+      log::warn!("{}", message);
+    }
+    Ok(crate::error::error_response(
+      status_code,
+      code,
+      &message,
+      None,
+    ))
+  }
+
+  /// Matches the segments of a base path, e.g. `/storage/v1`, to mount the [`routes`] at.
+  pub fn base_path(base_path: &str) -> BoxedFilter<()> {
+    base_path
+      .split('/')
+      .filter(|segment| !segment.is_empty())
+      .fold(warp::any().boxed(), |filter, segment| {
+        filter.and(warp::path(segment.to_string())).boxed()
+      })
+  }
+
+  /// Liveness and readiness probes, to be served at the root of the server.
+  pub fn health_routes(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    crate::health::server::liveness_route()
+      .or(crate::health::server::readiness_route(s3_configuration))
+  }
+
+  /// Prometheus metrics, to be served at the root of the server.
+  pub fn metrics_route() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    crate::metrics::route()
+  }
+
+  /// Status of the scheduled tasks, to be served at the root of the server.
+  pub fn scheduled_tasks_route(
+    scheduler: &Scheduler,
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    crate::scheduler::route(scheduler, s3_configuration)
   }
 
   pub fn request_builder() -> warp::http::response::Builder {
@@ -35,25 +305,69 @@ mod server {
       .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
   }
 
-  pub(crate) fn to_ok_json_response<T>(body_response: &T) -> Result<Response<Body>, Rejection>
+  /// Answers the typed result of a handler as JSON, or rejects the request with its error: the
+  /// warp layer over the [`handlers`](crate::handlers).
+  pub(crate) fn json_reply<T>(result: Result<T, Error>) -> Result<Response<Body>, Rejection>
+  where
+    T: Serialize,
+  {
+    result
+      .and_then(|body_response| to_ok_json_response(&body_response))
+      .map_err(warp::reject::custom)
+  }
+
+  pub(crate) fn to_ok_json_response<T>(body_response: &T) -> Result<Response<Body>, Error>
   where
     T: Serialize + ?Sized,
   {
-    let json = serde_json::to_string(body_response)
-      .map_err(|error| warp::reject::custom(Error::JsonError(error)))?;
+    to_json_response(StatusCode::OK, body_response)
+  }
+
+  pub(crate) fn to_json_response<T>(
+    status_code: StatusCode,
+    body_response: &T,
+  ) -> Result<Response<Body>, Error>
+  where
+    T: Serialize + ?Sized,
+  {
+    let json = serde_json::to_string(body_response).map_err(Error::JsonError)?;
 
     request_builder()
       .header(CONTENT_TYPE, "application/json")
-      .status(StatusCode::OK)
+      .status(status_code)
       .body(json.into())
-      .map_err(|error| warp::reject::custom(Error::HttpError(error)))
+      .map_err(Error::HttpError)
+  }
+
+  /// Answers a pre-signed URL, along with the headers to send it with, to the clients accepting
+  /// JSON (`accept` being the `Accept` header of the request), or redirects them to it.
+  pub(crate) fn to_presigned_response(
+    presigned_url: &crate::objects::PresignedUrlResponse,
+    accept: Option<&str>,
+  ) -> Result<Response<Body>, Error> {
+    if accepts_json(accept) {
+      to_ok_json_response(presigned_url)
+    } else {
+      to_redirect_response(&presigned_url.url)
+    }
+  }
+
+  /// Returns whether the `accept` header of a request asks for JSON, HAL documents included.
+  pub(crate) fn accepts_json(accept: Option<&str>) -> bool {
+    accept.is_some_and(|accept| {
+      accept.split(',').any(|media_range| {
+        let media_type = media_range.split(';').next().unwrap_or_default().trim();
+        media_type.eq_ignore_ascii_case("application/json")
+          || media_type.eq_ignore_ascii_case(crate::hypermedia::HAL_CONTENT_TYPE)
+      })
+    })
   }
 
-  pub(crate) fn to_redirect_response(url: &str) -> Result<Response<Body>, Rejection> {
+  pub(crate) fn to_redirect_response(url: &str) -> Result<Response<Body>, Error> {
     request_builder()
       .header(LOCATION, url)
       .status(StatusCode::FOUND)
       .body(Body::empty())
-      .map_err(|error| warp::reject::custom(Error::HttpError(error)))
+      .map_err(Error::HttpError)
   }
 }
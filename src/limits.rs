@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Maximum number of parts of a multipart upload
+pub const MAX_PARTS: i64 = 10_000;
+/// Minimum size of a multipart upload part (except the last one), in bytes
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// Maximum size of a multipart upload part, in bytes
+pub const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+/// Maximum size of an object, in bytes
+pub const MAX_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024 * 1024;
+/// Maximum size of the JSON request bodies by default, in bytes
+pub const DEFAULT_MAX_JSON_BODY_SIZE: u64 = 1024 * 1024;
+
+/// Policies applied by the signer, unset values meaning no limit
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct LimitsResponse {
+  /// Lifetime of the pre-signed URLs, in seconds
+  pub presigned_url_expiration: u64,
+  /// Lifetimes of the share URLs by preset name, in seconds
+  pub share_presets: BTreeMap<String, u64>,
+  /// Maximum number of parts of a multipart upload
+  pub max_parts: i64,
+  /// Minimum size of a multipart upload part (except the last one), in bytes
+  pub min_part_size: u64,
+  /// Maximum size of a multipart upload part, in bytes
+  pub max_part_size: u64,
+  /// Maximum size of an object, in bytes
+  pub max_object_size: u64,
+  /// Maximum size of JSON request bodies, in bytes
+  pub max_body_size: Option<u64>,
+  /// Maximum number of requests per second
+  pub rate_limit: Option<u64>,
+  /// Buckets which can be accessed through the signer
+  pub allowed_buckets: Option<Vec<String>>,
+  /// Whether URLs are pre-signed against S3 Transfer Acceleration endpoints by default
+  pub transfer_acceleration: bool,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{authentication, json_reply, Claims, S3Configuration};
+  use std::sync::Arc;
+  use warp::{Filter, Rejection, Reply};
+
+  /// Server limits
+  #[utoipa::path(
+    get,
+    path = "/limits",
+    tag = "Server",
+    responses(
+      (
+        status = 200,
+        description = "Returns the policies applied by the signer",
+        content_type = "application/json",
+        body = LimitsResponse
+      ),
+    ),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("limits")
+      .and(warp::get())
+      .and(authentication::claims(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |_claims: Option<Claims>, s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(Ok(limits(&s3_configuration)))
+        },
+      )
+  }
+
+  /// Returns the policies applied by the signer.
+  pub fn limits(s3_configuration: &S3Configuration) -> LimitsResponse {
+    LimitsResponse {
+      presigned_url_expiration: s3_configuration.presigned_url_expiration().as_secs(),
+      share_presets: s3_configuration
+        .share_presets()
+        .iter()
+        .map(|(name, expiration)| (name.clone(), expiration.as_secs()))
+        .collect(),
+      max_parts: MAX_PARTS,
+      min_part_size: MIN_PART_SIZE,
+      max_part_size: MAX_PART_SIZE,
+      max_object_size: MAX_OBJECT_SIZE,
+      max_body_size: Some(s3_configuration.max_json_body_size()),
+      rate_limit: None,
+      allowed_buckets: s3_configuration.allowed_buckets().cloned(),
+      transfer_acceleration: s3_configuration.transfer_acceleration(),
+    }
+  }
+}
@@ -0,0 +1,131 @@
+use crate::{objects::ListObjectsResponse, S3Configuration};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+/// In-memory cache of the object listings, absorbing bursts of identical listings, e.g. when
+/// file browsers refresh.
+///
+/// Listings are cached by bucket and prefix for `ttl`, up to `max_entries` listings (the oldest
+/// one being evicted first). The writes the signer completes itself (proxied uploads, multipart
+/// completions, archives and deletions) drop the cached listings of their prefixes. Uploads through
+/// pre-signed PUT URLs go straight to S3, so the listings can miss them for up to `ttl`.
+#[derive(Clone, Debug)]
+pub struct ListingCache {
+  ttl: Duration,
+  max_entries: usize,
+  listings: Arc<Mutex<HashMap<ListingKey, CachedListing>>>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct ListingKey {
+  bucket: String,
+  prefix: String,
+}
+
+#[derive(Clone, Debug)]
+struct CachedListing {
+  objects: ListObjectsResponse,
+  cached_at: Instant,
+}
+
+impl ListingCache {
+  pub fn new(ttl: Duration, max_entries: usize) -> Self {
+    Self {
+      ttl,
+      max_entries,
+      listings: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+
+  /// Returns this cache, with its listings, unless `other` caches them for another time or up
+  /// to another number of listings.
+  pub(crate) fn reloaded(&self, other: ListingCache) -> ListingCache {
+    if other.ttl == self.ttl && other.max_entries == self.max_entries {
+      self.clone()
+    } else {
+      other
+    }
+  }
+
+  /// Drops the cached listings which may contain the object `key` of `bucket`.
+  ///
+  /// Called after the objects created through the signer; objects created or deleted by other
+  /// means only show up once the listings expire, unless the embedding server calls it too.
+  pub fn invalidate(&self, bucket: &str, key: &str) {
+    let mut listings = self
+      .listings
+      .lock()
+      .unwrap_or_else(|error| error.into_inner());
+    listings.retain(|listing_key, _| {
+      listing_key.bucket != bucket || !key.starts_with(&listing_key.prefix)
+    });
+  }
+
+  /// Returns the cached objects of the listing, with their remaining lifetime.
+  pub(crate) fn get(&self, bucket: &str, prefix: &str) -> Option<(ListObjectsResponse, Duration)> {
+    let listings = self
+      .listings
+      .lock()
+      .unwrap_or_else(|error| error.into_inner());
+    listings
+      .get(&ListingKey::new(bucket, prefix))
+      .and_then(|listing| {
+        self
+          .ttl
+          .checked_sub(listing.cached_at.elapsed())
+          .map(|remaining| (listing.objects.clone(), remaining))
+      })
+  }
+
+  pub(crate) fn insert(&self, bucket: &str, prefix: &str, objects: ListObjectsResponse) {
+    let mut listings = self
+      .listings
+      .lock()
+      .unwrap_or_else(|error| error.into_inner());
+    let ttl = self.ttl;
+    listings.retain(|_, listing| listing.cached_at.elapsed() < ttl);
+
+    if listings.len() >= self.max_entries {
+      let oldest = listings
+        .iter()
+        .min_by_key(|(_, listing)| listing.cached_at)
+        .map(|(listing_key, _)| listing_key.clone());
+      if let Some(oldest) = oldest {
+        listings.remove(&oldest);
+      }
+    }
+
+    if self.max_entries > 0 {
+      listings.insert(
+        ListingKey::new(bucket, prefix),
+        CachedListing {
+          objects,
+          cached_at: Instant::now(),
+        },
+      );
+    }
+  }
+
+  pub fn ttl(&self) -> Duration {
+    self.ttl
+  }
+}
+
+impl ListingKey {
+  fn new(bucket: &str, prefix: &str) -> Self {
+    Self {
+      bucket: bucket.to_string(),
+      prefix: prefix.to_string(),
+    }
+  }
+}
+
+/// Drops the cached listings which may contain an object created through the signer.
+pub(crate) fn invalidate(s3_configuration: &S3Configuration, bucket: &str, key: &str) {
+  if let Some(listing_cache) = s3_configuration.listing_cache() {
+    listing_cache.invalidate(bucket, key);
+  }
+}
@@ -0,0 +1,676 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HlsQueryParameters {
+  pub bucket: String,
+  pub path: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DashQueryParameters {
+  pub bucket: String,
+  pub path: String,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    metrics,
+    objects::{get::read_object, key, site::server::resolve_reference},
+    policy::{self, Identity, Operation},
+    presign::{self, PresignOptions},
+    query, request_builder, Error, S3Configuration,
+  };
+  use std::{borrow::Cow, sync::Arc};
+  use warp::{
+    hyper::{header::CONTENT_TYPE, Body, Response, StatusCode},
+    Filter, Rejection, Reply,
+  };
+  use xml::{
+    attribute::Attribute,
+    reader::{ParserConfig, XmlEvent as ReaderEvent},
+    writer::{EmitterConfig, EventWriter, XmlEvent as WriterEvent},
+  };
+
+  /// Size of the playlists and manifests rewritten at most, in bytes
+  const MAX_MANIFEST_SIZE: usize = 4 * 1024 * 1024;
+
+  const HLS_CONTENT_TYPE: &str = "application/vnd.apple.mpegurl";
+  const PLAYLIST_EXTENSIONS: &[&str] = &[".m3u8", ".m3u"];
+  const URI_ATTRIBUTE: &str = "URI=\"";
+
+  const DASH_CONTENT_TYPE: &str = "application/dash+xml";
+  const BASE_URL_ELEMENT: &str = "BaseURL";
+  /// Attributes of the MPD elements referencing objects
+  const DASH_URL_ATTRIBUTES: &[(&str, &[&str])] = &[
+    (
+      "SegmentTemplate",
+      &["media", "initialization", "index", "bitstreamSwitching"],
+    ),
+    ("SegmentURL", &["media", "index"]),
+    ("Initialization", &["sourceURL"]),
+    ("RepresentationIndex", &["sourceURL"]),
+    ("BitstreamSwitching", &["sourceURL"]),
+  ];
+
+  /// Pre-sign an HLS playlist
+  #[utoipa::path(
+    get,
+    path = "/media/hls",
+    tag = "Media",
+    responses(
+      (
+        status = 200,
+        description = "Returns the playlist, with its segments pre-signed and its variant playlists served by this endpoint",
+        content_type = "application/vnd.apple.mpegurl",
+        body = String
+      ),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the playlist, e.g. master.m3u8")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("media" / "hls")
+      .and(warp::get())
+      .and(query::parameters::<HlsQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: HlsQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          hls_playlist(&s3_configuration, &identity, parameters)
+            .await
+            .map_err(warp::reject::custom)
+        },
+      )
+  }
+
+  /// Pre-sign a DASH manifest
+  #[utoipa::path(
+    get,
+    path = "/media/dash",
+    tag = "Media",
+    responses(
+      (
+        status = 200,
+        description = "Returns the MPD, with its segments pre-signed and its segment templates resolved by the object route",
+        content_type = "application/dash+xml",
+        body = String
+      ),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the MPD, e.g. manifest.mpd")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn dash_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("media" / "dash")
+      .and(warp::get())
+      .and(query::parameters::<DashQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: DashQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          dash_manifest(&s3_configuration, &identity, parameters)
+            .await
+            .map_err(warp::reject::custom)
+        },
+      )
+  }
+
+  /// Rewrites an HLS playlist for it to be played from private storage, on behalf of `identity`.
+  ///
+  /// The media segments, keys and initialization sections are pre-signed, all with the same
+  /// expiration. Variant playlists point back at this endpoint (with a relative URL), for their
+  /// own segments to be pre-signed when the player fetches them.
+  pub async fn hls_playlist(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: HlsQueryParameters,
+  ) -> Result<Response<Body>, Error> {
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    handle_hls_playlist(
+      s3_configuration,
+      identity,
+      parameters.bucket,
+      parameters.path,
+    )
+    .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_hls_playlist(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    bucket: String,
+    path: String,
+  ) -> Result<Response<Body>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    log::info!("Pre-sign HLS playlist: bucket={}, path={}", bucket, path);
+
+    let playlist = get_manifest(&s3_configuration, &bucket, &path).await?;
+    let playlist = String::from_utf8(playlist)
+      .ok()
+      .filter(|playlist| playlist.trim_start().starts_with("#EXTM3U"))
+      .ok_or_else(|| Error::InvalidParameter(format!("{} is not an HLS playlist", path)))?;
+
+    let playlist_directory = directory_of(&path);
+
+    let presign_configuration = s3_configuration.presign_configuration(&bucket).await;
+    let presign_options = PresignOptions::from(&s3_configuration);
+    let rewrite_uri = |uri: &str| -> Result<String, Error> {
+      let key = match resolve_reference(&playlist_directory, uri) {
+        Some(key) => key,
+        // External URIs are left untouched
+        None => return Ok(uri.to_string()),
+      };
+      policy::authorize(&s3_configuration, identity, Operation::Read, &bucket, &key)?;
+
+      if is_playlist(&key) {
+        let query = serde_urlencoded::to_string(HlsQueryParameters {
+          bucket: bucket.clone(),
+          path: key,
+        })
+        .map_err(|error| Error::MediaError(error.to_string()))?;
+        return Ok(format!("hls?{}", query));
+      }
+
+      metrics::presigned_url("GetObject");
+      Ok(presign::get_object(
+        &presign_configuration,
+        &bucket,
+        &key,
+        &presign_options,
+      )?)
+    };
+
+    let playlist = rewrite_playlist(&playlist, rewrite_uri)?;
+
+    request_builder()
+      .header(CONTENT_TYPE, HLS_CONTENT_TYPE)
+      .status(StatusCode::OK)
+      .body(playlist.into())
+      .map_err(Error::HttpError)
+  }
+
+  /// Rewrites a DASH manifest for it to be played from private storage, on behalf of `identity`.
+  ///
+  /// The segments referenced by the MPD are pre-signed, all with the same expiration. Segment
+  /// templates (e.g. `segment-$Number$.m4s`) are completed by the player, so they point at the
+  /// object route instead (with a relative URL), which redirects to pre-signed URLs. Relative
+  /// directory `BaseURL`s are resolved into the references, then dropped.
+  pub async fn dash_manifest(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: DashQueryParameters,
+  ) -> Result<Response<Body>, Error> {
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    handle_dash_manifest(
+      s3_configuration,
+      identity,
+      parameters.bucket,
+      parameters.path,
+    )
+    .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_dash_manifest(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    bucket: String,
+    path: String,
+  ) -> Result<Response<Body>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    log::info!("Pre-sign DASH manifest: bucket={}, path={}", bucket, path);
+
+    let manifest = get_manifest(&s3_configuration, &bucket, &path).await?;
+
+    let presign_configuration = s3_configuration.presign_configuration(&bucket).await;
+    let presign_options = PresignOptions::from(&s3_configuration);
+    let rewrite_key = |key: &str| -> Result<String, Error> {
+      policy::authorize(&s3_configuration, identity, Operation::Read, &bucket, key)?;
+
+      if key.contains('$') {
+        return Ok(format!(
+          "../object?bucket={}&path={}",
+          encode_query_value(&bucket),
+          encode_template(key)
+        ));
+      }
+
+      metrics::presigned_url("GetObject");
+      Ok(presign::get_object(
+        &presign_configuration,
+        &bucket,
+        key,
+        &presign_options,
+      )?)
+    };
+
+    let manifest =
+      rewrite_mpd(&manifest, &directory_of(&path), rewrite_key).map_err(|error| match error {
+        Error::MediaError(error) => {
+          Error::InvalidParameter(format!("{} is not a DASH manifest: {}", path, error))
+        }
+        error => error,
+      })?;
+
+    request_builder()
+      .header(CONTENT_TYPE, DASH_CONTENT_TYPE)
+      .status(StatusCode::OK)
+      .body(manifest.into())
+      .map_err(Error::HttpError)
+  }
+
+  async fn get_manifest(
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    path: &str,
+  ) -> Result<Vec<u8>, Error> {
+    read_object(
+      s3_configuration,
+      bucket,
+      path,
+      MAX_MANIFEST_SIZE,
+      Error::MediaError,
+    )
+    .await
+  }
+
+  /// Rewrites the URI lines of a playlist, and the `URI` attributes of its tags (e.g. `EXT-X-KEY`,
+  /// `EXT-X-MAP` or `EXT-X-MEDIA`).
+  fn rewrite_playlist<F>(playlist: &str, rewrite_uri: F) -> Result<String, Error>
+  where
+    F: Fn(&str) -> Result<String, Error>,
+  {
+    let mut rewritten = String::with_capacity(playlist.len());
+
+    for line in playlist.lines() {
+      let trimmed_line = line.trim();
+
+      if trimmed_line.is_empty() {
+        rewritten.push_str(line);
+      } else if !trimmed_line.starts_with('#') {
+        rewritten.push_str(&rewrite_uri(trimmed_line)?);
+      } else if let Some(index) = line.find(URI_ATTRIBUTE) {
+        let value_start = index + URI_ATTRIBUTE.len();
+        match line[value_start..].find('"') {
+          Some(value_length) => {
+            let value_end = value_start + value_length;
+            rewritten.push_str(&line[..value_start]);
+            rewritten.push_str(&rewrite_uri(&line[value_start..value_end])?);
+            rewritten.push_str(&line[value_end..]);
+          }
+          None => rewritten.push_str(line),
+        }
+      } else {
+        rewritten.push_str(line);
+      }
+
+      rewritten.push('\n');
+    }
+
+    Ok(rewritten)
+  }
+
+  fn is_playlist(key: &str) -> bool {
+    let key = key.to_lowercase();
+    PLAYLIST_EXTENSIONS
+      .iter()
+      .any(|extension| key.ends_with(extension))
+  }
+
+  /// Directory the references of an MPD element are relative to, `None` when it is outside of
+  /// the bucket.
+  struct Scope {
+    directory: Option<String>,
+    has_base_url: bool,
+  }
+
+  /// Rewrites the references of an MPD with `rewrite_key`, given the key of the referenced object.
+  fn rewrite_mpd<F>(mpd: &[u8], mpd_directory: &str, rewrite_key: F) -> Result<Vec<u8>, Error>
+  where
+    F: Fn(&str) -> Result<String, Error>,
+  {
+    let reader = ParserConfig::new()
+      .trim_whitespace(false)
+      .whitespace_to_characters(true)
+      .ignore_comments(false)
+      .create_reader(mpd);
+    let mut writer = EmitterConfig::new()
+      .perform_indent(false)
+      .write_document_declaration(false)
+      .create_writer(Vec::new());
+
+    let mut scopes = vec![Scope {
+      directory: Some(mpd_directory.to_string()),
+      has_base_url: false,
+    }];
+    // Start and text of the BaseURL element being read
+    let mut base_url: Option<(ReaderEvent, String)> = None;
+
+    for event in reader {
+      let event = event.map_err(|error| Error::MediaError(error.to_string()))?;
+
+      match &event {
+        ReaderEvent::StartElement {
+          name,
+          attributes,
+          namespace,
+        } => {
+          let directory = scopes.last().and_then(|scope| scope.directory.clone());
+          scopes.push(Scope {
+            directory: directory.clone(),
+            has_base_url: false,
+          });
+
+          if name.local_name == BASE_URL_ELEMENT {
+            base_url = Some((event.clone(), String::new()));
+            continue;
+          }
+
+          let url_attributes = DASH_URL_ATTRIBUTES
+            .iter()
+            .find(|(element, _)| *element == name.local_name)
+            .map(|(_, url_attributes)| *url_attributes)
+            .unwrap_or_default();
+          let values = attributes
+            .iter()
+            .map(|attribute| match &directory {
+              Some(directory) if url_attributes.contains(&attribute.name.local_name.as_str()) => {
+                match resolve_reference(directory, &attribute.value) {
+                  Some(key) => rewrite_key(&key),
+                  None => Ok(attribute.value.clone()),
+                }
+              }
+              _ => Ok(attribute.value.clone()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+          let attributes = attributes
+            .iter()
+            .zip(&values)
+            .map(|(attribute, value)| Attribute::new(attribute.name.borrow(), value))
+            .collect::<Vec<_>>();
+
+          write_event(
+            &mut writer,
+            WriterEvent::StartElement {
+              name: name.borrow(),
+              attributes: Cow::Owned(attributes),
+              namespace: Cow::Borrowed(namespace),
+            },
+          )?;
+        }
+        ReaderEvent::Characters(text) if base_url.is_some() => {
+          if let Some((_, url)) = &mut base_url {
+            url.push_str(text);
+          }
+        }
+        ReaderEvent::EndElement { .. } => {
+          scopes.pop();
+
+          let (start, url) = match base_url.take() {
+            Some(base_url) => base_url,
+            None => {
+              write_event(&mut writer, WriterEvent::end_element())?;
+              continue;
+            }
+          };
+
+          let scope = scopes
+            .last_mut()
+            .ok_or_else(|| Error::MediaError("BaseURL outside of the MPD".to_string()))?;
+          let url = url.trim();
+          let rewritten_url = match (&scope.directory, scope.has_base_url) {
+            // Alternative locations are left untouched
+            (Some(directory), false) => match resolve_reference(directory, url) {
+              Some(key) if url.ends_with('/') => {
+                scope.directory = Some(if key.is_empty() {
+                  key
+                } else {
+                  format!("{}/", key)
+                });
+                None
+              }
+              Some(key) => {
+                scope.directory = Some(directory_of(&key));
+                Some(rewrite_key(&key)?)
+              }
+              None if url.is_empty() => Some(url.to_string()),
+              None => {
+                scope.directory = None;
+                Some(url.to_string())
+              }
+            },
+            _ => Some(url.to_string()),
+          };
+          scope.has_base_url = true;
+
+          if let Some(rewritten_url) = rewritten_url {
+            if let Some(start) = start.as_writer_event() {
+              write_event(&mut writer, start)?;
+            }
+            write_event(&mut writer, WriterEvent::characters(&rewritten_url))?;
+            write_event(&mut writer, WriterEvent::end_element())?;
+          }
+        }
+        _ => {
+          if let Some(event) = event.as_writer_event() {
+            write_event(&mut writer, event)?;
+          }
+        }
+      }
+    }
+
+    Ok(writer.into_inner())
+  }
+
+  fn write_event<'a, E>(writer: &mut EventWriter<Vec<u8>>, event: E) -> Result<(), Error>
+  where
+    E: Into<WriterEvent<'a>>,
+  {
+    writer
+      .write(event)
+      .map_err(|error| Error::MediaError(error.to_string()))
+  }
+
+  fn directory_of(path: &str) -> String {
+    path
+      .rfind('/')
+      .map(|index| path[..=index].to_string())
+      .unwrap_or_default()
+  }
+
+  /// Encodes a segment template in a query, leaving its `$...$` identifiers for the player to
+  /// substitute.
+  fn encode_template(template: &str) -> String {
+    template
+      .split('$')
+      .enumerate()
+      .map(|(index, part)| {
+        if index % 2 == 0 {
+          encode_query_value(part)
+        } else {
+          part.to_string()
+        }
+      })
+      .collect::<Vec<_>>()
+      .join("$")
+  }
+
+  fn encode_query_value(value: &str) -> String {
+    value
+      .bytes()
+      .map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+          (byte as char).to_string()
+        }
+        byte => format!("%{:02X}", byte),
+      })
+      .collect()
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    fn signed(directory: &str, reference: &str) -> String {
+      match resolve_reference(directory, reference) {
+        Some(key) => format!("signed/{}", key),
+        None => reference.to_string(),
+      }
+    }
+
+    #[test]
+    fn references_resolve_to_keys() {
+      assert_eq!(
+        resolve_reference("videos/", "seg-1.ts").as_deref(),
+        Some("videos/seg-1.ts")
+      );
+      assert_eq!(
+        resolve_reference("videos/hd/", "../audio/a.aac").as_deref(),
+        Some("videos/audio/a.aac")
+      );
+      assert_eq!(
+        resolve_reference("videos/hd/", "./seg.ts?v=1#t=2").as_deref(),
+        Some("videos/hd/seg.ts")
+      );
+      assert_eq!(
+        resolve_reference("videos/hd/", "/shared/intro.ts").as_deref(),
+        Some("shared/intro.ts")
+      );
+      assert_eq!(resolve_reference("videos/", "../../outside.ts"), None);
+      assert_eq!(
+        resolve_reference("videos/", "https://cdn.example.com/a.ts"),
+        None
+      );
+      assert_eq!(resolve_reference("videos/", "//cdn.example.com/a.ts"), None);
+      assert_eq!(resolve_reference("videos/", "#fragment"), None);
+      assert_eq!(resolve_reference("videos/", ""), None);
+    }
+
+    #[test]
+    fn playlist_uris_and_uri_attributes_are_rewritten() {
+      let playlist = "#EXTM3U\n\
+        #EXT-X-KEY:METHOD=AES-128,URI=\"keys/key.bin\",IV=0x1\n\
+        #EXT-X-MAP:URI=\"../init.mp4\"\n\
+        #EXTINF:4.0,\n\
+        seg-1.ts\n\
+        \n\
+        #EXTINF:4.0,\n\
+        /shared/seg-2.ts\n\
+        #EXTINF:4.0,\n\
+        https://cdn.example.com/seg-3.ts\n\
+        #EXT-X-ENDLIST\n";
+
+      let rewritten = rewrite_playlist(playlist, |uri| Ok(signed("videos/hd/", uri))).unwrap();
+      assert_eq!(
+        rewritten,
+        "#EXTM3U\n\
+        #EXT-X-KEY:METHOD=AES-128,URI=\"signed/videos/hd/keys/key.bin\",IV=0x1\n\
+        #EXT-X-MAP:URI=\"signed/videos/init.mp4\"\n\
+        #EXTINF:4.0,\n\
+        signed/videos/hd/seg-1.ts\n\
+        \n\
+        #EXTINF:4.0,\n\
+        signed/shared/seg-2.ts\n\
+        #EXTINF:4.0,\n\
+        https://cdn.example.com/seg-3.ts\n\
+        #EXT-X-ENDLIST\n"
+      );
+    }
+
+    #[test]
+    fn playlist_errors_are_returned() {
+      let result = rewrite_playlist("#EXTM3U\nseg.ts\n", |_| {
+        Err(Error::Forbidden("denied".to_string()))
+      });
+      assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
+    fn rewrite(mpd: &str) -> String {
+      let rewritten = rewrite_mpd(mpd.as_bytes(), "videos/", |key| Ok(format!("K:{}", key)));
+      String::from_utf8(rewritten.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn mpd_references_are_resolved_from_their_base_url() {
+      let rewritten = rewrite(
+        r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011"><Period><BaseURL>hd/</BaseURL><AdaptationSet><SegmentTemplate media="seg-$Number$.m4s" initialization="../init.mp4"/><Representation id="1"><SegmentList><SegmentURL media="/shared/seg.m4s"/></SegmentList></Representation></AdaptationSet></Period></MPD>"#,
+      );
+
+      assert!(
+        rewritten.contains(r#"media="K:videos/hd/seg-$Number$.m4s""#),
+        "{}",
+        rewritten
+      );
+      assert!(
+        rewritten.contains(r#"initialization="K:videos/init.mp4""#),
+        "{}",
+        rewritten
+      );
+      assert!(
+        rewritten.contains(r#"media="K:shared/seg.m4s""#),
+        "{}",
+        rewritten
+      );
+      // Directory BaseURLs are resolved into the references
+      assert!(!rewritten.contains("BaseURL"), "{}", rewritten);
+    }
+
+    #[test]
+    fn mpd_references_under_an_external_base_url_are_left_untouched() {
+      let rewritten = rewrite(
+        r#"<MPD><Period><BaseURL>https://cdn.example.com/</BaseURL><AdaptationSet><SegmentTemplate media="seg-$Number$.m4s"/></AdaptationSet></Period></MPD>"#,
+      );
+
+      assert!(
+        rewritten.contains("<BaseURL>https://cdn.example.com/</BaseURL>"),
+        "{}",
+        rewritten
+      );
+      assert!(
+        rewritten.contains(r#"media="seg-$Number$.m4s""#),
+        "{}",
+        rewritten
+      );
+    }
+
+    #[test]
+    fn invalid_mpds_are_rejected() {
+      let result = rewrite_mpd(b"<MPD><Period></MPD>", "", |key| Ok(key.to_string()));
+      assert!(matches!(result, Err(Error::MediaError(_))));
+    }
+  }
+}
@@ -0,0 +1,166 @@
+use crate::{open_api::matches_template, request_builder, Error};
+use prometheus::{
+  Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+use utoipa::openapi::OpenApi;
+use warp::{
+  hyper::{header::CONTENT_TYPE, Body, Response, StatusCode},
+  log::{Info, Log},
+  Filter, Rejection, Reply,
+};
+
+const UNKNOWN_ROUTE: &str = "unknown";
+
+struct Metrics {
+  registry: Registry,
+  requests: IntCounterVec,
+  request_duration: HistogramVec,
+  presigned_urls: IntCounterVec,
+  s3_errors: IntCounterVec,
+  s3_retries: IntCounterVec,
+  multipart_uploads_in_flight: IntGauge,
+}
+
+impl Metrics {
+  fn new() -> Result<Self, prometheus::Error> {
+    let registry = Registry::new_custom(Some("s3_signer".to_string()), None)?;
+
+    let requests = IntCounterVec::new(
+      Opts::new("http_requests_total", "Number of HTTP requests"),
+      &["route", "method", "status"],
+    )?;
+    let request_duration = HistogramVec::new(
+      HistogramOpts::new(
+        "http_request_duration_seconds",
+        "Duration of HTTP requests, in seconds",
+      ),
+      &["route", "method"],
+    )?;
+    let presigned_urls = IntCounterVec::new(
+      Opts::new("presigned_urls_total", "Number of pre-signed URLs issued"),
+      &["operation"],
+    )?;
+    let s3_errors = IntCounterVec::new(
+      Opts::new("s3_errors_total", "Number of failed S3 API calls"),
+      &["operation"],
+    )?;
+    let s3_retries = IntCounterVec::new(
+      Opts::new("s3_retries_total", "Number of retried S3 API calls"),
+      &["operation"],
+    )?;
+    let multipart_uploads_in_flight = IntGauge::new(
+      "multipart_uploads_in_flight",
+      "Number of multipart uploads created and not yet completed or aborted through the signer",
+    )?;
+
+    registry.register(Box::new(requests.clone()))?;
+    registry.register(Box::new(request_duration.clone()))?;
+    registry.register(Box::new(presigned_urls.clone()))?;
+    registry.register(Box::new(s3_errors.clone()))?;
+    registry.register(Box::new(s3_retries.clone()))?;
+    registry.register(Box::new(multipart_uploads_in_flight.clone()))?;
+
+    Ok(Self {
+      registry,
+      requests,
+      request_duration,
+      presigned_urls,
+      s3_errors,
+      s3_retries,
+      multipart_uploads_in_flight,
+    })
+  }
+}
+
+fn metrics() -> &'static Metrics {
+  static METRICS: OnceLock<Metrics> = OnceLock::new();
+  METRICS.get_or_init(|| Metrics::new().expect("invalid metrics definition"))
+}
+
+/// Records the count and duration of every request, by route of the OpenAPI document.
+///
+/// Routes are labelled with their documented path template, so that path parameters do not
+/// create a time series per value.
+pub fn instrument(open_api: &OpenApi) -> Log<impl Fn(Info<'_>) + Clone> {
+  let templates: Vec<String> = open_api.paths.paths.keys().cloned().collect();
+
+  warp::log::custom(move |info: Info<'_>| {
+    let route = templates
+      .iter()
+      .find(|template| matches_template(template, info.path()))
+      .map(String::as_str)
+      .unwrap_or(UNKNOWN_ROUTE);
+    let method = info.method().as_str();
+
+    metrics()
+      .requests
+      .with_label_values(&[route, method, info.status().as_str()])
+      .inc();
+    metrics()
+      .request_duration
+      .with_label_values(&[route, method])
+      .observe(info.elapsed().as_secs_f64());
+  })
+}
+
+/// Exposes the metrics in the Prometheus text format
+#[utoipa::path(
+  get,
+  path = "/metrics",
+  tag = "Health",
+  responses(
+    (status = 200, description = "Metrics in the Prometheus text format", content_type = "text/plain"),
+  ),
+)]
+pub(crate) fn route() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  warp::path!("metrics")
+    .and(warp::get())
+    .and_then(|| async { metrics_response().map_err(warp::reject::custom) })
+}
+
+/// Renders the metrics in the Prometheus text format.
+pub fn metrics_response() -> Result<Response<Body>, Error> {
+  let encoder = TextEncoder::new();
+  let mut buffer = vec![];
+  encoder
+    .encode(&metrics().registry.gather(), &mut buffer)
+    .map_err(|error| Error::MetricsError(error.to_string()))?;
+
+  request_builder()
+    .header(CONTENT_TYPE, encoder.format_type())
+    .status(StatusCode::OK)
+    .body(buffer.into())
+    .map_err(Error::HttpError)
+}
+
+pub(crate) fn presigned_url(operation: &str) {
+  metrics()
+    .presigned_urls
+    .with_label_values(&[operation])
+    .inc();
+}
+
+pub(crate) fn s3_error(operation: &str) {
+  metrics().s3_errors.with_label_values(&[operation]).inc();
+}
+
+pub(crate) fn s3_retry(operation: &str) {
+  metrics().s3_retries.with_label_values(&[operation]).inc();
+}
+
+/// Counts the error of an S3 API call, to be used with `Result::map_err`.
+pub(crate) fn count_s3_error<E>(operation: &'static str) -> impl FnOnce(E) -> E {
+  move |error| {
+    s3_error(operation);
+    error
+  }
+}
+
+pub(crate) fn multipart_upload_started() {
+  metrics().multipart_uploads_in_flight.inc();
+}
+
+pub(crate) fn multipart_upload_ended() {
+  metrics().multipart_uploads_in_flight.dec();
+}
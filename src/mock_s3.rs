@@ -0,0 +1,885 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use md5::{Digest, Md5};
+use percent_encoding::percent_decode_str;
+use std::{
+  collections::{BTreeMap, HashMap},
+  future::Future,
+  net::SocketAddr,
+  ops::Bound,
+  sync::{Arc, Mutex},
+};
+use warp::{
+  hyper::{
+    body::Bytes,
+    header::HeaderValue,
+    header::{
+      ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+      ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_REQUEST_HEADERS, CONTENT_DISPOSITION,
+      CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED, RANGE,
+    },
+    http::response::Builder,
+    Body, HeaderMap, Method, Response, StatusCode,
+  },
+  path::FullPath,
+  Filter, Rejection,
+};
+use xml::reader::{EventReader, XmlEvent};
+
+const DEFAULT_CONTENT_TYPE: &str = "binary/octet-stream";
+const DEFAULT_MAX_KEYS: usize = 1000;
+const S3_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+const XML_DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>";
+
+/// In-memory S3 storage, to run the signer without any bucket, e.g. for frontend development or
+/// tests.
+///
+/// It answers the S3 calls of the signer and the pre-signed URLs it issues, with path-style
+/// requests: objects, multipart uploads, listings, tags and Object Lock settings are kept in
+/// memory. Signatures are not checked, and buckets exist as soon as they are used.
+#[derive(Clone, Debug, Default)]
+pub struct MockS3 {
+  storage: Arc<Mutex<Storage>>,
+}
+
+#[derive(Debug, Default)]
+struct Storage {
+  buckets: BTreeMap<String, BTreeMap<String, MockObject>>,
+  uploads: HashMap<String, MultipartUpload>,
+  next_upload_id: u64,
+  /// Lifecycle configurations of the buckets, as their XML document without declaration
+  lifecycles: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+struct MockObject {
+  body: Bytes,
+  content_type: String,
+  etag: String,
+  /// `x-amz-checksum-sha256` header of the upload
+  checksum_sha256: Option<String>,
+  /// Sizes of the parts of an object uploaded in several parts
+  part_sizes: Vec<usize>,
+  last_modified: DateTime<Utc>,
+  tags: Vec<(String, String)>,
+  retention: Option<(String, String)>,
+  legal_hold: Option<String>,
+}
+
+#[derive(Debug)]
+struct MultipartUpload {
+  bucket: String,
+  key: String,
+  content_type: String,
+  parts: BTreeMap<i64, (Bytes, String, DateTime<Utc>)>,
+  initiated: DateTime<Utc>,
+}
+
+/// S3 error, answered as an XML `Error` document.
+struct MockError(StatusCode, &'static str, String);
+
+type MockResult = Result<Response<Body>, MockError>;
+
+impl MockObject {
+  fn new(body: Bytes, content_type: String) -> Self {
+    Self {
+      etag: etag(&body),
+      checksum_sha256: None,
+      part_sizes: vec![],
+      body,
+      content_type,
+      last_modified: Utc::now(),
+      tags: vec![],
+      retention: None,
+      legal_hold: None,
+    }
+  }
+}
+
+impl MockS3 {
+  /// Stores an object, e.g. to seed the storage of a test.
+  pub fn insert(&self, bucket: &str, key: &str, body: impl Into<Bytes>, content_type: &str) {
+    self
+      .storage
+      .lock()
+      .unwrap()
+      .buckets
+      .entry(bucket.to_string())
+      .or_default()
+      .insert(
+        key.to_string(),
+        MockObject::new(body.into(), content_type.to_string()),
+      );
+  }
+
+  /// Body of a stored object.
+  pub fn get(&self, bucket: &str, key: &str) -> Option<Bytes> {
+    self
+      .storage
+      .lock()
+      .unwrap()
+      .buckets
+      .get(bucket)
+      .and_then(|objects| objects.get(key))
+      .map(|object| object.body.clone())
+  }
+
+  /// Routes answering the S3 requests, and the CORS preflight requests of browsers using
+  /// pre-signed URLs.
+  pub fn routes(&self) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let mock_s3 = self.clone();
+    warp::method()
+      .and(warp::path::full())
+      .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+      .and(warp::header::headers_cloned())
+      .and(warp::body::bytes())
+      .map(
+        move |method: Method, path: FullPath, query: String, headers: HeaderMap, body: Bytes| {
+          let mut response = mock_s3
+            .handle(&method, path.as_str(), &query, &headers, body)
+            .unwrap_or_else(error_response);
+
+          let cors_headers = response.headers_mut();
+          cors_headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+          cors_headers.insert(
+            ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static("ETag"),
+          );
+          response
+        },
+      )
+  }
+
+  /// Serves the storage at this address (the port being picked by the system when 0), returning
+  /// its bound address and the future running the server.
+  pub fn serve(
+    &self,
+    address: impl Into<SocketAddr>,
+  ) -> Result<(SocketAddr, impl Future<Output = ()>), warp::Error> {
+    warp::serve(self.routes()).try_bind_ephemeral(address)
+  }
+
+  fn handle(
+    &self,
+    method: &Method,
+    path: &str,
+    query: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+  ) -> MockResult {
+    let query: HashMap<String, String> = serde_urlencoded::from_str(query).unwrap_or_default();
+    let path = percent_decode_str(path.trim_start_matches('/')).decode_utf8_lossy();
+    let (bucket, key) = path.split_once('/').unwrap_or((&path, ""));
+    let has = |parameter: &str| query.contains_key(parameter);
+    let mut storage = self.storage.lock().unwrap();
+
+    if method == Method::OPTIONS {
+      let allowed_headers = headers
+        .get(ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|allowed_headers| allowed_headers.to_str().ok())
+        .unwrap_or("*");
+      return Response::builder()
+        .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD, PUT, POST, DELETE")
+        .header(ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers)
+        .body(Body::empty())
+        .map_err(internal_error);
+    }
+
+    if bucket.is_empty() {
+      return Err(MockError(
+        StatusCode::NOT_IMPLEMENTED,
+        "NotImplemented",
+        "Listing buckets is not supported".to_string(),
+      ));
+    }
+
+    if key.is_empty() {
+      return match *method {
+        Method::GET if has("location") => xml_response(
+          Response::builder(),
+          &format!("<LocationConstraint xmlns=\"{}\"/>", S3_NAMESPACE),
+        ),
+        Method::GET if has("accelerate") => xml_response(
+          Response::builder(),
+          &format!("<AccelerateConfiguration xmlns=\"{}\"/>", S3_NAMESPACE),
+        ),
+        Method::GET if has("lifecycle") => match storage.lifecycles.get(bucket) {
+          Some(configuration) => xml_response(Response::builder(), configuration),
+          None => Err(MockError(
+            StatusCode::NOT_FOUND,
+            "NoSuchLifecycleConfiguration",
+            "The lifecycle configuration does not exist".to_string(),
+          )),
+        },
+        Method::GET if has("uploads") => storage.list_uploads(bucket, &query),
+        Method::GET => storage.list_objects(bucket, &query),
+        Method::PUT if has("lifecycle") => {
+          let configuration = String::from_utf8_lossy(&body);
+          let configuration = configuration
+            .trim_start()
+            .strip_prefix("<?xml")
+            .and_then(|declared| declared.split_once("?>"))
+            .map_or(configuration.as_ref(), |(_declaration, xml)| xml);
+          storage
+            .lifecycles
+            .insert(bucket.to_string(), configuration.to_string());
+          Ok(Response::new(Body::empty()))
+        }
+        Method::HEAD | Method::PUT => {
+          storage.buckets.entry(bucket.to_string()).or_default();
+          Ok(Response::new(Body::empty()))
+        }
+        _ => Err(not_implemented(method, &path)),
+      };
+    }
+
+    match *method {
+      Method::GET if has("uploadId") => storage.list_parts(&query["uploadId"]),
+      Method::GET if has("attributes") => xml_response(
+        Response::builder(),
+        &object_attributes(storage.object(bucket, key)?),
+      ),
+      Method::GET if has("tagging") => {
+        let tags = storage
+          .object(bucket, key)?
+          .tags
+          .iter()
+          .map(|(key, value)| {
+            format!(
+              "<Tag><Key>{}</Key><Value>{}</Value></Tag>",
+              escape(key),
+              escape(value)
+            )
+          })
+          .collect::<String>();
+        xml_response(
+          Response::builder(),
+          &format!("<Tagging><TagSet>{}</TagSet></Tagging>", tags),
+        )
+      }
+      Method::GET if has("retention") => match &storage.object(bucket, key)?.retention {
+        Some((mode, retain_until_date)) => xml_response(
+          Response::builder(),
+          &format!(
+            "<Retention><Mode>{}</Mode><RetainUntilDate>{}</RetainUntilDate></Retention>",
+            escape(mode),
+            escape(retain_until_date)
+          ),
+        ),
+        None => Err(no_object_lock_configuration()),
+      },
+      Method::GET if has("legal-hold") => match &storage.object(bucket, key)?.legal_hold {
+        Some(status) => xml_response(
+          Response::builder(),
+          &format!("<LegalHold><Status>{}</Status></LegalHold>", escape(status)),
+        ),
+        None => Err(no_object_lock_configuration()),
+      },
+      Method::GET | Method::HEAD => {
+        let object = storage.object(bucket, key)?;
+        object_response(object, method, headers, &query)
+      }
+      Method::PUT if has("partNumber") && has("uploadId") => {
+        let part_number = query["partNumber"]
+          .parse()
+          .map_err(|_| invalid_argument("partNumber"))?;
+        let upload = storage.upload(&query["uploadId"])?;
+        let etag = etag(&body);
+        upload
+          .parts
+          .insert(part_number, (body, etag.clone(), Utc::now()));
+        Response::builder()
+          .header(ETAG, etag)
+          .body(Body::empty())
+          .map_err(internal_error)
+      }
+      Method::PUT if has("tagging") => {
+        let elements = elements(&body)?;
+        let tags = elements
+          .iter()
+          .filter(|(name, _)| name == "Key")
+          .map(|(_, key)| key.clone())
+          .zip(
+            elements
+              .iter()
+              .filter(|(name, _)| name == "Value")
+              .map(|(_, value)| value.clone()),
+          )
+          .collect();
+        storage.object_mut(bucket, key)?.tags = tags;
+        Ok(Response::new(Body::empty()))
+      }
+      Method::PUT if has("retention") => {
+        let elements = elements(&body)?;
+        let value = |name: &str| {
+          elements
+            .iter()
+            .find(|(element, _)| element == name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| invalid_argument(name))
+        };
+        let retention = (value("Mode")?, value("RetainUntilDate")?);
+        storage.object_mut(bucket, key)?.retention = Some(retention);
+        Ok(Response::new(Body::empty()))
+      }
+      Method::PUT if has("legal-hold") => {
+        let status = elements(&body)?
+          .into_iter()
+          .find(|(name, _)| name == "Status")
+          .map(|(_, status)| status)
+          .ok_or_else(|| invalid_argument("Status"))?;
+        storage.object_mut(bucket, key)?.legal_hold = Some(status);
+        Ok(Response::new(Body::empty()))
+      }
+      Method::PUT => {
+        let mut object = MockObject::new(body, content_type(headers));
+        object.checksum_sha256 = headers
+          .get("x-amz-checksum-sha256")
+          .and_then(|checksum| checksum.to_str().ok())
+          .map(str::to_string);
+        let etag = object.etag.clone();
+        storage
+          .buckets
+          .entry(bucket.to_string())
+          .or_default()
+          .insert(key.to_string(), object);
+        Response::builder()
+          .header(ETAG, etag)
+          .body(Body::empty())
+          .map_err(internal_error)
+      }
+      Method::POST if has("uploads") => {
+        storage.next_upload_id += 1;
+        let upload_id = format!("mock-upload-{}", storage.next_upload_id);
+        storage.uploads.insert(
+          upload_id.clone(),
+          MultipartUpload {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            content_type: content_type(headers),
+            parts: BTreeMap::new(),
+            initiated: Utc::now(),
+          },
+        );
+        xml_response(
+          Response::builder(),
+          &format!(
+            "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+            escape(bucket),
+            escape(key),
+            upload_id
+          ),
+        )
+      }
+      Method::POST if has("uploadId") => {
+        storage.complete_multipart_upload(&query["uploadId"], &body, headers)
+      }
+      Method::DELETE if has("uploadId") => {
+        storage
+          .uploads
+          .remove(&query["uploadId"])
+          .ok_or_else(no_such_upload)?;
+        empty_response(StatusCode::NO_CONTENT)
+      }
+      Method::DELETE if has("tagging") => {
+        storage.object_mut(bucket, key)?.tags.clear();
+        empty_response(StatusCode::NO_CONTENT)
+      }
+      Method::DELETE => {
+        if let Some(objects) = storage.buckets.get_mut(bucket) {
+          objects.remove(key);
+        }
+        empty_response(StatusCode::NO_CONTENT)
+      }
+      _ => Err(not_implemented(method, &path)),
+    }
+  }
+}
+
+impl Storage {
+  fn object(&self, bucket: &str, key: &str) -> Result<&MockObject, MockError> {
+    self
+      .buckets
+      .get(bucket)
+      .and_then(|objects| objects.get(key))
+      .ok_or_else(|| no_such_key(key))
+  }
+
+  fn object_mut(&mut self, bucket: &str, key: &str) -> Result<&mut MockObject, MockError> {
+    self
+      .buckets
+      .get_mut(bucket)
+      .and_then(|objects| objects.get_mut(key))
+      .ok_or_else(|| no_such_key(key))
+  }
+
+  fn upload(&mut self, upload_id: &str) -> Result<&mut MultipartUpload, MockError> {
+    self.uploads.get_mut(upload_id).ok_or_else(no_such_upload)
+  }
+
+  /// Lists the objects of the bucket like ListObjectsV2, the continuation token being the last
+  /// listed key or common prefix.
+  fn list_objects(&self, bucket: &str, query: &HashMap<String, String>) -> MockResult {
+    let empty = BTreeMap::new();
+    let objects = self.buckets.get(bucket).unwrap_or(&empty);
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+    let delimiter = query
+      .get("delimiter")
+      .map(String::as_str)
+      .filter(|delimiter| !delimiter.is_empty());
+    let max_keys = query
+      .get("max-keys")
+      .and_then(|max_keys| max_keys.parse().ok())
+      .unwrap_or(DEFAULT_MAX_KEYS);
+    let after = query
+      .get("continuation-token")
+      .or_else(|| query.get("start-after"))
+      .map(String::as_str)
+      .unwrap_or_default();
+
+    // Keys or common prefixes, the latter without objects, following the continuation token
+    let mut entries: Vec<(&str, Option<&MockObject>)> = vec![];
+    let is_common_prefix = |token: &str| {
+      delimiter.is_some_and(|delimiter| token.len() > prefix.len() && token.ends_with(delimiter))
+    };
+    for (key, object) in objects.range::<str, _>((Bound::Excluded(after), Bound::Unbounded)) {
+      if !key.starts_with(prefix) || (is_common_prefix(after) && key.starts_with(after)) {
+        continue;
+      }
+
+      let common_prefix = delimiter.and_then(|delimiter| {
+        key[prefix.len()..]
+          .find(delimiter)
+          .map(|index| &key[..prefix.len() + index + delimiter.len()])
+      });
+      let entry = match common_prefix {
+        Some(common_prefix) => (common_prefix, None),
+        None => (key.as_str(), Some(object)),
+      };
+      if entry.1.is_none() && entries.last().map(|(name, _)| *name) == Some(entry.0) {
+        continue;
+      }
+
+      entries.push(entry);
+      if entries.len() > max_keys {
+        break;
+      }
+    }
+
+    let is_truncated = entries.len() > max_keys;
+    entries.truncate(max_keys);
+    let next_continuation_token = match entries.last().filter(|_| is_truncated) {
+      Some((token, _)) => format!(
+        "<NextContinuationToken>{}</NextContinuationToken>",
+        escape(token)
+      ),
+      None => String::new(),
+    };
+    let contents = entries
+      .iter()
+      .filter_map(|(key, object)| object.map(|object| (key, object)))
+      .map(|(key, object)| {
+        format!(
+          "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+          escape(key),
+          object.last_modified.to_rfc3339_opts(SecondsFormat::Millis, true),
+          escape(&object.etag),
+          object.body.len()
+        )
+      })
+      .collect::<String>();
+    let common_prefixes = entries
+      .iter()
+      .filter(|(_, object)| object.is_none())
+      .map(|(common_prefix, _)| {
+        format!(
+          "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+          escape(common_prefix)
+        )
+      })
+      .collect::<String>();
+
+    xml_response(
+      Response::builder(),
+      &format!(
+        "<ListBucketResult xmlns=\"{}\"><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>{}{}{}</ListBucketResult>",
+        S3_NAMESPACE,
+        escape(bucket),
+        escape(prefix),
+        entries.len(),
+        max_keys,
+        is_truncated,
+        next_continuation_token,
+        contents,
+        common_prefixes
+      ),
+    )
+  }
+
+  fn list_parts(&mut self, upload_id: &str) -> MockResult {
+    let upload = self.upload(upload_id)?;
+    let parts = upload
+      .parts
+      .iter()
+      .map(|(part_number, (body, etag, last_modified))| {
+        format!(
+          "<Part><PartNumber>{}</PartNumber><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size></Part>",
+          part_number,
+          last_modified.to_rfc3339_opts(SecondsFormat::Millis, true),
+          escape(etag),
+          body.len()
+        )
+      })
+      .collect::<String>();
+
+    xml_response(
+      Response::builder(),
+      &format!(
+        "<ListPartsResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId><IsTruncated>false</IsTruncated>{}</ListPartsResult>",
+        escape(&upload.bucket),
+        escape(&upload.key),
+        escape(upload_id),
+        parts
+      ),
+    )
+  }
+
+  /// Lists the uploads in progress of the bucket like ListMultipartUploads, at once.
+  fn list_uploads(&self, bucket: &str, query: &HashMap<String, String>) -> MockResult {
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+    let mut uploads = self
+      .uploads
+      .iter()
+      .filter(|(_, upload)| upload.bucket == bucket && upload.key.starts_with(prefix))
+      .collect::<Vec<_>>();
+    uploads
+      .sort_by(|(id, upload), (other_id, other)| (&upload.key, id).cmp(&(&other.key, other_id)));
+    let uploads = uploads
+      .into_iter()
+      .map(|(upload_id, upload)| {
+        format!(
+          "<Upload><Key>{}</Key><UploadId>{}</UploadId><Initiated>{}</Initiated></Upload>",
+          escape(&upload.key),
+          escape(upload_id),
+          upload
+            .initiated
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+        )
+      })
+      .collect::<String>();
+
+    xml_response(
+      Response::builder(),
+      &format!(
+        "<ListMultipartUploadsResult><Bucket>{}</Bucket><Prefix>{}</Prefix><IsTruncated>false</IsTruncated>{}</ListMultipartUploadsResult>",
+        escape(bucket),
+        escape(prefix),
+        uploads
+      ),
+    )
+  }
+
+  /// Concatenates the listed parts, in their order, into the object of the upload.
+  fn complete_multipart_upload(
+    &mut self,
+    upload_id: &str,
+    body: &[u8],
+    headers: &HeaderMap,
+  ) -> MockResult {
+    let upload = self.upload(upload_id)?;
+    let part_numbers = elements(body)?
+      .into_iter()
+      .filter(|(name, _)| name == "PartNumber")
+      .map(|(_, part_number)| part_number.parse::<i64>())
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|_| invalid_argument("PartNumber"))?;
+
+    let mut content = Vec::new();
+    let mut part_digests = Md5::new();
+    let mut part_sizes = vec![];
+    for part_number in &part_numbers {
+      let (part, _, _) = upload.parts.get(part_number).ok_or_else(|| {
+        MockError(
+          StatusCode::BAD_REQUEST,
+          "InvalidPart",
+          format!("Part {} was not uploaded", part_number),
+        )
+      })?;
+      content.extend_from_slice(part);
+      part_digests.update(Md5::digest(part));
+      part_sizes.push(part.len());
+    }
+
+    let upload = self.uploads.remove(upload_id).unwrap();
+    let mut object = MockObject::new(content.into(), upload.content_type);
+    // MD5 digest of the MD5 digests of the parts, as S3 computes it
+    object.etag = format!("\"{:x}-{}\"", part_digests.finalize(), part_numbers.len());
+    object.part_sizes = part_sizes;
+    let etag = object.etag.clone();
+    self
+      .buckets
+      .entry(upload.bucket.clone())
+      .or_default()
+      .insert(upload.key.clone(), object);
+
+    let host = headers
+      .get("host")
+      .and_then(|host| host.to_str().ok())
+      .unwrap_or("localhost");
+    xml_response(
+      Response::builder(),
+      &format!(
+        "<CompleteMultipartUploadResult><Location>http://{}/{}/{}</Location><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+        escape(host),
+        escape(&upload.bucket),
+        escape(&upload.key),
+        escape(&upload.bucket),
+        escape(&upload.key),
+        escape(&etag)
+      ),
+    )
+  }
+}
+
+/// Answers the object, or the requested `bytes=` range of it, with the response headers
+/// overridden by the `response-*` parameters of pre-signed URLs.
+fn object_response(
+  object: &MockObject,
+  method: &Method,
+  headers: &HeaderMap,
+  query: &HashMap<String, String>,
+) -> MockResult {
+  let length = object.body.len();
+  let range = headers
+    .get(RANGE)
+    .and_then(|range| range.to_str().ok())
+    .and_then(|range| range.strip_prefix("bytes="))
+    .map(|range| {
+      let (start, end) = range.split_once('-').unwrap_or((range, ""));
+      let (start, end) = match (start.parse::<usize>(), end.parse::<usize>()) {
+        (Ok(start), Ok(end)) => (start, end.min(length.saturating_sub(1))),
+        (Ok(start), Err(_)) => (start, length.saturating_sub(1)),
+        (Err(_), Ok(suffix)) => (length.saturating_sub(suffix), length.saturating_sub(1)),
+        (Err(_), Err(_)) => return Err(invalid_range(length)),
+      };
+      if start > end || start >= length {
+        return Err(invalid_range(length));
+      }
+      Ok((start, end))
+    })
+    .transpose()?;
+
+  let content_type = query
+    .get("response-content-type")
+    .unwrap_or(&object.content_type);
+  let mut response = Response::builder()
+    .header(CONTENT_TYPE, content_type.as_str())
+    .header(ETAG, object.etag.as_str())
+    .header(
+      LAST_MODIFIED,
+      object
+        .last_modified
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string(),
+    )
+    .header("accept-ranges", "bytes");
+  if let Some(checksum_sha256) = &object.checksum_sha256 {
+    let checksum_mode = headers
+      .get("x-amz-checksum-mode")
+      .and_then(|mode| mode.to_str().ok());
+    if checksum_mode == Some("ENABLED") {
+      response = response.header("x-amz-checksum-sha256", checksum_sha256.as_str());
+    }
+  }
+  if let Some(content_disposition) = query.get("response-content-disposition") {
+    response = response.header(CONTENT_DISPOSITION, content_disposition.as_str());
+  }
+
+  let body = match range {
+    Some((start, end)) => {
+      response = response
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, length));
+      object.body.slice(start..=end)
+    }
+    None => object.body.clone(),
+  };
+
+  let response = response.header(CONTENT_LENGTH, body.len());
+  if method == Method::HEAD {
+    response.body(Body::empty())
+  } else {
+    response.body(Body::from(body))
+  }
+  .map_err(internal_error)
+}
+
+/// `GetObjectAttributesResponse` document of an object, with all its attributes.
+fn object_attributes(object: &MockObject) -> String {
+  let checksum = object
+    .checksum_sha256
+    .as_ref()
+    .map(|checksum| {
+      format!(
+        "<Checksum><ChecksumSHA256>{}</ChecksumSHA256></Checksum>",
+        escape(checksum)
+      )
+    })
+    .unwrap_or_default();
+  let parts = if object.part_sizes.is_empty() {
+    String::new()
+  } else {
+    let parts = object
+      .part_sizes
+      .iter()
+      .enumerate()
+      .map(|(index, size)| {
+        format!(
+          "<Part><PartNumber>{}</PartNumber><Size>{}</Size></Part>",
+          index + 1,
+          size
+        )
+      })
+      .collect::<String>();
+    format!(
+      "<ObjectParts><TotalPartsCount>{0}</TotalPartsCount><PartNumberMarker>0</PartNumberMarker><NextPartNumberMarker>{0}</NextPartNumberMarker><MaxParts>1000</MaxParts><IsTruncated>false</IsTruncated>{1}</ObjectParts>",
+      object.part_sizes.len(),
+      parts
+    )
+  };
+  format!(
+    "<GetObjectAttributesResponse><ETag>{}</ETag>{}{}<StorageClass>STANDARD</StorageClass><ObjectSize>{}</ObjectSize></GetObjectAttributesResponse>",
+    escape(object.etag.trim_matches('"')),
+    checksum,
+    parts,
+    object.body.len()
+  )
+}
+
+/// Leaf elements of an XML request body, as their local name and text, in document order.
+fn elements(body: &[u8]) -> Result<Vec<(String, String)>, MockError> {
+  let mut elements = vec![];
+  let mut current = None;
+  for event in EventReader::new(body) {
+    match event
+      .map_err(|error| MockError(StatusCode::BAD_REQUEST, "MalformedXML", error.to_string()))?
+    {
+      XmlEvent::StartElement { name, .. } => current = Some((name.local_name, String::new())),
+      XmlEvent::Characters(text) => {
+        if let Some((_, content)) = &mut current {
+          content.push_str(&text);
+        }
+      }
+      XmlEvent::EndElement { .. } => elements.extend(current.take()),
+      _ => {}
+    }
+  }
+  Ok(elements)
+}
+
+fn content_type(headers: &HeaderMap) -> String {
+  headers
+    .get(CONTENT_TYPE)
+    .and_then(|content_type| content_type.to_str().ok())
+    .unwrap_or(DEFAULT_CONTENT_TYPE)
+    .to_string()
+}
+
+fn etag(body: &[u8]) -> String {
+  format!("\"{:x}\"", Md5::digest(body))
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
+
+fn xml_response(builder: Builder, xml: &str) -> MockResult {
+  builder
+    .header(CONTENT_TYPE, "application/xml")
+    .body(Body::from(format!("{}{}", XML_DECLARATION, xml)))
+    .map_err(internal_error)
+}
+
+fn error_response(MockError(status_code, code, message): MockError) -> Response<Body> {
+  let mut response = Response::new(Body::from(format!(
+    "{}<Error><Code>{}</Code><Message>{}</Message></Error>",
+    XML_DECLARATION,
+    code,
+    escape(&message)
+  )));
+  *response.status_mut() = status_code;
+  response
+    .headers_mut()
+    .insert(CONTENT_TYPE, HeaderValue::from_static("application/xml"));
+  response
+}
+
+fn empty_response(status_code: StatusCode) -> MockResult {
+  Response::builder()
+    .status(status_code)
+    .body(Body::empty())
+    .map_err(internal_error)
+}
+
+fn no_such_key(key: &str) -> MockError {
+  MockError(
+    StatusCode::NOT_FOUND,
+    "NoSuchKey",
+    format!("The specified key does not exist: {}", key),
+  )
+}
+
+fn no_such_upload() -> MockError {
+  MockError(
+    StatusCode::NOT_FOUND,
+    "NoSuchUpload",
+    "The specified multipart upload does not exist".to_string(),
+  )
+}
+
+fn no_object_lock_configuration() -> MockError {
+  MockError(
+    StatusCode::NOT_FOUND,
+    "NoSuchObjectLockConfiguration",
+    "The specified object does not have an Object Lock configuration".to_string(),
+  )
+}
+
+fn invalid_argument(name: &str) -> MockError {
+  MockError(
+    StatusCode::BAD_REQUEST,
+    "InvalidArgument",
+    format!("Invalid or missing {}", name),
+  )
+}
+
+fn invalid_range(length: usize) -> MockError {
+  MockError(
+    StatusCode::RANGE_NOT_SATISFIABLE,
+    "InvalidRange",
+    format!(
+      "The requested range is not satisfiable, the object has {} bytes",
+      length
+    ),
+  )
+}
+
+fn not_implemented(method: &Method, path: &str) -> MockError {
+  MockError(
+    StatusCode::NOT_IMPLEMENTED,
+    "NotImplemented",
+    format!("{} /{} is not supported by the mock storage", method, path),
+  )
+}
+
+fn internal_error(error: warp::http::Error) -> MockError {
+  MockError(
+    StatusCode::INTERNAL_SERVER_ERROR,
+    "InternalError",
+    error.to_string(),
+  )
+}
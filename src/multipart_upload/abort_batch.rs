@@ -0,0 +1,341 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct AbortUploadsBody {
+  pub uploads: Vec<AbortUploadItem>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct AbortUploadItem {
+  pub bucket: String,
+  pub path: String,
+  pub upload_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AbortKeyUploadsQueryParameters {
+  pub bucket: String,
+  pub path: String,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::{AbortKeyUploadsQueryParameters, AbortUploadItem, AbortUploadsBody};
+  use crate::{
+    batch::{server::error_code, BatchItemResult, BatchResult},
+    body, json_reply, metrics,
+    objects::key,
+    policy::{self, Identity, Operation},
+    query, retry, telemetry, Error, S3Configuration,
+  };
+  use chrono::{DateTime, Utc};
+  use futures::StreamExt;
+  use rusoto_core::RusotoError;
+  use rusoto_s3::{
+    AbortMultipartUploadError, AbortMultipartUploadRequest, ListMultipartUploadsRequest,
+    MultipartUpload, S3Client, S3,
+  };
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{Filter, Rejection, Reply};
+
+  /// Uploads aborted at once by a batch
+  const ABORT_CONCURRENCY: usize = 8;
+
+  /// Abort multipart uploads in batch
+  #[utoipa::path(
+    post,
+    context_path = "/multipart-upload",
+    path = "/abort",
+    tag = "Multipart upload",
+    request_body(
+      content = AbortUploadsBody,
+      description = "Uploads to abort",
+      content_type = "application/json"
+    ),
+    responses(
+      (
+        status = 200,
+        description = "Returns the outcome of every abortion",
+        content_type = "application/json",
+        body = AbortUploadsBatchResult
+      ),
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("abort")
+      .and(warp::post())
+      .and(body::json::<AbortUploadsBody>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |body: AbortUploadsBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(Ok(
+            abort_multipart_uploads(&s3_configuration, &identity, body).await,
+          ))
+        },
+      )
+  }
+
+  /// Abort the multipart uploads of an object
+  #[utoipa::path(
+    delete,
+    context_path = "/multipart-upload",
+    path = "",
+    tag = "Multipart upload",
+    responses(
+      (
+        status = 200,
+        description = "Returns the outcome of the abortion of every upload in progress of the object",
+        content_type = "application/json",
+        body = AbortUploadsBatchResult
+      ),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn key_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path::end()
+      .and(warp::delete())
+      .and(query::parameters::<AbortKeyUploadsQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: AbortKeyUploadsQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(abort_key_multipart_uploads(&s3_configuration, &identity, parameters).await)
+        },
+      )
+  }
+
+  /// Aborts every multipart upload in progress of an object, e.g. the ones left behind by
+  /// cancelled uploads, on behalf of `identity`, reporting the outcome of each.
+  pub async fn abort_key_multipart_uploads(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: AbortKeyUploadsQueryParameters,
+  ) -> Result<BatchResult<AbortUploadItem>, Error> {
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    let AbortKeyUploadsQueryParameters { bucket, path: key } = parameters;
+    // The prefix also matches the uploads of the longer keys
+    let uploads = list_uploads(s3_configuration, &bucket, &key, |upload| {
+      upload.key.as_deref() == Some(key.as_str())
+    })
+    .await?;
+    Ok(abort_multipart_uploads(s3_configuration, identity, AbortUploadsBody { uploads }).await)
+  }
+
+  /// Lists the multipart uploads under a prefix initiated before `initiated_before`, e.g. the
+  /// stale ones which a lifecycle rule would abort.
+  pub(crate) async fn list_stale_uploads(
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    prefix: &str,
+    initiated_before: DateTime<Utc>,
+  ) -> Result<Vec<AbortUploadItem>, Error> {
+    list_uploads(s3_configuration, bucket, prefix, |upload| {
+      upload
+        .initiated
+        .as_deref()
+        .and_then(|initiated| DateTime::parse_from_rfc3339(initiated).ok())
+        .is_some_and(|initiated| initiated < initiated_before)
+    })
+    .await
+  }
+
+  /// Lists the multipart uploads in progress under a prefix which `keep` retains, following the
+  /// pages of the listing.
+  #[tracing::instrument(skip_all)]
+  async fn list_uploads(
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    prefix: &str,
+    keep: impl Fn(&MultipartUpload) -> bool,
+  ) -> Result<Vec<AbortUploadItem>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(bucket);
+    let client = S3Client::try_from(&s3_configuration)?;
+    let mut request = ListMultipartUploadsRequest {
+      bucket: bucket.to_string(),
+      prefix: Some(prefix.to_string()).filter(|prefix| !prefix.is_empty()),
+      ..Default::default()
+    };
+
+    let mut uploads = Vec::new();
+    loop {
+      let output = retry::s3_call(&s3_configuration, "ListMultipartUploads", || {
+        client
+          .list_multipart_uploads(request.clone())
+          .instrument(telemetry::s3_span("ListMultipartUploads"))
+      })
+      .await
+      .map_err(metrics::count_s3_error("ListMultipartUploads"))
+      .map_err(Error::ListUploadsError)?;
+
+      uploads.extend(
+        output
+          .uploads
+          .unwrap_or_default()
+          .into_iter()
+          .filter(|upload| keep(upload))
+          .filter_map(|upload| {
+            Some(AbortUploadItem {
+              bucket: bucket.to_string(),
+              path: upload.key?,
+              upload_id: upload.upload_id?,
+            })
+          }),
+      );
+      if output.is_truncated != Some(true) {
+        break;
+      }
+      request.key_marker = output.next_key_marker;
+      request.upload_id_marker = output.next_upload_id_marker;
+    }
+
+    log::info!(
+      "Listed uploads: bucket={}, prefix={}, count={}",
+      bucket,
+      prefix,
+      uploads.len()
+    );
+    Ok(uploads)
+  }
+
+  /// Aborts multipart uploads in batch, on behalf of `identity`, reporting the outcome of each.
+  #[tracing::instrument(skip_all)]
+  pub async fn abort_multipart_uploads(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    body: AbortUploadsBody,
+  ) -> BatchResult<AbortUploadItem> {
+    let uploads = body.uploads;
+    log::info!("Abort multipart uploads: count={}", uploads.len());
+
+    let mut items = futures::stream::iter(uploads.into_iter().enumerate())
+      .map(|(index, upload)| abort_multipart_upload(s3_configuration, identity, index, upload))
+      .buffer_unordered(ABORT_CONCURRENCY)
+      .collect::<Vec<_>>()
+      .await;
+    items.sort_by_key(|item| item.index);
+
+    BatchResult::new(items)
+  }
+
+  async fn abort_multipart_upload(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    index: usize,
+    mut upload: AbortUploadItem,
+  ) -> BatchItemResult<AbortUploadItem> {
+    identity.scope(&mut upload.path);
+    if let Err(error) = key::check_key(&upload.path, s3_configuration.max_key_length()) {
+      return BatchItemResult::failed(index, "InvalidKey", &error);
+    }
+
+    if !policy::is_allowed(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &upload.bucket,
+      &upload.path,
+    ) {
+      return BatchItemResult::failed(index, "Forbidden", "operation not allowed by policies");
+    }
+
+    match abort_upload(s3_configuration, &upload).await {
+      Ok(()) => BatchItemResult::succeeded(index, upload),
+      Err(Error::MultipartUploadAbortionError(error)) => {
+        BatchItemResult::failed(index, &abort_error_code(&error), &error.to_string())
+      }
+      Err(error) => BatchItemResult::failed(index, "S3Connection", &error.to_string()),
+    }
+  }
+
+  /// Aborts a multipart upload, without any policy, e.g. for the scheduled cleanups.
+  pub(crate) async fn abort_upload(
+    s3_configuration: &S3Configuration,
+    upload: &AbortUploadItem,
+  ) -> Result<(), Error> {
+    let s3_configuration = s3_configuration.for_bucket(&upload.bucket);
+    let client = S3Client::try_from(&s3_configuration)?;
+    let request = AbortMultipartUploadRequest {
+      bucket: upload.bucket.clone(),
+      key: upload.path.clone(),
+      upload_id: upload.upload_id.clone(),
+      ..Default::default()
+    };
+
+    retry::s3_call(&s3_configuration, "AbortMultipartUpload", || {
+      client
+        .abort_multipart_upload(request.clone())
+        .instrument(telemetry::s3_span("AbortMultipartUpload"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("AbortMultipartUpload"))
+    .map_err(Error::MultipartUploadAbortionError)?;
+    metrics::multipart_upload_ended();
+    Ok(())
+  }
+  /// Code of a failed abortion, e.g. `NoSuchUpload`.
+  fn abort_error_code(error: &RusotoError<AbortMultipartUploadError>) -> String {
+    match error {
+      RusotoError::Service(AbortMultipartUploadError::NoSuchUpload(_)) => {
+        "NoSuchUpload".to_string()
+      }
+      error => error_code(error),
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+    use rusoto_core::{request::BufferedHttpResponse, request::HttpDispatchError};
+    use warp::http::StatusCode;
+
+    #[test]
+    fn abortion_errors_are_coded() {
+      let no_such_upload = RusotoError::Service(AbortMultipartUploadError::NoSuchUpload(
+        "The specified upload does not exist".to_string(),
+      ));
+      assert_eq!(abort_error_code(&no_such_upload), "NoSuchUpload");
+
+      let access_denied = RusotoError::Unknown(BufferedHttpResponse {
+        status: StatusCode::FORBIDDEN,
+        body: "<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>".into(),
+        headers: Default::default(),
+      });
+      assert_eq!(abort_error_code(&access_denied), "AccessDenied");
+
+      let without_body = RusotoError::Unknown(BufferedHttpResponse {
+        status: StatusCode::SERVICE_UNAVAILABLE,
+        body: Default::default(),
+        headers: Default::default(),
+      });
+      assert_eq!(abort_error_code(&without_body), "Http503");
+
+      let dispatch = RusotoError::HttpDispatch(HttpDispatchError::new("reset".to_string()));
+      assert_eq!(abort_error_code(&dispatch), "HttpDispatch");
+    }
+  }
+}
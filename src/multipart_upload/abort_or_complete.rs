@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AbortOrCompleteUploadQueryParameters {
@@ -16,8 +16,11 @@ pub enum AbortOrCompleteUploadBody {
 
 #[derive(Debug, Deserialize, Serialize)]
 #[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
 pub struct CompletedUploadPart {
   pub number: i64,
+  /// ETag of the part upload response, with or without its quotes
+  #[serde(deserialize_with = "deserialize_etag")]
   pub etag: String,
 }
 
@@ -26,26 +29,52 @@ impl From<CompletedUploadPart> for rusoto_s3::CompletedPart {
   fn from(part: CompletedUploadPart) -> Self {
     Self {
       part_number: Some(part.number),
-      e_tag: Some(part.etag),
+      e_tag: Some(format!("\"{}\"", part.etag)),
     }
   }
 }
 
+/// Normalizes an ETag sent by a client: without its quotes nor surrounding whitespace, in lower
+/// case like the ones of S3.
+pub fn normalize_etag(etag: &str) -> String {
+  etag.trim().trim_matches('"').to_ascii_lowercase()
+}
+
+fn deserialize_etag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+  String::deserialize(deserializer).map(|etag| normalize_etag(&etag))
+}
+
 #[cfg(feature = "server")]
 pub(crate) mod server {
   use super::{
-    AbortOrCompleteUploadBody, AbortOrCompleteUploadQueryParameters, CompletedUploadPart,
+    normalize_etag, AbortOrCompleteUploadBody, AbortOrCompleteUploadQueryParameters,
+    CompletedUploadPart,
+  };
+  use crate::{
+    body, concurrency_limit,
+    error::{self, PartError},
+    events::{self, UploadEventKind},
+    limits::MAX_PARTS,
+    listing_cache, metrics,
+    multipart_upload::{S3Client, Served},
+    objects::key,
+    policy::{self, Identity, Operation},
+    query, quotas, retry, telemetry, Error, S3Configuration,
   };
-  use crate::{multipart_upload::S3Client, to_ok_json_response, Error, S3Configuration};
   use rusoto_s3::{
     AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
     CompletedPart, S3,
   };
-  use std::convert::TryFrom;
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
   use warp::{
-    hyper::{Body, Response},
+    hyper::{body::Bytes, header::CONTENT_TYPE},
     Filter, Rejection, Reply,
   };
+  use xml::reader::{EventReader, XmlEvent};
+
+  const MD5_HEX_LENGTH: usize = 32;
+  const XML_CONTENT_TYPES: &[&str] = &["application/xml", "text/xml"];
 
   /// Abort or complete multipart upload
   #[utoipa::path(
@@ -55,17 +84,20 @@ pub(crate) mod server {
     tag = "Multipart upload",
     request_body(
       content = AbortOrCompleteUploadBody,
-      description = "Description of the abortion or completion request",
+      description = "Description of the abortion or completion request, or the `CompleteMultipartUpload` XML document of S3 with an `application/xml` content type",
       content_type = "application/json"
     ),
     responses(
       (status = 200, description = "Successfully aborted or completed multipart upload"),
+      (status = 400, description = "A part is invalid (completion only), e.g. its ETag is not an MD5 digest"),
+      (status = 503, description = "Too many concurrent S3 calls (completion only), retry after the Retry-After delay"),
     ),
     params(
       ("upload_id" = String, Path, description = "ID of the upload to abort or complete"),
       ("bucket" = String, Query, description = "Name of the bucket"),
       ("path" = String, Query, description = "Key of the object to upload")
     ),
+    security(("bearer_token" = []), ("api_key" = [])),
   )]
   pub(crate) fn route(
     s3_configuration: &S3Configuration,
@@ -73,96 +105,249 @@ pub(crate) mod server {
     let s3_configuration = s3_configuration.clone();
     warp::path!(String)
       .and(warp::post())
-      .and(warp::query::<AbortOrCompleteUploadQueryParameters>())
-      .and(warp::body::json::<AbortOrCompleteUploadBody>())
-      .map(
-        move |upload_id: String,
-              parameters: AbortOrCompleteUploadQueryParameters,
-              body: AbortOrCompleteUploadBody| {
-          (
+      .and(query::parameters::<AbortOrCompleteUploadQueryParameters>())
+      .and(request_body(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |upload_id: String,
+         parameters: AbortOrCompleteUploadQueryParameters,
+         body: AbortOrCompleteUploadBody,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          abort_or_complete_multipart_upload(
+            &s3_configuration,
+            &identity,
             upload_id,
-            parameters.bucket,
-            parameters.path,
+            parameters,
             body,
-            s3_configuration.clone(),
           )
+          .await
+          .and_then(|served| served.to_json_response())
+          .map_err(warp::reject::custom)
         },
       )
-      .and_then(
-        |(upload_id, bucket, path, body, s3_configuration): (
-          String,
-          String,
-          String,
-          AbortOrCompleteUploadBody,
-          S3Configuration,
-        )| async move {
-          match body {
-            AbortOrCompleteUploadBody::Abort => {
-              handle_abort_multipart_upload(&s3_configuration, bucket, path, upload_id).await
-            }
-            AbortOrCompleteUploadBody::Complete { parts } => {
-              handle_complete_multipart_upload(&s3_configuration, bucket, path, upload_id, parts)
-                .await
+  }
+
+  /// Body of the abortion or completion requests: JSON, or the `CompleteMultipartUpload` XML
+  /// document of S3 when sent with an XML content type, as by S3 client libraries.
+  fn request_body(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (AbortOrCompleteUploadBody,), Error = Rejection> + Clone {
+    warp::header::<String>(CONTENT_TYPE.as_str())
+      .and_then(|content_type: String| async move {
+        let mime_type = content_type.split(';').next().unwrap_or_default().trim();
+        if XML_CONTENT_TYPES
+          .iter()
+          .any(|xml_content_type| mime_type.eq_ignore_ascii_case(xml_content_type))
+        {
+          Ok(())
+        } else {
+          Err(warp::reject())
+        }
+      })
+      .untuple_one()
+      .and(warp::body::content_length_limit(
+        s3_configuration.max_json_body_size(),
+      ))
+      .and(warp::body::bytes())
+      .and_then(|body: Bytes| async move {
+        parse_complete_multipart_upload(&body)
+          .map(|parts| AbortOrCompleteUploadBody::Complete { parts })
+          .map_err(|error| {
+            warp::reject::custom(Error::InvalidParameter(format!(
+              "invalid CompleteMultipartUpload document: {}",
+              error
+            )))
+          })
+      })
+      .or(body::json::<AbortOrCompleteUploadBody>(s3_configuration))
+      .unify()
+  }
+
+  /// Parses the parts of a `CompleteMultipartUpload` document, as sent to S3.
+  fn parse_complete_multipart_upload(body: &[u8]) -> Result<Vec<CompletedUploadPart>, String> {
+    let mut parts = vec![];
+    let mut path: Vec<String> = vec![];
+
+    for event in EventReader::new(body) {
+      match event.map_err(|error| error.to_string())? {
+        XmlEvent::StartElement { name, .. } => {
+          match (path.last().map(String::as_str), name.local_name.as_str()) {
+            (None, "CompleteMultipartUpload") => {}
+            (None, root) => return Err(format!("unexpected root element {}", root)),
+            (Some("CompleteMultipartUpload"), "Part") => parts.push(CompletedUploadPart {
+              number: 0,
+              etag: String::new(),
+            }),
+            _ => {}
+          }
+          path.push(name.local_name);
+        }
+        XmlEvent::Characters(text) => {
+          let elements: Vec<&str> = path.iter().skip(1).map(String::as_str).collect();
+          if let (["Part", element], Some(part)) = (elements.as_slice(), parts.last_mut()) {
+            match *element {
+              "PartNumber" => {
+                part.number = text
+                  .trim()
+                  .parse()
+                  .map_err(|_| format!("part number {:?} is not a number", text))?
+              }
+              "ETag" => part.etag = normalize_etag(&text),
+              _ => {}
             }
           }
-        },
-      )
+        }
+        XmlEvent::EndElement { .. } => {
+          path.pop();
+        }
+        _ => {}
+      }
+    }
+    Ok(parts)
   }
 
+  /// Aborts or completes a multipart upload, on behalf of `identity`.
+  pub async fn abort_or_complete_multipart_upload(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    upload_id: String,
+    parameters: AbortOrCompleteUploadQueryParameters,
+    body: AbortOrCompleteUploadBody,
+  ) -> Result<Served<()>, Error> {
+    let AbortOrCompleteUploadQueryParameters { bucket, mut path } = parameters;
+    identity.scope(&mut path);
+    key::server::validate_key(s3_configuration, &path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &bucket,
+      &path,
+    )?;
+    match body {
+      AbortOrCompleteUploadBody::Abort => {
+        handle_abort_multipart_upload(s3_configuration, bucket, path, upload_id).await
+      }
+      AbortOrCompleteUploadBody::Complete { parts } => {
+        handle_complete_multipart_upload(s3_configuration, bucket, path, upload_id, parts).await
+      }
+    }
+  }
+
+  #[tracing::instrument(skip_all)]
   async fn handle_abort_multipart_upload(
     s3_configuration: &S3Configuration,
     bucket: String,
     key: String,
     upload_id: String,
-  ) -> Result<Response<Body>, Rejection> {
+  ) -> Result<Served<()>, Error> {
+    let s3_configuration = &s3_configuration.for_bucket(&bucket);
     log::info!("Abort multipart upload: upload_id={}", upload_id);
+    let request = AbortMultipartUploadRequest {
+      bucket: bucket.clone(),
+      key: key.clone(),
+      upload_id: upload_id.clone(),
+      ..Default::default()
+    };
     let client = S3Client::try_from(s3_configuration)?;
     client
-      .execute(|client: rusoto_s3::S3Client| async move {
-        let request = AbortMultipartUploadRequest {
-          bucket,
-          key,
-          upload_id,
-          ..Default::default()
-        };
-
-        client
-          .abort_multipart_upload(request)
+      .execute(|client: rusoto_s3::S3Client| {
+        let request = &request;
+        let (bucket, key, upload_id) = (&bucket, &key, &upload_id);
+        async move {
+          retry::s3_call(s3_configuration, "AbortMultipartUpload", || {
+            client
+              .abort_multipart_upload(request.clone())
+              .instrument(telemetry::s3_span("AbortMultipartUpload"))
+          })
           .await
-          .map_err(|error| warp::reject::custom(Error::MultipartUploadAbortionError(error)))
-          .and_then(|_output| to_ok_json_response(&()))
+          .map_err(metrics::count_s3_error("AbortMultipartUpload"))
+          .map_err(Error::MultipartUploadAbortionError)
+          .map(|_output| {
+            metrics::multipart_upload_ended();
+            events::server::publish(UploadEventKind::Aborted, bucket, key, upload_id, None);
+          })
+        }
       })
       .await
   }
 
+  #[tracing::instrument(skip_all)]
   async fn handle_complete_multipart_upload(
     s3_configuration: &S3Configuration,
     bucket: String,
     key: String,
     upload_id: String,
     body: Vec<CompletedUploadPart>,
-  ) -> Result<Response<Body>, Rejection> {
+  ) -> Result<Served<()>, Error> {
+    let s3_configuration = &s3_configuration.for_bucket(&bucket);
+    let _permit = concurrency_limit::acquire(s3_configuration)?;
     log::info!("Complete multipart upload: upload_id={}", upload_id);
+    body.iter().try_for_each(check_part)?;
+    let parts = body.into_iter().map(CompletedPart::from).collect();
+    let request = CompleteMultipartUploadRequest {
+      bucket: bucket.clone(),
+      key: key.clone(),
+      upload_id: upload_id.clone(),
+      multipart_upload: Some(CompletedMultipartUpload { parts: Some(parts) }),
+      ..Default::default()
+    };
     let client = S3Client::try_from(s3_configuration)?;
-    client
-      .execute(|client: rusoto_s3::S3Client| async move {
-        let parts = body.into_iter().map(CompletedPart::from).collect();
-        let parts = CompletedMultipartUpload { parts: Some(parts) };
-
-        let request = CompleteMultipartUploadRequest {
-          bucket,
-          key,
-          upload_id,
-          multipart_upload: Some(parts),
-          ..Default::default()
-        };
-
-        client
-          .complete_multipart_upload(request)
+    let served = client
+      .execute(|client: rusoto_s3::S3Client| {
+        let request = &request;
+        let (bucket, key, upload_id) = (&bucket, &key, &upload_id);
+        async move {
+          retry::s3_call(s3_configuration, "CompleteMultipartUpload", || {
+            client
+              .complete_multipart_upload(request.clone())
+              .instrument(telemetry::s3_span("CompleteMultipartUpload"))
+          })
           .await
-          .map_err(|error| warp::reject::custom(Error::MultipartUploadCompletionError(error)))
-          .and_then(|_output| to_ok_json_response(&()))
+          .map_err(metrics::count_s3_error("CompleteMultipartUpload"))
+          .map_err(error::completion_error)
+          .map(|_output| {
+            listing_cache::invalidate(s3_configuration, bucket, key);
+            metrics::multipart_upload_ended();
+            events::server::publish(UploadEventKind::Completed, bucket, key, upload_id, None);
+          })
+        }
       })
-      .await
+      .await?;
+    quotas::account_upload(s3_configuration, &bucket, &key).await;
+    Ok(served)
+  }
+
+  /// Rejects the parts out of the part numbers, and the ETags which are not hex MD5 digests
+  /// (optionally followed by the number of parts of a multipart ETag), naming the part.
+  fn check_part(part: &CompletedUploadPart) -> Result<(), Error> {
+    if !(1..=MAX_PARTS).contains(&part.number) {
+      return Err(Error::InvalidPart(PartError {
+        part_number: Some(part.number),
+        message: format!(
+          "part number {} is not within 1..={}",
+          part.number, MAX_PARTS
+        ),
+      }));
+    }
+    let (digest, parts) = match part.etag.split_once('-') {
+      Some((digest, parts)) => (digest, Some(parts)),
+      None => (part.etag.as_str(), None),
+    };
+    let valid = digest.len() == MD5_HEX_LENGTH
+      && digest.chars().all(|c| c.is_ascii_hexdigit())
+      && parts.is_none_or(|parts| !parts.is_empty() && parts.chars().all(|c| c.is_ascii_digit()));
+    if valid {
+      return Ok(());
+    }
+    Err(Error::InvalidPart(PartError {
+      part_number: Some(part.number),
+      message: format!(
+        "the ETag {:?} of part {} is not an MD5 digest as answered by the part upload",
+        part.etag, part.number
+      ),
+    }))
   }
 }
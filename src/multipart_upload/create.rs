@@ -1,27 +1,45 @@
+use crate::encryption::ServerSideEncryption;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CreateUploadQueryParameters {
   pub bucket: String,
   pub path: String,
+  pub sse: Option<ServerSideEncryption>,
+  pub kms_key_id: Option<String>,
+  pub content_type: Option<String>,
+  /// `Cache-Control` header of the object, e.g. `public, max-age=31536000`
+  pub cache_control: Option<String>,
+  /// `Expires` header of the object, as an HTTP date
+  pub expires: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 #[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
 pub struct CreateUploadResponse {
   pub upload_id: String,
+  /// Key of the object, when generated from the key template of the bucket
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub key: Option<String>,
 }
 
 #[cfg(feature = "server")]
 pub(crate) mod server {
   use super::{CreateUploadQueryParameters, CreateUploadResponse};
-  use crate::{multipart_upload::S3Client, to_ok_json_response, Error, S3Configuration};
-  use rusoto_s3::{CreateMultipartUploadRequest, S3};
-  use std::convert::TryFrom;
-  use warp::{
-    hyper::{Body, Response},
-    Filter, Rejection, Reply,
+  use crate::{
+    cache_headers, concurrency_limit, encryption,
+    events::{self, UploadEventKind},
+    key_template, metrics,
+    multipart_upload::{S3Client, Served},
+    objects::key,
+    policy::{self, Identity, Operation},
+    query, quotas, retry, telemetry, Error, S3Configuration,
   };
+  use rusoto_s3::{CreateMultipartUploadRequest, S3};
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{Filter, Rejection, Reply};
 
   /// Create multipart upload
   #[utoipa::path(
@@ -31,11 +49,18 @@ pub(crate) mod server {
     tag = "Multipart upload",
     responses(
       (status = 200, description = "Successfully created multipart upload", body = CreateUploadResponse),
+      (status = 503, description = "Too many concurrent S3 calls, retry after the Retry-After delay"),
     ),
     params(
       ("bucket" = String, Query, description = "Name of the bucket"),
-      ("path" = String, Query, description = "Key of the object to upload")
+      ("path" = String, Query, description = "Key of the object to upload, or its file name when the bucket has a key template"),
+      ("sse" = Option<ServerSideEncryption>, Query, description = "Server-side encryption of the object (AES256 or aws:kms)"),
+      ("kms_key_id" = Option<String>, Query, description = "ID of the KMS key used with aws:kms server-side encryption"),
+      ("content_type" = Option<String>, Query, description = "Content type of the object"),
+      ("cache_control" = Option<String>, Query, description = "`Cache-Control` header of the object"),
+      ("expires" = Option<String>, Query, description = "`Expires` header of the object, as an HTTP date")
     ),
+    security(("bearer_token" = []), ("api_key" = [])),
   )]
   pub(crate) fn route(
     s3_configuration: &S3Configuration,
@@ -43,48 +68,121 @@ pub(crate) mod server {
     let s3_configuration = s3_configuration.clone();
     warp::path::end()
       .and(warp::post())
-      .and(warp::query::<CreateUploadQueryParameters>())
-      .and(warp::any().map(move || s3_configuration.clone()))
+      .and(query::parameters::<CreateUploadQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
       .and_then(
-        |parameters: CreateUploadQueryParameters, s3_configuration: S3Configuration| async move {
-          handle_create_multipart_upload(&s3_configuration, parameters.bucket, parameters.path)
+        |parameters: CreateUploadQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          create_multipart_upload(&s3_configuration, &identity, parameters)
             .await
+            .and_then(|served| served.to_json_response())
+            .map_err(warp::reject::custom)
         },
       )
   }
 
+  /// Creates a multipart upload, on behalf of `identity`.
+  ///
+  /// In buckets with a key template, the key is generated from the file name of the path and
+  /// answered along with the upload ID.
+  pub async fn create_multipart_upload(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: CreateUploadQueryParameters,
+  ) -> Result<Served<CreateUploadResponse>, Error> {
+    let generated_key = key_template::generate_key(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &parameters.bucket,
+      &parameters.path,
+    )
+    .await?;
+    if let Some(generated_key) = &generated_key {
+      parameters.path = generated_key.clone();
+    }
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    quotas::check(
+      &s3_configuration.for_bucket(&parameters.bucket),
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    let mut served = handle_create_multipart_upload(s3_configuration, parameters).await?;
+    served.body.key = generated_key;
+    Ok(served)
+  }
+
+  #[tracing::instrument(skip_all)]
   async fn handle_create_multipart_upload(
     s3_configuration: &S3Configuration,
-    bucket: String,
-    key: String,
-  ) -> Result<Response<Body>, Rejection> {
+    parameters: CreateUploadQueryParameters,
+  ) -> Result<Served<CreateUploadResponse>, Error> {
+    let CreateUploadQueryParameters {
+      bucket,
+      path: key,
+      sse,
+      kms_key_id,
+      content_type,
+      cache_control,
+      expires,
+    } = parameters;
+    let s3_configuration = &s3_configuration.for_bucket(&bucket);
+    let _permit = concurrency_limit::acquire(s3_configuration)?;
     log::info!("Create multipart upload...");
+    encryption::server::check_parameters(sse, &kms_key_id)?;
+    cache_headers::check_parameters(&cache_control, &expires)?;
+    let request = CreateMultipartUploadRequest {
+      bucket: bucket.clone(),
+      key: key.clone(),
+      server_side_encryption: sse.map(|sse| sse.as_str().to_string()),
+      ssekms_key_id: kms_key_id,
+      content_type,
+      cache_control,
+      expires,
+      ..Default::default()
+    };
     let client = S3Client::try_from(s3_configuration)?;
     client
-      .execute(|client: rusoto_s3::S3Client| async move {
-        let request = CreateMultipartUploadRequest {
-          bucket,
-          key,
-          ..Default::default()
-        };
-
-        client
-          .create_multipart_upload(request)
+      .execute(|client: rusoto_s3::S3Client| {
+        let request = &request;
+        let (bucket, key) = (&bucket, &key);
+        async move {
+          retry::s3_call(s3_configuration, "CreateMultipartUpload", || {
+            client
+              .create_multipart_upload(request.clone())
+              .instrument(telemetry::s3_span("CreateMultipartUpload"))
+          })
           .await
-          .map_err(|error| warp::reject::custom(Error::MultipartUploadCreationError(error)))
+          .map_err(metrics::count_s3_error("CreateMultipartUpload"))
+          .map_err(Error::MultipartUploadCreationError)
           .and_then(|output| {
             output
               .upload_id
               .ok_or_else(|| {
-                warp::reject::custom(Error::MultipartUploadError(
+                Error::MultipartUploadError(
                   "Invalid multipart upload creation response".to_string(),
-                ))
+                )
               })
-              .and_then(|upload_id| {
-                let body_response = CreateUploadResponse { upload_id };
-                to_ok_json_response(&body_response)
+              .map(|upload_id| {
+                metrics::multipart_upload_started();
+                events::server::publish(UploadEventKind::Created, bucket, key, &upload_id, None);
+                CreateUploadResponse {
+                  upload_id,
+                  key: None,
+                }
               })
           })
+        }
       })
       .await
   }
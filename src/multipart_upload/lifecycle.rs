@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+
+/// Days after which the incomplete multipart uploads are aborted, unless specified
+pub const DEFAULT_ABORT_INCOMPLETE_UPLOADS_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LifecycleQueryParameters {
+  pub bucket: String,
+  pub days: Option<i64>,
+}
+
+/// Lifecycle rule of a bucket, aborting its incomplete multipart uploads.
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct LifecycleRuleResponse {
+  pub bucket: String,
+  pub rule_id: String,
+  pub days_after_initiation: i64,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::{
+    LifecycleQueryParameters, LifecycleRuleResponse, DEFAULT_ABORT_INCOMPLETE_UPLOADS_DAYS,
+  };
+  use crate::{
+    json_reply, metrics,
+    policy::{self, Identity, Operation},
+    query, retry, telemetry, Error, S3Configuration,
+  };
+  use rusoto_core::RusotoError;
+  use rusoto_s3::{
+    AbortIncompleteMultipartUpload, BucketLifecycleConfiguration,
+    GetBucketLifecycleConfigurationRequest, LifecycleRule, LifecycleRuleFilter,
+    PutBucketLifecycleConfigurationRequest, S3Client, S3,
+  };
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{hyper::StatusCode, Filter, Rejection, Reply};
+
+  /// ID of the lifecycle rule installed by the signer, replaced when installed again
+  const LIFECYCLE_RULE_ID: &str = "s3-signer-abort-incomplete-multipart-uploads";
+
+  /// Abort the incomplete multipart uploads of a bucket after some days
+  #[utoipa::path(
+    put,
+    context_path = "/multipart-upload",
+    path = "/lifecycle",
+    tag = "Multipart upload",
+    responses(
+      (
+        status = 200,
+        description = "Returns the lifecycle rule installed on the bucket",
+        content_type = "application/json",
+        body = LifecycleRuleResponse
+      ),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("days" = Option<i64>, Query, description = "Days after their initiation the uploads are aborted (7 by default)")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("lifecycle")
+      .and(warp::put())
+      .and(query::parameters::<LifecycleQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: LifecycleQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(put_lifecycle_rule(&s3_configuration, &identity, parameters).await)
+        },
+      )
+  }
+
+  /// Installs the lifecycle rule aborting the incomplete multipart uploads of a bucket, on behalf
+  /// of `identity`, which must be allowed multipart uploads on the whole bucket.
+  pub async fn put_lifecycle_rule(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    parameters: LifecycleQueryParameters,
+  ) -> Result<LifecycleRuleResponse, Error> {
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &parameters.bucket,
+      "",
+    )?;
+    ensure_lifecycle_rule(
+      s3_configuration,
+      &parameters.bucket,
+      parameters
+        .days
+        .unwrap_or(DEFAULT_ABORT_INCOMPLETE_UPLOADS_DAYS),
+    )
+    .await
+  }
+
+  /// Installs a lifecycle rule aborting the multipart uploads of the bucket still incomplete
+  /// `days` after their initiation, with PutBucketLifecycleConfiguration.
+  ///
+  /// The other rules of the bucket are kept, and the rule previously installed by the signer is
+  /// replaced.
+  #[tracing::instrument(skip(s3_configuration))]
+  pub async fn ensure_lifecycle_rule(
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    days: i64,
+  ) -> Result<LifecycleRuleResponse, Error> {
+    if days < 1 {
+      return Err(Error::InvalidParameter(
+        "days must be a positive number of days".to_string(),
+      ));
+    }
+
+    let s3_configuration = &s3_configuration.for_bucket(bucket);
+    log::info!("Ensure lifecycle rule: bucket={}, days={}", bucket, days);
+    let client = S3Client::try_from(s3_configuration)?;
+
+    let request = GetBucketLifecycleConfigurationRequest {
+      bucket: bucket.to_string(),
+      ..Default::default()
+    };
+    let rules = match retry::s3_call(s3_configuration, "GetBucketLifecycleConfiguration", || {
+      client
+        .get_bucket_lifecycle_configuration(request.clone())
+        .instrument(telemetry::s3_span("GetBucketLifecycleConfiguration"))
+    })
+    .await
+    {
+      Ok(output) => output.rules.unwrap_or_default(),
+      // NoSuchLifecycleConfiguration
+      Err(RusotoError::Unknown(response)) if response.status == StatusCode::NOT_FOUND => vec![],
+      Err(error) => {
+        metrics::s3_error("GetBucketLifecycleConfiguration");
+        return Err(Error::LifecycleConfigurationError(error));
+      }
+    };
+
+    let rules = rules
+      .into_iter()
+      .filter(|rule| rule.id.as_deref() != Some(LIFECYCLE_RULE_ID))
+      .chain(std::iter::once(LifecycleRule {
+        id: Some(LIFECYCLE_RULE_ID.to_string()),
+        status: "Enabled".to_string(),
+        filter: Some(LifecycleRuleFilter {
+          prefix: Some(String::new()),
+          ..Default::default()
+        }),
+        abort_incomplete_multipart_upload: Some(AbortIncompleteMultipartUpload {
+          days_after_initiation: Some(days),
+        }),
+        ..Default::default()
+      }))
+      .collect();
+
+    let request = PutBucketLifecycleConfigurationRequest {
+      bucket: bucket.to_string(),
+      lifecycle_configuration: Some(BucketLifecycleConfiguration { rules }),
+      ..Default::default()
+    };
+    retry::s3_call(s3_configuration, "PutBucketLifecycleConfiguration", || {
+      client
+        .put_bucket_lifecycle_configuration(request.clone())
+        .instrument(telemetry::s3_span("PutBucketLifecycleConfiguration"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("PutBucketLifecycleConfiguration"))
+    .map_err(Error::LifecycleUpdateError)?;
+
+    Ok(LifecycleRuleResponse {
+      bucket: bucket.to_string(),
+      rule_id: LIFECYCLE_RULE_ID.to_string(),
+      days_after_initiation: days,
+    })
+  }
+}
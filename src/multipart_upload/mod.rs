@@ -1,57 +1,138 @@
+pub(crate) mod abort_batch;
 pub(crate) mod abort_or_complete;
 pub(crate) mod create;
+pub(crate) mod lifecycle;
 pub(crate) mod part_upload_url;
+pub(crate) mod plan;
 
+pub use abort_batch::{AbortKeyUploadsQueryParameters, AbortUploadItem, AbortUploadsBody};
 pub use abort_or_complete::{
-  AbortOrCompleteUploadBody, AbortOrCompleteUploadQueryParameters, CompletedUploadPart,
+  normalize_etag, AbortOrCompleteUploadBody, AbortOrCompleteUploadQueryParameters,
+  CompletedUploadPart,
 };
 pub use create::{CreateUploadQueryParameters, CreateUploadResponse};
-pub use part_upload_url::{PartUploadQueryParameters, PartUploadResponse};
+#[cfg(feature = "server")]
+pub use lifecycle::server::ensure_lifecycle_rule;
+pub use lifecycle::{
+  LifecycleQueryParameters, LifecycleRuleResponse, DEFAULT_ABORT_INCOMPLETE_UPLOADS_DAYS,
+};
+pub use part_upload_url::{
+  PartRangeQueryParameters, PartUploadQueryParameters, PartUploadResponse, PartUploadUrl,
+  PartUploadUrlsResponse,
+};
+pub use plan::{MultipartPlan, PlanQueryParameters, PlannedPart};
 
+#[cfg(feature = "server")]
+pub use server::Served;
 #[cfg(feature = "server")]
 pub(crate) use server::{routes, S3Client};
 
 #[cfg(feature = "server")]
 mod server {
   use super::*;
-  use crate::{Error, S3Configuration};
-  use std::convert::TryFrom;
+  use crate::{to_ok_json_response, Error, S3Configuration};
+  use serde::Serialize;
+  use std::{convert::TryFrom, time::Duration};
   use warp::{hyper, Filter, Rejection, Reply};
 
+  /// Header of the responses naming the storage which served them, `primary` or `secondary`, when
+  /// a secondary storage is configured
+  const S3_ENDPOINT_HEADER: &str = "x-s3-endpoint";
+
   pub(crate) fn routes(
     s3_configuration: &S3Configuration,
   ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     warp::path("multipart-upload").and(
       create::server::route(s3_configuration)
         .or(part_upload_url::server::route(s3_configuration))
+        .or(part_upload_url::server::range_route(s3_configuration))
+        .or(abort_batch::server::route(s3_configuration))
+        .or(abort_batch::server::key_route(s3_configuration))
+        .or(lifecycle::server::route(s3_configuration))
+        .or(plan::server::route(s3_configuration))
         .or(abort_or_complete::server::route(s3_configuration)),
     )
   }
 
+  /// Result of a handler calling the storage with failover, and the storage which served it
+  /// (`primary` or `secondary`) when a secondary storage is configured.
+  #[derive(Clone, Debug)]
+  pub struct Served<T> {
+    pub body: T,
+    pub endpoint: Option<&'static str>,
+  }
+
+  impl<T> Served<T> {
+    /// Names the storage which served the response in its `X-S3-Endpoint` header.
+    pub(crate) fn with_endpoint_header(
+      &self,
+      mut response: hyper::Response<hyper::Body>,
+    ) -> hyper::Response<hyper::Body> {
+      if let Some(endpoint) = self.endpoint {
+        response.headers_mut().insert(
+          S3_ENDPOINT_HEADER,
+          hyper::header::HeaderValue::from_static(endpoint),
+        );
+      }
+      response
+    }
+  }
+
+  impl<T: Serialize> Served<T> {
+    pub(crate) fn to_json_response(&self) -> Result<hyper::Response<hyper::Body>, Error> {
+      to_ok_json_response(&self.body).map(|response| self.with_endpoint_header(response))
+    }
+  }
+
   pub(crate) struct S3Client {
     client: rusoto_s3::S3Client,
+    secondary: Option<(rusoto_s3::S3Client, Duration)>,
   }
 
   impl TryFrom<&S3Configuration> for S3Client {
-    type Error = Rejection;
+    type Error = Error;
 
     fn try_from(s3_configuration: &S3Configuration) -> Result<Self, Self::Error> {
-      let client = rusoto_s3::S3Client::try_from(s3_configuration)
-        .map_err(|error| warp::reject::custom(Error::S3ConnectionError(error)))?;
-      Ok(Self { client })
+      let client = rusoto_s3::S3Client::try_from(s3_configuration)?;
+      let secondary = s3_configuration
+        .secondary_s3_client()
+        .transpose()?
+        .map(|client| (client, s3_configuration.secondary_timeout()));
+      Ok(Self { client, secondary })
     }
   }
 
   impl S3Client {
-    pub async fn execute<F, Fut>(
-      self,
-      operation: F,
-    ) -> Result<hyper::Response<hyper::Body>, Rejection>
+    /// Runs the operation against the primary storage, then against the secondary one (if any)
+    /// when the primary one is unavailable or times out.
+    pub async fn execute<F, Fut, T>(self, operation: F) -> Result<Served<T>, Error>
     where
-      F: FnOnce(rusoto_s3::S3Client) -> Fut,
-      Fut: std::future::Future<Output = Result<hyper::Response<hyper::Body>, Rejection>>,
+      F: Fn(rusoto_s3::S3Client) -> Fut,
+      Fut: std::future::Future<Output = Result<T, Error>>,
     {
-      operation(self.client).await
+      let (secondary_client, timeout) = match self.secondary {
+        Some(secondary) => secondary,
+        None => return served_by(operation(self.client).await, None),
+      };
+
+      match tokio::time::timeout(timeout, operation(self.client)).await {
+        Ok(Err(error)) if error.is_storage_unavailable() => {
+          log::warn!("Failing over to the secondary storage: {}", error)
+        }
+        Ok(result) => return served_by(result, Some("primary")),
+        Err(_) => log::warn!(
+          "Failing over to the secondary storage: no answer after {}s",
+          timeout.as_secs_f32()
+        ),
+      }
+      served_by(operation(secondary_client).await, Some("secondary"))
     }
   }
+
+  fn served_by<T>(
+    result: Result<T, Error>,
+    endpoint: Option<&'static str>,
+  ) -> Result<Served<T>, Error> {
+    result.map(|body| Served { body, endpoint })
+  }
 }
@@ -4,27 +4,70 @@ use serde::{Deserialize, Serialize};
 pub struct PartUploadQueryParameters {
   pub bucket: String,
   pub path: String,
+  pub accelerate: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 #[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
 pub struct PartUploadResponse {
   pub presigned_url: String,
+  /// HTTP method of the request to send to the URL, `PUT`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub method: Option<String>,
+  /// Names of the signed headers the request must be sent with
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub headers: Vec<String>,
+  /// Time at which the URL expires, in RFC 3339
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PartRangeQueryParameters {
+  pub bucket: String,
+  pub path: String,
+  /// Number of the first part to sign
+  pub from: i64,
+  /// Number of the last part to sign, included
+  pub to: i64,
+  pub accelerate: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct PartUploadUrlsResponse {
+  /// Pre-signed URLs of the parts of the range, in order
+  pub parts: Vec<PartUploadUrl>,
+  /// Time at which the URLs expire, in RFC 3339
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct PartUploadUrl {
+  pub part_number: i64,
+  pub presigned_url: String,
 }
 
 #[cfg(feature = "server")]
 pub(crate) mod server {
-  use super::{PartUploadQueryParameters, PartUploadResponse};
-  use crate::{to_ok_json_response, S3Configuration};
-  use rusoto_credential::AwsCredentials;
-  use rusoto_s3::{
-    util::{PreSignedRequest, PreSignedRequestOption},
-    UploadPartRequest,
+  use super::{
+    PartRangeQueryParameters, PartUploadQueryParameters, PartUploadResponse, PartUploadUrl,
+    PartUploadUrlsResponse,
   };
-  use warp::{
-    hyper::{Body, Response},
-    Filter, Rejection, Reply,
+  use crate::{
+    events::{self, UploadEventKind},
+    json_reply,
+    limits::MAX_PARTS,
+    metrics,
+    objects::{self, key},
+    policy::{self, Identity, Operation},
+    presign::{self, PresignConfiguration, PresignOptions},
+    query, transfer_acceleration, Error, S3Configuration,
   };
+  use std::sync::Arc;
+  use warp::{Filter, Rejection, Reply};
 
   /// Pre-sign part upload URL
   #[utoipa::path(
@@ -44,8 +87,10 @@ pub(crate) mod server {
       ("upload_id" = String, Path, description = "ID of the upload"),
       ("part_number" = i64, Path, description = "Index number of the part to upload"),
       ("bucket" = String, Query, description = "Name of the bucket"),
-      ("path" = String, Query, description = "Key of the object to get")
+      ("path" = String, Query, description = "Key of the object to get"),
+      ("accelerate" = Option<bool>, Query, description = "Sign against the S3 Transfer Acceleration endpoint")
     ),
+    security(("bearer_token" = []), ("api_key" = [])),
   )]
   pub(crate) fn route(
     s3_configuration: &S3Configuration,
@@ -53,54 +98,244 @@ pub(crate) mod server {
     let s3_configuration = s3_configuration.clone();
     warp::path!(String / "part" / i64)
       .and(warp::get())
-      .and(warp::query::<PartUploadQueryParameters>())
-      .and(warp::any().map(move || s3_configuration.clone()))
+      .and(query::parameters::<PartUploadQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
       .and_then(
         |upload_id: String,
          part_number: i64,
          parameters: PartUploadQueryParameters,
-         s3_configuration: S3Configuration| async move {
-          handle_part_upload_presigned_url(
-            &s3_configuration,
-            parameters.bucket,
-            parameters.path,
-            upload_id,
-            part_number,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(
+            part_upload_url(
+              &s3_configuration,
+              &identity,
+              upload_id,
+              part_number,
+              parameters,
+            )
+            .await,
           )
-          .await
         },
       )
   }
 
+  /// Pre-signs the URL to upload a part of a multipart upload, on behalf of `identity`.
+  pub async fn part_upload_url(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    upload_id: String,
+    part_number: i64,
+    mut parameters: PartUploadQueryParameters,
+  ) -> Result<PartUploadResponse, Error> {
+    if !(1..=MAX_PARTS).contains(&part_number) {
+      return Err(Error::InvalidParameter(format!(
+        "part_number must be within 1..={}",
+        MAX_PARTS
+      )));
+    }
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    handle_part_upload_presigned_url(
+      s3_configuration,
+      parameters.bucket,
+      parameters.path,
+      upload_id,
+      part_number,
+      parameters.accelerate,
+    )
+    .await
+  }
+
+  #[tracing::instrument(skip_all)]
   async fn handle_part_upload_presigned_url(
     s3_configuration: &S3Configuration,
     bucket: String,
     key: String,
     upload_id: String,
     part_number: i64,
-  ) -> Result<Response<Body>, Rejection> {
+    accelerate: Option<bool>,
+  ) -> Result<PartUploadResponse, Error> {
+    let s3_configuration = &s3_configuration.for_bucket(&bucket);
     log::info!(
       "Upload part: upload_id={}, part_number={}",
       upload_id,
       part_number,
     );
-    let request = UploadPartRequest {
+    let accelerate = accelerate.unwrap_or_else(|| s3_configuration.transfer_acceleration());
+    if accelerate {
+      transfer_acceleration::check_enabled(s3_configuration, &bucket).await?;
+    }
+
+    let presign_configuration = s3_configuration.presign_configuration(&bucket).await;
+    let options = PresignOptions::from(s3_configuration).with_transfer_acceleration(accelerate);
+    let presigned_url = sign_part(
+      &presign_configuration,
+      &options,
+      &bucket,
+      &key,
+      &upload_id,
+      part_number,
+    )?;
+    Ok(PartUploadResponse {
+      presigned_url,
+      method: Some("PUT".to_string()),
+      headers: options.header_names(),
+      expires_at: Some(objects::expires_at(&presign_configuration, &options)?),
+    })
+  }
+
+  /// Pre-sign the upload URLs of a range of parts
+  #[utoipa::path(
+    get,
+    context_path = "/multipart-upload",
+    path = "/{upload_id}/parts",
+    tag = "Multipart upload",
+    responses(
+      (
+        status = 200,
+        description = "Returns the pre-signed URLs of the parts of the range",
+        content_type = "application/json",
+        body = PartUploadUrlsResponse
+      ),
+      (status = 400, description = "The range is empty or exceeds the part numbers"),
+    ),
+    params(
+      ("upload_id" = String, Path, description = "ID of the upload"),
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object to upload"),
+      ("from" = i64, Query, description = "Number of the first part to sign, from 1"),
+      ("to" = i64, Query, description = "Number of the last part to sign, included, up to 10000"),
+      ("accelerate" = Option<bool>, Query, description = "Sign against the S3 Transfer Acceleration endpoint")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn range_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!(String / "parts")
+      .and(warp::get())
+      .and(query::parameters::<PartRangeQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |upload_id: String,
+         parameters: PartRangeQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(part_upload_urls(&s3_configuration, &identity, upload_id, parameters).await)
+        },
+      )
+  }
+
+  /// Pre-signs the URLs to upload a range of parts of a multipart upload, e.g. to retry them, on
+  /// behalf of `identity`.
+  pub async fn part_upload_urls(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    upload_id: String,
+    mut parameters: PartRangeQueryParameters,
+  ) -> Result<PartUploadUrlsResponse, Error> {
+    if parameters.from < 1 || parameters.to > MAX_PARTS || parameters.from > parameters.to {
+      return Err(Error::InvalidParameter(format!(
+        "from and to must be part numbers within 1..={}, from not exceeding to",
+        MAX_PARTS
+      )));
+    }
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    handle_part_upload_presigned_urls(s3_configuration, upload_id, parameters).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_part_upload_presigned_urls(
+    s3_configuration: &S3Configuration,
+    upload_id: String,
+    parameters: PartRangeQueryParameters,
+  ) -> Result<PartUploadUrlsResponse, Error> {
+    let PartRangeQueryParameters {
+      bucket,
+      path: key,
+      from,
+      to,
+      accelerate,
+    } = parameters;
+    let s3_configuration = &s3_configuration.for_bucket(&bucket);
+    log::info!(
+      "Upload parts: upload_id={}, part_numbers={}..={}",
+      upload_id,
+      from,
+      to,
+    );
+    let accelerate = accelerate.unwrap_or_else(|| s3_configuration.transfer_acceleration());
+    if accelerate {
+      transfer_acceleration::check_enabled(s3_configuration, &bucket).await?;
+    }
+
+    let presign_configuration = s3_configuration.presign_configuration(&bucket).await;
+    let options = PresignOptions::from(s3_configuration).with_transfer_acceleration(accelerate);
+    let parts = (from..=to)
+      .map(|part_number| {
+        Ok(PartUploadUrl {
+          part_number,
+          presigned_url: sign_part(
+            &presign_configuration,
+            &options,
+            &bucket,
+            &key,
+            &upload_id,
+            part_number,
+          )?,
+        })
+      })
+      .collect::<Result<_, Error>>()?;
+    Ok(PartUploadUrlsResponse {
+      parts,
+      expires_at: Some(objects::expires_at(&presign_configuration, &options)?),
+    })
+  }
+
+  fn sign_part(
+    presign_configuration: &PresignConfiguration,
+    options: &PresignOptions,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    part_number: i64,
+  ) -> Result<String, Error> {
+    let presigned_url = presign::upload_part(
+      presign_configuration,
       bucket,
       key,
       upload_id,
       part_number,
-      ..Default::default()
-    };
-
-    let credentials = AwsCredentials::from(s3_configuration);
+      options,
+    )?;
 
-    let presigned_url = request.get_presigned_url(
-      s3_configuration.region(),
-      &credentials,
-      &PreSignedRequestOption::default(),
+    metrics::presigned_url("UploadPart");
+    events::server::publish(
+      UploadEventKind::PartSigned,
+      bucket,
+      key,
+      upload_id,
+      Some(part_number),
     );
-
-    let response = PartUploadResponse { presigned_url };
-    to_ok_json_response(&response)
+    Ok(presigned_url)
   }
 }
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PlanQueryParameters {
+  pub bucket: String,
+  pub path: String,
+  /// Size of the object to upload, in bytes
+  pub size: u64,
+  pub content_type: Option<String>,
+  pub accelerate: Option<bool>,
+}
+
+/// Multipart upload created for an object, to complete once its parts are uploaded.
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct MultipartPlan {
+  pub upload_id: String,
+  /// Key of the object, when generated from the key template of the bucket
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub key: Option<String>,
+  /// Size of the parts, in bytes, except the last one
+  pub part_size: u64,
+  pub parts: Vec<PlannedPart>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct PlannedPart {
+  pub part_number: i64,
+  /// Size of the part, in bytes
+  pub size: u64,
+  /// Pre-signed URL to upload the part
+  pub url: String,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::{MultipartPlan, PlanQueryParameters, PlannedPart};
+  use crate::{
+    events::{self, UploadEventKind},
+    limits::{MAX_OBJECT_SIZE, MAX_PARTS, MAX_PART_SIZE, MIN_PART_SIZE},
+    metrics,
+    multipart_upload::{
+      create::server::create_multipart_upload, CreateUploadQueryParameters, Served,
+    },
+    policy::{self, Identity},
+    presign::{self, PresignOptions},
+    query, transfer_acceleration, Error, S3Configuration,
+  };
+  use std::sync::Arc;
+  use warp::{Filter, Rejection, Reply};
+
+  /// Parts aimed at by the planned part sizes, keeping the number of pre-signed URLs answered low
+  const TARGET_PARTS: u64 = 1_000;
+  const MIB: u64 = 1024 * 1024;
+
+  /// Plan multipart upload
+  #[utoipa::path(
+    post,
+    context_path = "/multipart-upload",
+    path = "/plan",
+    tag = "Multipart upload",
+    responses(
+      (
+        status = 200,
+        description = "Returns the created multipart upload with the part size and the pre-signed URLs of all its parts",
+        content_type = "application/json",
+        body = MultipartPlan
+      ),
+      (status = 503, description = "Too many concurrent S3 calls, retry after the Retry-After delay"),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object to upload, or its file name when the bucket has a key template"),
+      ("size" = u64, Query, description = "Size of the object, in bytes"),
+      ("content_type" = Option<String>, Query, description = "Content type of the object"),
+      ("accelerate" = Option<bool>, Query, description = "Sign against the S3 Transfer Acceleration endpoint")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("plan")
+      .and(warp::post())
+      .and(query::parameters::<PlanQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: PlanQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          plan_multipart_upload(&s3_configuration, &identity, parameters)
+            .await
+            .and_then(|served| served.to_json_response())
+            .map_err(warp::reject::custom)
+        },
+      )
+  }
+
+  /// Creates a multipart upload with a part size fitting the size of the object, and pre-signs
+  /// the URLs of all its parts, on behalf of `identity`.
+  pub async fn plan_multipart_upload(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: PlanQueryParameters,
+  ) -> Result<Served<MultipartPlan>, Error> {
+    let size = parameters.size;
+    if size == 0 || size > MAX_OBJECT_SIZE {
+      return Err(Error::InvalidParameter(format!(
+        "size must be between 1 and {} bytes",
+        MAX_OBJECT_SIZE
+      )));
+    }
+    let bucket_configuration = s3_configuration.for_bucket(&parameters.bucket);
+    let accelerate = parameters
+      .accelerate
+      .unwrap_or_else(|| bucket_configuration.transfer_acceleration());
+    if accelerate {
+      transfer_acceleration::check_enabled(&bucket_configuration, &parameters.bucket).await?;
+    }
+
+    identity.scope(&mut parameters.path);
+    let served = create_multipart_upload(
+      s3_configuration,
+      identity,
+      CreateUploadQueryParameters {
+        bucket: parameters.bucket.clone(),
+        path: parameters.path.clone(),
+        sse: None,
+        kms_key_id: None,
+        content_type: parameters.content_type,
+        cache_control: None,
+        expires: None,
+      },
+    )
+    .await?;
+    let key = served.body.key.as_deref().unwrap_or(&parameters.path);
+    let mut plan = sign_parts(
+      s3_configuration,
+      &parameters.bucket,
+      key,
+      served.body.upload_id.clone(),
+      part_size(size),
+      size,
+      accelerate,
+    )
+    .await?;
+    plan.key = served.body.key.clone();
+    Ok(Served {
+      body: plan,
+      endpoint: served.endpoint,
+    })
+  }
+
+  /// Part size of an object: about a thousandth of its size, rounded up to a MiB, within the
+  /// limits of S3.
+  fn part_size(size: u64) -> u64 {
+    (size.div_ceil(TARGET_PARTS).div_ceil(MIB) * MIB)
+      .clamp(MIN_PART_SIZE, MAX_PART_SIZE)
+      .max(size.div_ceil(MAX_PARTS as u64))
+  }
+
+  /// Pre-signs the parts of a multipart upload just created, the policies being checked already.
+  pub(crate) async fn sign_parts(
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    key: &str,
+    upload_id: String,
+    part_size: u64,
+    size: u64,
+    accelerate: bool,
+  ) -> Result<MultipartPlan, Error> {
+    let s3_configuration = s3_configuration.for_bucket(bucket);
+    let presign_configuration = s3_configuration.presign_configuration(bucket).await;
+    let options = PresignOptions::from(&s3_configuration).with_transfer_acceleration(accelerate);
+    let parts = (0..size.div_ceil(part_size))
+      .map(|index| {
+        let part_number = index as i64 + 1;
+        let url = presign::upload_part(
+          &presign_configuration,
+          bucket,
+          key,
+          &upload_id,
+          part_number,
+          &options,
+        )?;
+        metrics::presigned_url("UploadPart");
+        events::server::publish(
+          UploadEventKind::PartSigned,
+          bucket,
+          key,
+          &upload_id,
+          Some(part_number),
+        );
+        Ok(PlannedPart {
+          part_number,
+          size: part_size.min(size - index * part_size),
+          url,
+        })
+      })
+      .collect::<Result<_, Error>>()?;
+
+    Ok(MultipartPlan {
+      upload_id,
+      key: None,
+      part_size,
+      parts,
+    })
+  }
+}
@@ -0,0 +1,457 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ArchiveBody {
+  /// Name of the bucket
+  pub bucket: String,
+  /// Keys of the objects to archive, named after their key in the archive
+  pub keys: Option<Vec<String>>,
+  /// Prefix of the objects to archive, named after their path relative to the prefix
+  pub prefix: Option<String>,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    body, concurrency_limit, metrics,
+    objects::key,
+    policy::{self, Identity, Operation},
+    request_builder, retry, telemetry,
+    zip::ZipArchive,
+    Error, S3Configuration,
+  };
+  use chrono::{DateTime, Utc};
+  use futures::TryStreamExt;
+  use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CreateMultipartUploadRequest, GetObjectRequest, ListObjectsV2Request, S3Client,
+    UploadPartRequest, S3,
+  };
+  use std::{convert::TryFrom, sync::Arc};
+  use tokio::sync::OwnedSemaphorePermit;
+  use tracing::Instrument;
+  use warp::{
+    hyper::{
+      body::{Bytes, Sender},
+      header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+      Body, Response, StatusCode,
+    },
+    Filter, Rejection, Reply,
+  };
+
+  const DEFAULT_ARCHIVE_NAME: &str = "archive";
+  const MAX_ARCHIVE_OBJECTS: usize = 10000;
+  /// Size of the parts of the stored archives, but the last one
+  const ARCHIVE_PART_SIZE: usize = 8 * 1024 * 1024;
+
+  /// Download objects as a ZIP archive
+  #[utoipa::path(
+    post,
+    path = "/objects/archive",
+    tag = "Objects",
+    request_body(
+      content = ArchiveBody,
+      description = "Keys or prefix of the objects to archive",
+      content_type = "application/json"
+    ),
+    responses(
+      (status = 200, description = "Streams the objects as an uncompressed ZIP archive", content_type = "application/zip"),
+      (status = 503, description = "Too many concurrent S3 calls, retry after the Retry-After delay"),
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "archive")
+      .and(warp::post())
+      .and(body::json::<ArchiveBody>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |body: ArchiveBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+          archive_objects(&s3_configuration, &identity, body)
+            .await
+            .map_err(warp::reject::custom)
+        },
+      )
+  }
+
+  /// Streams objects, given by their keys or their prefix, as a ZIP archive, on behalf of
+  /// `identity`.
+  pub async fn archive_objects(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut body: ArchiveBody,
+  ) -> Result<Response<Body>, Error> {
+    authorize_archive(s3_configuration, identity, &mut body)?;
+    handle_archive_objects(s3_configuration, body.bucket, body.keys, body.prefix).await
+  }
+
+  /// Checks that `identity` may read the objects to archive, given by their keys or their
+  /// prefix, scoping them under the prefix of its tenant.
+  pub(crate) fn authorize_archive(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    body: &mut ArchiveBody,
+  ) -> Result<(), Error> {
+    match (&mut body.keys, &mut body.prefix) {
+      (Some(keys), None) => {
+        if keys.is_empty() {
+          return Err(Error::InvalidParameter("no keys to archive".to_string()));
+        }
+        if keys.len() > MAX_ARCHIVE_OBJECTS {
+          return Err(Error::InvalidParameter(format!(
+            "more than {} keys to archive",
+            MAX_ARCHIVE_OBJECTS
+          )));
+        }
+        for key in keys {
+          identity.scope(key);
+          key::server::validate_key(s3_configuration, key)?;
+          policy::authorize(
+            s3_configuration,
+            identity,
+            Operation::Read,
+            &body.bucket,
+            key,
+          )?;
+        }
+      }
+      (None, Some(prefix)) => {
+        identity.scope(prefix);
+        key::server::validate_prefix(s3_configuration, prefix)?;
+        policy::authorize(
+          s3_configuration,
+          identity,
+          Operation::Read,
+          &body.bucket,
+          prefix,
+        )?;
+      }
+      _ => {
+        return Err(Error::InvalidParameter(
+          "either keys or prefix must be set".to_string(),
+        ))
+      }
+    }
+    Ok(())
+  }
+
+  /// Archives the objects, once authorized by [`authorize_archive`], into the object
+  /// `destination` of their bucket instead of a response, uploading the archive in parts as it is
+  /// written.
+  ///
+  /// Returns the size of the archive, in bytes.
+  pub(crate) async fn store_archive(
+    s3_configuration: &S3Configuration,
+    body: ArchiveBody,
+    destination: &str,
+  ) -> Result<u64, Error> {
+    let bucket = body.bucket.clone();
+    let archive = handle_archive_objects(s3_configuration, body.bucket, body.keys, body.prefix)
+      .await?
+      .into_body();
+
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    let client = S3Client::try_from(&s3_configuration)?;
+    let request = CreateMultipartUploadRequest {
+      bucket: bucket.clone(),
+      key: destination.to_string(),
+      content_type: Some("application/zip".to_string()),
+      ..Default::default()
+    };
+    let upload_id = retry::s3_call(&s3_configuration, "CreateMultipartUpload", || {
+      client
+        .create_multipart_upload(request.clone())
+        .instrument(telemetry::s3_span("CreateMultipartUpload"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("CreateMultipartUpload"))
+    .map_err(Error::MultipartUploadCreationError)?
+    .upload_id
+    .ok_or_else(|| Error::MultipartUploadError("no upload ID returned".to_string()))?;
+
+    let uploaded = upload_archive(
+      &s3_configuration,
+      &client,
+      &bucket,
+      destination,
+      &upload_id,
+      archive,
+    )
+    .await;
+    if uploaded.is_err() {
+      let request = AbortMultipartUploadRequest {
+        bucket: bucket.clone(),
+        key: destination.to_string(),
+        upload_id,
+        ..Default::default()
+      };
+      if let Err(error) = client.abort_multipart_upload(request).await {
+        log::error!(
+          "Cannot abort archive upload: bucket={}, key={}, {}",
+          bucket,
+          destination,
+          error
+        );
+      }
+    }
+    uploaded
+  }
+
+  /// Uploads the archive in parts of [`ARCHIVE_PART_SIZE`] bytes, then completes its upload.
+  async fn upload_archive(
+    s3_configuration: &S3Configuration,
+    client: &S3Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    mut archive: Body,
+  ) -> Result<u64, Error> {
+    let mut parts = vec![];
+    let mut part = Vec::with_capacity(ARCHIVE_PART_SIZE);
+    let mut size = 0;
+    loop {
+      let chunk = archive
+        .try_next()
+        .await
+        .map_err(|error| Error::ArchiveError(error.to_string()))?;
+      let end = chunk.is_none();
+      if let Some(chunk) = chunk {
+        size += chunk.len() as u64;
+        part.extend_from_slice(&chunk);
+      }
+      // The last part may be smaller, or even empty when it is the only one.
+      if part.len() >= ARCHIVE_PART_SIZE || (end && (!part.is_empty() || parts.is_empty())) {
+        let part_number = parts.len() as i64 + 1;
+        let request = UploadPartRequest {
+          bucket: bucket.to_string(),
+          key: key.to_string(),
+          upload_id: upload_id.to_string(),
+          part_number,
+          content_length: Some(part.len() as i64),
+          body: Some(std::mem::take(&mut part).into()),
+          ..Default::default()
+        };
+        let output = client
+          .upload_part(request)
+          .instrument(telemetry::s3_span("UploadPart"))
+          .await
+          .map_err(metrics::count_s3_error("UploadPart"))
+          .map_err(Error::UploadPartError)?;
+        parts.push(CompletedPart {
+          e_tag: output.e_tag,
+          part_number: Some(part_number),
+        });
+      }
+      if end {
+        break;
+      }
+    }
+
+    let request = CompleteMultipartUploadRequest {
+      bucket: bucket.to_string(),
+      key: key.to_string(),
+      upload_id: upload_id.to_string(),
+      multipart_upload: Some(CompletedMultipartUpload { parts: Some(parts) }),
+      ..Default::default()
+    };
+    retry::s3_call(s3_configuration, "CompleteMultipartUpload", || {
+      client
+        .complete_multipart_upload(request.clone())
+        .instrument(telemetry::s3_span("CompleteMultipartUpload"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("CompleteMultipartUpload"))
+    .map_err(Error::MultipartUploadCompletionError)?;
+    log::info!(
+      "Stored archive: bucket={}, key={}, size={}",
+      bucket,
+      key,
+      size
+    );
+    Ok(size)
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_archive_objects(
+    s3_configuration: &S3Configuration,
+    bucket: String,
+    keys: Option<Vec<String>>,
+    prefix: Option<String>,
+  ) -> Result<Response<Body>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    let permit = concurrency_limit::acquire(&s3_configuration)?;
+    let client = S3Client::try_from(&s3_configuration)?;
+
+    let (archive_name, entries) = match (keys, prefix) {
+      (Some(keys), _) => {
+        log::info!("Archive objects: bucket={}, keys={}", bucket, keys.len());
+        let entries = keys.into_iter().map(|key| (key.clone(), key)).collect();
+        (DEFAULT_ARCHIVE_NAME.to_string(), entries)
+      }
+      (None, prefix) => {
+        let prefix = prefix.unwrap_or_default();
+        let prefix = if prefix.is_empty() || prefix.ends_with('/') {
+          prefix
+        } else {
+          format!("{}/", prefix)
+        };
+        log::info!("Archive objects: bucket={}, prefix={}", bucket, prefix);
+        let archive_name = prefix
+          .trim_end_matches('/')
+          .rsplit('/')
+          .next()
+          .filter(|name| !name.is_empty())
+          .unwrap_or(DEFAULT_ARCHIVE_NAME)
+          .to_string();
+        let entries = list_prefix(&s3_configuration, &client, &bucket, &prefix).await?;
+        (archive_name, entries)
+      }
+    };
+
+    // The objects are fetched once the response started: their errors interrupt it.
+    let (sender, body) = Body::channel();
+    tokio::spawn(async move {
+      if let Err(error) = write_archive(&client, &bucket, entries, sender, permit).await {
+        log::error!("Archive stream interrupted: bucket={}, {}", bucket, error);
+      }
+    });
+
+    request_builder()
+      .header(CONTENT_TYPE, "application/zip")
+      .header(
+        CONTENT_DISPOSITION,
+        format!(
+          "attachment; filename=\"{}.zip\"",
+          archive_name.replace(|c: char| c == '"' || c == '\\' || c.is_control(), "_")
+        ),
+      )
+      .status(StatusCode::OK)
+      .body(body)
+      .map_err(Error::HttpError)
+  }
+
+  /// Keys of the objects under a prefix, with their path relative to the prefix.
+  async fn list_prefix(
+    s3_configuration: &S3Configuration,
+    client: &S3Client,
+    bucket: &str,
+    prefix: &str,
+  ) -> Result<Vec<(String, String)>, Error> {
+    let mut entries = vec![];
+    let mut continuation_token = None;
+    loop {
+      let request = ListObjectsV2Request {
+        bucket: bucket.to_string(),
+        prefix: Some(prefix.to_string()),
+        continuation_token: continuation_token.take(),
+        ..Default::default()
+      };
+      let response = retry::s3_call(s3_configuration, "ListObjectsV2", || {
+        client
+          .list_objects_v2(request.clone())
+          .instrument(telemetry::s3_span("ListObjectsV2"))
+      })
+      .await
+      .map_err(metrics::count_s3_error("ListObjectsV2"))
+      .map_err(Error::ListObjectsError)?;
+
+      entries.extend(
+        response
+          .contents
+          .unwrap_or_default()
+          .into_iter()
+          .filter_map(|content| content.key)
+          // Folder markers
+          .filter(|key| !key.ends_with('/'))
+          .map(|key| (key[prefix.len()..].to_string(), key)),
+      );
+
+      if entries.len() > MAX_ARCHIVE_OBJECTS {
+        return Err(Error::InvalidParameter(format!(
+          "more than {} objects under prefix {}",
+          MAX_ARCHIVE_OBJECTS, prefix
+        )));
+      }
+
+      continuation_token = response.next_continuation_token;
+      if !response.is_truncated.unwrap_or_default() || continuation_token.is_none() {
+        break;
+      }
+    }
+
+    if entries.is_empty() {
+      return Err(Error::NotFound(format!(
+        "no objects under prefix {}",
+        prefix
+      )));
+    }
+    Ok(entries)
+  }
+
+  /// Fetches the objects one after the other, sending them as the entries of the archive.
+  ///
+  /// The concurrency slot is held until the archive is sent.
+  async fn write_archive(
+    client: &S3Client,
+    bucket: &str,
+    entries: Vec<(String, String)>,
+    mut sender: Sender,
+    _permit: Option<OwnedSemaphorePermit>,
+  ) -> Result<(), Error> {
+    let mut archive = ZipArchive::default();
+    let result = async {
+      for (name, key) in entries {
+        let output = client
+          .get_object(GetObjectRequest {
+            bucket: bucket.to_string(),
+            key: key.clone(),
+            ..Default::default()
+          })
+          .instrument(telemetry::s3_span("GetObject"))
+          .await
+          .map_err(metrics::count_s3_error("GetObject"))
+          .map_err(Error::GetObjectError)?;
+
+        let modified = output
+          .last_modified
+          .and_then(|last_modified| DateTime::parse_from_rfc2822(&last_modified).ok())
+          .map(|last_modified| last_modified.with_timezone(&Utc));
+        send(&mut sender, archive.start_entry(&name, modified)).await?;
+
+        if let Some(mut content) = output.body {
+          while let Some(chunk) = content
+            .try_next()
+            .await
+            .map_err(|error| Error::ArchiveError(format!("key={}, {}", key, error)))?
+          {
+            archive.write(&chunk);
+            send(&mut sender, chunk).await?;
+          }
+        }
+        send(&mut sender, archive.end_entry()).await?;
+      }
+
+      send(&mut sender, archive.finish()).await
+    }
+    .await;
+
+    if result.is_err() {
+      // Signals the client that the archive is incomplete
+      sender.abort();
+    }
+    result
+  }
+
+  async fn send(sender: &mut Sender, data: impl Into<Bytes>) -> Result<(), Error> {
+    sender
+      .send_data(data.into())
+      .await
+      .map_err(|error| Error::ArchiveError(error.to_string()))
+  }
+}
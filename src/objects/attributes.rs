@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ObjectAttributesQueryParameters {
+  pub bucket: String,
+  pub path: String,
+  pub version_id: Option<String>,
+  pub max_parts: Option<i64>,
+  pub part_number_marker: Option<i64>,
+}
+
+/// Attributes of an object, as answered by GetObjectAttributes.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ObjectAttributesResponse {
+  pub bucket: String,
+  pub path: String,
+  pub version_id: Option<String>,
+  pub last_modified: Option<String>,
+  /// ETag of the object, without quotes
+  pub etag: Option<String>,
+  pub size: Option<i64>,
+  pub storage_class: Option<String>,
+  /// Checksum of the object, when uploaded with one
+  pub checksum: Option<ObjectChecksum>,
+  /// Parts of an object uploaded in several parts
+  pub parts: Option<ObjectParts>,
+}
+
+/// Base64-encoded checksums of an object, or of a part.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ObjectChecksum {
+  pub crc32: Option<String>,
+  pub crc32c: Option<String>,
+  pub sha1: Option<String>,
+  pub sha256: Option<String>,
+}
+
+/// Page of the parts of an object uploaded in several parts.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ObjectParts {
+  pub total_parts_count: Option<i64>,
+  pub part_number_marker: Option<i64>,
+  /// Marker of the next page, when truncated
+  pub next_part_number_marker: Option<i64>,
+  pub max_parts: Option<i64>,
+  pub is_truncated: bool,
+  /// Parts of the page, only listed by S3 for objects uploaded with checksums
+  pub parts: Vec<ObjectPart>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ObjectPart {
+  pub part_number: i64,
+  pub size: Option<i64>,
+  pub checksum: Option<ObjectChecksum>,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    json_reply, metrics,
+    objects::key,
+    policy::{self, Identity, Operation},
+    query, retry, telemetry, Error, S3Configuration,
+  };
+  use rusoto_core::{
+    param::{Params, ServiceParams},
+    signature::SignedRequest,
+    Client, RusotoError,
+  };
+  use rusoto_s3::GetObjectError;
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{Filter, Rejection, Reply};
+  use xml::reader::{EventReader, XmlEvent};
+
+  /// Attributes requested from S3, all of them
+  const OBJECT_ATTRIBUTES: &str = "ETag,Checksum,ObjectParts,StorageClass,ObjectSize";
+
+  /// Get the attributes of an object
+  #[utoipa::path(
+    get,
+    path = "/objects/attributes",
+    tag = "Objects",
+    responses(
+      (
+        status = 200,
+        description = "Returns the checksum, parts, storage class and size of the object",
+        content_type = "application/json",
+        body = ObjectAttributesResponse
+      ),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object"),
+      ("version_id" = Option<String>, Query, description = "Version of the object (default: latest)"),
+      ("max_parts" = Option<i64>, Query, description = "Maximum number of parts listed (1000 by default)"),
+      ("part_number_marker" = Option<i64>, Query, description = "Number of the part after which the parts are listed")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "attributes")
+      .and(warp::get())
+      .and(query::parameters::<ObjectAttributesQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: ObjectAttributesQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(get_object_attributes(&s3_configuration, &identity, parameters).await)
+        },
+      )
+  }
+
+  /// Gets the attributes of an object, on behalf of `identity`.
+  pub async fn get_object_attributes(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ObjectAttributesQueryParameters,
+  ) -> Result<ObjectAttributesResponse, Error> {
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    handle_get_object_attributes(s3_configuration, parameters).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_get_object_attributes(
+    s3_configuration: &S3Configuration,
+    parameters: ObjectAttributesQueryParameters,
+  ) -> Result<ObjectAttributesResponse, Error> {
+    if matches!(parameters.max_parts, Some(max_parts) if !(1..=1000).contains(&max_parts)) {
+      return Err(Error::InvalidParameter(
+        "max_parts must be between 1 and 1000".to_string(),
+      ));
+    }
+
+    let s3_configuration = s3_configuration.for_bucket(&parameters.bucket);
+    log::info!(
+      "Get object attributes: bucket={}, key={}",
+      parameters.bucket,
+      parameters.path
+    );
+    let client = Client::try_from(&s3_configuration)?;
+    retry::s3_call(&s3_configuration, "GetObjectAttributes", || {
+      get_attributes(&client, &s3_configuration, &parameters)
+        .instrument(telemetry::s3_span("GetObjectAttributes"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("GetObjectAttributes"))
+    .map_err(Error::ObjectAttributesError)
+  }
+
+  /// Sends the GetObjectAttributes request, which rusoto does not implement, its errors being
+  /// the ones of GetObject.
+  async fn get_attributes(
+    client: &Client,
+    s3_configuration: &S3Configuration,
+    parameters: &ObjectAttributesQueryParameters,
+  ) -> Result<ObjectAttributesResponse, RusotoError<GetObjectError>> {
+    let mut request = SignedRequest::new(
+      "GET",
+      "s3",
+      s3_configuration.region(),
+      &format!("/{}/{}", parameters.bucket, parameters.path),
+    );
+    let mut params = Params::new();
+    params.put_key("attributes");
+    if let Some(version_id) = &parameters.version_id {
+      params.put("versionId", version_id);
+    }
+    request.set_params(params);
+    request.add_header("x-amz-object-attributes", OBJECT_ATTRIBUTES);
+    if let Some(max_parts) = parameters.max_parts {
+      request.add_header("x-amz-max-parts", &max_parts.to_string());
+    }
+    if let Some(part_number_marker) = parameters.part_number_marker {
+      request.add_header("x-amz-part-number-marker", &part_number_marker.to_string());
+    }
+
+    let mut response = client.sign_and_dispatch(request).await?;
+    let response = response.buffer().await.map_err(RusotoError::HttpDispatch)?;
+    if !response.status.is_success() {
+      return Err(GetObjectError::from_response(response));
+    }
+
+    let mut attributes = parse_attributes(&response.body).map_err(RusotoError::ParseError)?;
+    attributes.bucket = parameters.bucket.clone();
+    attributes.path = parameters.path.clone();
+    attributes.version_id = response.headers.get("x-amz-version-id").cloned();
+    attributes.last_modified = response.headers.get("last-modified").cloned();
+    Ok(attributes)
+  }
+
+  /// Parses the `GetObjectAttributesResponse` document of S3.
+  fn parse_attributes(body: &[u8]) -> Result<ObjectAttributesResponse, String> {
+    let mut attributes = ObjectAttributesResponse::default();
+    let mut path: Vec<String> = vec![];
+
+    for event in EventReader::new(body) {
+      match event.map_err(|error| error.to_string())? {
+        XmlEvent::StartElement { name, .. } => {
+          match (path.last().map(String::as_str), name.local_name.as_str()) {
+            (_, "ObjectParts") => {
+              attributes.parts.get_or_insert_with(Default::default);
+            }
+            (Some("ObjectParts"), "Part") => {
+              if let Some(parts) = &mut attributes.parts {
+                parts.parts.push(ObjectPart::default());
+              }
+            }
+            _ => {}
+          }
+          path.push(name.local_name);
+        }
+        XmlEvent::Characters(text) => {
+          let elements: Vec<&str> = path.iter().skip(1).map(String::as_str).collect();
+          let number = || text.parse::<i64>().map_err(|error| error.to_string());
+          match elements.as_slice() {
+            ["ETag"] => attributes.etag = Some(text.trim_matches('"').to_string()),
+            ["ObjectSize"] => attributes.size = Some(number()?),
+            ["StorageClass"] => attributes.storage_class = Some(text),
+            ["Checksum", algorithm] => set_checksum(
+              attributes.checksum.get_or_insert_with(Default::default),
+              algorithm,
+              text,
+            ),
+            ["ObjectParts", element] => {
+              let parts = attributes.parts.get_or_insert_with(Default::default);
+              match *element {
+                "TotalPartsCount" | "PartsCount" => parts.total_parts_count = Some(number()?),
+                "PartNumberMarker" => parts.part_number_marker = Some(number()?),
+                "NextPartNumberMarker" => parts.next_part_number_marker = Some(number()?),
+                "MaxParts" => parts.max_parts = Some(number()?),
+                "IsTruncated" => parts.is_truncated = text == "true",
+                _ => {}
+              }
+            }
+            ["ObjectParts", "Part", element] => {
+              let part = attributes
+                .parts
+                .as_mut()
+                .and_then(|parts| parts.parts.last_mut())
+                .ok_or_else(|| "Part outside of ObjectParts".to_string())?;
+              match *element {
+                "PartNumber" => part.part_number = number()?,
+                "Size" => part.size = Some(number()?),
+                algorithm => set_checksum(
+                  part.checksum.get_or_insert_with(Default::default),
+                  algorithm,
+                  text,
+                ),
+              }
+            }
+            _ => {}
+          }
+        }
+        XmlEvent::EndElement { .. } => {
+          path.pop();
+        }
+        _ => {}
+      }
+    }
+    Ok(attributes)
+  }
+
+  fn set_checksum(checksum: &mut ObjectChecksum, element: &str, text: String) {
+    match element {
+      "ChecksumCRC32" => checksum.crc32 = Some(text),
+      "ChecksumCRC32C" => checksum.crc32c = Some(text),
+      "ChecksumSHA1" => checksum.sha1 = Some(text),
+      "ChecksumSHA256" => checksum.sha256 = Some(text),
+      _ => {}
+    }
+  }
+}
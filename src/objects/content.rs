@@ -0,0 +1,480 @@
+use crate::encryption::ServerSideEncryption;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ObjectContentQueryParameters {
+  pub bucket: String,
+  pub path: String,
+  pub sse: Option<ServerSideEncryption>,
+  pub kms_key_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ObjectContentResponse {
+  /// ETag of the uploaded object
+  pub etag: Option<String>,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    concurrency_limit,
+    encryption::{self, ServerSideEncryption},
+    limits::{MAX_OBJECT_SIZE, MAX_PARTS, MIN_PART_SIZE},
+    listing_cache, metrics,
+    objects::key,
+    policy::{self, Identity, Operation},
+    query, quotas, telemetry, to_ok_json_response, Error, S3Configuration,
+  };
+  use futures::{Stream, TryStreamExt};
+  use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CreateMultipartUploadRequest, PutObjectRequest, S3Client, UploadPartRequest, S3,
+  };
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{
+    hyper::{body::Buf, header::CONTENT_TYPE, Body, Response},
+    Filter, Rejection, Reply,
+  };
+
+  /// Size of the parts of the uploads of unknown length, in bytes
+  const DEFAULT_PART_SIZE: u64 = 8 * 1024 * 1024;
+  /// Size of the parts held in memory at most, bounding the size of the uploaded objects
+  const MAX_PART_SIZE: u64 = 32 * 1024 * 1024;
+  /// Size of the largest object uploaded through the signer, in parts of [`MAX_PART_SIZE`]
+  const MAX_CONTENT_LENGTH: u64 = MAX_PARTS as u64 * MAX_PART_SIZE;
+
+  /// Upload object content through the signer
+  #[utoipa::path(
+    put,
+    path = "/objects/content",
+    tag = "Objects",
+    request_body(
+      content = String,
+      description = "Content of the object",
+      content_type = "application/octet-stream"
+    ),
+    responses(
+      (
+        status = 200,
+        description = "Successfully uploaded the object",
+        content_type = "application/json",
+        body = ObjectContentResponse
+      ),
+      (status = 503, description = "Too many concurrent S3 calls, retry after the Retry-After delay"),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object to upload"),
+      ("sse" = Option<ServerSideEncryption>, Query, description = "Server-side encryption of the object (AES256 or aws:kms)"),
+      ("kms_key_id" = Option<String>, Query, description = "ID of the KMS key used with aws:kms server-side encryption"),
+      ("Content-Type" = Option<String>, Header, description = "Content type of the object"),
+      ("Content-Length" = Option<u64>, Header, description = "Size of the object, to size the parts of large uploads")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "content")
+      .and(warp::put())
+      .and(query::parameters::<ObjectContentQueryParameters>())
+      .and(warp::header::optional::<String>(CONTENT_TYPE.as_str()))
+      .and(warp::header::optional::<u64>("content-length"))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and(warp::body::stream())
+      .and_then(
+        |parameters: ObjectContentQueryParameters,
+         content_type: Option<String>,
+         content_length: Option<u64>,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>,
+         body| async move {
+          put_object_content(
+            &s3_configuration,
+            &identity,
+            parameters,
+            content_type,
+            content_length,
+            body,
+          )
+          .await
+          .map_err(warp::reject::custom)
+        },
+      )
+  }
+
+  /// Uploads the content of an object streamed in `body`, on behalf of `identity`.
+  ///
+  /// Bodies larger than a part (sized after `content_length` when known, up to 32 MiB) are
+  /// uploaded as a multipart upload, buffering one part at a time.
+  pub async fn put_object_content<S, B>(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ObjectContentQueryParameters,
+    content_type: Option<String>,
+    content_length: Option<u64>,
+    body: S,
+  ) -> Result<Response<Body>, Error>
+  where
+    S: Stream<Item = Result<B, warp::Error>> + Unpin,
+    B: Buf,
+  {
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Write,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+    encryption::server::check_parameters(parameters.sse, &parameters.kms_key_id)?;
+    if content_length.unwrap_or_default() > MAX_OBJECT_SIZE {
+      return Err(Error::InvalidParameter(format!(
+        "objects cannot exceed {} bytes",
+        MAX_OBJECT_SIZE
+      )));
+    }
+    if content_length.unwrap_or_default() > MAX_CONTENT_LENGTH {
+      return Err(Error::InvalidParameter(format!(
+        "objects uploaded through the signer cannot exceed {} bytes",
+        MAX_CONTENT_LENGTH
+      )));
+    }
+    handle_put_object_content(
+      s3_configuration,
+      parameters,
+      content_type,
+      content_length,
+      body,
+    )
+    .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_put_object_content<S, B>(
+    s3_configuration: &S3Configuration,
+    parameters: ObjectContentQueryParameters,
+    content_type: Option<String>,
+    content_length: Option<u64>,
+    mut body: S,
+  ) -> Result<Response<Body>, Error>
+  where
+    S: Stream<Item = Result<B, warp::Error>> + Unpin,
+    B: Buf,
+  {
+    let ObjectContentQueryParameters {
+      bucket,
+      path: key,
+      sse,
+      kms_key_id,
+    } = parameters;
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    quotas::check(&s3_configuration, &bucket, &key)?;
+    let _permit = concurrency_limit::acquire(&s3_configuration)?;
+    log::info!(
+      "Put object content: bucket={}, key={}, content_length={:?}",
+      bucket,
+      key,
+      content_length
+    );
+    let client = S3Client::try_from(&s3_configuration)?;
+
+    let part_size = content_length
+      .map(|content_length| content_length.div_ceil(MAX_PARTS as u64))
+      .unwrap_or_default()
+      .clamp(DEFAULT_PART_SIZE.max(MIN_PART_SIZE), MAX_PART_SIZE) as usize;
+    let upload = Upload {
+      client: &client,
+      bucket: &bucket,
+      key: &key,
+      content_type,
+      sse,
+      kms_key_id,
+    };
+
+    let mut buffer = vec![];
+    let first_part = next_part(&mut body, &mut buffer, part_size)
+      .await?
+      .unwrap_or_default();
+    let etag = match next_part(&mut body, &mut buffer, part_size).await? {
+      None => upload.put_object(first_part).await?,
+      Some(second_part) => {
+        let upload_id = upload.create_multipart_upload().await?;
+        metrics::multipart_upload_started();
+        let result = upload
+          .upload_parts(
+            &upload_id,
+            first_part,
+            second_part,
+            &mut body,
+            &mut buffer,
+            part_size,
+          )
+          .await;
+        if result.is_err() {
+          upload.abort_multipart_upload(&upload_id).await;
+        }
+        metrics::multipart_upload_ended();
+        result?
+      }
+    };
+
+    // The object shows up in the listings of its prefixes.
+    listing_cache::invalidate(&s3_configuration, &bucket, &key);
+    quotas::account_upload(&s3_configuration, &bucket, &key).await;
+    to_ok_json_response(&ObjectContentResponse { etag })
+  }
+
+  /// Reads the next part of the body: `part_size` bytes, or the rest of the body at its end.
+  async fn next_part<S, B>(
+    body: &mut S,
+    buffer: &mut Vec<u8>,
+    part_size: usize,
+  ) -> Result<Option<Vec<u8>>, Error>
+  where
+    S: Stream<Item = Result<B, warp::Error>> + Unpin,
+    B: Buf,
+  {
+    while buffer.len() < part_size {
+      let mut chunk = match body
+        .try_next()
+        .await
+        .map_err(|error| Error::UploadError(error.to_string()))?
+      {
+        Some(chunk) => chunk,
+        None => break,
+      };
+      while chunk.has_remaining() {
+        let bytes = chunk.chunk();
+        buffer.extend_from_slice(bytes);
+        let length = bytes.len();
+        chunk.advance(length);
+      }
+    }
+
+    if buffer.is_empty() {
+      return Ok(None);
+    }
+    let rest = buffer.split_off(buffer.len().min(part_size));
+    Ok(Some(std::mem::replace(buffer, rest)))
+  }
+
+  /// Object being uploaded through the signer.
+  struct Upload<'a> {
+    client: &'a S3Client,
+    bucket: &'a str,
+    key: &'a str,
+    content_type: Option<String>,
+    sse: Option<ServerSideEncryption>,
+    kms_key_id: Option<String>,
+  }
+
+  impl Upload<'_> {
+    async fn put_object(&self, content: Vec<u8>) -> Result<Option<String>, Error> {
+      let output = self
+        .client
+        .put_object(PutObjectRequest {
+          bucket: self.bucket.to_string(),
+          key: self.key.to_string(),
+          content_length: Some(content.len() as i64),
+          body: Some(content.into()),
+          content_type: self.content_type.clone(),
+          server_side_encryption: self.sse.map(|sse| sse.as_str().to_string()),
+          ssekms_key_id: self.kms_key_id.clone(),
+          ..Default::default()
+        })
+        .instrument(telemetry::s3_span("PutObject"))
+        .await
+        .map_err(metrics::count_s3_error("PutObject"))
+        .map_err(Error::PutObjectError)?;
+      Ok(output.e_tag)
+    }
+
+    async fn create_multipart_upload(&self) -> Result<String, Error> {
+      self
+        .client
+        .create_multipart_upload(CreateMultipartUploadRequest {
+          bucket: self.bucket.to_string(),
+          key: self.key.to_string(),
+          content_type: self.content_type.clone(),
+          server_side_encryption: self.sse.map(|sse| sse.as_str().to_string()),
+          ssekms_key_id: self.kms_key_id.clone(),
+          ..Default::default()
+        })
+        .instrument(telemetry::s3_span("CreateMultipartUpload"))
+        .await
+        .map_err(metrics::count_s3_error("CreateMultipartUpload"))
+        .map_err(Error::MultipartUploadCreationError)?
+        .upload_id
+        .ok_or_else(|| {
+          Error::MultipartUploadError("Invalid multipart upload creation response".to_string())
+        })
+    }
+
+    /// Uploads the parts of the body, from its first two parts, and completes the upload.
+    async fn upload_parts<S, B>(
+      &self,
+      upload_id: &str,
+      first_part: Vec<u8>,
+      second_part: Vec<u8>,
+      body: &mut S,
+      buffer: &mut Vec<u8>,
+      part_size: usize,
+    ) -> Result<Option<String>, Error>
+    where
+      S: Stream<Item = Result<B, warp::Error>> + Unpin,
+      B: Buf,
+    {
+      let mut completed_parts = vec![self.upload_part(upload_id, 1, first_part).await?];
+      let mut next = Some(second_part);
+      while let Some(part) = next {
+        let part_number = completed_parts.len() as i64 + 1;
+        if part_number > MAX_PARTS {
+          return Err(Error::InvalidParameter(format!(
+            "uploads of unknown length cannot exceed {} parts of {} bytes",
+            MAX_PARTS, part_size
+          )));
+        }
+        completed_parts.push(self.upload_part(upload_id, part_number, part).await?);
+        next = next_part(body, buffer, part_size).await?;
+      }
+
+      let output = self
+        .client
+        .complete_multipart_upload(CompleteMultipartUploadRequest {
+          bucket: self.bucket.to_string(),
+          key: self.key.to_string(),
+          upload_id: upload_id.to_string(),
+          multipart_upload: Some(CompletedMultipartUpload {
+            parts: Some(completed_parts),
+          }),
+          ..Default::default()
+        })
+        .instrument(telemetry::s3_span("CompleteMultipartUpload"))
+        .await
+        .map_err(metrics::count_s3_error("CompleteMultipartUpload"))
+        .map_err(crate::error::completion_error)?;
+      Ok(output.e_tag)
+    }
+
+    async fn upload_part(
+      &self,
+      upload_id: &str,
+      part_number: i64,
+      content: Vec<u8>,
+    ) -> Result<CompletedPart, Error> {
+      let output = self
+        .client
+        .upload_part(UploadPartRequest {
+          bucket: self.bucket.to_string(),
+          key: self.key.to_string(),
+          upload_id: upload_id.to_string(),
+          part_number,
+          content_length: Some(content.len() as i64),
+          body: Some(content.into()),
+          ..Default::default()
+        })
+        .instrument(telemetry::s3_span("UploadPart"))
+        .await
+        .map_err(metrics::count_s3_error("UploadPart"))
+        .map_err(Error::UploadPartError)?;
+      Ok(CompletedPart {
+        part_number: Some(part_number),
+        e_tag: output.e_tag,
+      })
+    }
+
+    /// Aborts a failed upload, so that its parts are not kept (and billed).
+    async fn abort_multipart_upload(&self, upload_id: &str) {
+      let result = self
+        .client
+        .abort_multipart_upload(AbortMultipartUploadRequest {
+          bucket: self.bucket.to_string(),
+          key: self.key.to_string(),
+          upload_id: upload_id.to_string(),
+          ..Default::default()
+        })
+        .instrument(telemetry::s3_span("AbortMultipartUpload"))
+        .await
+        .map_err(metrics::count_s3_error("AbortMultipartUpload"));
+      if let Err(error) = result {
+        log::warn!(
+          "Could not abort the failed upload: upload_id={}, error={}",
+          upload_id,
+          error
+        );
+      }
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+    use warp::hyper::body::Bytes;
+
+    fn body(chunks: &[&'static [u8]]) -> impl Stream<Item = Result<Bytes, warp::Error>> + Unpin {
+      futures::stream::iter(
+        chunks
+          .iter()
+          .map(|chunk| Ok(Bytes::from_static(chunk)))
+          .collect::<Vec<_>>(),
+      )
+    }
+
+    #[tokio::test]
+    async fn bodies_exceeding_the_parts_held_in_memory_are_rejected() {
+      let s3_configuration =
+        S3Configuration::new_with_hostname("access", "secret", "us-east-1", "http://127.0.0.1:9")
+          .unwrap();
+      let identity = Identity::from_headers(&s3_configuration, &Default::default())
+        .await
+        .unwrap();
+
+      let result = put_object_content(
+        &s3_configuration,
+        &identity,
+        ObjectContentQueryParameters {
+          bucket: "bucket".to_string(),
+          path: "large.bin".to_string(),
+          sse: None,
+          kms_key_id: None,
+        },
+        None,
+        Some(MAX_CONTENT_LENGTH + 1),
+        body(&[]),
+      )
+      .await;
+      assert!(
+        matches!(result, Err(Error::InvalidParameter(_))),
+        "{:?}",
+        result.map(|response| response.status())
+      );
+    }
+
+    #[tokio::test]
+    async fn parts_are_cut_from_the_chunks_of_the_body() {
+      let mut body = body(&[b"abc", b"defgh", b"ij"]);
+      let mut buffer = vec![];
+      assert_eq!(
+        next_part(&mut body, &mut buffer, 4).await.unwrap(),
+        Some(b"abcd".to_vec())
+      );
+      assert_eq!(
+        next_part(&mut body, &mut buffer, 4).await.unwrap(),
+        Some(b"efgh".to_vec())
+      );
+      assert_eq!(
+        next_part(&mut body, &mut buffer, 4).await.unwrap(),
+        Some(b"ij".to_vec())
+      );
+      assert_eq!(next_part(&mut body, &mut buffer, 4).await.unwrap(), None);
+    }
+  }
+}
@@ -1,13 +1,22 @@
-use crate::{objects::SignQueryParameters, to_redirect_response, S3Configuration};
-use rusoto_credential::AwsCredentials;
-use rusoto_s3::{
-  util::{PreSignedRequest, PreSignedRequestOption},
-  PutObjectRequest,
-};
-use warp::{
-  hyper::{Body, Response},
-  Filter, Rejection, Reply,
+use crate::{
+  cache_headers,
+  encryption::{
+    self,
+    server::{customer_key, CustomerKey},
+  },
+  hypermedia, key_template, metrics,
+  objects::{self, key, CreateQueryParameters, PresignedUrlResponse},
+  policy::{self, Identity, Operation},
+  presign::{self, PresignOptions},
+  query, quotas, transfer_acceleration, Error, S3Configuration,
 };
+use std::sync::Arc;
+use warp::{hyper::header::ACCEPT, Filter, Rejection, Reply};
+
+const CONTENT_MD5_HEADER: &str = "Content-MD5";
+const CHECKSUM_SHA256_HEADER: &str = "x-amz-checksum-sha256";
+const MD5_LENGTH: usize = 16;
+const SHA256_LENGTH: usize = 32;
 
 /// Pre-sign object creation URL
 #[utoipa::path(
@@ -15,12 +24,28 @@ use warp::{
   path = "/objects",
   tag = "Objects",
   responses(
+    (
+      status = 200,
+      description = "Returns the pre-signed URL for object creation, when JSON is accepted",
+      content_type = "application/json",
+      body = PresignedUrlResponse
+    ),
     (status = 302, description = "Redirect to pre-signed URL for object creation"),
   ),
   params(
     ("bucket" = String, Query, description = "Name of the bucket"),
-    ("path" = String, Query, description = "Key of the object to create")
+    ("path" = String, Query, description = "Key of the object to create, or its file name when the bucket has a key template"),
+    ("accelerate" = Option<bool>, Query, description = "Sign against the S3 Transfer Acceleration endpoint"),
+    ("sse" = Option<ServerSideEncryption>, Query, description = "Server-side encryption of the object (AES256 or aws:kms)"),
+    ("kms_key_id" = Option<String>, Query, description = "ID of the KMS key used with aws:kms server-side encryption"),
+    ("cache_control" = Option<String>, Query, description = "`Cache-Control` header of the object, signed into the upload"),
+    ("expires" = Option<String>, Query, description = "`Expires` header of the object as an HTTP date, signed into the upload"),
+    ("content_md5" = Option<String>, Query, description = "Base64-encoded MD5 digest of the object, signed as the `Content-MD5` header of the upload"),
+    ("checksum_sha256" = Option<String>, Query, description = "Base64-encoded SHA-256 checksum of the object, signed as the `x-amz-checksum-sha256` header of the upload"),
+    ("Accept" = Option<String>, Header, description = "Answer the pre-signed URL as JSON rather than redirecting to it, with `application/json`"),
+    ("x-amz-server-side-encryption-customer-key" = Option<String>, Header, description = "Base64-encoded SSE-C key of the object, to send again along with the pre-signed request")
   ),
+  security(("bearer_token" = []), ("api_key" = [])),
 )]
 pub(crate) fn route(
   s3_configuration: &S3Configuration,
@@ -28,34 +53,158 @@ pub(crate) fn route(
   let s3_configuration = s3_configuration.clone();
   warp::path("objects")
     .and(warp::post())
-    .and(warp::query::<SignQueryParameters>())
-    .and(warp::any().map(move || s3_configuration.clone()))
+    .and(query::parameters::<CreateQueryParameters>())
+    .and(warp::header::optional::<String>(ACCEPT.as_str()))
+    .and(customer_key())
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
     .and_then(
-      |parameters: SignQueryParameters, s3_configuration: S3Configuration| async move {
-        handle_create_object_signed_url(s3_configuration, parameters.bucket, parameters.path).await
+      |parameters: CreateQueryParameters,
+       accept: Option<String>,
+       customer_key: Option<CustomerKey>,
+       identity: Identity,
+       s3_configuration: Arc<S3Configuration>| async move {
+        let (bucket, path) = (parameters.bucket.clone(), parameters.path.clone());
+        create_object(&s3_configuration, &identity, parameters, customer_key)
+          .await
+          .and_then(|presigned_url| {
+            let key = presigned_url.key.as_deref().unwrap_or(&path);
+            hypermedia::upload_response(
+              &s3_configuration,
+              &presigned_url,
+              accept.as_deref(),
+              &bucket,
+              key,
+            )
+          })
+          .map_err(warp::reject::custom)
       },
     )
 }
 
+/// Pre-signs the URL to create an object, on behalf of `identity`.
+///
+/// The route redirects clients to the URL, unless their `Accept` header asks for JSON. In buckets
+/// with a key template, the key is generated from the file name of the path and answered along
+/// with the URL.
+pub async fn create_object(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  mut parameters: CreateQueryParameters,
+  customer_key: Option<CustomerKey>,
+) -> Result<PresignedUrlResponse, Error> {
+  let generated_key = key_template::generate_key(
+    s3_configuration,
+    identity,
+    Operation::Write,
+    &parameters.bucket,
+    &parameters.path,
+  )
+  .await?;
+  if let Some(generated_key) = &generated_key {
+    parameters.path = generated_key.clone();
+  }
+  identity.scope(&mut parameters.path);
+  key::server::validate_key(s3_configuration, &parameters.path)?;
+  policy::authorize(
+    s3_configuration,
+    identity,
+    Operation::Write,
+    &parameters.bucket,
+    &parameters.path,
+  )?;
+  quotas::check(
+    &s3_configuration.for_bucket(&parameters.bucket),
+    &parameters.bucket,
+    &parameters.path,
+  )?;
+  let mut presigned_url =
+    handle_create_object_signed_url(s3_configuration, parameters, customer_key).await?;
+  presigned_url.key = generated_key;
+  Ok(presigned_url)
+}
+
+#[tracing::instrument(skip_all)]
 async fn handle_create_object_signed_url(
-  s3_configuration: S3Configuration,
-  bucket: String,
-  key: String,
-) -> Result<Response<Body>, Rejection> {
+  s3_configuration: &S3Configuration,
+  parameters: CreateQueryParameters,
+  customer_key: Option<CustomerKey>,
+) -> Result<PresignedUrlResponse, Error> {
+  let CreateQueryParameters {
+    bucket,
+    path: key,
+    accelerate,
+    sse,
+    kms_key_id,
+    cache_control,
+    expires,
+    content_md5,
+    checksum_sha256,
+  } = parameters;
+  let s3_configuration = s3_configuration.for_bucket(&bucket);
   log::info!("Create object signed URL: bucket={}, key={}", bucket, key);
-  let credentials = AwsCredentials::from(&s3_configuration);
+  encryption::server::check_parameters(sse, &kms_key_id)?;
+  if sse.is_some() && customer_key.is_some() {
+    return Err(Error::InvalidParameter(
+      "sse cannot be combined with a customer-provided key".to_string(),
+    ));
+  }
+  cache_headers::check_parameters(&cache_control, &expires)?;
+  let checksum_headers = checksum_headers(content_md5, checksum_sha256)?;
+  let accelerate = accelerate.unwrap_or_else(|| s3_configuration.transfer_acceleration());
+  if accelerate {
+    transfer_acceleration::check_enabled(&s3_configuration, &bucket).await?;
+  }
 
-  let put_object = PutObjectRequest {
-    bucket,
-    key,
-    ..Default::default()
-  };
+  let options = PresignOptions::from(&s3_configuration)
+    .with_transfer_acceleration(accelerate)
+    .with_headers(encryption::server::headers(sse, &kms_key_id))
+    .with_headers(
+      customer_key
+        .map(|customer_key| customer_key.headers())
+        .unwrap_or_default(),
+    )
+    .with_headers(cache_headers::headers(&cache_control, &expires))
+    .with_headers(checksum_headers);
+  let presign_configuration = s3_configuration.presign_configuration(&bucket).await;
+  let presigned_url = presign::put_object(&presign_configuration, &bucket, &key, &options)?;
+
+  // The upload goes straight to S3, unseen by the listing cache: the cached listings of the
+  // prefixes of the object miss it until they expire.
+  metrics::presigned_url("PutObject");
+  Ok(PresignedUrlResponse {
+    url: presigned_url,
+    method: Some("PUT".to_string()),
+    headers: options.header_names(),
+    key: None,
+    expires_at: Some(objects::expires_at(&presign_configuration, &options)?),
+  })
+}
+
+/// Headers to sign so that S3 rejects the uploads not matching the digests of the object.
+fn checksum_headers(
+  content_md5: Option<String>,
+  checksum_sha256: Option<String>,
+) -> Result<Vec<(&'static str, String)>, Error> {
+  let mut headers = Vec::new();
+  if let Some(content_md5) = content_md5 {
+    check_digest("content_md5", &content_md5, MD5_LENGTH)?;
+    headers.push((CONTENT_MD5_HEADER, content_md5));
+  }
+  if let Some(checksum_sha256) = checksum_sha256 {
+    check_digest("checksum_sha256", &checksum_sha256, SHA256_LENGTH)?;
+    headers.push((CHECKSUM_SHA256_HEADER, checksum_sha256));
+  }
 
-  let presigned_url = put_object.get_presigned_url(
-    s3_configuration.region(),
-    &credentials,
-    &PreSignedRequestOption::default(),
-  );
+  Ok(headers)
+}
 
-  to_redirect_response(&presigned_url)
+fn check_digest(parameter: &str, digest: &str, length: usize) -> Result<(), Error> {
+  match base64::decode(digest) {
+    Ok(decoded) if decoded.len() == length => Ok(()),
+    _ => Err(Error::InvalidParameter(format!(
+      "{} must be a base64-encoded {}-byte digest",
+      parameter, length
+    ))),
+  }
 }
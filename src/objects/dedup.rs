@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+
+/// Checksums of the content about to be uploaded, searched for among the existing objects.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct DedupBody {
+  /// Name of the bucket
+  pub bucket: String,
+  /// Hex-encoded MD5 digest of the content, compared to the ETag of objects uploaded at once
+  pub md5: Option<String>,
+  /// Base64-encoded SHA-256 checksum of the content, compared to the one stored on upload
+  pub sha256: Option<String>,
+  /// Size of the content, in bytes, narrowing the objects compared
+  pub size: Option<i64>,
+}
+
+/// Object of the deduplication prefix with the same content, if any.
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct DedupResponse {
+  pub bucket: String,
+  /// Key of an object with the same content, missing when none was found
+  pub key: Option<String>,
+  pub size: Option<i64>,
+  /// Whether the search stopped before comparing every object of the prefix, with too many
+  /// candidates
+  pub truncated: bool,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    body, json_reply, metrics,
+    objects::verify::server::head_object,
+    policy::{self, Identity, Operation},
+    retry, telemetry, Error, S3Configuration,
+  };
+  use rusoto_core::Client;
+  use rusoto_s3::{ListObjectsV2Request, Object, S3Client, S3};
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{Filter, Rejection, Reply};
+
+  /// Objects listed before giving up on finding a duplicate
+  const MAX_LISTED_OBJECTS: usize = 10_000;
+  /// Objects of which the SHA-256 checksum is fetched before giving up on finding a duplicate
+  const MAX_CHECKSUM_REQUESTS: usize = 100;
+  const SHA256_LENGTH: usize = 32;
+
+  /// Search for an object with the same content
+  #[utoipa::path(
+    post,
+    path = "/objects/dedup",
+    tag = "Objects",
+    request_body(
+      content = DedupBody,
+      description = "Checksums of the content about to be uploaded",
+      content_type = "application/json"
+    ),
+    responses(
+      (
+        status = 200,
+        description = "Returns the key of an object of the deduplication prefix with the same content, if any",
+        content_type = "application/json",
+        body = DedupResponse
+      ),
+      (status = 404, description = "No deduplication prefix is configured"),
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "dedup")
+      .and(warp::post())
+      .and(body::json::<DedupBody>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |body: DedupBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(find_duplicate(&s3_configuration, &identity, body).await)
+        },
+      )
+  }
+
+  /// Searches the deduplication prefix of the bucket for an object with the checksums of the
+  /// content about to be uploaded, on behalf of `identity`, which must be allowed to read the
+  /// whole prefix (the one of its tenant, with tenancy enabled).
+  pub async fn find_duplicate(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    body: DedupBody,
+  ) -> Result<DedupResponse, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&body.bucket);
+    let mut prefix = s3_configuration
+      .dedup_prefix()
+      .cloned()
+      .ok_or_else(|| Error::NotFound("deduplication is not enabled".to_string()))?;
+    identity.scope(&mut prefix);
+    policy::authorize(
+      &s3_configuration,
+      identity,
+      Operation::Read,
+      &body.bucket,
+      &prefix,
+    )?;
+    handle_find_duplicate(&s3_configuration, &prefix, body).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_find_duplicate(
+    s3_configuration: &S3Configuration,
+    prefix: &str,
+    body: DedupBody,
+  ) -> Result<DedupResponse, Error> {
+    let md5 = body.md5.as_deref().map(check_md5).transpose()?;
+    if let Some(sha256) = &body.sha256 {
+      if !matches!(base64::decode(sha256), Ok(decoded) if decoded.len() == SHA256_LENGTH) {
+        return Err(Error::InvalidParameter(format!(
+          "sha256 must be a base64-encoded {}-byte digest",
+          SHA256_LENGTH
+        )));
+      }
+    }
+    if md5.is_none() && body.sha256.is_none() {
+      return Err(Error::InvalidParameter(
+        "md5 or sha256 must be specified".to_string(),
+      ));
+    }
+
+    log::info!(
+      "Find duplicate: bucket={}, prefix={}, size={:?}",
+      body.bucket,
+      prefix,
+      body.size
+    );
+    let client = S3Client::try_from(s3_configuration)?;
+    let head_client = Client::try_from(s3_configuration)?;
+    let mut response = DedupResponse {
+      bucket: body.bucket.clone(),
+      key: None,
+      size: None,
+      truncated: false,
+    };
+    let mut listed_objects = 0;
+    let mut checksum_requests = 0;
+    let mut continuation_token = None;
+    loop {
+      let request = ListObjectsV2Request {
+        bucket: body.bucket.clone(),
+        prefix: Some(prefix.to_string()),
+        continuation_token: continuation_token.take(),
+        ..Default::default()
+      };
+      let output = retry::s3_call(s3_configuration, "ListObjectsV2", || {
+        client
+          .list_objects_v2(request.clone())
+          .instrument(telemetry::s3_span("ListObjectsV2"))
+      })
+      .await
+      .map_err(metrics::count_s3_error("ListObjectsV2"))
+      .map_err(Error::ListObjectsError)?;
+
+      for object in output.contents.unwrap_or_default() {
+        listed_objects += 1;
+        let (key, etag) = match candidate(&object, body.size) {
+          Some(candidate) => candidate,
+          None => continue,
+        };
+        let md5_matches = etag_md5(etag)
+          .zip(md5.as_ref())
+          .map(|(etag, md5)| &etag == md5);
+        if md5_matches == Some(false) {
+          continue;
+        }
+
+        let found = match &body.sha256 {
+          Some(sha256) => {
+            if checksum_requests == MAX_CHECKSUM_REQUESTS {
+              response.truncated = true;
+              return Ok(response);
+            }
+            checksum_requests += 1;
+            let stored = retry::s3_call(s3_configuration, "HeadObject", || {
+              head_object(&head_client, s3_configuration, &body.bucket, key, None)
+                .instrument(telemetry::s3_span("HeadObject"))
+            })
+            .await
+            .map_err(metrics::count_s3_error("HeadObject"))
+            .map_err(Error::HeadObjectError)?;
+            stored.sha256.as_ref() == Some(sha256)
+          }
+          None => md5_matches == Some(true),
+        };
+        if found {
+          log::info!("Duplicate found: bucket={}, key={}", body.bucket, key);
+          response.key = Some(key.to_string());
+          response.size = object.size;
+          return Ok(response);
+        }
+      }
+
+      continuation_token = output.next_continuation_token;
+      if !output.is_truncated.unwrap_or_default() || continuation_token.is_none() {
+        return Ok(response);
+      }
+      if listed_objects >= MAX_LISTED_OBJECTS {
+        response.truncated = true;
+        return Ok(response);
+      }
+    }
+  }
+
+  /// Key and ETag of a listed object which may have the searched content, given its size.
+  fn candidate(object: &Object, size: Option<i64>) -> Option<(&str, &str)> {
+    let key = object.key.as_deref().filter(|key| !key.ends_with('/'))?;
+    if size.is_some() && object.size != size {
+      return None;
+    }
+    Some((key, object.e_tag.as_deref().unwrap_or_default()))
+  }
+
+  /// MD5 digest of the content of an object, its ETag when uploaded at once without KMS.
+  fn etag_md5(etag: &str) -> Option<String> {
+    let etag = etag.trim_matches('"');
+    (etag.len() == 32 && etag.bytes().all(|byte| byte.is_ascii_hexdigit()))
+      .then(|| etag.to_lowercase())
+  }
+
+  fn check_md5(md5: &str) -> Result<String, Error> {
+    etag_md5(md5).ok_or_else(|| Error::InvalidParameter(format!("invalid MD5 digest: {}", md5)))
+  }
+}
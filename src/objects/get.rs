@@ -1,11 +1,23 @@
-use crate::{objects::SignQueryParameters, to_redirect_response, S3Configuration};
-use rusoto_credential::AwsCredentials;
-use rusoto_s3::{
-  util::{PreSignedRequest, PreSignedRequestOption},
-  GetObjectRequest,
+use crate::{
+  encryption::server::{customer_key, CustomerKey},
+  hypermedia, metrics,
+  objects::{self, key, PresignedUrlResponse, SignQueryParameters},
+  policy::{self, Identity, Operation},
+  presign::{self, PresignOptions},
+  query, request_builder, retry, telemetry, transfer_acceleration, Error, S3Configuration,
 };
+use futures::TryStreamExt;
+use rusoto_s3::{GetObjectRequest, S3Client, S3};
+use std::{convert::TryFrom, sync::Arc};
+use tracing::Instrument;
 use warp::{
-  hyper::{Body, Response},
+  hyper::{
+    header::{
+      ACCEPT, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_ENCODING,
+      CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED, RANGE,
+    },
+    Body, Response, StatusCode,
+  },
   Filter, Rejection, Reply,
 };
 
@@ -15,12 +27,24 @@ use warp::{
   path = "/object",
   tag = "Objects",
   responses(
+    (
+      status = 200,
+      description = "Object content, when proxied, or pre-signed URL for getting an object, when JSON is accepted",
+      body = PresignedUrlResponse
+    ),
+    (status = 206, description = "Requested range of the object content, when proxied"),
     (status = 302, description = "Redirect to pre-signed URL for getting an object"),
   ),
   params(
     ("bucket" = String, Query, description = "Name of the bucket"),
-    ("path" = String, Query, description = "Key of the object to get")
+    ("path" = String, Query, description = "Key of the object to get"),
+    ("accelerate" = Option<bool>, Query, description = "Sign against the S3 Transfer Acceleration endpoint"),
+    ("proxy" = Option<bool>, Query, description = "Stream the object through the signer instead of redirecting to S3"),
+    ("Accept" = Option<String>, Header, description = "Answer the pre-signed URL as JSON rather than redirecting to it, with `application/json`"),
+    ("Range" = Option<String>, Header, description = "Range of the object content to get, when proxied"),
+    ("x-amz-server-side-encryption-customer-key" = Option<String>, Header, description = "Base64-encoded SSE-C key of the object, to send again along with the pre-signed request")
   ),
+  security(("bearer_token" = []), ("api_key" = [])),
 )]
 pub(crate) fn route(
   s3_configuration: &S3Configuration,
@@ -29,34 +53,286 @@ pub(crate) fn route(
 
   warp::path("object")
     .and(warp::get())
-    .and(warp::query::<SignQueryParameters>())
-    .and(warp::any().map(move || s3_configuration.clone()))
+    .and(query::parameters::<SignQueryParameters>())
+    .and(warp::header::optional::<String>(ACCEPT.as_str()))
+    .and(warp::header::optional::<String>(RANGE.as_str()))
+    .and(customer_key())
+    .and(policy::identity(&s3_configuration))
+    .and(crate::endpoint_override::current(&s3_configuration))
     .and_then(
-      |parameters: SignQueryParameters, s3_configuration: S3Configuration| async move {
-        handle_get_object_signed_url(s3_configuration, parameters.bucket, parameters.path).await
+      |parameters: SignQueryParameters,
+       accept: Option<String>,
+       range: Option<String>,
+       customer_key: Option<CustomerKey>,
+       identity: Identity,
+       s3_configuration: Arc<S3Configuration>| async move {
+        get_object(
+          &s3_configuration,
+          &identity,
+          parameters,
+          accept,
+          range,
+          customer_key,
+        )
+        .await
+        .map_err(warp::reject::custom)
       },
     )
 }
 
+/// Pre-signs the URL to get an object (or issues a download link), on behalf of `identity`.
+///
+/// Clients are redirected to the URL, unless their `accept` header asks for JSON. With the `proxy`
+/// parameter, the object (or its `range`) is streamed instead. The caller is recorded by the access
+/// tags, if enabled and authenticated.
+pub async fn get_object(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  mut parameters: SignQueryParameters,
+  accept: Option<String>,
+  range: Option<String>,
+  customer_key: Option<CustomerKey>,
+) -> Result<Response<Body>, Error> {
+  identity.scope(&mut parameters.path);
+  key::server::validate_key(s3_configuration, &parameters.path)?;
+  policy::authorize(
+    s3_configuration,
+    identity,
+    Operation::Read,
+    &parameters.bucket,
+    &parameters.path,
+  )?;
+  if parameters.proxy.unwrap_or_default() {
+    return handle_proxy_object(
+      s3_configuration,
+      parameters.bucket,
+      parameters.path,
+      range,
+      customer_key,
+      identity.owner(),
+    )
+    .await;
+  }
+  let presigned_url = handle_get_object_signed_url(
+    s3_configuration,
+    parameters.bucket.clone(),
+    parameters.path.clone(),
+    parameters.accelerate,
+    customer_key,
+    identity.owner(),
+  )
+  .await?;
+  hypermedia::download_response(
+    s3_configuration,
+    &presigned_url,
+    accept.as_deref(),
+    &parameters.bucket,
+    &parameters.path,
+  )
+}
+
+/// Pre-signs the URL to get an object (or issues a download link, relative to the root of the
+/// API), on behalf of `identity`, like [`get_object`] without the proxy.
+pub async fn presign_get_object(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  mut parameters: SignQueryParameters,
+  customer_key: Option<CustomerKey>,
+) -> Result<String, Error> {
+  identity.scope(&mut parameters.path);
+  key::server::validate_key(s3_configuration, &parameters.path)?;
+  policy::authorize(
+    s3_configuration,
+    identity,
+    Operation::Read,
+    &parameters.bucket,
+    &parameters.path,
+  )?;
+  handle_get_object_signed_url(
+    s3_configuration,
+    parameters.bucket,
+    parameters.path,
+    parameters.accelerate,
+    customer_key,
+    identity.owner(),
+  )
+  .await
+  .map(|presigned_url| presigned_url.url)
+}
+
+#[tracing::instrument(skip_all)]
 async fn handle_get_object_signed_url(
-  s3_configuration: S3Configuration,
+  s3_configuration: &S3Configuration,
   bucket: String,
   key: String,
-) -> Result<Response<Body>, Rejection> {
+  accelerate: Option<bool>,
+  customer_key: Option<CustomerKey>,
+  requester: Option<String>,
+) -> Result<PresignedUrlResponse, Error> {
+  let s3_configuration = s3_configuration.for_bucket(&bucket);
   log::info!("Get object signed URL: bucket={}, key={}", bucket, key);
-  let credentials = AwsCredentials::from(&s3_configuration);
+  if let Some(access_tagging) = s3_configuration.access_tagging() {
+    access_tagging.stamp(&s3_configuration, &bucket, &key, requester);
+  }
+
+  if let Some(download_links) = s3_configuration.download_links() {
+    if customer_key.is_some() {
+      return Err(Error::InvalidParameter(
+        "customer-provided keys are not supported with download links".to_string(),
+      ));
+    }
+
+    // Relative to the request URL, so that the link is served under the same root path
+    return Ok(PresignedUrlResponse {
+      url: format!("d/{}", download_links.issue(&bucket, &key)),
+      method: Some("GET".to_string()),
+      headers: Vec::new(),
+      key: None,
+      expires_at: None,
+    });
+  }
+
+  let accelerate = accelerate.unwrap_or_else(|| s3_configuration.transfer_acceleration());
+  if accelerate {
+    transfer_acceleration::check_enabled(&s3_configuration, &bucket).await?;
+  }
+
+  let options = PresignOptions::from(&s3_configuration)
+    .with_transfer_acceleration(accelerate)
+    .with_headers(
+      customer_key
+        .map(|customer_key| customer_key.headers())
+        .unwrap_or_default(),
+    );
+  let presign_configuration = s3_configuration.presign_configuration(&bucket).await;
+  let url = presign::get_object(&presign_configuration, &bucket, &key, &options)?;
+
+  metrics::presigned_url("GetObject");
+  Ok(PresignedUrlResponse {
+    url,
+    method: Some("GET".to_string()),
+    headers: options.header_names(),
+    key: None,
+    expires_at: Some(objects::expires_at(&presign_configuration, &options)?),
+  })
+}
 
-  let get_object = GetObjectRequest {
+#[tracing::instrument(skip_all)]
+async fn handle_proxy_object(
+  s3_configuration: &S3Configuration,
+  bucket: String,
+  key: String,
+  range: Option<String>,
+  customer_key: Option<CustomerKey>,
+  requester: Option<String>,
+) -> Result<Response<Body>, Error> {
+  let s3_configuration = s3_configuration.for_bucket(&bucket);
+  log::info!(
+    "Proxy object: bucket={}, key={}, range={:?}",
     bucket,
     key,
+    range
+  );
+  if let Some(access_tagging) = s3_configuration.access_tagging() {
+    access_tagging.stamp(&s3_configuration, &bucket, &key, requester);
+  }
+
+  let client = S3Client::try_from(&s3_configuration)?;
+  let output = client
+    .get_object(GetObjectRequest {
+      bucket,
+      key,
+      range,
+      sse_customer_algorithm: customer_key.as_ref().map(CustomerKey::algorithm),
+      sse_customer_key: customer_key.as_ref().map(CustomerKey::key),
+      sse_customer_key_md5: customer_key.as_ref().map(CustomerKey::key_md5),
+      ..Default::default()
+    })
+    .instrument(telemetry::s3_span("GetObject"))
+    .await
+    .map_err(metrics::count_s3_error("GetObject"))
+    .map_err(Error::GetObjectError)?;
+
+  let status = if output.content_range.is_some() {
+    StatusCode::PARTIAL_CONTENT
+  } else {
+    StatusCode::OK
+  };
+  let mut response = request_builder()
+    .status(status)
+    .header(ACCEPT_RANGES, "bytes");
+  for (header, value) in [
+    (CACHE_CONTROL, output.cache_control),
+    (CONTENT_DISPOSITION, output.content_disposition),
+    (CONTENT_ENCODING, output.content_encoding),
+    (CONTENT_LANGUAGE, output.content_language),
+    (CONTENT_RANGE, output.content_range),
+    (CONTENT_TYPE, output.content_type),
+    (ETAG, output.e_tag),
+    (LAST_MODIFIED, output.last_modified),
+    (
+      CONTENT_LENGTH,
+      output.content_length.map(|length| length.to_string()),
+    ),
+  ] {
+    if let Some(value) = value {
+      response = response.header(header, value);
+    }
+  }
+
+  response
+    .body(
+      output
+        .body
+        .map(Body::wrap_stream)
+        .unwrap_or_else(Body::empty),
+    )
+    .map_err(Error::HttpError)
+}
+
+/// Reads an object into memory, e.g. a document to rewrite, rejecting the ones larger than
+/// `max_size` bytes without reading them further. Failures to read the content are answered with
+/// `error`.
+pub(crate) async fn read_object(
+  s3_configuration: &S3Configuration,
+  bucket: &str,
+  key: &str,
+  max_size: usize,
+  error: fn(String) -> Error,
+) -> Result<Vec<u8>, Error> {
+  let client = S3Client::try_from(s3_configuration)?;
+  let request = GetObjectRequest {
+    bucket: bucket.to_string(),
+    key: key.to_string(),
     ..Default::default()
   };
+  let output = retry::s3_call(s3_configuration, "GetObject", || {
+    client
+      .get_object(request.clone())
+      .instrument(telemetry::s3_span("GetObject"))
+  })
+  .await
+  .map_err(metrics::count_s3_error("GetObject"))
+  .map_err(Error::GetObjectError)?;
 
-  let presigned_url = get_object.get_presigned_url(
-    s3_configuration.region(),
-    &credentials,
-    &PreSignedRequestOption::default(),
-  );
+  let too_large = || Error::InvalidParameter(format!("{} exceeds {} bytes", key, max_size));
+  if output.content_length.unwrap_or_default() as u64 > max_size as u64 {
+    return Err(too_large());
+  }
 
-  to_redirect_response(&presigned_url)
+  let mut body = output
+    .body
+    .ok_or_else(|| error(format!("{} is empty", key)))?;
+  let mut content = vec![];
+  while let Some(bytes) = body
+    .try_next()
+    .await
+    .map_err(|read_error| error(read_error.to_string()))?
+  {
+    if content.len() + bytes.len() > max_size {
+      return Err(too_large());
+    }
+    content.extend_from_slice(&bytes);
+  }
+  Ok(content)
 }
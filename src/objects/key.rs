@@ -0,0 +1,139 @@
+/// Maximum length of object keys supported by S3, in bytes.
+pub const DEFAULT_MAX_KEY_LENGTH: usize = 1024;
+
+/// Checks that an object key is safe to sign.
+///
+/// Keys must not be empty nor exceed `max_length` bytes, and must not contain control
+/// characters, `.` or `..` segments, leading or double slashes.
+pub fn check_key(key: &str, max_length: usize) -> Result<(), String> {
+  if key.is_empty() {
+    return Err("empty key".to_string());
+  }
+
+  check_prefix(key, max_length)
+}
+
+/// Checks that a key prefix is safe to sign, like [`check_key`] but accepting empty prefixes.
+pub fn check_prefix(prefix: &str, max_length: usize) -> Result<(), String> {
+  if prefix.len() > max_length {
+    return Err(format!("key longer than {} bytes", max_length));
+  }
+
+  if prefix.chars().any(char::is_control) {
+    return Err(format!("control character in key {:?}", prefix));
+  }
+
+  if prefix.starts_with('/') || prefix.contains("//") {
+    return Err(format!("leading or double slash in key {:?}", prefix));
+  }
+
+  if prefix
+    .split('/')
+    .any(|segment| segment == "." || segment == "..")
+  {
+    return Err(format!("relative segment in key {:?}", prefix));
+  }
+
+  Ok(())
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{Error, S3Configuration};
+
+  pub(crate) fn validate_key(s3_configuration: &S3Configuration, key: &str) -> Result<(), Error> {
+    check_key(key, s3_configuration.max_key_length()).map_err(Error::InvalidParameter)
+  }
+
+  pub(crate) fn validate_prefix(
+    s3_configuration: &S3Configuration,
+    prefix: &str,
+  ) -> Result<(), Error> {
+    check_prefix(prefix, s3_configuration.max_key_length()).map_err(Error::InvalidParameter)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn valid_keys_are_accepted() {
+    for key in [
+      "file.txt",
+      "folder/file.txt",
+      "a/b/c.d/e..f",
+      "trailing/",
+      "é/日本.txt",
+    ]
+    .iter()
+    {
+      assert_eq!(check_key(key, DEFAULT_MAX_KEY_LENGTH), Ok(()), "{}", key);
+    }
+  }
+
+  #[test]
+  fn empty_keys_are_rejected_but_not_empty_prefixes() {
+    assert!(check_key("", DEFAULT_MAX_KEY_LENGTH).is_err());
+    assert_eq!(check_prefix("", DEFAULT_MAX_KEY_LENGTH), Ok(()));
+  }
+
+  #[test]
+  fn length_is_checked_in_bytes() {
+    let key = "a".repeat(DEFAULT_MAX_KEY_LENGTH);
+    assert_eq!(check_key(&key, DEFAULT_MAX_KEY_LENGTH), Ok(()));
+    assert!(check_key(&format!("{}a", key), DEFAULT_MAX_KEY_LENGTH).is_err());
+    assert!(check_prefix(&format!("{}a", key), DEFAULT_MAX_KEY_LENGTH).is_err());
+
+    // 2 bytes per character
+    assert_eq!(check_key("éé", 4), Ok(()));
+    assert!(check_key("ééé", 4).is_err());
+  }
+
+  #[test]
+  fn control_characters_are_rejected() {
+    for key in ["file\n.txt", "file\r", "\0", "folder/\u{7f}", "tab\there"].iter() {
+      assert!(check_key(key, DEFAULT_MAX_KEY_LENGTH).is_err(), "{:?}", key);
+      assert!(
+        check_prefix(key, DEFAULT_MAX_KEY_LENGTH).is_err(),
+        "{:?}",
+        key
+      );
+    }
+  }
+
+  #[test]
+  fn relative_segments_are_rejected() {
+    for key in [
+      "..",
+      "../file",
+      "folder/../file",
+      "folder/..",
+      ".",
+      "./file",
+      "folder/./file",
+    ]
+    .iter()
+    {
+      assert!(check_key(key, DEFAULT_MAX_KEY_LENGTH).is_err(), "{}", key);
+      assert!(
+        check_prefix(key, DEFAULT_MAX_KEY_LENGTH).is_err(),
+        "{}",
+        key
+      );
+    }
+  }
+
+  #[test]
+  fn leading_and_double_slashes_are_rejected() {
+    for key in ["/file", "/", "folder//file", "folder//"].iter() {
+      assert!(check_key(key, DEFAULT_MAX_KEY_LENGTH).is_err(), "{}", key);
+      assert!(
+        check_prefix(key, DEFAULT_MAX_KEY_LENGTH).is_err(),
+        "{}",
+        key
+      );
+    }
+  }
+}
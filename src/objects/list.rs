@@ -8,8 +8,9 @@ pub struct ListObjectsQueryParameters {
 
 pub type ListObjectsResponse = Vec<Object>;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
 pub struct Object {
   pub path: String,
   pub is_dir: bool,
@@ -31,11 +32,23 @@ impl Object {
 #[cfg(feature = "server")]
 pub(crate) mod server {
   use super::*;
-  use crate::{to_ok_json_response, Error, S3Configuration};
-  use rusoto_credential::{AwsCredentials, StaticProvider};
-  use rusoto_s3::{ListObjectsV2Request, S3Client, S3};
+  use crate::{
+    compression, concurrency_limit, hypermedia, metrics, multipart_upload,
+    multipart_upload::Served,
+    objects::key,
+    policy::{self, Identity, Operation},
+    query, request_builder, retry, telemetry, Error, S3Configuration,
+  };
+  use futures::{stream, StreamExt, TryStreamExt};
+  use rusoto_s3::{ListObjectsV2Output, ListObjectsV2Request, S3Client, S3};
+  use std::{convert::TryFrom, sync::Arc, time::Duration};
+  use tokio::sync::OwnedSemaphorePermit;
+  use tracing::Instrument;
   use warp::{
-    hyper::{Body, Response},
+    hyper::{
+      header::{HeaderValue, ACCEPT, CACHE_CONTROL, CONTENT_TYPE, VARY},
+      Body, Response, StatusCode,
+    },
     Filter, Rejection, Reply,
   };
 
@@ -47,83 +60,586 @@ pub(crate) mod server {
     responses(
       (
         status = 200,
-        description = "Successfully list objects",
-        content_type = "application/json",
-        body = ListObjectsResponse
+        description = "Successfully list objects, as JSON, or as CSV or XML when accepted",
+        content(
+          ("application/json" = [Object]),
+          ("text/csv" = String),
+          ("application/xml" = String)
+        )
       ),
+      (status = 503, description = "Too many concurrent S3 calls, retry after the Retry-After delay"),
     ),
     params(
       ("bucket" = String, Query, description = "Name of the bucket"),
-      ("prefix" = Option<String>, Query, description = "Prefix to filter objects to list")
+      ("prefix" = Option<String>, Query, description = "Prefix to filter objects to list"),
+      ("Accept" = Option<String>, Header, description = "Format of the listing: `application/json` (by default), `text/csv` or `application/xml`")
     ),
+    security(("bearer_token" = []), ("api_key" = [])),
   )]
   pub(crate) fn route(
     s3_configuration: &S3Configuration,
   ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     let s3_configuration = s3_configuration.clone();
-    warp::path("objects")
+    compression::compressed(
+      // Exact, for the errors of the other `/objects/...` GET routes not to fall through to it
+      warp::path!("objects")
+        .and(warp::get())
+        .and(query::parameters::<ListObjectsQueryParameters>())
+        .and(warp::header::optional::<String>(ACCEPT.as_str()))
+        .and(policy::identity(&s3_configuration))
+        .and(crate::endpoint_override::current(&s3_configuration))
+        .and_then(
+          |parameters: ListObjectsQueryParameters,
+           accept: Option<String>,
+           identity: Identity,
+           s3_configuration: Arc<S3Configuration>| async move {
+            let (bucket, prefix) = (parameters.bucket.clone(), parameters.prefix.clone());
+            let format = ListingFormat::negotiate(accept.as_deref());
+            list_objects(&s3_configuration, &identity, parameters)
+              .await
+              .and_then(|listing| {
+                listing_response(
+                  &s3_configuration,
+                  &bucket,
+                  prefix.as_deref(),
+                  format,
+                  listing,
+                )
+              })
+              .map_err(warp::reject::custom)
+          },
+        ),
+    )
+  }
+
+  /// Stream objects
+  #[utoipa::path(
+    get,
+    path = "/objects/stream",
+    tag = "Objects",
+    responses(
+      (
+        status = 200,
+        description = "Objects and folders, streamed as one JSON object per line, or as CSV or XML when accepted",
+        content(
+          ("application/x-ndjson" = Object),
+          ("text/csv" = String),
+          ("application/xml" = String)
+        )
+      ),
+      (status = 503, description = "Too many concurrent S3 calls, retry after the Retry-After delay"),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("prefix" = Option<String>, Query, description = "Prefix to filter objects to list"),
+      ("Accept" = Option<String>, Header, description = "Format of the listing: newline-delimited JSON (by default), `text/csv` or `application/xml`")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn stream_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "stream")
       .and(warp::get())
-      .and(warp::query::<ListObjectsQueryParameters>())
-      .and(warp::any().map(move || s3_configuration.clone()))
+      .and(query::parameters::<ListObjectsQueryParameters>())
+      .and(warp::header::optional::<String>(ACCEPT.as_str()))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
       .and_then(
-        |parameters: ListObjectsQueryParameters, s3_configuration: S3Configuration| async move {
-          handle_list_objects(s3_configuration, parameters.bucket, parameters.prefix).await
+        |parameters: ListObjectsQueryParameters,
+         accept: Option<String>,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          stream_objects(&s3_configuration, &identity, parameters, accept)
+            .await
+            .map_err(warp::reject::custom)
         },
       )
   }
 
+  /// Objects and folders of a listing, and how long they can be cached by the client.
+  #[derive(Clone, Debug)]
+  pub struct Listing {
+    pub objects: ListObjectsResponse,
+    /// Remaining lifetime of the listing in the listing cache, when enabled
+    pub max_age: Option<Duration>,
+  }
+
+  /// Lists the objects and folders under a prefix of a bucket, on behalf of `identity`.
+  pub async fn list_objects(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ListObjectsQueryParameters,
+  ) -> Result<Served<Listing>, Error> {
+    identity.scope_prefix(&mut parameters.prefix);
+    let prefix = parameters.prefix.as_deref().unwrap_or_default();
+    key::server::validate_prefix(s3_configuration, prefix)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &parameters.bucket,
+      prefix,
+    )?;
+    handle_list_objects(s3_configuration, parameters.bucket, parameters.prefix).await
+  }
+
+  /// Streams the objects and folders under a prefix of a bucket as newline-delimited JSON, or as
+  /// CSV or XML when the `accept` header asks for it, on behalf of `identity`.
+  ///
+  /// Unlike [`list_objects`], every page of the listing is fetched, and sent as soon as it is.
+  pub async fn stream_objects(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ListObjectsQueryParameters,
+    accept: Option<String>,
+  ) -> Result<Response<Body>, Error> {
+    identity.scope_prefix(&mut parameters.prefix);
+    let prefix = parameters.prefix.as_deref().unwrap_or_default();
+    key::server::validate_prefix(s3_configuration, prefix)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &parameters.bucket,
+      prefix,
+    )?;
+    handle_stream_objects(
+      s3_configuration,
+      parameters.bucket,
+      parameters.prefix,
+      ListingFormat::negotiate(accept.as_deref()),
+    )
+    .await
+  }
+
+  #[tracing::instrument(skip_all)]
   async fn handle_list_objects(
-    s3_configuration: S3Configuration,
+    s3_configuration: &S3Configuration,
+    bucket: String,
+    source_prefix: Option<String>,
+  ) -> Result<Served<Listing>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    let cache_prefix = source_prefix.clone().unwrap_or_default();
+    if let Some(listing_cache) = s3_configuration.listing_cache() {
+      if let Some((objects, max_age)) = listing_cache.get(&bucket, &cache_prefix) {
+        log::debug!(
+          "List objects from cache: bucket={}, source_prefix={:?}",
+          bucket,
+          source_prefix
+        );
+        return Ok(Served {
+          body: Listing {
+            objects,
+            max_age: Some(max_age),
+          },
+          endpoint: None,
+        });
+      }
+    }
+
+    fetch_listing(&s3_configuration, bucket, source_prefix).await
+  }
+
+  /// Lists the objects and folders under a prefix of a bucket into the listing cache, without
+  /// any policy, e.g. on a schedule before the clients list them.
+  ///
+  /// Returns the number of listed objects and folders.
+  pub(crate) async fn warm_listing(
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    prefix: &str,
+  ) -> Result<usize, Error> {
+    let s3_configuration = s3_configuration.for_bucket(bucket);
+    if s3_configuration.listing_cache().is_none() {
+      return Err(Error::InvalidParameter(
+        "the listing cache is not enabled".to_string(),
+      ));
+    }
+    let source_prefix = Some(prefix.to_string()).filter(|prefix| !prefix.is_empty());
+    let served = fetch_listing(&s3_configuration, bucket.to_string(), source_prefix).await?;
+    Ok(served.body.objects.len())
+  }
+
+  /// Lists the objects and folders under a prefix from S3, caching them when enabled.
+  async fn fetch_listing(
+    s3_configuration: &S3Configuration,
     bucket: String,
     source_prefix: Option<String>,
-  ) -> Result<Response<Body>, Rejection> {
+  ) -> Result<Served<Listing>, Error> {
+    let cache_prefix = source_prefix.clone().unwrap_or_default();
+    let _permit = concurrency_limit::acquire(s3_configuration)?;
     log::info!(
       "List objects signed URL: bucket={}, source_prefix={:?}",
       bucket,
       source_prefix
     );
-    let credentials = AwsCredentials::from(&s3_configuration);
+    // Fails over to the secondary storage, if any, like the multipart upload calls
+    let client = multipart_upload::S3Client::try_from(s3_configuration)?;
+    client
+      .execute(|client: S3Client| {
+        let (bucket, source_prefix) = (&bucket, &source_prefix);
+        let cache_prefix = &cache_prefix;
+        async move {
+          let page = list_page(
+            s3_configuration,
+            &client,
+            bucket,
+            source_prefix,
+            false,
+            None,
+          )
+          .await?;
+          let objects = objects_of(page, source_prefix);
+
+          let max_age = s3_configuration.listing_cache().map(|listing_cache| {
+            listing_cache.insert(bucket, cache_prefix, objects.clone());
+            listing_cache.ttl()
+          });
+          Ok(Listing { objects, max_age })
+        }
+      })
+      .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_stream_objects(
+    s3_configuration: &S3Configuration,
+    bucket: String,
+    source_prefix: Option<String>,
+    format: ListingFormat,
+  ) -> Result<Response<Body>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    let permit = concurrency_limit::acquire(&s3_configuration)?;
+    log::info!(
+      "Stream objects: bucket={}, source_prefix={:?}",
+      bucket,
+      source_prefix
+    );
+    let client = S3Client::try_from(&s3_configuration)?;
+
+    // The first page is listed before answering, for the S3 errors to set the status code. Errors
+    // on the next pages interrupt the stream.
+    let first_page = list_page(
+      &s3_configuration,
+      &client,
+      &bucket,
+      &source_prefix,
+      false,
+      None,
+    )
+    .await?;
+
+    let rows = stream::try_unfold(
+      StreamState {
+        next_page: Some(NextPage::Listed(Box::new(first_page))),
+        _permit: permit,
+      },
+      move |state| {
+        let s3_configuration = s3_configuration.clone();
+        let client = client.clone();
+        let bucket = bucket.clone();
+        let source_prefix = source_prefix.clone();
+        async move {
+          let page = match state.next_page {
+            Some(NextPage::Listed(page)) => *page,
+            Some(NextPage::Continuation(continuation_token)) => {
+              list_page(
+                &s3_configuration,
+                &client,
+                &bucket,
+                &source_prefix,
+                false,
+                Some(continuation_token),
+              )
+              .await?
+            }
+            None => return Ok(None),
+          };
+
+          let next_page = match (page.is_truncated, &page.next_continuation_token) {
+            (Some(true), Some(continuation_token)) => {
+              Some(NextPage::Continuation(continuation_token.clone()))
+            }
+            _ => None,
+          };
 
-    let list_objects = ListObjectsV2Request {
+          let mut rows = vec![];
+          for object in objects_of(page, &source_prefix) {
+            format.write_row(&mut rows, &object)?;
+          }
+
+          Ok(Some((
+            rows,
+            StreamState {
+              next_page,
+              _permit: state._permit,
+            },
+          )))
+        }
+      },
+    )
+    .map_err(|error: Error| {
+      log::error!("Objects stream interrupted: {}", error);
+      error
+    });
+    let rows = stream::once(async move { Ok::<_, Error>(format.header().to_vec()) })
+      .chain(rows)
+      .chain(stream::once(async move { Ok(format.footer().to_vec()) }));
+
+    request_builder()
+      .header(CONTENT_TYPE, format.stream_content_type())
+      .status(StatusCode::OK)
+      .body(Body::wrap_stream(rows))
+      .map_err(Error::HttpError)
+  }
+
+  /// Progress of an objects stream, holding its concurrency slot until it ends.
+  struct StreamState {
+    next_page: Option<NextPage>,
+    _permit: Option<OwnedSemaphorePermit>,
+  }
+
+  enum NextPage {
+    Listed(Box<ListObjectsV2Output>),
+    Continuation(String),
+  }
+
+  /// Object or folder of a listing page, with the size and modification date of objects.
+  #[derive(Debug)]
+  pub struct ListedObject {
+    /// Key of the object, or prefix of the folder
+    pub key: String,
+    pub is_dir: bool,
+    pub size: Option<i64>,
+    pub last_modified: Option<String>,
+  }
+
+  /// Lists a page of the objects and folders under a prefix of a bucket, without any policy, e.g.
+  /// for command line tools. Recursive listings have no folders.
+  ///
+  /// Returns the objects, then the folders, and the continuation token of the next page, if any.
+  pub async fn list_objects_page(
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    prefix: &str,
+    recursive: bool,
+    continuation_token: Option<String>,
+  ) -> Result<(Vec<ListedObject>, Option<String>), Error> {
+    let s3_configuration = s3_configuration.for_bucket(bucket);
+    let client = S3Client::try_from(&s3_configuration)?;
+    let source_prefix = Some(prefix.to_string()).filter(|prefix| !prefix.is_empty());
+    let page = list_page(
+      &s3_configuration,
+      &client,
+      bucket,
+      &source_prefix,
+      recursive,
+      continuation_token,
+    )
+    .await?;
+
+    let next_continuation_token = match page.is_truncated {
+      Some(true) => page.next_continuation_token,
+      _ => None,
+    };
+    let objects = page
+      .contents
+      .unwrap_or_default()
+      .into_iter()
+      .map(|content| ListedObject {
+        key: content.key.unwrap_or_default(),
+        is_dir: false,
+        size: content.size,
+        last_modified: content.last_modified,
+      });
+    let folders = page
+      .common_prefixes
+      .unwrap_or_default()
+      .into_iter()
+      .map(|prefix| ListedObject {
+        key: prefix.prefix.unwrap_or_default(),
+        is_dir: true,
+        size: None,
+        last_modified: None,
+      });
+
+    Ok((objects.chain(folders).collect(), next_continuation_token))
+  }
+
+  async fn list_page(
+    s3_configuration: &S3Configuration,
+    client: &S3Client,
+    bucket: &str,
+    source_prefix: &Option<String>,
+    recursive: bool,
+    continuation_token: Option<String>,
+  ) -> Result<ListObjectsV2Output, Error> {
+    let request = ListObjectsV2Request {
       bucket: bucket.to_string(),
-      delimiter: Some(String::from("/")),
+      delimiter: Some(String::from("/")).filter(|_| !recursive),
       prefix: source_prefix.clone(),
+      continuation_token,
       ..Default::default()
     };
+    retry::s3_call(s3_configuration, "ListObjectsV2", || {
+      client
+        .list_objects_v2(request.clone())
+        .instrument(telemetry::s3_span("ListObjectsV2"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("ListObjectsV2"))
+    .map_err(Error::ListObjectsError)
+  }
 
-    let http_client = rusoto_core::request::HttpClient::new()
-      .map_err(|error| warp::reject::custom(Error::S3ConnectionError(error)))?;
-    let credentials: StaticProvider = credentials.into();
-
-    let client = S3Client::new_with(http_client, credentials, s3_configuration.region().clone());
-
-    let response = client
-      .list_objects_v2(list_objects)
-      .await
-      .map_err(|error| warp::reject::custom(Error::ListObjectsError(error)))?;
-
-    let mut objects = response
+  /// Objects, then folders, of a listing page.
+  fn objects_of(page: ListObjectsV2Output, source_prefix: &Option<String>) -> ListObjectsResponse {
+    let mut objects = page
       .contents
       .map(|contents| {
         contents
           .iter()
-          .filter_map(|content| Object::build(&content.key, &source_prefix, false))
+          .filter_map(|content| Object::build(&content.key, source_prefix, false))
           .collect::<ListObjectsResponse>()
       })
       .unwrap_or_default();
 
-    let mut folders = response
+    let mut folders = page
       .common_prefixes
       .map(|prefixes| {
         prefixes
           .iter()
-          .filter_map(|prefix| Object::build(&prefix.prefix, &source_prefix, true))
+          .filter_map(|prefix| Object::build(&prefix.prefix, source_prefix, true))
           .collect::<ListObjectsResponse>()
       })
       .unwrap_or_default();
 
     objects.append(&mut folders);
+    objects
+  }
+
+  /// Answers the listing in its format, cacheable by the client while it is in the listing cache.
+  fn listing_response(
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    prefix: Option<&str>,
+    format: ListingFormat,
+    listing: Served<Listing>,
+  ) -> Result<Response<Body>, Error> {
+    let mut response = if format == ListingFormat::Json {
+      hypermedia::listing_response(s3_configuration, bucket, prefix, &listing.body.objects)?
+    } else {
+      let mut body = format.header().to_vec();
+      for object in &listing.body.objects {
+        format.write_row(&mut body, object)?;
+      }
+      body.extend_from_slice(format.footer());
+      request_builder()
+        .header(CONTENT_TYPE, format.stream_content_type())
+        .status(StatusCode::OK)
+        .body(body.into())
+        .map_err(Error::HttpError)?
+    };
+    // Cached per format by the client and the proxies
+    response
+      .headers_mut()
+      .append(VARY, HeaderValue::from_static("accept"));
+    if let Some(max_age) = listing.body.max_age {
+      response.headers_mut().insert(
+        CACHE_CONTROL,
+        HeaderValue::from_str(&format!("private, max-age={}", max_age.as_secs()))
+          .map_err(|error| Error::HttpError(error.into()))?,
+      );
+    }
+    Ok(listing.with_endpoint_header(response))
+  }
+
+  /// Format of the listings, negotiated from the `Accept` header of the request.
+  #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+  enum ListingFormat {
+    /// A JSON array, or newline-delimited JSON objects when streamed
+    Json,
+    /// A `path,is_dir` header, then a row per object
+    Csv,
+    /// An `Objects` element, with an `Object` element per object
+    Xml,
+  }
 
-    to_ok_json_response(&objects)
+  impl ListingFormat {
+    /// First format of the `accept` header supported, JSON by default.
+    fn negotiate(accept: Option<&str>) -> Self {
+      accept
+        .into_iter()
+        .flat_map(|accept| accept.split(','))
+        .find_map(|media_range| {
+          let media_type = media_range.split(';').next().unwrap_or_default().trim();
+          match media_type.to_ascii_lowercase().as_str() {
+            "text/csv" => Some(ListingFormat::Csv),
+            "application/xml" | "text/xml" => Some(ListingFormat::Xml),
+            "application/json" | "application/hal+json" | "*/*" => Some(ListingFormat::Json),
+            _ => None,
+          }
+        })
+        .unwrap_or(ListingFormat::Json)
+    }
+
+    fn stream_content_type(self) -> &'static str {
+      match self {
+        ListingFormat::Json => "application/x-ndjson",
+        ListingFormat::Csv => "text/csv; charset=utf-8",
+        ListingFormat::Xml => "application/xml",
+      }
+    }
+
+    fn header(self) -> &'static [u8] {
+      match self {
+        ListingFormat::Json => b"",
+        ListingFormat::Csv => b"path,is_dir\r\n",
+        ListingFormat::Xml => b"<?xml version=\"1.0\" encoding=\"UTF-8\"?><Objects>",
+      }
+    }
+
+    fn footer(self) -> &'static [u8] {
+      match self {
+        ListingFormat::Xml => b"</Objects>",
+        _ => b"",
+      }
+    }
+
+    fn write_row(self, rows: &mut Vec<u8>, object: &Object) -> Result<(), Error> {
+      match self {
+        ListingFormat::Json => {
+          serde_json::to_writer(&mut *rows, object).map_err(Error::JsonError)?;
+          rows.push(b'\n');
+        }
+        ListingFormat::Csv => {
+          rows.extend_from_slice(csv_field(&object.path).as_bytes());
+          rows.extend_from_slice(if object.is_dir {
+            b",true\r\n"
+          } else {
+            b",false\r\n"
+          });
+        }
+        ListingFormat::Xml => rows.extend_from_slice(
+          format!(
+            "<Object><Path>{}</Path><IsDir>{}</IsDir></Object>",
+            xml::escape::escape_str_pcdata(&object.path),
+            object.is_dir
+          )
+          .as_bytes(),
+        ),
+      }
+      Ok(())
+    }
+  }
+
+  /// Quotes a CSV field containing a separator, a quote or a line break, as in RFC 4180.
+  fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\r', '\n']) {
+      format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+      field.into()
+    }
   }
 }
@@ -0,0 +1,519 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ObjectLockQueryParameters {
+  pub bucket: String,
+  pub path: String,
+  pub version_id: Option<String>,
+  pub bypass_governance_retention: Option<bool>,
+}
+
+/// Object Lock retention mode
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RetentionMode {
+  /// Users with the `s3:BypassGovernanceRetention` permission can alter or delete the object
+  Governance,
+  /// Nobody can alter or delete the object until the retention date
+  Compliance,
+}
+
+impl RetentionMode {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      RetentionMode::Governance => "GOVERNANCE",
+      RetentionMode::Compliance => "COMPLIANCE",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ObjectRetention {
+  pub mode: RetentionMode,
+  /// Date until which the object is retained (RFC 3339)
+  pub retain_until_date: String,
+}
+
+/// Object Lock legal hold status
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LegalHoldStatus {
+  On,
+  Off,
+}
+
+impl LegalHoldStatus {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      LegalHoldStatus::On => "ON",
+      LegalHoldStatus::Off => "OFF",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ObjectLegalHold {
+  pub status: LegalHoldStatus,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    body, json_reply, metrics,
+    objects::key,
+    policy::{self, Identity, Operation},
+    query, retry, telemetry, Error, S3Configuration,
+  };
+  use md5::{Digest, Md5};
+  use rusoto_s3::{
+    GetObjectLegalHoldRequest, GetObjectRetentionRequest, ObjectLockLegalHold,
+    ObjectLockLegalHoldSerializer, ObjectLockRetention, ObjectLockRetentionSerializer,
+    PutObjectLegalHoldRequest, PutObjectRetentionRequest, S3Client, S3,
+  };
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{Filter, Rejection, Reply};
+  use xml::EventWriter;
+
+  /// Get object retention
+  #[utoipa::path(
+    get,
+    path = "/objects/retention",
+    tag = "Objects",
+    responses(
+      (
+        status = 200,
+        description = "Returns the Object Lock retention of the object",
+        content_type = "application/json",
+        body = ObjectRetention
+      ),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object"),
+      ("version_id" = Option<String>, Query, description = "Version of the object (default: latest)")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn get_retention_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "retention")
+      .and(warp::get())
+      .and(query::parameters::<ObjectLockQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: ObjectLockQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(get_object_retention(&s3_configuration, &identity, parameters).await)
+        },
+      )
+  }
+
+  /// Set object retention
+  #[utoipa::path(
+    put,
+    path = "/objects/retention",
+    tag = "Objects",
+    request_body(
+      content = ObjectRetention,
+      description = "Object Lock retention to apply",
+      content_type = "application/json"
+    ),
+    responses(
+      (status = 200, description = "Successfully set the Object Lock retention of the object"),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object"),
+      ("version_id" = Option<String>, Query, description = "Version of the object (default: latest)"),
+      ("bypass_governance_retention" = Option<bool>, Query, description = "Shorten or remove a GOVERNANCE retention")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn put_retention_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "retention")
+      .and(warp::put())
+      .and(query::parameters::<ObjectLockQueryParameters>())
+      .and(body::json::<ObjectRetention>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: ObjectLockQueryParameters,
+         retention: ObjectRetention,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(
+            put_object_retention(&s3_configuration, &identity, parameters, retention).await,
+          )
+        },
+      )
+  }
+
+  /// Get object legal hold
+  #[utoipa::path(
+    get,
+    path = "/objects/legal-hold",
+    tag = "Objects",
+    responses(
+      (
+        status = 200,
+        description = "Returns the Object Lock legal hold status of the object",
+        content_type = "application/json",
+        body = ObjectLegalHold
+      ),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object"),
+      ("version_id" = Option<String>, Query, description = "Version of the object (default: latest)")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn get_legal_hold_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "legal-hold")
+      .and(warp::get())
+      .and(query::parameters::<ObjectLockQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: ObjectLockQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(get_object_legal_hold(&s3_configuration, &identity, parameters).await)
+        },
+      )
+  }
+
+  /// Set object legal hold
+  #[utoipa::path(
+    put,
+    path = "/objects/legal-hold",
+    tag = "Objects",
+    request_body(
+      content = ObjectLegalHold,
+      description = "Object Lock legal hold status to apply",
+      content_type = "application/json"
+    ),
+    responses(
+      (status = 200, description = "Successfully set the Object Lock legal hold status of the object"),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object"),
+      ("version_id" = Option<String>, Query, description = "Version of the object (default: latest)")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn put_legal_hold_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "legal-hold")
+      .and(warp::put())
+      .and(query::parameters::<ObjectLockQueryParameters>())
+      .and(body::json::<ObjectLegalHold>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: ObjectLockQueryParameters,
+         legal_hold: ObjectLegalHold,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(
+            put_object_legal_hold(&s3_configuration, &identity, parameters, legal_hold).await,
+          )
+        },
+      )
+  }
+
+  /// Returns the Object Lock retention of an object, on behalf of `identity`.
+  pub async fn get_object_retention(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ObjectLockQueryParameters,
+  ) -> Result<ObjectRetention, Error> {
+    authorize(s3_configuration, identity, Operation::Read, &mut parameters)?;
+    handle_get_object_retention(
+      s3_configuration,
+      parameters.bucket,
+      parameters.path,
+      parameters.version_id,
+    )
+    .await
+  }
+
+  /// Applies an Object Lock retention to an object, on behalf of `identity`.
+  pub async fn put_object_retention(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ObjectLockQueryParameters,
+    retention: ObjectRetention,
+  ) -> Result<(), Error> {
+    authorize(
+      s3_configuration,
+      identity,
+      Operation::Write,
+      &mut parameters,
+    )?;
+    handle_put_object_retention(s3_configuration, parameters, retention).await
+  }
+
+  /// Returns the Object Lock legal hold status of an object, on behalf of `identity`.
+  pub async fn get_object_legal_hold(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ObjectLockQueryParameters,
+  ) -> Result<ObjectLegalHold, Error> {
+    authorize(s3_configuration, identity, Operation::Read, &mut parameters)?;
+    handle_get_object_legal_hold(
+      s3_configuration,
+      parameters.bucket,
+      parameters.path,
+      parameters.version_id,
+    )
+    .await
+  }
+
+  /// Applies an Object Lock legal hold status to an object, on behalf of `identity`.
+  pub async fn put_object_legal_hold(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ObjectLockQueryParameters,
+    legal_hold: ObjectLegalHold,
+  ) -> Result<(), Error> {
+    authorize(
+      s3_configuration,
+      identity,
+      Operation::Write,
+      &mut parameters,
+    )?;
+    handle_put_object_legal_hold(s3_configuration, parameters, legal_hold).await
+  }
+
+  fn authorize(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    operation: Operation,
+    parameters: &mut ObjectLockQueryParameters,
+  ) -> Result<(), Error> {
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      operation,
+      &parameters.bucket,
+      &parameters.path,
+    )
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_get_object_retention(
+    s3_configuration: &S3Configuration,
+    bucket: String,
+    key: String,
+    version_id: Option<String>,
+  ) -> Result<ObjectRetention, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    log::info!("Get object retention: bucket={}, key={}", bucket, key);
+    let client = S3Client::try_from(&s3_configuration)?;
+
+    let request = GetObjectRetentionRequest {
+      bucket,
+      key,
+      version_id,
+      ..Default::default()
+    };
+    let retention = retry::s3_call(&s3_configuration, "GetObjectRetention", || {
+      client
+        .get_object_retention(request.clone())
+        .instrument(telemetry::s3_span("GetObjectRetention"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("GetObjectRetention"))
+    .map_err(|error| Error::ObjectLockError(error.to_string()))?
+    .retention
+    .unwrap_or_default();
+
+    let mode = match retention.mode.as_deref() {
+      Some("GOVERNANCE") => RetentionMode::Governance,
+      Some("COMPLIANCE") => RetentionMode::Compliance,
+      mode => {
+        return Err(Error::ObjectLockError(format!(
+          "unexpected retention mode {:?}",
+          mode
+        )))
+      }
+    };
+
+    Ok(ObjectRetention {
+      mode,
+      retain_until_date: retention.retain_until_date.unwrap_or_default(),
+    })
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_put_object_retention(
+    s3_configuration: &S3Configuration,
+    parameters: ObjectLockQueryParameters,
+    retention: ObjectRetention,
+  ) -> Result<(), Error> {
+    let s3_configuration = s3_configuration.for_bucket(&parameters.bucket);
+    log::info!(
+      "Put object retention: bucket={}, key={}, mode={}, retain_until_date={}",
+      parameters.bucket,
+      parameters.path,
+      retention.mode.as_str(),
+      retention.retain_until_date
+    );
+
+    let retain_until_date = chrono::DateTime::parse_from_rfc3339(&retention.retain_until_date)
+      .map_err(|error| {
+        Error::InvalidParameter(format!(
+          "retain_until_date {:?}: {}",
+          retention.retain_until_date, error
+        ))
+      })?;
+
+    let retention = ObjectLockRetention {
+      mode: Some(retention.mode.as_str().to_string()),
+      retain_until_date: Some(
+        retain_until_date
+          .with_timezone(&chrono::Utc)
+          .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+      ),
+    };
+
+    let content_md5 = content_md5(|writer| {
+      ObjectLockRetentionSerializer::serialize(writer, "Retention", &retention)
+    })?;
+
+    let client = S3Client::try_from(&s3_configuration)?;
+    let request = PutObjectRetentionRequest {
+      bucket: parameters.bucket,
+      key: parameters.path,
+      version_id: parameters.version_id,
+      bypass_governance_retention: parameters.bypass_governance_retention,
+      content_md5: Some(content_md5),
+      retention: Some(retention),
+      ..Default::default()
+    };
+    retry::s3_call(&s3_configuration, "PutObjectRetention", || {
+      client
+        .put_object_retention(request.clone())
+        .instrument(telemetry::s3_span("PutObjectRetention"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("PutObjectRetention"))
+    .map_err(|error| Error::ObjectLockError(error.to_string()))?;
+
+    Ok(())
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_get_object_legal_hold(
+    s3_configuration: &S3Configuration,
+    bucket: String,
+    key: String,
+    version_id: Option<String>,
+  ) -> Result<ObjectLegalHold, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    log::info!("Get object legal hold: bucket={}, key={}", bucket, key);
+    let client = S3Client::try_from(&s3_configuration)?;
+
+    let request = GetObjectLegalHoldRequest {
+      bucket,
+      key,
+      version_id,
+      ..Default::default()
+    };
+    let legal_hold = retry::s3_call(&s3_configuration, "GetObjectLegalHold", || {
+      client
+        .get_object_legal_hold(request.clone())
+        .instrument(telemetry::s3_span("GetObjectLegalHold"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("GetObjectLegalHold"))
+    .map_err(|error| Error::ObjectLockError(error.to_string()))?
+    .legal_hold
+    .unwrap_or_default();
+
+    let status = match legal_hold.status.as_deref() {
+      Some("ON") => LegalHoldStatus::On,
+      _ => LegalHoldStatus::Off,
+    };
+
+    Ok(ObjectLegalHold { status })
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_put_object_legal_hold(
+    s3_configuration: &S3Configuration,
+    parameters: ObjectLockQueryParameters,
+    legal_hold: ObjectLegalHold,
+  ) -> Result<(), Error> {
+    let s3_configuration = s3_configuration.for_bucket(&parameters.bucket);
+    log::info!(
+      "Put object legal hold: bucket={}, key={}, status={}",
+      parameters.bucket,
+      parameters.path,
+      legal_hold.status.as_str()
+    );
+
+    let legal_hold = ObjectLockLegalHold {
+      status: Some(legal_hold.status.as_str().to_string()),
+    };
+
+    let content_md5 = content_md5(|writer| {
+      ObjectLockLegalHoldSerializer::serialize(writer, "LegalHold", &legal_hold)
+    })?;
+
+    let client = S3Client::try_from(&s3_configuration)?;
+    let request = PutObjectLegalHoldRequest {
+      bucket: parameters.bucket,
+      key: parameters.path,
+      version_id: parameters.version_id,
+      content_md5: Some(content_md5),
+      legal_hold: Some(legal_hold),
+      ..Default::default()
+    };
+    retry::s3_call(&s3_configuration, "PutObjectLegalHold", || {
+      client
+        .put_object_legal_hold(request.clone())
+        .instrument(telemetry::s3_span("PutObjectLegalHold"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("PutObjectLegalHold"))
+    .map_err(|error| Error::ObjectLockError(error.to_string()))?;
+
+    Ok(())
+  }
+
+  /// Computes the `Content-MD5` header required by S3 for Object Lock requests.
+  ///
+  /// The payload is serialized the same way Rusoto does when sending the request.
+  fn content_md5<F>(serialize: F) -> Result<String, Error>
+  where
+    F: FnOnce(&mut EventWriter<Vec<u8>>) -> Result<(), xml::writer::Error>,
+  {
+    let mut writer = EventWriter::new(Vec::new());
+    serialize(&mut writer).map_err(|error| Error::ObjectLockError(error.to_string()))?;
+
+    Ok(base64::encode(Md5::digest(&writer.into_inner())))
+  }
+}
@@ -0,0 +1,298 @@
+use crate::multipart_upload::MultipartPlan;
+use serde::{Deserialize, Serialize};
+
+/// Files of a folder to upload under a prefix, signed all at once.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct UploadManifestBody {
+  /// Name of the bucket
+  pub bucket: String,
+  /// Prefix of the keys of the files, e.g. `uploads/holidays/`
+  #[serde(default)]
+  pub prefix: String,
+  pub files: Vec<ManifestFile>,
+  /// Size of the parts of the files uploaded as multipart uploads, in bytes, the files up to
+  /// this size being uploaded at once
+  pub part_size: Option<u64>,
+  /// Sign against the S3 Transfer Acceleration endpoint
+  pub accelerate: Option<bool>,
+}
+
+/// File of an upload manifest.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ManifestFile {
+  /// Path of the file relative to the folder, e.g. `2024/beach.jpg`
+  pub path: String,
+  /// Size of the file, in bytes
+  pub size: u64,
+  /// Content type of the file, set on its multipart upload
+  pub content_type: Option<String>,
+}
+
+/// Upload of a file of a manifest: a pre-signed URL, or a multipart upload with its parts.
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ManifestUpload {
+  /// Path of the file relative to the folder
+  pub path: String,
+  /// Key of the object
+  pub key: String,
+  /// Pre-signed URL to upload the file at once
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub url: Option<String>,
+  /// Names of the signed headers the upload must be sent with
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub headers: Vec<String>,
+  /// Multipart upload of the file, when larger than the part size
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub multipart: Option<MultipartPlan>,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    batch::{BatchItemResult, BatchResult},
+    body, json_reply,
+    limits::{MAX_OBJECT_SIZE, MAX_PARTS, MAX_PART_SIZE, MIN_PART_SIZE},
+    multipart_upload::{
+      create::server::create_multipart_upload, plan::server::sign_parts,
+      CreateUploadQueryParameters,
+    },
+    objects::{create::create_object, CreateQueryParameters},
+    policy::{self, Identity},
+    transfer_acceleration, Error, S3Configuration,
+  };
+  use futures::StreamExt;
+  use std::sync::Arc;
+  use warp::{Filter, Rejection, Reply};
+
+  /// Files of a manifest, bounding the work of a single request
+  const MAX_MANIFEST_FILES: usize = 1_000;
+  /// Parts pre-signed for the files of a manifest
+  const MAX_MANIFEST_PARTS: u64 = 10_000;
+  /// Part size of the multipart uploads when unspecified
+  const DEFAULT_PART_SIZE: u64 = 64 * 1024 * 1024;
+  /// Files signed concurrently, each multipart upload creation being an S3 call
+  const CONCURRENT_FILES: usize = 8;
+
+  /// Pre-sign the uploads of a folder
+  #[utoipa::path(
+    post,
+    path = "/objects/upload-manifest",
+    tag = "Objects",
+    request_body(
+      content = UploadManifestBody,
+      description = "Files of the folder to upload",
+      content_type = "application/json"
+    ),
+    responses(
+      (
+        status = 200,
+        description = "Returns the upload of every file: a pre-signed URL, or a multipart upload with its pre-signed parts",
+        content_type = "application/json",
+        body = UploadManifestResult
+      ),
+      (status = 400, description = "Too many files or parts, or the bucket has a key template"),
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "upload-manifest")
+      .and(warp::post())
+      .and(body::json::<UploadManifestBody>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |body: UploadManifestBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(sign_upload_manifest(&s3_configuration, &identity, body).await)
+        },
+      )
+  }
+
+  /// Pre-signs the uploads of the files of a folder under a prefix, on behalf of `identity`,
+  /// reporting the outcome of each.
+  ///
+  /// The files up to the part size get a pre-signed URL, as with `POST /objects`, the larger
+  /// ones a multipart upload with the pre-signed URLs of all their parts.
+  pub async fn sign_upload_manifest(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    body: UploadManifestBody,
+  ) -> Result<BatchResult<ManifestUpload>, Error> {
+    let UploadManifestBody {
+      bucket,
+      mut prefix,
+      files,
+      part_size,
+      accelerate,
+    } = body;
+    let bucket_configuration = s3_configuration.for_bucket(&bucket);
+    if bucket_configuration.key_template().is_some() {
+      return Err(Error::InvalidParameter(format!(
+        "the keys of bucket {} are generated from a key template",
+        bucket
+      )));
+    }
+    if files.len() > MAX_MANIFEST_FILES {
+      return Err(Error::InvalidParameter(format!(
+        "a manifest has at most {} files",
+        MAX_MANIFEST_FILES
+      )));
+    }
+    let part_size = part_size.unwrap_or(DEFAULT_PART_SIZE);
+    if !(MIN_PART_SIZE..=MAX_PART_SIZE).contains(&part_size) {
+      return Err(Error::InvalidParameter(format!(
+        "part_size must be between {} and {} bytes",
+        MIN_PART_SIZE, MAX_PART_SIZE
+      )));
+    }
+    let parts: u64 = files
+      .iter()
+      .filter(|file| file.size > part_size)
+      .map(|file| file.size.div_ceil(file_part_size(file.size, part_size)))
+      .sum();
+    if parts > MAX_MANIFEST_PARTS {
+      return Err(Error::InvalidParameter(format!(
+        "the files of a manifest have at most {} parts, use a larger part_size",
+        MAX_MANIFEST_PARTS
+      )));
+    }
+    if !prefix.is_empty() && !prefix.ends_with('/') {
+      prefix.push('/');
+    }
+    let accelerate = accelerate.unwrap_or_else(|| bucket_configuration.transfer_acceleration());
+    if accelerate {
+      transfer_acceleration::check_enabled(&bucket_configuration, &bucket).await?;
+    }
+
+    log::info!(
+      "Upload manifest: bucket={}, prefix={}, files={}, parts={}",
+      bucket,
+      prefix,
+      files.len(),
+      parts
+    );
+    let manifest = Manifest {
+      bucket: &bucket,
+      prefix: &prefix,
+      part_size,
+      accelerate,
+    };
+    let manifest = &manifest;
+    let items = futures::stream::iter(files.into_iter().enumerate())
+      .map(|(index, file)| async move {
+        match sign_file(s3_configuration, identity, manifest, file).await {
+          Ok(upload) => BatchItemResult::succeeded(index, upload),
+          Err(error) => BatchItemResult::failed(index, &error.code(), &error.public_message()),
+        }
+      })
+      .buffered(CONCURRENT_FILES)
+      .collect()
+      .await;
+
+    Ok(BatchResult::new(items))
+  }
+
+  struct Manifest<'a> {
+    bucket: &'a str,
+    prefix: &'a str,
+    part_size: u64,
+    accelerate: bool,
+  }
+
+  async fn sign_file(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    manifest: &Manifest<'_>,
+    file: ManifestFile,
+  ) -> Result<ManifestUpload, Error> {
+    check_path(&file.path)?;
+    if file.size > MAX_OBJECT_SIZE {
+      return Err(Error::InvalidParameter(format!(
+        "objects have at most {} bytes",
+        MAX_OBJECT_SIZE
+      )));
+    }
+    let mut key = format!("{}{}", manifest.prefix, file.path);
+    identity.scope(&mut key);
+
+    if file.size <= manifest.part_size {
+      let parameters = CreateQueryParameters {
+        bucket: manifest.bucket.to_string(),
+        path: key.clone(),
+        accelerate: Some(manifest.accelerate),
+        sse: None,
+        kms_key_id: None,
+        cache_control: None,
+        expires: None,
+        content_md5: None,
+        checksum_sha256: None,
+      };
+      let presigned_url = create_object(s3_configuration, identity, parameters, None).await?;
+      return Ok(ManifestUpload {
+        path: file.path,
+        key,
+        url: Some(presigned_url.url),
+        headers: presigned_url.headers,
+        multipart: None,
+      });
+    }
+
+    let parameters = CreateUploadQueryParameters {
+      bucket: manifest.bucket.to_string(),
+      path: key.clone(),
+      sse: None,
+      kms_key_id: None,
+      content_type: file.content_type,
+      cache_control: None,
+      expires: None,
+    };
+    let upload_id = create_multipart_upload(s3_configuration, identity, parameters)
+      .await?
+      .body
+      .upload_id;
+    let multipart = sign_parts(
+      s3_configuration,
+      manifest.bucket,
+      &key,
+      upload_id,
+      file_part_size(file.size, manifest.part_size),
+      file.size,
+      manifest.accelerate,
+    )
+    .await?;
+    Ok(ManifestUpload {
+      path: file.path,
+      key,
+      url: None,
+      headers: Vec::new(),
+      multipart: Some(multipart),
+    })
+  }
+
+  /// Part size of a file, grown for the file to fit in the maximum number of parts.
+  fn file_part_size(size: u64, part_size: u64) -> u64 {
+    part_size.max(size.div_ceil(MAX_PARTS as u64))
+  }
+
+  /// Rejects the paths escaping the prefix of the manifest.
+  fn check_path(path: &str) -> Result<(), Error> {
+    if path.is_empty()
+      || path.starts_with('/')
+      || path
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+      return Err(Error::InvalidParameter(format!(
+        "{:?} is not a relative file path",
+        path
+      )));
+    }
+    Ok(())
+  }
+}
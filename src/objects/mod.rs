@@ -1,33 +1,170 @@
+pub(crate) mod archive;
+pub(crate) mod attributes;
+pub(crate) mod content;
 #[cfg(feature = "server")]
 pub(crate) mod create;
+pub(crate) mod dedup;
 #[cfg(feature = "server")]
 pub(crate) mod get;
+pub mod key;
 pub(crate) mod list;
+pub(crate) mod lock;
+pub(crate) mod manifest;
+pub(crate) mod select;
+pub(crate) mod share;
+pub(crate) mod site;
+#[cfg(feature = "server")]
+pub(crate) mod tagging;
+pub(crate) mod verify;
 
+pub use archive::ArchiveBody;
+pub use attributes::{
+  ObjectAttributesQueryParameters, ObjectAttributesResponse, ObjectChecksum, ObjectPart,
+  ObjectParts,
+};
+pub use content::{ObjectContentQueryParameters, ObjectContentResponse};
+pub use dedup::{DedupBody, DedupResponse};
+#[cfg(feature = "server")]
+pub use list::server::{list_objects_page, ListedObject};
 pub use list::{ListObjectsQueryParameters, ListObjectsResponse, Object};
+pub use lock::{
+  LegalHoldStatus, ObjectLegalHold, ObjectLockQueryParameters, ObjectRetention, RetentionMode,
+};
+pub use manifest::{ManifestFile, ManifestUpload, UploadManifestBody};
+pub use select::{SelectBody, SelectCompression, SelectInputFormat, SelectOutputFormat};
+pub use share::{ShareQueryParameters, ShareResponse, DEFAULT_SHARE_PRESET, DEFAULT_SHARE_PRESETS};
+pub use site::{SiteQueryParameters, SiteResponse};
+#[cfg(feature = "server")]
+pub use tagging::AccessTagging;
+pub use verify::{ChecksumAlgorithm, ChecksumCheck, Verdict, VerifyBody, VerifyResponse};
 
+use crate::encryption::ServerSideEncryption;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SignQueryParameters {
   pub bucket: String,
   pub path: String,
+  pub accelerate: Option<bool>,
+  pub proxy: Option<bool>,
+}
+
+/// Query parameters of `POST /objects`, describing the object to create.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CreateQueryParameters {
+  pub bucket: String,
+  pub path: String,
+  pub accelerate: Option<bool>,
+  pub sse: Option<ServerSideEncryption>,
+  pub kms_key_id: Option<String>,
+  /// `Cache-Control` header of the object to create, e.g. `public, max-age=31536000`
+  pub cache_control: Option<String>,
+  /// `Expires` header of the object to create, as an HTTP date
+  pub expires: Option<String>,
+  /// Base64-encoded MD5 digest of the object to create, checked by S3 on upload
+  pub content_md5: Option<String>,
+  /// Base64-encoded SHA-256 checksum of the object to create, checked by S3 on upload
+  pub checksum_sha256: Option<String>,
+}
+
+impl From<SignQueryParameters> for CreateQueryParameters {
+  /// Parameters of an object created without encryption, cache headers or digests.
+  fn from(parameters: SignQueryParameters) -> Self {
+    CreateQueryParameters {
+      bucket: parameters.bucket,
+      path: parameters.path,
+      accelerate: parameters.accelerate,
+      sse: None,
+      kms_key_id: None,
+      cache_control: None,
+      expires: None,
+      content_md5: None,
+      checksum_sha256: None,
+    }
+  }
+}
+
+/// Pre-signed URL, answered instead of a redirection to clients accepting JSON.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct PresignedUrlResponse {
+  pub url: String,
+  /// HTTP method of the request to send to the URL, e.g. `PUT`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub method: Option<String>,
+  /// Names of the signed headers the request must be sent with, e.g. `Content-MD5`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub headers: Vec<String>,
+  /// Key of the object, when generated from the key template of the bucket
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub key: Option<String>,
+  /// Time at which the URL expires, in RFC 3339, at the latest when the credentials of the signer
+  /// do
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<String>,
 }
 
 #[cfg(feature = "server")]
-pub(crate) use server::routes;
+pub(crate) use server::{expires_at, routes};
 
 #[cfg(feature = "server")]
 mod server {
   use super::*;
-  use crate::S3Configuration;
-  use warp::{Filter, Rejection, Reply};
+  use crate::{
+    presign::{PresignConfiguration, PresignOptions},
+    Error, S3Configuration,
+  };
+  use chrono::{DateTime, SecondsFormat, Utc};
+  use warp::{
+    filters::BoxedFilter,
+    hyper::{Body, Response},
+    Filter, Reply,
+  };
 
-  pub(crate) fn routes(
-    s3_configuration: &S3Configuration,
-  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    get::route(s3_configuration)
+  pub(crate) fn routes(s3_configuration: &S3Configuration) -> BoxedFilter<(Response<Body>,)> {
+    // Boxed, like the routes they are mounted with, to keep the stack of their futures shallow.
+    site::server::route(s3_configuration)
+      .or(lock_routes(s3_configuration))
+      .or(get::route(s3_configuration))
+      .or(archive::server::route(s3_configuration))
+      .or(content::server::route(s3_configuration))
+      .or(inspection_routes(s3_configuration))
+      .or(share::server::route(s3_configuration))
       .or(create::route(s3_configuration))
+      .or(list::server::stream_route(s3_configuration))
       .or(list::server::route(s3_configuration))
+      .map(Reply::into_response)
+      .boxed()
+  }
+
+  /// Time at which the URLs signed now expire, in RFC 3339.
+  pub(crate) fn expires_at(
+    presign_configuration: &PresignConfiguration,
+    options: &PresignOptions,
+  ) -> Result<String, Error> {
+    Ok(
+      DateTime::<Utc>::from(presign_configuration.expires_at(options)?)
+        .to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
+  }
+
+  fn inspection_routes(s3_configuration: &S3Configuration) -> BoxedFilter<(Response<Body>,)> {
+    attributes::server::route(s3_configuration)
+      .or(select::server::route(s3_configuration))
+      .or(verify::server::route(s3_configuration))
+      .or(dedup::server::route(s3_configuration))
+      .or(manifest::server::route(s3_configuration))
+      .map(Reply::into_response)
+      .boxed()
+  }
+
+  fn lock_routes(s3_configuration: &S3Configuration) -> BoxedFilter<(Response<Body>,)> {
+    lock::server::get_retention_route(s3_configuration)
+      .or(lock::server::put_retention_route(s3_configuration))
+      .or(lock::server::get_legal_hold_route(s3_configuration))
+      .or(lock::server::put_legal_hold_route(s3_configuration))
+      .map(Reply::into_response)
+      .boxed()
   }
 }
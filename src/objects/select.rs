@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+
+/// Query of an object with S3 Select.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct SelectBody {
+  /// Name of the bucket
+  pub bucket: String,
+  /// Key of the queried object
+  pub path: String,
+  /// SQL expression, e.g. `SELECT s.title FROM S3Object s WHERE s.duration > 60`
+  pub expression: String,
+  /// Format of the object
+  pub input_format: SelectInputFormat,
+  /// Whether the first line of a CSV object names its columns, to be used in the expression
+  pub csv_header: Option<bool>,
+  /// Compression of a CSV or JSON object
+  pub compression: Option<SelectCompression>,
+  /// Format of the streamed rows, JSON lines by default
+  pub output_format: Option<SelectOutputFormat>,
+}
+
+/// Format of a queried object
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SelectInputFormat {
+  Csv,
+  /// A single JSON document
+  Json,
+  /// A JSON document per line
+  JsonLines,
+  Parquet,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SelectCompression {
+  Gzip,
+  Bzip2,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SelectOutputFormat {
+  Csv,
+  /// A JSON object per line
+  Json,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    body, concurrency_limit, metrics,
+    objects::key,
+    policy::{self, Identity, Operation},
+    request_builder, retry, telemetry, Error, S3Configuration,
+  };
+  use futures::StreamExt;
+  use rusoto_core::{
+    event_stream::{DeserializeEvent, EventStream},
+    param::{Params, ServiceParams},
+    signature::SignedRequest,
+    Client, RusotoError,
+  };
+  use rusoto_s3::{
+    CSVInput, CSVOutput, InputSerialization, JSONInput, JSONOutput, OutputSerialization,
+    ParquetInput, SelectObjectContentError, SelectObjectContentRequest,
+    SelectObjectContentRequestSerializer,
+  };
+  use std::{convert::TryFrom, sync::Arc};
+  use tokio::sync::OwnedSemaphorePermit;
+  use tracing::Instrument;
+  use warp::{
+    hyper::{
+      body::{Bytes, Sender},
+      header::CONTENT_TYPE,
+      Body, Response, StatusCode,
+    },
+    Filter, Rejection, Reply,
+  };
+  use xml::EventWriter;
+
+  const S3_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+  /// Query an object with S3 Select
+  #[utoipa::path(
+    post,
+    path = "/objects/select",
+    tag = "Objects",
+    request_body(
+      content = SelectBody,
+      description = "Object to query, and the SQL expression",
+      content_type = "application/json"
+    ),
+    responses(
+      (status = 200, description = "Streams the selected rows as JSON lines", content_type = "application/x-ndjson"),
+      (status = 503, description = "Too many concurrent S3 calls, retry after the Retry-After delay"),
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "select")
+      .and(warp::post())
+      .and(body::json::<SelectBody>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |body: SelectBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+          select_object(&s3_configuration, &identity, body)
+            .await
+            .map_err(warp::reject::custom)
+        },
+      )
+  }
+
+  /// Queries an object with S3 Select, on behalf of `identity`, streaming the selected rows.
+  pub async fn select_object(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut body: SelectBody,
+  ) -> Result<Response<Body>, Error> {
+    identity.scope(&mut body.path);
+    key::server::validate_key(s3_configuration, &body.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &body.bucket,
+      &body.path,
+    )?;
+    handle_select_object(s3_configuration, body).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_select_object(
+    s3_configuration: &S3Configuration,
+    body: SelectBody,
+  ) -> Result<Response<Body>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&body.bucket);
+    log::info!("Select object: bucket={}, key={}", body.bucket, body.path);
+    if body.input_format == SelectInputFormat::Parquet && body.compression.is_some() {
+      return Err(Error::InvalidParameter(
+        "Parquet objects cannot be compressed".to_string(),
+      ));
+    }
+    let output_format = body.output_format.unwrap_or(SelectOutputFormat::Json);
+    let payload = select_request_payload(&body, output_format)?;
+
+    let permit = concurrency_limit::acquire(&s3_configuration)?;
+    let client = Client::try_from(&s3_configuration)?;
+    let events = retry::s3_call(&s3_configuration, "SelectObjectContent", || {
+      select_object_content(&client, &s3_configuration, &body, &payload)
+        .instrument(telemetry::s3_span("SelectObjectContent"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("SelectObjectContent"))
+    .map_err(Error::SelectObjectContentError)?;
+
+    // The rows are streamed as S3 selects them: its errors interrupt the response.
+    let (sender, response_body) = Body::channel();
+    let (bucket, key) = (body.bucket, body.path);
+    tokio::spawn(async move {
+      if let Err(error) = send_records(events, sender, permit).await {
+        log::error!(
+          "Select stream interrupted: bucket={}, key={}, {}",
+          bucket,
+          key,
+          error
+        );
+      }
+    });
+
+    let content_type = match output_format {
+      SelectOutputFormat::Csv => "text/csv",
+      SelectOutputFormat::Json => "application/x-ndjson",
+    };
+    request_builder()
+      .header(CONTENT_TYPE, content_type)
+      .status(StatusCode::OK)
+      .body(response_body)
+      .map_err(Error::HttpError)
+  }
+
+  /// Body of the SelectObjectContent request, serialized as rusoto does.
+  fn select_request_payload(
+    body: &SelectBody,
+    output_format: SelectOutputFormat,
+  ) -> Result<Vec<u8>, Error> {
+    let input_serialization = match body.input_format {
+      SelectInputFormat::Csv => InputSerialization {
+        csv: Some(CSVInput {
+          file_header_info: Some(
+            if body.csv_header.unwrap_or_default() {
+              "USE"
+            } else {
+              "NONE"
+            }
+            .to_string(),
+          ),
+          ..Default::default()
+        }),
+        ..Default::default()
+      },
+      SelectInputFormat::Json | SelectInputFormat::JsonLines => InputSerialization {
+        json: Some(JSONInput {
+          type_: Some(
+            if body.input_format == SelectInputFormat::Json {
+              "DOCUMENT"
+            } else {
+              "LINES"
+            }
+            .to_string(),
+          ),
+        }),
+        ..Default::default()
+      },
+      SelectInputFormat::Parquet => InputSerialization {
+        parquet: Some(ParquetInput {}),
+        ..Default::default()
+      },
+    };
+    let input_serialization = InputSerialization {
+      compression_type: body.compression.map(|compression| {
+        match compression {
+          SelectCompression::Gzip => "GZIP",
+          SelectCompression::Bzip2 => "BZIP2",
+        }
+        .to_string()
+      }),
+      ..input_serialization
+    };
+
+    let output_serialization = match output_format {
+      SelectOutputFormat::Csv => OutputSerialization {
+        csv: Some(CSVOutput::default()),
+        ..Default::default()
+      },
+      SelectOutputFormat::Json => OutputSerialization {
+        json: Some(JSONOutput {
+          record_delimiter: Some("\n".to_string()),
+        }),
+        ..Default::default()
+      },
+    };
+
+    let request = SelectObjectContentRequest {
+      bucket: body.bucket.clone(),
+      key: body.path.clone(),
+      expression: body.expression.clone(),
+      expression_type: "SQL".to_string(),
+      input_serialization,
+      output_serialization,
+      ..Default::default()
+    };
+    let mut writer = EventWriter::new(Vec::new());
+    SelectObjectContentRequestSerializer::serialize(
+      &mut writer,
+      "SelectObjectContentRequest",
+      &request,
+      S3_NAMESPACE,
+    )
+    .map_err(|error| Error::InvalidParameter(format!("cannot serialize the query: {}", error)))?;
+    Ok(writer.into_inner())
+  }
+
+  /// Sends the SelectObjectContent request, which the `S3Client` of rusoto does not implement,
+  /// returning the stream of its events once S3 accepted the query.
+  async fn select_object_content(
+    client: &Client,
+    s3_configuration: &S3Configuration,
+    body: &SelectBody,
+    payload: &[u8],
+  ) -> Result<EventStream<SelectEvent>, RusotoError<SelectObjectContentError>> {
+    let mut request = SignedRequest::new(
+      "POST",
+      "s3",
+      s3_configuration.region(),
+      &format!("/{}/{}", body.bucket, body.path),
+    );
+    let mut params = Params::new();
+    params.put_key("select");
+    params.put("select-type", "2");
+    request.set_params(params);
+    request.set_payload(Some(payload.to_vec()));
+
+    let mut response = client.sign_and_dispatch(request).await?;
+    if !response.status.is_success() {
+      let response = response.buffer().await.map_err(RusotoError::HttpDispatch)?;
+      return Err(SelectObjectContentError::from_response(response));
+    }
+    Ok(EventStream::new(response))
+  }
+
+  /// Event of the SelectObjectContent response stream.
+  enum SelectEvent {
+    Records(Bytes),
+    End,
+    /// Continuation, progress and statistics events
+    Other,
+  }
+
+  impl DeserializeEvent for SelectEvent {
+    fn deserialize_event(event_type: &str, data: &[u8]) -> Result<Self, RusotoError<()>> {
+      match event_type {
+        "Records" => Ok(SelectEvent::Records(Bytes::copy_from_slice(data))),
+        "End" => Ok(SelectEvent::End),
+        "Cont" | "Progress" | "Stats" => Ok(SelectEvent::Other),
+        event_type => Err(RusotoError::ParseError(format!(
+          "unexpected {} event: {}",
+          event_type,
+          String::from_utf8_lossy(data)
+        ))),
+      }
+    }
+  }
+
+  /// Sends the records of the events, until the end event which S3 sends once the whole object
+  /// was queried.
+  ///
+  /// The concurrency slot is held until the records are sent.
+  async fn send_records(
+    mut events: EventStream<SelectEvent>,
+    mut sender: Sender,
+    _permit: Option<OwnedSemaphorePermit>,
+  ) -> Result<(), Error> {
+    let result = async {
+      while let Some(event) = events.next().await {
+        match event.map_err(|error| Error::SelectError(format!("{:?}", error)))? {
+          SelectEvent::Records(records) => sender
+            .send_data(records)
+            .await
+            .map_err(|error| Error::SelectError(error.to_string()))?,
+          SelectEvent::End => return Ok(()),
+          SelectEvent::Other => {}
+        }
+      }
+      Err(Error::SelectError(
+        "the stream ended before the end event".to_string(),
+      ))
+    }
+    .await;
+
+    if result.is_err() {
+      // Signals the client that the rows are incomplete
+      sender.abort();
+    }
+    result
+  }
+}
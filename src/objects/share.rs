@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+
+/// Lifetimes of the share URLs by preset name, in seconds, unless configured
+pub const DEFAULT_SHARE_PRESETS: &[(&str, u64)] = &[
+  ("preview", 15 * 60),
+  ("share", 24 * 3600),
+  ("archive", 7 * 24 * 3600),
+];
+/// Preset of the share URLs, unless requested
+pub const DEFAULT_SHARE_PRESET: &str = "share";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ShareQueryParameters {
+  pub bucket: String,
+  pub path: String,
+  pub preset: Option<String>,
+}
+
+/// Pre-signed URL downloading an object as an attachment, valid for the lifetime of a preset.
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ShareResponse {
+  pub url: String,
+  pub preset: String,
+  /// Lifetime of the URL, in seconds
+  pub expires_in: u64,
+  /// Expiration date of the URL, in RFC 3339 format
+  pub expires_at: String,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    json_reply, metrics,
+    objects::key,
+    policy::{self, Identity, Operation},
+    presign::{self, PresignOptions},
+    query, Error, S3Configuration,
+  };
+  use chrono::{SecondsFormat, Utc};
+  use std::sync::Arc;
+  use warp::{Filter, Rejection, Reply};
+
+  /// Share an object
+  #[utoipa::path(
+    get,
+    path = "/objects/share",
+    tag = "Objects",
+    responses(
+      (
+        status = 200,
+        description = "Returns a pre-signed URL downloading the object, valid for the lifetime of the preset",
+        content_type = "application/json",
+        body = ShareResponse
+      ),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("path" = String, Query, description = "Key of the object to share"),
+      ("preset" = Option<String>, Query, description = "Name of the lifetime preset, e.g. `preview`, `share` or `archive` (default: share)")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "share")
+      .and(warp::get())
+      .and(query::parameters::<ShareQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: ShareQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(share_object(&s3_configuration, &identity, parameters).await)
+        },
+      )
+  }
+
+  /// Pre-signs the URL downloading an object as an attachment, for the lifetime of the requested
+  /// preset, on behalf of `identity`.
+  ///
+  /// Unlike [`get_object`](crate::handlers::get_object), share URLs are always pre-signed, even
+  /// with download links enabled.
+  pub async fn share_object(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: ShareQueryParameters,
+  ) -> Result<ShareResponse, Error> {
+    identity.scope(&mut parameters.path);
+    key::server::validate_key(s3_configuration, &parameters.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &parameters.bucket,
+      &parameters.path,
+    )?;
+
+    let preset = parameters
+      .preset
+      .unwrap_or_else(|| DEFAULT_SHARE_PRESET.to_string());
+    let expiration = *s3_configuration
+      .share_presets()
+      .get(&preset)
+      .ok_or_else(|| {
+        Error::InvalidParameter(format!(
+          "unknown preset {:?}, expected one of: {}",
+          preset,
+          s3_configuration
+            .share_presets()
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+        ))
+      })?;
+
+    let s3_configuration = s3_configuration.for_bucket(&parameters.bucket);
+    log::info!(
+      "Share object: bucket={}, key={}, preset={}",
+      parameters.bucket,
+      parameters.path,
+      preset
+    );
+    let options = PresignOptions::from(&s3_configuration)
+      .with_expiration(expiration)
+      .with_content_disposition(&attachment(&parameters.path));
+    let presign_configuration = s3_configuration
+      .presign_configuration(&parameters.bucket)
+      .await;
+    let url = presign::get_object(
+      &presign_configuration,
+      &parameters.bucket,
+      &parameters.path,
+      &options,
+    )?;
+    // Shorter than the preset when the credentials expire first.
+    let expiration = presign_configuration.expiration(&options)?;
+    let expires_at = Utc::now()
+      + chrono::Duration::from_std(expiration)
+        .map_err(|error| Error::SignatureError(error.to_string()))?;
+
+    metrics::presigned_url("GetObject");
+    Ok(ShareResponse {
+      url,
+      preset,
+      expires_in: expiration.as_secs(),
+      expires_at: expires_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+    })
+  }
+
+  /// `Content-Disposition` downloading an object under the last segment of its key, with an
+  /// ASCII fallback of the UTF-8 file name.
+  fn attachment(key: &str) -> String {
+    let filename = key.rsplit('/').next().unwrap_or(key);
+    let fallback: String = filename
+      .chars()
+      .map(|c| {
+        if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+          c
+        } else {
+          '_'
+        }
+      })
+      .collect();
+    let encoded: String = filename
+      .bytes()
+      .map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+          (byte as char).to_string()
+        }
+        byte => format!("%{:02X}", byte),
+      })
+      .collect();
+    format!(
+      "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+      fallback, encoded
+    )
+  }
+}
@@ -0,0 +1,354 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SiteQueryParameters {
+  pub bucket: String,
+  pub prefix: String,
+  pub entry: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct SiteResponse {
+  /// Path of the entry HTML document, relative to the prefix
+  pub entry: String,
+  /// Entry HTML document, with its asset references pointing at pre-signed URLs
+  pub html: String,
+  /// Pre-signed URLs of every object of the site, by path relative to the prefix
+  pub objects: BTreeMap<String, String>,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    concurrency_limit, metrics,
+    objects::{get::read_object, key},
+    policy::{self, Identity, Operation},
+    presign::{self, PresignOptions},
+    query, retry, telemetry, to_ok_json_response, Error, S3Configuration,
+  };
+  use rusoto_s3::{ListObjectsV2Request, S3Client, S3};
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{
+    hyper::{Body, Response},
+    Filter, Rejection, Reply,
+  };
+
+  const DEFAULT_ENTRY: &str = "index.html";
+  const MAX_ENTRY_SIZE: usize = 4 * 1024 * 1024;
+  const MAX_SITE_OBJECTS: usize = 1000;
+  const URL_ATTRIBUTES: &[&str] = &["src=", "href="];
+
+  /// Pre-sign a static site
+  #[utoipa::path(
+    get,
+    path = "/objects/site",
+    tag = "Objects",
+    responses(
+      (
+        status = 200,
+        description = "Returns the rewritten entry document and the pre-signed URLs of the site objects",
+        content_type = "application/json",
+        body = SiteResponse
+      ),
+      (status = 400, description = "The entry document exceeds 4 MiB"),
+      (status = 503, description = "Too many concurrent S3 calls, retry after the Retry-After delay"),
+    ),
+    params(
+      ("bucket" = String, Query, description = "Name of the bucket"),
+      ("prefix" = String, Query, description = "Prefix of the site objects"),
+      ("entry" = Option<String>, Query, description = "Path of the entry HTML document, relative to the prefix (default: index.html)")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "site")
+      .and(warp::get())
+      .and(query::parameters::<SiteQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |parameters: SiteQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          site(&s3_configuration, &identity, parameters)
+            .await
+            .map_err(warp::reject::custom)
+        },
+      )
+  }
+
+  /// Pre-signs the objects of a static site and rewrites its entry document, on behalf of
+  /// `identity`.
+  pub async fn site(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut parameters: SiteQueryParameters,
+  ) -> Result<Response<Body>, Error> {
+    identity.scope(&mut parameters.prefix);
+    key::server::validate_prefix(s3_configuration, &parameters.prefix)?;
+    if let Some(entry) = &parameters.entry {
+      key::server::validate_key(s3_configuration, entry)?;
+    }
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &parameters.bucket,
+      &parameters.prefix,
+    )?;
+    handle_site(
+      s3_configuration,
+      parameters.bucket,
+      parameters.prefix,
+      parameters.entry,
+    )
+    .await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_site(
+    s3_configuration: &S3Configuration,
+    bucket: String,
+    prefix: String,
+    entry: Option<String>,
+  ) -> Result<Response<Body>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    let _permit = concurrency_limit::acquire(&s3_configuration)?;
+    let prefix = if prefix.is_empty() || prefix.ends_with('/') {
+      prefix
+    } else {
+      format!("{}/", prefix)
+    };
+    let entry = entry.unwrap_or_else(|| DEFAULT_ENTRY.to_string());
+    log::info!(
+      "Pre-sign site: bucket={}, prefix={}, entry={}",
+      bucket,
+      prefix,
+      entry
+    );
+
+    let client = S3Client::try_from(&s3_configuration)?;
+    let presign_configuration = s3_configuration.presign_configuration(&bucket).await;
+    let presign_options = PresignOptions::from(&s3_configuration);
+
+    let mut objects = BTreeMap::new();
+    let mut continuation_token = None;
+    loop {
+      let request = ListObjectsV2Request {
+        bucket: bucket.clone(),
+        prefix: Some(prefix.clone()),
+        continuation_token: continuation_token.take(),
+        ..Default::default()
+      };
+      let response = retry::s3_call(&s3_configuration, "ListObjectsV2", || {
+        client
+          .list_objects_v2(request.clone())
+          .instrument(telemetry::s3_span("ListObjectsV2"))
+      })
+      .await
+      .map_err(metrics::count_s3_error("ListObjectsV2"))
+      .map_err(Error::ListObjectsError)?;
+
+      for key in response
+        .contents
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|content| content.key)
+      {
+        let presigned_url =
+          presign::get_object(&presign_configuration, &bucket, &key, &presign_options)?;
+
+        metrics::presigned_url("GetObject");
+        objects.insert(key[prefix.len()..].to_string(), presigned_url);
+      }
+
+      if objects.len() > MAX_SITE_OBJECTS {
+        return Err(Error::SiteError(format!(
+          "more than {} objects under prefix {}",
+          MAX_SITE_OBJECTS, prefix
+        )));
+      }
+
+      continuation_token = response.next_continuation_token;
+      if !response.is_truncated.unwrap_or_default() || continuation_token.is_none() {
+        break;
+      }
+    }
+
+    let html = read_object(
+      &s3_configuration,
+      &bucket,
+      &format!("{}{}", prefix, entry),
+      MAX_ENTRY_SIZE,
+      Error::SiteError,
+    )
+    .await?;
+
+    let entry_directory = entry
+      .rfind('/')
+      .map(|index| entry[..=index].to_string())
+      .unwrap_or_default();
+
+    let html = rewrite_html(&String::from_utf8_lossy(&html), &entry_directory, &objects);
+
+    to_ok_json_response(&SiteResponse {
+      entry,
+      html,
+      objects,
+    })
+  }
+
+  /// Rewrites the `src` and `href` attributes of an HTML document which reference objects of the site.
+  ///
+  /// References are resolved relatively to the directory of the entry document, absolute ones
+  /// relatively to the root of the site. External and unknown references are left untouched.
+  fn rewrite_html(
+    html: &str,
+    entry_directory: &str,
+    presigned_urls: &BTreeMap<String, String>,
+  ) -> String {
+    let mut rewritten = String::with_capacity(html.len());
+    let mut remaining = html;
+
+    while let Some((index, attribute)) = URL_ATTRIBUTES
+      .iter()
+      .filter_map(|attribute| remaining.find(attribute).map(|index| (index, attribute)))
+      .min_by_key(|(index, _)| *index)
+    {
+      let value_start = index + attribute.len();
+      rewritten.push_str(&remaining[..value_start]);
+      remaining = &remaining[value_start..];
+
+      let quote = match remaining.chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => quote,
+        _ => continue,
+      };
+
+      let value_end = match remaining[1..].find(quote) {
+        Some(value_end) => value_end + 1,
+        None => continue,
+      };
+
+      let value = &remaining[1..value_end];
+      let presigned_url = resolve_reference(entry_directory, value)
+        .and_then(|path| presigned_urls.get(&path))
+        .map(|presigned_url| presigned_url.replace('&', "&amp;"));
+
+      rewritten.push(quote);
+      rewritten.push_str(presigned_url.as_deref().unwrap_or(value));
+      rewritten.push(quote);
+      remaining = &remaining[value_end + 1..];
+    }
+
+    rewritten.push_str(remaining);
+    rewritten
+  }
+
+  /// Resolves a reference of a document to the path of the object, relatively to the prefix.
+  pub(crate) fn resolve_reference(entry_directory: &str, reference: &str) -> Option<String> {
+    if reference.is_empty()
+      || reference.starts_with('#')
+      || reference.starts_with("//")
+      || reference.contains(':')
+    {
+      return None;
+    }
+
+    let reference = reference.split(['?', '#']).next().unwrap_or_default();
+
+    let path = if let Some(absolute_reference) = reference.strip_prefix('/') {
+      absolute_reference.to_string()
+    } else {
+      format!("{}{}", entry_directory, reference)
+    };
+
+    let mut segments: Vec<&str> = vec![];
+    for segment in path.split('/') {
+      match segment {
+        "" | "." => {}
+        ".." => {
+          segments.pop()?;
+        }
+        segment => segments.push(segment),
+      }
+    }
+
+    Some(segments.join("/"))
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    fn presigned_urls() -> BTreeMap<String, String> {
+      [
+        ("index.html", "https://s3/index.html?X-Amz-Signature=1"),
+        (
+          "css/site.css",
+          "https://s3/css/site.css?X-Amz-Date=2&X-Amz-Signature=2",
+        ),
+        (
+          "docs/guide.html",
+          "https://s3/docs/guide.html?X-Amz-Signature=3",
+        ),
+        (
+          "docs/img/logo.png",
+          "https://s3/docs/img/logo.png?X-Amz-Signature=4",
+        ),
+      ]
+      .iter()
+      .map(|&(path, url)| (path.to_string(), url.to_string()))
+      .collect()
+    }
+
+    #[test]
+    fn references_resolve_from_the_entry_directory() {
+      assert_eq!(
+        resolve_reference("docs/", "img/logo.png").as_deref(),
+        Some("docs/img/logo.png")
+      );
+      assert_eq!(
+        resolve_reference("docs/", "../css/site.css").as_deref(),
+        Some("css/site.css")
+      );
+      assert_eq!(
+        resolve_reference("docs/", "/index.html#top").as_deref(),
+        Some("index.html")
+      );
+      assert_eq!(resolve_reference("", "../index.html"), None);
+      assert_eq!(resolve_reference("docs/", "mailto:admin@example.com"), None);
+      assert_eq!(resolve_reference("docs/", "//cdn.example.com/a.js"), None);
+      assert_eq!(resolve_reference("docs/", "#section"), None);
+    }
+
+    #[test]
+    fn known_references_are_replaced_by_escaped_presigned_urls() {
+      let html = r#"<link href="../css/site.css"><img src='img/logo.png'><a href="/index.html">"#;
+
+      assert_eq!(
+        rewrite_html(html, "docs/", &presigned_urls()),
+        r#"<link href="https://s3/css/site.css?X-Amz-Date=2&amp;X-Amz-Signature=2"><img src='https://s3/docs/img/logo.png?X-Amz-Signature=4'><a href="https://s3/index.html?X-Amz-Signature=1">"#
+      );
+    }
+
+    #[test]
+    fn external_unknown_and_malformed_references_are_left_untouched() {
+      let html = concat!(
+        r#"<a href="https://example.com/">"#,
+        r##"<a href="#top">"##,
+        r#"<img src="missing.png">"#,
+        r#"<a href=guide.html>"#,
+        r#"<a href="guide.html"#,
+      );
+
+      assert_eq!(rewrite_html(html, "docs/", &presigned_urls()), html);
+    }
+  }
+}
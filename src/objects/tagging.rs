@@ -0,0 +1,147 @@
+use crate::{telemetry, S3Configuration};
+use chrono::{SecondsFormat, Utc};
+use rusoto_s3::{GetObjectTaggingRequest, PutObjectTaggingRequest, S3Client, Tag, Tagging, S3};
+use std::{
+  collections::HashMap,
+  convert::TryFrom,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+use tracing::Instrument;
+
+const LAST_PRESIGNED_AT_TAG: &str = "last-presigned-at";
+const LAST_PRESIGNED_BY_TAG: &str = "last-presigned-by";
+/// Longest tag value accepted by S3, in characters
+const MAX_TAG_VALUE_LENGTH: usize = 256;
+
+/// Stamps objects with access tags when GET pre-signed URLs are issued.
+///
+/// Each object is tagged at most once per `interval`, to limit the amount of tagging requests
+/// sent to the storage for frequently accessed objects.
+#[derive(Clone, Debug)]
+pub struct AccessTagging {
+  interval: Duration,
+  last_stamps: Arc<Mutex<HashMap<(String, String), Instant>>>,
+}
+
+impl AccessTagging {
+  pub fn new(interval: Duration) -> Self {
+    Self {
+      interval,
+      last_stamps: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+
+  /// Stamps the object in the background, with the `requester` if any, i.e. the authenticated
+  /// caller rather than an address which clients could forge.
+  pub(crate) fn stamp(
+    &self,
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    key: &str,
+    requester: Option<String>,
+  ) {
+    if !self.should_stamp(bucket, key) {
+      return;
+    }
+
+    let s3_configuration = s3_configuration.clone();
+    let bucket = bucket.to_string();
+    let key = key.to_string();
+
+    tokio::spawn(async move {
+      if let Err(error) = put_access_tags(&s3_configuration, &bucket, &key, requester).await {
+        log::warn!(
+          "Could not stamp access tags: bucket={}, key={}, error={}",
+          bucket,
+          key,
+          error
+        );
+      }
+    });
+  }
+
+  fn should_stamp(&self, bucket: &str, key: &str) -> bool {
+    let now = Instant::now();
+    let mut last_stamps = self
+      .last_stamps
+      .lock()
+      .unwrap_or_else(|error| error.into_inner());
+
+    last_stamps.retain(|_, last_stamp| now.duration_since(*last_stamp) < self.interval);
+
+    let object = (bucket.to_string(), key.to_string());
+    if last_stamps.contains_key(&object) {
+      return false;
+    }
+
+    last_stamps.insert(object, now);
+    true
+  }
+}
+
+async fn put_access_tags(
+  s3_configuration: &S3Configuration,
+  bucket: &str,
+  key: &str,
+  requester: Option<String>,
+) -> Result<(), String> {
+  let client = S3Client::try_from(s3_configuration).map_err(|error| error.to_string())?;
+
+  let current_tags = client
+    .get_object_tagging(GetObjectTaggingRequest {
+      bucket: bucket.to_string(),
+      key: key.to_string(),
+      ..Default::default()
+    })
+    .instrument(telemetry::s3_span("GetObjectTagging"))
+    .await
+    .map_err(|error| error.to_string())?
+    .tag_set;
+
+  let mut tag_set = current_tags
+    .into_iter()
+    .filter(|tag| tag.key != LAST_PRESIGNED_AT_TAG && tag.key != LAST_PRESIGNED_BY_TAG)
+    .collect::<Vec<Tag>>();
+
+  tag_set.push(Tag {
+    key: LAST_PRESIGNED_AT_TAG.to_string(),
+    value: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+  });
+
+  if let Some(requester) = requester {
+    tag_set.push(Tag {
+      key: LAST_PRESIGNED_BY_TAG.to_string(),
+      value: tag_value(&requester),
+    });
+  }
+
+  client
+    .put_object_tagging(PutObjectTaggingRequest {
+      bucket: bucket.to_string(),
+      key: key.to_string(),
+      tagging: Tagging { tag_set },
+      ..Default::default()
+    })
+    .instrument(telemetry::s3_span("PutObjectTagging"))
+    .await
+    .map_err(|error| error.to_string())?;
+
+  Ok(())
+}
+
+/// Tag value naming `requester`, e.g. a token subject such as `auth0|123`, with the characters S3
+/// rejects in tags replaced, and truncated to the 256 characters S3 accepts.
+fn tag_value(requester: &str) -> String {
+  requester
+    .chars()
+    .map(|character| {
+      if character.is_alphanumeric() || " +-=._:/@".contains(character) {
+        character
+      } else {
+        '_'
+      }
+    })
+    .take(MAX_TAG_VALUE_LENGTH)
+    .collect()
+}
@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+
+/// Checksums of an object, as computed by the client, to compare with the stored ones.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct VerifyBody {
+  /// Name of the bucket
+  pub bucket: String,
+  /// Key of the verified object
+  pub path: String,
+  /// Version of the object (default: latest)
+  pub version_id: Option<String>,
+  /// Hex-encoded MD5 digest of the object, compared to the ETag of objects uploaded at once
+  pub md5: Option<String>,
+  /// Hex-encoded MD5 digests of the parts, in order, of which the ETag of objects uploaded in
+  /// several parts is computed
+  pub part_md5s: Option<Vec<String>>,
+  /// Size of the parts, but the last one, checked against the size of the object
+  pub part_size: Option<i64>,
+  /// Base64-encoded SHA-256 checksum of the object, compared to the one stored on upload
+  pub sha256: Option<String>,
+}
+
+/// Verdict of the comparison of the checksums of an object.
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct VerifyResponse {
+  pub bucket: String,
+  pub path: String,
+  pub size: i64,
+  /// `mismatch` when a checksum differs, `match` when one is equal and none differs
+  pub verdict: Verdict,
+  pub checks: Vec<ChecksumCheck>,
+}
+
+/// Comparison of a checksum supplied by the client with the one of the stored object.
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ChecksumCheck {
+  pub algorithm: ChecksumAlgorithm,
+  pub expected: String,
+  /// Checksum of the stored object, missing when S3 has none to compare with
+  pub actual: Option<String>,
+  pub verdict: Verdict,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ChecksumAlgorithm {
+  Md5,
+  /// MD5 digest of the MD5 digests of the parts, followed by their number
+  MultipartEtag,
+  Sha256,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+  Match,
+  Mismatch,
+  /// The stored object has no checksum of this kind, e.g. the ETag of an object uploaded in
+  /// several parts is no MD5 digest of its content
+  Unverifiable,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    body, json_reply, metrics,
+    objects::key,
+    policy::{self, Identity, Operation},
+    retry, telemetry, Error, S3Configuration,
+  };
+  use md5::{Digest, Md5};
+  use rusoto_core::{
+    param::{Params, ServiceParams},
+    signature::SignedRequest,
+    Client, RusotoError,
+  };
+  use rusoto_s3::HeadObjectError;
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{Filter, Rejection, Reply};
+
+  /// Stored checksums of an object.
+  pub(crate) struct StoredChecksums {
+    pub(crate) etag: String,
+    pub(crate) size: i64,
+    pub(crate) sha256: Option<String>,
+  }
+
+  /// Verify the integrity of an object
+  #[utoipa::path(
+    post,
+    path = "/objects/verify",
+    tag = "Objects",
+    request_body(
+      content = VerifyBody,
+      description = "Object to verify, and its checksums computed by the client",
+      content_type = "application/json"
+    ),
+    responses(
+      (
+        status = 200,
+        description = "Returns the verdict of the comparison with the stored checksums",
+        content_type = "application/json",
+        body = VerifyResponse
+      ),
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("objects" / "verify")
+      .and(warp::post())
+      .and(body::json::<VerifyBody>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |body: VerifyBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(verify_object(&s3_configuration, &identity, body).await)
+        },
+      )
+  }
+
+  /// Compares the checksums computed by the client with the ones of the stored object, on behalf
+  /// of `identity`.
+  pub async fn verify_object(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    mut body: VerifyBody,
+  ) -> Result<VerifyResponse, Error> {
+    identity.scope(&mut body.path);
+    key::server::validate_key(s3_configuration, &body.path)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Read,
+      &body.bucket,
+      &body.path,
+    )?;
+    handle_verify_object(s3_configuration, body).await
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_verify_object(
+    s3_configuration: &S3Configuration,
+    body: VerifyBody,
+  ) -> Result<VerifyResponse, Error> {
+    if body.md5.is_none() && body.part_md5s.is_none() && body.sha256.is_none() {
+      return Err(Error::InvalidParameter(
+        "md5, part_md5s or sha256 must be specified".to_string(),
+      ));
+    }
+    if body.part_size.is_some() && body.part_md5s.is_none() {
+      return Err(Error::InvalidParameter(
+        "part_size requires part_md5s".to_string(),
+      ));
+    }
+    let part_etag = body.part_md5s.as_deref().map(multipart_etag).transpose()?;
+
+    let s3_configuration = s3_configuration.for_bucket(&body.bucket);
+    log::info!("Verify object: bucket={}, key={}", body.bucket, body.path);
+    let client = Client::try_from(&s3_configuration)?;
+    let stored = retry::s3_call(&s3_configuration, "HeadObject", || {
+      head_object(
+        &client,
+        &s3_configuration,
+        &body.bucket,
+        &body.path,
+        body.version_id.as_deref(),
+      )
+      .instrument(telemetry::s3_span("HeadObject"))
+    })
+    .await
+    .map_err(metrics::count_s3_error("HeadObject"))
+    .map_err(Error::HeadObjectError)?;
+
+    let etag = stored.etag.trim_matches('"').to_string();
+    let stored_parts = etag
+      .split_once('-')
+      .and_then(|(_, parts)| parts.parse::<i64>().ok());
+    let mut checks = vec![];
+
+    if let Some(md5) = &body.md5 {
+      let actual = match stored_parts {
+        // The ETag of an object uploaded at once is the MD5 digest of its content, unless
+        // encrypted with KMS.
+        None if etag.len() == 32 => Some(etag.clone()),
+        _ => None,
+      };
+      checks.push(check(ChecksumAlgorithm::Md5, md5.to_lowercase(), actual));
+    }
+
+    if let Some(part_etag) = part_etag {
+      let actual = stored_parts.map(|_| etag.clone());
+      let mut check = check(ChecksumAlgorithm::MultipartEtag, part_etag, actual);
+      if let (Some(part_size), Some(parts)) = (body.part_size, &body.part_md5s) {
+        if !parts_fit(stored.size, part_size, parts.len() as i64) {
+          check.verdict = Verdict::Mismatch;
+        }
+      }
+      checks.push(check);
+    }
+
+    if let Some(sha256) = &body.sha256 {
+      checks.push(check(
+        ChecksumAlgorithm::Sha256,
+        sha256.clone(),
+        stored.sha256.clone(),
+      ));
+    }
+
+    let verdict = if checks
+      .iter()
+      .any(|check| check.verdict == Verdict::Mismatch)
+    {
+      Verdict::Mismatch
+    } else if checks.iter().any(|check| check.verdict == Verdict::Match) {
+      Verdict::Match
+    } else {
+      Verdict::Unverifiable
+    };
+    if verdict == Verdict::Mismatch {
+      log::warn!(
+        "Checksum mismatch: bucket={}, key={}",
+        body.bucket,
+        body.path
+      );
+    }
+
+    Ok(VerifyResponse {
+      bucket: body.bucket,
+      path: body.path,
+      size: stored.size,
+      verdict,
+      checks,
+    })
+  }
+
+  fn check(
+    algorithm: ChecksumAlgorithm,
+    expected: String,
+    actual: Option<String>,
+  ) -> ChecksumCheck {
+    let verdict = match &actual {
+      Some(actual) if *actual == expected => Verdict::Match,
+      Some(_) => Verdict::Mismatch,
+      None => Verdict::Unverifiable,
+    };
+    ChecksumCheck {
+      algorithm,
+      expected,
+      actual,
+      verdict,
+    }
+  }
+
+  /// ETag of an object uploaded in parts of the given MD5 digests, as computed by S3.
+  fn multipart_etag(part_md5s: &[String]) -> Result<String, Error> {
+    if part_md5s.is_empty() {
+      return Err(Error::InvalidParameter(
+        "part_md5s must not be empty".to_string(),
+      ));
+    }
+    let mut digests = Md5::new();
+    for part_md5 in part_md5s {
+      digests.update(hex_digest(part_md5)?);
+    }
+    Ok(format!("{:x}-{}", digests.finalize(), part_md5s.len()))
+  }
+
+  fn hex_digest(md5: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::InvalidParameter(format!("invalid MD5 digest: {}", md5));
+    if md5.len() != 32 || !md5.is_ascii() {
+      return Err(invalid());
+    }
+    (0..md5.len())
+      .step_by(2)
+      .map(|index| u8::from_str_radix(&md5[index..index + 2], 16).map_err(|_| invalid()))
+      .collect()
+  }
+
+  /// Returns whether an object of `size` bytes is made of `parts` parts of `part_size` bytes,
+  /// the last one being smaller.
+  fn parts_fit(size: i64, part_size: i64, parts: i64) -> bool {
+    part_size > 0 && (parts - 1) * part_size < size.max(1) && size <= parts * part_size
+  }
+
+  /// Gets the ETag, size and SHA-256 checksum of the object with HeadObject, the checksum mode
+  /// of which the `S3Client` of rusoto does not support.
+  pub(crate) async fn head_object(
+    client: &Client,
+    s3_configuration: &S3Configuration,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+  ) -> Result<StoredChecksums, RusotoError<HeadObjectError>> {
+    let mut request = SignedRequest::new(
+      "HEAD",
+      "s3",
+      s3_configuration.region(),
+      &format!("/{}/{}", bucket, key),
+    );
+    if let Some(version_id) = version_id {
+      let mut params = Params::new();
+      params.put("versionId", version_id);
+      request.set_params(params);
+    }
+    request.add_header("x-amz-checksum-mode", "ENABLED");
+
+    let mut response = client.sign_and_dispatch(request).await?;
+    let response = response.buffer().await.map_err(RusotoError::HttpDispatch)?;
+    if !response.status.is_success() {
+      return Err(HeadObjectError::from_response(response));
+    }
+    let header = |name: &str| response.headers.get(name).cloned();
+    Ok(StoredChecksums {
+      etag: header("etag").unwrap_or_default(),
+      size: header("content-length")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or_default(),
+      sha256: header("x-amz-checksum-sha256"),
+    })
+  }
+}
@@ -1,12 +1,27 @@
-use crate::Error;
+use crate::{request_builder, ApiVersion, Error, S3Configuration};
 use std::{collections::BTreeMap, str::FromStr, sync::Arc};
 use utoipa::{
-  openapi::{Components, OpenApiBuilder, PathItem, Paths},
-  OpenApi,
+  openapi::{
+    path::{Operation, ParameterBuilder, ParameterIn, PathItemType},
+    security::{
+      ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme,
+    },
+    Components, ContentBuilder, ObjectBuilder, OpenApiBuilder, PathItem, Paths, Ref, Required,
+    ResponseBuilder, SchemaType,
+  },
+  Modify, OpenApi,
 };
 use utoipa_swagger_ui::Config;
 use warp::{
-  hyper::{header::CONTENT_TYPE, Response, StatusCode, Uri},
+  http::Method,
+  hyper::{
+    header::{
+      HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+      ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ALLOW, CONTENT_TYPE, VARY,
+    },
+    Body, Response, StatusCode, Uri,
+  },
+  log::{Info, Log},
   path::{FullPath, Tail},
   Filter, Rejection, Reply,
 };
@@ -15,34 +30,214 @@ use warp::{
 #[openapi(
   paths(
     crate::objects::list::server::route,
+    crate::objects::list::server::stream_route,
+    crate::objects::site::server::route,
+    crate::objects::lock::server::get_retention_route,
+    crate::objects::lock::server::put_retention_route,
+    crate::objects::lock::server::get_legal_hold_route,
+    crate::objects::lock::server::put_legal_hold_route,
     crate::objects::get::route,
     crate::objects::create::route,
+    crate::objects::manifest::server::route,
+    crate::objects::archive::server::route,
+    crate::objects::content::server::route,
+    crate::objects::attributes::server::route,
+    crate::objects::select::server::route,
+    crate::objects::share::server::route,
+    crate::objects::verify::server::route,
+    crate::objects::dedup::server::route,
     crate::multipart_upload::create::server::route,
     crate::multipart_upload::part_upload_url::server::route,
+    crate::multipart_upload::part_upload_url::server::range_route,
+    crate::multipart_upload::abort_batch::server::route,
+    crate::multipart_upload::abort_batch::server::key_route,
+    crate::multipart_upload::lifecycle::server::route,
+    crate::multipart_upload::plan::server::route,
     crate::multipart_upload::abort_or_complete::server::route,
+    crate::events::server::route,
+    crate::jobs::get_route,
+    crate::jobs::prefix_delete_route,
+    crate::jobs::inventory_route,
+    crate::jobs::archive_route,
+    crate::jobs::upload_cleanup_route,
+    crate::limits::server::route,
+    crate::media::server::route,
+    crate::media::server::dash_route,
+    crate::download_links::download_route,
+    crate::download_links::revoke_route,
+    crate::uppy::server::create_route,
+    crate::uppy::server::list_parts_route,
+    crate::uppy::server::part_url_route,
+    crate::uppy::server::complete_route,
+    crate::uppy::server::abort_route,
   ),
   components(
     schemas(
+      crate::batch::AbortUploadsBatchResult,
+      crate::batch::UploadManifestResult,
+      crate::batch::BatchItemStatus,
+      crate::batch::BatchItemError,
+      crate::error_body::ErrorBody,
+      crate::encryption::ServerSideEncryption,
+      crate::limits::LimitsResponse,
       crate::objects::list::Object,
+      crate::objects::site::SiteResponse,
+      crate::objects::archive::ArchiveBody,
+      crate::objects::PresignedUrlResponse,
+      crate::objects::UploadManifestBody,
+      crate::objects::ManifestFile,
+      crate::objects::ManifestUpload,
+      crate::objects::content::ObjectContentResponse,
+      crate::objects::lock::RetentionMode,
+      crate::objects::lock::ObjectRetention,
+      crate::objects::lock::LegalHoldStatus,
+      crate::objects::lock::ObjectLegalHold,
+      crate::objects::attributes::ObjectAttributesResponse,
+      crate::objects::attributes::ObjectChecksum,
+      crate::objects::attributes::ObjectParts,
+      crate::objects::attributes::ObjectPart,
+      crate::objects::select::SelectBody,
+      crate::objects::select::SelectInputFormat,
+      crate::objects::select::SelectCompression,
+      crate::objects::select::SelectOutputFormat,
+      crate::objects::share::ShareResponse,
+      crate::objects::verify::VerifyBody,
+      crate::objects::verify::VerifyResponse,
+      crate::objects::verify::ChecksumCheck,
+      crate::objects::verify::ChecksumAlgorithm,
+      crate::objects::verify::Verdict,
+      crate::objects::dedup::DedupBody,
+      crate::objects::dedup::DedupResponse,
       crate::multipart_upload::create::CreateUploadResponse,
       crate::multipart_upload::part_upload_url::PartUploadResponse,
+      crate::multipart_upload::part_upload_url::PartUploadUrlsResponse,
+      crate::multipart_upload::part_upload_url::PartUploadUrl,
       crate::multipart_upload::abort_or_complete::CompletedUploadPart,
       crate::multipart_upload::abort_or_complete::AbortOrCompleteUploadBody,
+      crate::multipart_upload::abort_batch::AbortUploadsBody,
+      crate::multipart_upload::abort_batch::AbortUploadItem,
+      crate::multipart_upload::lifecycle::LifecycleRuleResponse,
+      crate::multipart_upload::plan::MultipartPlan,
+      crate::multipart_upload::plan::PlannedPart,
+      crate::events::UploadEventKind,
+      crate::events::UploadEvent,
+      crate::jobs::Job,
+      crate::jobs::JobKind,
+      crate::jobs::JobStatus,
+      crate::jobs::JobError,
+      crate::jobs::PrefixJobBody,
+      crate::jobs::ArchiveJobBody,
+      crate::jobs::UploadCleanupJobBody,
+      crate::jobs::PrefixDeleteResult,
+      crate::jobs::InventoryResult,
+      crate::jobs::ArchiveResult,
+      crate::uppy::UppyCreateBody,
+      crate::uppy::UppyCreateResponse,
+      crate::uppy::UppyPart,
+      crate::uppy::UppyPartUrlResponse,
+      crate::uppy::UppyCompleteBody,
+      crate::uppy::UppyCompleteResponse,
      )
   ),
   tags(
     (name = "Objects", description = "Objects-related API"),
-    (name = "Multipart upload", description = "Multipart upload API")
-  )
+    (name = "Multipart upload", description = "Multipart upload API"),
+    (name = "Media", description = "Media-related API"),
+    (name = "Jobs", description = "Background jobs of the long-running operations"),
+    (name = "Uppy", description = "Multipart upload API in the shape expected by Uppy")
+  ),
+  modifiers(&ErrorResponses, &SecuritySchemes)
+)]
+struct ApiV1Doc;
+
+/// Documents the [`ErrorBody`](crate::ErrorBody) of the error responses of every operation.
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+  fn modify(&self, open_api: &mut utoipa::openapi::OpenApi) {
+    let error_response = ResponseBuilder::new()
+      .description("Error")
+      .content(
+        "application/json",
+        ContentBuilder::new()
+          .schema(Ref::from_schema_name("ErrorBody"))
+          .build(),
+      )
+      .build();
+
+    for path_item in open_api.paths.paths.values_mut() {
+      for operation in path_item.operations.values_mut() {
+        operation
+          .responses
+          .responses
+          .entry("default".to_string())
+          .or_insert_with(|| error_response.clone().into());
+      }
+    }
+  }
+}
+
+const BEARER_TOKEN_SCHEME: &str = "bearer_token";
+const API_KEY_SCHEME: &str = "api_key";
+
+/// Documents the bearer tokens and API keys accepted by the operations declaring a `security`
+/// requirement, until [`insert_security_schemes`] narrows them to the configured ones.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+  fn modify(&self, open_api: &mut utoipa::openapi::OpenApi) {
+    let components = open_api.components.get_or_insert_with(Components::new);
+    components.add_security_scheme(
+      BEARER_TOKEN_SCHEME,
+      SecurityScheme::Http(
+        HttpBuilder::new()
+          .scheme(HttpAuthScheme::Bearer)
+          .bearer_format("JWT")
+          .build(),
+      ),
+    );
+    components.add_security_scheme(
+      API_KEY_SCHEME,
+      SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))),
+    );
+  }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+  paths(
+    crate::health::server::liveness_route,
+    crate::health::server::readiness_route,
+    crate::metrics::route,
+    crate::scheduler::route,
+  ),
+  components(schemas(
+    crate::health::HealthResponse,
+    crate::health::HealthStatus,
+    crate::scheduler::ScheduledTaskStatus,
+    crate::scheduler::TaskRun
+  )),
+  tags((name = "Health", description = "Probes, metrics and scheduled tasks"))
+)]
+struct HealthApiDoc;
+
+#[derive(OpenApi)]
+#[openapi(
+  paths(crate::version::route),
+  components(schemas(crate::version::VersionResponse)),
+  tags((name = "Version", description = "Build of the signer"))
 )]
-struct ApiDoc;
+struct VersionApiDoc;
 
+/// Serves the Swagger UI at `path`, listing the OpenAPI documents served at `open_api_routes`.
 pub fn swagger_route(
   path: &str,
-  open_api_route: &str,
+  open_api_routes: &[&str],
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-  let open_api_route = format!("/{}", open_api_route.trim_start_matches('/'));
-  let config = Arc::new(Config::from(open_api_route));
+  let config =
+    Arc::new(Config::new(open_api_routes.iter().map(|open_api_route| {
+      format!("/{}", open_api_route.trim_start_matches('/'))
+    })));
 
   let path = path
     .trim_start_matches('/')
@@ -58,11 +253,134 @@ pub fn swagger_route(
     .and_then(serve_swagger)
 }
 
+/// Completes the `base` document with the probes, and the build information and the routes of
+/// every API version mounted at `prefix_path`, including the unversioned paths of the default
+/// version.
 pub fn insert_open_api_at(
   base: utoipa::openapi::OpenApi,
   prefix_path: &str,
 ) -> utoipa::openapi::OpenApi {
-  concat(base, ApiDoc::openapi(), prefix_path)
+  let base = concat(base, HealthApiDoc::openapi(), "");
+  let base = concat(base, VersionApiDoc::openapi(), prefix_path);
+  let base = ApiVersion::ALL.iter().fold(base, |base, version| {
+    concat(
+      base,
+      open_api(*version),
+      &versioned_path(prefix_path, *version),
+    )
+  });
+  concat(base, open_api(ApiVersion::DEFAULT), prefix_path)
+}
+
+/// Completes the document of the routes mounted at `prefix_path` with the GraphQL endpoint, which
+/// the OpenAPI documents leave out, so that [`preflight_route`] answers its preflight requests.
+#[cfg(feature = "graphql")]
+pub(crate) fn insert_graphql_path(
+  mut open_api: utoipa::openapi::OpenApi,
+  prefix_path: &str,
+) -> utoipa::openapi::OpenApi {
+  let prefix_paths = std::iter::once(prefix_path.to_string()).chain(
+    ApiVersion::ALL
+      .iter()
+      .map(|version| versioned_path(prefix_path, *version)),
+  );
+  for prefix_path in prefix_paths {
+    open_api.paths.paths.insert(
+      format!("{}/graphql", prefix_path.trim_end_matches('/')),
+      PathItem::new(PathItemType::Post, Operation::new()),
+    );
+  }
+  open_api
+}
+
+/// Completes the `base` document with the probes, and the build information and the routes of a
+/// single API version mounted at `prefix_path`, e.g. to document `/api/v1` on its own.
+pub fn insert_versioned_open_api_at(
+  base: utoipa::openapi::OpenApi,
+  prefix_path: &str,
+  version: ApiVersion,
+) -> utoipa::openapi::OpenApi {
+  let base = concat(base, HealthApiDoc::openapi(), "");
+  let base = concat(base, VersionApiDoc::openapi(), prefix_path);
+  concat(
+    base,
+    open_api(version),
+    &versioned_path(prefix_path, version),
+  )
+}
+
+/// Documents the authentication the configuration requires: bearer tokens when they are
+/// validated, API keys when policies grant some.
+///
+/// The operations are documented without authentication when neither is configured. With
+/// `--allow-endpoint-override`, they also document the optional `X-S3-Endpoint` header.
+pub fn insert_security_schemes(
+  mut open_api: utoipa::openapi::OpenApi,
+  s3_configuration: &S3Configuration,
+) -> utoipa::openapi::OpenApi {
+  let has_api_keys = s3_configuration
+    .policies()
+    .is_some_and(|policies| policies.iter().any(|policy| policy.api_key.is_some()));
+  let schemes = [
+    (
+      BEARER_TOKEN_SCHEME,
+      s3_configuration.jwt_validator().is_some(),
+    ),
+    (API_KEY_SCHEME, has_api_keys),
+  ];
+
+  if let Some(components) = &mut open_api.components {
+    for (scheme, enabled) in schemes {
+      if !enabled {
+        components.security_schemes.remove(scheme);
+      }
+    }
+  }
+
+  let requirements = schemes
+    .iter()
+    .filter(|(_scheme, enabled)| *enabled)
+    .map(|(scheme, _enabled)| SecurityRequirement::new(*scheme, Vec::<String>::new()))
+    .collect::<Vec<_>>();
+
+  let endpoint_override = (!s3_configuration.endpoint_overrides().is_empty()).then(|| {
+    ParameterBuilder::new()
+      .name("X-S3-Endpoint")
+      .parameter_in(ParameterIn::Header)
+      .required(Required::False)
+      .description(Some(
+        "S3 endpoint to serve the request with, e.g. http://minio-42:9000 (for development only)",
+      ))
+      .schema(Some(ObjectBuilder::new().schema_type(SchemaType::String)))
+      .build()
+  });
+
+  for path_item in open_api.paths.paths.values_mut() {
+    for operation in path_item.operations.values_mut() {
+      if operation.security.is_some() {
+        operation.security =
+          Some(requirements.clone()).filter(|requirements| !requirements.is_empty());
+        if let Some(endpoint_override) = &endpoint_override {
+          operation
+            .parameters
+            .get_or_insert_with(Vec::new)
+            .push(endpoint_override.clone());
+        }
+      }
+    }
+  }
+
+  open_api
+}
+
+fn open_api(version: ApiVersion) -> utoipa::openapi::OpenApi {
+  match version {
+    ApiVersion::V1 => ApiV1Doc::openapi(),
+  }
+}
+
+fn versioned_path(prefix_path: &str, version: ApiVersion) -> String {
+  format!("{}/{}", prefix_path.trim_end_matches('/'), version)
 }
 
 fn concat(
@@ -86,11 +404,12 @@ fn merge_paths(mut base: Paths, other: Paths, prefix_path: &str) -> Paths {
     .paths
     .into_iter()
     .map(|(key, value)| {
-      let new_key = format!(
-        "/{}/{}",
-        prefix_path.trim_start_matches('/').trim_end_matches('/'),
-        key.trim_start_matches('/')
-      );
+      let prefix_path = prefix_path.trim_start_matches('/').trim_end_matches('/');
+      let new_key = if prefix_path.is_empty() {
+        key
+      } else {
+        format!("/{}/{}", prefix_path, key.trim_start_matches('/'))
+      };
       (new_key, value)
     })
     .collect::<BTreeMap<String, PathItem>>();
@@ -163,3 +482,258 @@ async fn serve_swagger(
     )),
   }
 }
+
+/// Validates requests and responses against an OpenAPI document.
+///
+/// Requests carrying query parameters that are not documented for the matching operation are
+/// rejected, while responses with an undocumented status code are logged as schema drift.
+/// Paths which are not described in the document are left untouched.
+///
+/// Only the names of the query parameters (once percent-decoded) and the response status codes
+/// are checked: headers and bodies are not validated against their schemas.
+#[derive(Clone)]
+pub struct ContractValidator {
+  open_api: Arc<utoipa::openapi::OpenApi>,
+  enabled: bool,
+}
+
+impl ContractValidator {
+  pub fn new(open_api: utoipa::openapi::OpenApi, enabled: bool) -> Self {
+    Self {
+      open_api: Arc::new(open_api),
+      enabled,
+    }
+  }
+
+  pub fn requests(&self) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    let validator = self.clone();
+    warp::method()
+      .and(warp::path::full())
+      .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+      .and_then(move |method: Method, full_path: FullPath, query: String| {
+        let validator = validator.clone();
+        async move { validator.validate_request(&method, full_path.as_str(), &query) }
+      })
+      .untuple_one()
+  }
+
+  pub fn responses(&self) -> Log<impl Fn(Info<'_>) + Clone> {
+    let validator = self.clone();
+    warp::log::custom(move |info: Info<'_>| {
+      validator.validate_response(info.method(), info.path(), info.status())
+    })
+  }
+
+  fn validate_request(&self, method: &Method, path: &str, query: &str) -> Result<(), Rejection> {
+    if !self.enabled {
+      return Ok(());
+    }
+
+    let operation = if let Some(operation) = self.find_operation(method, path) {
+      operation
+    } else {
+      return Ok(());
+    };
+
+    let documented_parameters = operation
+      .parameters
+      .iter()
+      .flatten()
+      .filter(|parameter| matches!(parameter.parameter_in, ParameterIn::Query))
+      .map(|parameter| parameter.name.as_str())
+      .collect::<Vec<_>>();
+
+    let undocumented_parameters = serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+      .unwrap_or_default()
+      .into_iter()
+      .map(|(name, _)| name)
+      .filter(|name| !documented_parameters.contains(&name.as_str()))
+      .collect::<Vec<_>>();
+
+    if undocumented_parameters.is_empty() {
+      return Ok(());
+    }
+
+    Err(warp::reject::custom(Error::ContractViolation(format!(
+      "undocumented query parameters for {} {}: {}",
+      method,
+      path,
+      undocumented_parameters.join(", ")
+    ))))
+  }
+
+  fn validate_response(&self, method: &Method, path: &str, status: StatusCode) {
+    if !self.enabled {
+      return;
+    }
+
+    if let Some(operation) = self.find_operation(method, path) {
+      if !operation.responses.responses.contains_key(status.as_str()) {
+        log::warn!(
+          "Schema drift: undocumented response status {} for {} {}",
+          status,
+          method,
+          path
+        );
+      }
+    }
+  }
+
+  fn find_operation(&self, method: &Method, path: &str) -> Option<&Operation> {
+    let path_item_type = match *method {
+      Method::GET => PathItemType::Get,
+      Method::POST => PathItemType::Post,
+      Method::PUT => PathItemType::Put,
+      Method::DELETE => PathItemType::Delete,
+      Method::OPTIONS => PathItemType::Options,
+      Method::HEAD => PathItemType::Head,
+      Method::PATCH => PathItemType::Patch,
+      Method::TRACE => PathItemType::Trace,
+      Method::CONNECT => PathItemType::Connect,
+      _ => return None,
+    };
+
+    self
+      .open_api
+      .paths
+      .paths
+      .iter()
+      .filter(|(template, _)| matches_template(template, path))
+      .find_map(|(_, path_item)| path_item.operations.get(&path_item_type))
+  }
+}
+
+/// Answers CORS preflight requests with the methods of the route serving the requested path, as
+/// documented along with it.
+///
+/// The preflight requests of other paths are left to the routes, which do not serve them. The
+/// headers the browser asks for, e.g. `Authorization` which a `*` wildcard does not cover, are
+/// allowed. When `max_age` is set, browsers are allowed to cache the preflight response for that
+/// duration (in seconds).
+pub fn preflight_route(
+  open_api: &utoipa::openapi::OpenApi,
+  max_age: Option<u64>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let allowed_methods = Arc::new(
+    open_api
+      .paths
+      .paths
+      .iter()
+      .map(|(template, path_item)| {
+        let methods = path_item
+          .operations
+          .keys()
+          .map(path_item_type_method)
+          .chain(std::iter::once("OPTIONS"))
+          .collect::<Vec<_>>()
+          .join(", ");
+        (template.clone(), methods)
+      })
+      .collect::<Vec<_>>(),
+  );
+
+  // Other methods are not found rather than not allowed, not to take precedence over the
+  // rejections of the other routes.
+  warp::method()
+    .and_then(|method: Method| async move {
+      if method == Method::OPTIONS {
+        Ok(())
+      } else {
+        Err(warp::reject::not_found())
+      }
+    })
+    .untuple_one()
+    .and(warp::path::full())
+    .and_then(move |full_path: FullPath| {
+      let methods = allowed_methods
+        .iter()
+        .find(|(template, _)| matches_template(template, full_path.as_str()))
+        .map(|(_, methods)| methods.clone());
+      async move { methods.ok_or_else(warp::reject::not_found) }
+    })
+    .and(warp::header::optional::<HeaderValue>(
+      ACCESS_CONTROL_REQUEST_HEADERS.as_str(),
+    ))
+    .map(
+      move |methods: String, request_headers: Option<HeaderValue>| {
+        let builder = request_builder()
+          .header(ALLOW, methods.as_str())
+          .header(ACCESS_CONTROL_ALLOW_METHODS, methods.as_str())
+          .header(VARY, ACCESS_CONTROL_REQUEST_HEADERS.as_str());
+        let builder = if let Some(max_age) = max_age {
+          builder.header(ACCESS_CONTROL_MAX_AGE, max_age)
+        } else {
+          builder
+        };
+
+        let mut response = builder.body(Body::empty()).unwrap();
+        if let Some(request_headers) = request_headers {
+          response
+            .headers_mut()
+            .insert(ACCESS_CONTROL_ALLOW_HEADERS, request_headers);
+        }
+        response
+      },
+    )
+}
+
+fn path_item_type_method(path_item_type: &PathItemType) -> &'static str {
+  match path_item_type {
+    PathItemType::Get => "GET",
+    PathItemType::Post => "POST",
+    PathItemType::Put => "PUT",
+    PathItemType::Delete => "DELETE",
+    PathItemType::Options => "OPTIONS",
+    PathItemType::Head => "HEAD",
+    PathItemType::Patch => "PATCH",
+    PathItemType::Trace => "TRACE",
+    PathItemType::Connect => "CONNECT",
+  }
+}
+
+pub(crate) fn matches_template(template: &str, path: &str) -> bool {
+  let template_segments = template.trim_matches('/').split('/');
+  let path_segments = path.trim_matches('/').split('/');
+
+  template_segments.clone().count() == path_segments.clone().count()
+    && template_segments
+      .zip(path_segments)
+      .all(|(expected, actual)| {
+        (expected.starts_with('{') && expected.ends_with('}')) || expected == actual
+      })
+}
+
+#[cfg(all(test, feature = "graphql"))]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn graphql_preflight_requests_are_answered() {
+    let open_api = insert_graphql_path(
+      insert_open_api_at(OpenApiBuilder::new().build(), "/api"),
+      "/api",
+    );
+    let route = preflight_route(&open_api, None);
+
+    for path in ["/api/graphql", "/api/v1/graphql"].iter() {
+      let response = warp::test::request()
+        .method("OPTIONS")
+        .path(path)
+        .header(
+          ACCESS_CONTROL_REQUEST_HEADERS,
+          "authorization, content-type",
+        )
+        .reply(&route)
+        .await;
+      assert_eq!(response.status(), StatusCode::OK, "{}", path);
+      assert_eq!(
+        response.headers()[ACCESS_CONTROL_ALLOW_METHODS],
+        "POST, OPTIONS"
+      );
+      assert_eq!(
+        response.headers()[ACCESS_CONTROL_ALLOW_HEADERS],
+        "authorization, content-type"
+      );
+    }
+  }
+}
@@ -0,0 +1,471 @@
+use crate::{
+  authentication::{claims, validate_authorization},
+  Claims, Error, S3Configuration, S3ConfigurationError,
+};
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use std::fmt::{Debug, Formatter};
+use warp::{
+  hyper::{header::AUTHORIZATION, HeaderMap},
+  Filter, Rejection,
+};
+
+const API_KEY_HEADER: &str = "x-api-key";
+const TENANT_HEADER: &str = "x-tenant";
+/// Prefix of the keys of a tenant, unless configured
+pub const DEFAULT_TENANT_PREFIX: &str = "tenants/{tenant}/";
+
+/// Operation performed by a request, as granted by policies.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+  Read,
+  Write,
+  Delete,
+  Multipart,
+}
+
+/// Grants an identity some operations on buckets and key prefixes.
+///
+/// The identity is either the subject of a bearer token or an API key sent in the `x-api-key`
+/// header. Buckets accept `*` wildcards, empty prefixes and operations grant everything.
+#[derive(Clone, Deserialize)]
+pub struct Policy {
+  pub subject: Option<String>,
+  pub api_key: Option<String>,
+  pub buckets: Vec<String>,
+  #[serde(default)]
+  pub prefixes: Vec<String>,
+  #[serde(default)]
+  pub operations: Vec<Operation>,
+}
+
+impl Debug for Policy {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Policy")
+      .field("subject", &self.subject)
+      .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+      .field("buckets", &self.buckets)
+      .field("prefixes", &self.prefixes)
+      .field("operations", &self.operations)
+      .finish()
+  }
+}
+
+impl Policy {
+  fn applies_to(&self, identity: &Identity) -> bool {
+    let subject_matches = match (&self.subject, &identity.claims) {
+      (Some(subject), Some(claims)) => claims.sub.as_ref() == Some(subject),
+      _ => false,
+    };
+    let api_key_matches = match (&self.api_key, &identity.api_key) {
+      (Some(api_key), Some(identity_api_key)) => api_key == identity_api_key,
+      _ => false,
+    };
+
+    subject_matches || api_key_matches
+  }
+
+  fn allows(&self, operation: Operation, bucket: &str, key: &str) -> bool {
+    (self.operations.is_empty() || self.operations.contains(&operation))
+      && self
+        .buckets
+        .iter()
+        .any(|pattern| crate::s3_configuration::matches_pattern(pattern, bucket))
+      && (self.prefixes.is_empty() || self.prefixes.iter().any(|prefix| key.starts_with(prefix)))
+  }
+}
+
+/// Isolates the tenants served by a signer under key prefixes.
+///
+/// The tenant of a request is the `claim` of its bearer token or, when `header` is trusted (e.g.
+/// set by a gateway), its `X-Tenant` header. Keys are then scoped under the `prefix` of the
+/// tenant, e.g. `tenants/{tenant}/`, and requests without a tenant are rejected.
+#[derive(Clone, Debug)]
+pub struct Tenancy {
+  pub claim: Option<String>,
+  pub header: bool,
+  pub prefix: String,
+}
+
+impl Tenancy {
+  pub fn new(claim: Option<String>, header: bool) -> Self {
+    Self {
+      claim,
+      header,
+      prefix: DEFAULT_TENANT_PREFIX.to_string(),
+    }
+  }
+
+  /// Sets the prefix of the keys of the tenants, where `{tenant}` is replaced with the tenant.
+  ///
+  /// The prefix must contain `{tenant}` and end with `/`, for the prefix of a tenant never to be
+  /// shared with, or be the beginning of, the prefix of another one.
+  pub fn with_prefix(mut self, prefix: &str) -> Result<Self, S3ConfigurationError> {
+    if !prefix.contains("{tenant}") || !prefix.ends_with('/') {
+      return Err(S3ConfigurationError::InvalidTenantPrefix(format!(
+        "{} must contain {{tenant}} and end with /",
+        prefix
+      )));
+    }
+
+    self.prefix = prefix.to_string();
+    Ok(self)
+  }
+
+  /// Prefix of the keys of the tenant of a request, given its claims and `X-Tenant` header.
+  fn tenant_prefix(&self, claims: Option<&Claims>, header: Option<&str>) -> Option<String> {
+    let claim = self
+      .claim
+      .as_ref()
+      .zip(claims)
+      .and_then(|(claim, claims)| claims.extra.get(claim))
+      .and_then(|tenant| tenant.as_str());
+    let header = header.filter(|_| self.header);
+    claim
+      .or(header)
+      .filter(|tenant| {
+        *tenant != "."
+          && *tenant != ".."
+          && !tenant.is_empty()
+          && tenant
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+      })
+      .map(|tenant| self.prefix.replace("{tenant}", tenant))
+  }
+}
+
+/// Caller of a request: the claims of its bearer token and/or its API key.
+#[derive(Clone)]
+pub struct Identity {
+  pub claims: Option<Claims>,
+  api_key: Option<String>,
+  /// Prefix of the keys of the tenant of the caller, with tenancy enabled
+  tenant_prefix: Option<String>,
+}
+
+impl Debug for Identity {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Identity")
+      .field(
+        "subject",
+        &self.claims.as_ref().and_then(|claims| claims.sub.as_ref()),
+      )
+      .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+      .field("tenant_prefix", &self.tenant_prefix)
+      .finish()
+  }
+}
+
+/// Extracts the identity of the caller, validating its bearer token when required.
+pub fn identity(
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (Identity,), Error = Rejection> + Clone {
+  let s3_configuration = s3_configuration.clone();
+  claims(&s3_configuration)
+    .and(warp::header::optional::<String>(API_KEY_HEADER))
+    .and(warp::header::optional::<String>(TENANT_HEADER))
+    .map(
+      move |claims: Option<Claims>, api_key: Option<String>, tenant: Option<String>| {
+        Identity::new(
+          &s3_configuration.current(),
+          claims,
+          api_key,
+          tenant.as_deref(),
+        )
+      },
+    )
+    // Boxed, being part of every route, to keep the stack of their futures shallow.
+    .boxed()
+}
+
+impl Identity {
+  /// Extracts the identity of the caller from the request headers, like [`identity`] does.
+  pub async fn from_headers(
+    s3_configuration: &S3Configuration,
+    headers: &HeaderMap,
+  ) -> Result<Self, Error> {
+    let header = |name: &str| {
+      headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+    };
+
+    let claims =
+      validate_authorization(s3_configuration, header(AUTHORIZATION.as_str()).as_deref()).await?;
+    Ok(Identity::new(
+      s3_configuration,
+      claims,
+      header(API_KEY_HEADER),
+      header(TENANT_HEADER).as_deref(),
+    ))
+  }
+
+  fn new(
+    s3_configuration: &S3Configuration,
+    claims: Option<Claims>,
+    api_key: Option<String>,
+    tenant: Option<&str>,
+  ) -> Self {
+    let tenant_prefix = s3_configuration
+      .tenancy()
+      .and_then(|tenancy| tenancy.tenant_prefix(claims.as_ref(), tenant));
+    Identity {
+      claims,
+      api_key,
+      tenant_prefix,
+    }
+  }
+
+  /// Scopes a key, or a prefix, requested by the caller under the prefix of its tenant.
+  ///
+  /// Keys already under the prefix, e.g. the ones listed, are kept as is.
+  pub fn scope(&self, key: &mut String) {
+    if let Some(tenant_prefix) = &self.tenant_prefix {
+      if !key.starts_with(tenant_prefix.as_str()) {
+        key.insert_str(0, tenant_prefix);
+      }
+    }
+  }
+
+  /// Names the caller, e.g. as the owner of its jobs: the subject of its bearer token, else the
+  /// MD5 digest of its API key, which is kept secret.
+  pub(crate) fn owner(&self) -> Option<String> {
+    self
+      .claims
+      .as_ref()
+      .and_then(|claims| claims.sub.clone())
+      .or_else(|| {
+        self
+          .api_key
+          .as_ref()
+          .map(|api_key| format!("api-key:{:x}", Md5::digest(api_key.as_bytes())))
+      })
+  }
+
+  /// Scopes a prefix requested by the caller, the whole bucket by default, under the prefix of
+  /// its tenant.
+  pub fn scope_prefix(&self, prefix: &mut Option<String>) {
+    if self.tenant_prefix.is_some() {
+      self.scope(prefix.get_or_insert_with(String::new));
+    }
+  }
+}
+
+/// Returns whether the identity is authenticated, by a validated bearer token or by an API key
+/// of the policies.
+pub fn is_authenticated(s3_configuration: &S3Configuration, identity: &Identity) -> bool {
+  let has_api_key = match (&identity.api_key, s3_configuration.policies()) {
+    (Some(api_key), Some(policies)) => policies
+      .iter()
+      .any(|policy| policy.api_key.as_ref() == Some(api_key)),
+    _ => false,
+  };
+
+  identity.claims.is_some() || has_api_key
+}
+
+/// Returns whether the identity administers the signer, e.g. to read the status of its scheduled
+/// tasks: authenticated and, when policies are configured, granted every operation on every
+/// bucket by one of them.
+pub fn is_admin(s3_configuration: &S3Configuration, identity: &Identity) -> bool {
+  is_authenticated(s3_configuration, identity)
+    && s3_configuration
+      .policies()
+      .map(|policies| {
+        policies.iter().any(|policy| {
+          policy.applies_to(identity)
+            && policy.buckets.iter().any(|bucket| bucket == "*")
+            && policy.prefixes.is_empty()
+            && policy.operations.is_empty()
+        })
+      })
+      .unwrap_or(true)
+}
+
+/// Returns whether the policies grant the operation on the object (or prefix) to the identity.
+///
+/// Buckets outside the allowlist or in the denylist are never allowed, everything else is
+/// allowed when no policy is configured.
+pub fn is_allowed(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  operation: Operation,
+  bucket: &str,
+  key: &str,
+) -> bool {
+  if !s3_configuration.is_bucket_allowed(bucket, key) {
+    return false;
+  }
+  // Tenants are confined to their prefix, without relative segments to escape it, the requests
+  // without a tenant being rejected.
+  if s3_configuration.tenancy().is_some()
+    && !matches!(
+      identity.tenant_prefix.as_deref().and_then(|tenant_prefix| key.strip_prefix(tenant_prefix)),
+      Some(scoped_key) if !scoped_key.split('/').any(|segment| segment == "..")
+    )
+  {
+    return false;
+  }
+
+  s3_configuration
+    .policies()
+    .map(|policies| {
+      policies
+        .iter()
+        .filter(|policy| policy.applies_to(identity))
+        .any(|policy| policy.allows(operation, bucket, key))
+    })
+    .unwrap_or(true)
+}
+
+/// Rejects the request unless the policies grant the operation on the object to the identity.
+pub fn authorize(
+  s3_configuration: &S3Configuration,
+  identity: &Identity,
+  operation: Operation,
+  bucket: &str,
+  key: &str,
+) -> Result<(), Error> {
+  if is_allowed(s3_configuration, identity, operation, bucket, key) {
+    return Ok(());
+  }
+
+  Err(Error::Forbidden(format!(
+    "{:?} is not allowed to {:?} {}/{}",
+    identity, operation, bucket, key
+  )))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn claims(tenant: &str) -> Claims {
+    serde_json::from_value(json!({ "sub": "user", "tenant": tenant })).unwrap()
+  }
+
+  fn s3_configuration() -> S3Configuration {
+    S3Configuration::new_with_hostname("access", "secret", "us-east-1", "https://s3.example.com")
+      .unwrap()
+      .with_tenancy(Tenancy::new(Some("tenant".to_string()), true))
+  }
+
+  fn identity(s3_configuration: &S3Configuration, tenant: Option<&str>) -> Identity {
+    Identity::new(s3_configuration, tenant.map(claims), None, None)
+  }
+
+  #[test]
+  fn prefixes_must_name_the_tenant_and_end_with_a_slash() {
+    let tenancy = Tenancy::new(None, true);
+    assert!(tenancy.clone().with_prefix("orgs/{tenant}/").is_ok());
+    assert!(tenancy.clone().with_prefix("orgs/").is_err());
+    assert!(tenancy.clone().with_prefix("orgs/{tenant}").is_err());
+    assert!(tenancy.with_prefix("").is_err());
+  }
+
+  #[test]
+  fn tenant_prefix_comes_from_the_claim_or_the_trusted_header() {
+    let tenancy = Tenancy::new(Some("tenant".to_string()), false);
+    assert_eq!(
+      tenancy.tenant_prefix(Some(&claims("acme")), None),
+      Some("tenants/acme/".to_string())
+    );
+    assert_eq!(tenancy.tenant_prefix(None, Some("acme")), None);
+
+    let tenancy = Tenancy::new(None, true)
+      .with_prefix("orgs/{tenant}/")
+      .unwrap();
+    assert_eq!(
+      tenancy.tenant_prefix(None, Some("acme")),
+      Some("orgs/acme/".to_string())
+    );
+  }
+
+  #[test]
+  fn tenant_prefix_rejects_unsafe_tenants() {
+    let tenancy = Tenancy::new(None, true);
+    for tenant in ["", ".", "..", "a/b", "a b", "a\nb"] {
+      assert_eq!(
+        tenancy.tenant_prefix(None, Some(tenant)),
+        None,
+        "{:?}",
+        tenant
+      );
+    }
+  }
+
+  #[test]
+  fn scope_puts_keys_under_the_tenant_prefix() {
+    let s3_configuration = s3_configuration();
+    let identity = identity(&s3_configuration, Some("acme"));
+
+    let mut key = "a.txt".to_string();
+    identity.scope(&mut key);
+    assert_eq!(key, "tenants/acme/a.txt");
+    identity.scope(&mut key);
+    assert_eq!(key, "tenants/acme/a.txt");
+
+    let mut prefix = None;
+    identity.scope_prefix(&mut prefix);
+    assert_eq!(prefix.as_deref(), Some("tenants/acme/"));
+  }
+
+  #[test]
+  fn tenants_are_confined_to_their_prefix() {
+    let s3_configuration = s3_configuration();
+    let acme = identity(&s3_configuration, Some("acme"));
+    let allowed = |identity: &Identity, key: &str| {
+      is_allowed(&s3_configuration, identity, Operation::Read, "bucket", key)
+    };
+
+    assert!(allowed(&acme, "tenants/acme/a.txt"));
+    assert!(!allowed(&acme, "tenants/other/a.txt"));
+    assert!(!allowed(&acme, "tenants/acme-corp/a.txt"));
+    assert!(!allowed(&acme, "a.txt"));
+    assert!(!allowed(&acme, "tenants/acme/../other/a.txt"));
+
+    let mut key = "../other/a.txt".to_string();
+    acme.scope(&mut key);
+    assert!(!allowed(&acme, &key));
+  }
+
+  #[test]
+  fn admins_are_granted_everything_by_a_policy() {
+    let policies = serde_json::from_value(json!([
+      { "api_key": "admin", "buckets": ["*"] },
+      { "api_key": "reader", "buckets": ["*"], "operations": ["read"] },
+      { "api_key": "media", "buckets": ["media"] },
+    ]))
+    .unwrap();
+    let s3_configuration =
+      S3Configuration::new_with_hostname("access", "secret", "us-east-1", "https://s3.example.com")
+        .unwrap()
+        .with_policies(policies);
+    let caller = |api_key: Option<&str>| {
+      Identity::new(&s3_configuration, None, api_key.map(str::to_string), None)
+    };
+
+    assert!(is_admin(&s3_configuration, &caller(Some("admin"))));
+    assert!(!is_admin(&s3_configuration, &caller(Some("reader"))));
+    assert!(!is_admin(&s3_configuration, &caller(Some("media"))));
+    assert!(!is_admin(&s3_configuration, &caller(Some("unknown"))));
+    assert!(!is_admin(&s3_configuration, &caller(None)));
+  }
+
+  #[test]
+  fn requests_without_a_tenant_are_rejected() {
+    let s3_configuration = s3_configuration();
+    let anonymous = identity(&s3_configuration, None);
+    assert!(!is_allowed(
+      &s3_configuration,
+      &anonymous,
+      Operation::Read,
+      "bucket",
+      "tenants/acme/a.txt"
+    ));
+  }
+}
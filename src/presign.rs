@@ -0,0 +1,392 @@
+//! Pre-signing of S3 requests, without the HTTP server, e.g. for CLI tools and batch jobs.
+
+use rusoto_credential::AwsCredentials;
+use rusoto_signature::{signature::Params, SignedRequest};
+use std::{
+  fmt::{Display, Formatter},
+  time::{Duration, SystemTime},
+};
+
+pub use rusoto_signature::Region;
+
+/// Validity of the pre-signed URLs, unless set in the [`PresignOptions`]
+pub const DEFAULT_EXPIRATION: Duration = Duration::from_secs(3600);
+/// Longest validity of the pre-signed URLs accepted by S3, of 7 days
+pub const MAX_EXPIRATION: Duration = Duration::from_secs(7 * 24 * 3600);
+/// Shortest validity of the pre-signed URLs, below which the credentials are deemed expired
+const MIN_EXPIRATION: Duration = Duration::from_secs(1);
+
+/// The temporary credentials of a [`PresignConfiguration`] expired, or are about to: the URLs
+/// signed with them would be rejected by S3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CredentialsExpired;
+
+impl Display for CredentialsExpired {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "credentials expired, reload the configuration")
+  }
+}
+
+impl std::error::Error for CredentialsExpired {}
+
+/// AWS partition of a region: each has its own endpoints, and credentials of a partition are not
+/// valid in the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partition {
+  Aws,
+  /// China regions, served under `amazonaws.com.cn`
+  AwsCn,
+  /// AWS GovCloud (US) regions
+  AwsUsGov,
+}
+
+impl Partition {
+  /// Partition of a region, told by its name for custom regions, e.g. `cn-north-1`.
+  pub fn of(region: &Region) -> Self {
+    let name = region.name();
+    if name.starts_with("cn-") {
+      Partition::AwsCn
+    } else if name.starts_with("us-gov-") {
+      Partition::AwsUsGov
+    } else {
+      Partition::Aws
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Partition::Aws => "aws",
+      Partition::AwsCn => "aws-cn",
+      Partition::AwsUsGov => "aws-us-gov",
+    }
+  }
+
+  /// Domain of the endpoints of the partition.
+  pub fn dns_suffix(&self) -> &'static str {
+    match self {
+      Partition::AwsCn => "amazonaws.com.cn",
+      Partition::Aws | Partition::AwsUsGov => "amazonaws.com",
+    }
+  }
+
+  /// Region of the buckets created without a location constraint.
+  pub fn default_region(&self) -> Region {
+    match self {
+      Partition::Aws => Region::UsEast1,
+      Partition::AwsCn => Region::CnNorth1,
+      Partition::AwsUsGov => Region::UsGovWest1,
+    }
+  }
+
+  /// Whether S3 Transfer Acceleration is available, only in the commercial regions.
+  pub fn supports_transfer_acceleration(&self) -> bool {
+    *self == Partition::Aws
+  }
+}
+
+/// Credentials and region the URLs are signed for.
+///
+/// The server builds it from its `S3Configuration`, for the bucket of the request.
+#[derive(Clone, Debug)]
+pub struct PresignConfiguration {
+  access_key_id: String,
+  secret_access_key: String,
+  session_token: Option<String>,
+  credentials_expiration: Option<SystemTime>,
+  region: Region,
+}
+
+impl PresignConfiguration {
+  pub fn new(access_key_id: &str, secret_access_key: &str, region: Region) -> Self {
+    Self {
+      access_key_id: access_key_id.to_string(),
+      secret_access_key: secret_access_key.to_string(),
+      session_token: None,
+      credentials_expiration: None,
+      region,
+    }
+  }
+
+  /// Signs with temporary credentials, e.g. from STS, valid until `expiration` if known: the URLs
+  /// expire with them at the latest, see [`PresignConfiguration::expiration`].
+  pub fn with_session_token(mut self, session_token: &str, expiration: Option<SystemTime>) -> Self {
+    self.session_token = Some(session_token.to_string());
+    self.credentials_expiration = expiration;
+    self
+  }
+
+  /// Signs for another region, e.g. the one of the bucket.
+  pub fn with_region(mut self, region: Region) -> Self {
+    self.region = region;
+    self
+  }
+
+  pub fn region(&self) -> &Region {
+    &self.region
+  }
+
+  /// Validity of the URLs signed with the options: the one of the options, clamped to the
+  /// expiration of the credentials, past which S3 rejects the URLs anyway.
+  ///
+  /// Fails when the credentials expired or expire within a second, rather than signing URLs which
+  /// cannot be used.
+  pub fn expiration(&self, options: &PresignOptions) -> Result<Duration, CredentialsExpired> {
+    let expiration = options.expiration();
+    let expiration = match self.credentials_expiration {
+      Some(credentials_expiration) => expiration.min(
+        credentials_expiration
+          .duration_since(SystemTime::now())
+          .unwrap_or_default(),
+      ),
+      None => expiration,
+    };
+    if expiration < MIN_EXPIRATION {
+      return Err(CredentialsExpired);
+    }
+    Ok(expiration)
+  }
+
+  /// Time at which the URLs signed now with the options expire.
+  pub fn expires_at(&self, options: &PresignOptions) -> Result<SystemTime, CredentialsExpired> {
+    Ok(SystemTime::now() + self.expiration(options)?)
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PresignOptions {
+  expiration: Option<Duration>,
+  transfer_acceleration: bool,
+  headers: Vec<(String, String)>,
+  content_disposition: Option<String>,
+}
+
+impl PresignOptions {
+  /// Sets the validity of the URL, one hour by default.
+  pub fn with_expiration(mut self, expiration: Duration) -> Self {
+    self.expiration = Some(expiration);
+    self
+  }
+
+  /// Signs the URL for the `bucket.s3-accelerate.amazonaws.com` endpoint.
+  ///
+  /// Transfer Acceleration must be enabled on the bucket, which is not checked here. It is
+  /// ignored in the partitions not supporting it, see [`Partition`].
+  pub fn with_transfer_acceleration(mut self, transfer_acceleration: bool) -> Self {
+    self.transfer_acceleration = transfer_acceleration;
+    self
+  }
+
+  /// Signs a header the request must be sent with, e.g. `x-amz-server-side-encryption`.
+  pub fn with_header(self, name: &str, value: &str) -> Self {
+    self.with_headers(vec![(name, value)])
+  }
+
+  pub fn with_headers<N, V>(mut self, headers: impl IntoIterator<Item = (N, V)>) -> Self
+  where
+    N: AsRef<str>,
+    V: AsRef<str>,
+  {
+    self.headers.extend(
+      headers
+        .into_iter()
+        .map(|(name, value)| (name.as_ref().to_string(), value.as_ref().to_string())),
+    );
+    self
+  }
+
+  /// Overrides the `Content-Disposition` header of the downloaded object, e.g. `attachment`.
+  pub fn with_content_disposition(mut self, content_disposition: &str) -> Self {
+    self.content_disposition = Some(content_disposition.to_string());
+    self
+  }
+
+  pub fn expiration(&self) -> Duration {
+    self.expiration.unwrap_or(DEFAULT_EXPIRATION)
+  }
+
+  /// Names of the signed headers, which the request must be sent with.
+  pub fn header_names(&self) -> Vec<String> {
+    self.headers.iter().map(|(name, _)| name.clone()).collect()
+  }
+}
+
+/// Pre-signed URL to download an object.
+pub fn get_object(
+  configuration: &PresignConfiguration,
+  bucket: &str,
+  key: &str,
+  options: &PresignOptions,
+) -> Result<String, CredentialsExpired> {
+  presign(configuration, "GET", bucket, key, Params::new(), options)
+}
+
+/// Pre-signed URL to upload an object in a single request.
+pub fn put_object(
+  configuration: &PresignConfiguration,
+  bucket: &str,
+  key: &str,
+  options: &PresignOptions,
+) -> Result<String, CredentialsExpired> {
+  presign(configuration, "PUT", bucket, key, Params::new(), options)
+}
+
+/// Pre-signed URL to delete an object.
+pub fn delete_object(
+  configuration: &PresignConfiguration,
+  bucket: &str,
+  key: &str,
+  options: &PresignOptions,
+) -> Result<String, CredentialsExpired> {
+  presign(configuration, "DELETE", bucket, key, Params::new(), options)
+}
+
+/// Pre-signed URL to upload the part `part_number` (from 1) of a multipart upload.
+pub fn upload_part(
+  configuration: &PresignConfiguration,
+  bucket: &str,
+  key: &str,
+  upload_id: &str,
+  part_number: i64,
+  options: &PresignOptions,
+) -> Result<String, CredentialsExpired> {
+  let mut params = Params::new();
+  params.insert("partNumber".to_string(), Some(part_number.to_string()));
+  params.insert("uploadId".to_string(), Some(upload_id.to_string()));
+
+  presign(configuration, "PUT", bucket, key, params, options)
+}
+
+fn presign(
+  configuration: &PresignConfiguration,
+  method: &str,
+  bucket: &str,
+  key: &str,
+  mut params: Params,
+  options: &PresignOptions,
+) -> Result<String, CredentialsExpired> {
+  let credentials = AwsCredentials::new(
+    &configuration.access_key_id,
+    &configuration.secret_access_key,
+    configuration.session_token.clone(),
+    None,
+  );
+
+  // Accelerated endpoints only support virtual-hosted style requests, so the bucket is part of
+  // the hostname and the path only contains the object key.
+  let partition = Partition::of(&configuration.region);
+  let mut request = if options.transfer_acceleration && partition.supports_transfer_acceleration() {
+    let mut request = SignedRequest::new(method, "s3", &configuration.region, &format!("/{}", key));
+    request.set_hostname(Some(format!(
+      "{}.s3-accelerate.{}",
+      bucket,
+      partition.dns_suffix()
+    )));
+    request
+  } else {
+    SignedRequest::new(
+      method,
+      "s3",
+      &configuration.region,
+      &format!("/{}/{}", bucket, key),
+    )
+  };
+
+  if let Some(content_disposition) = &options.content_disposition {
+    params.insert(
+      "response-content-disposition".to_string(),
+      Some(content_disposition.clone()),
+    );
+  }
+  request.set_params(params);
+  for (name, value) in &options.headers {
+    request.add_header(name, value);
+  }
+
+  let expiration = configuration.expiration(options)?;
+  Ok(request.generate_presigned_url(&credentials, &expiration, false))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn configuration(credentials_expire_in: Option<Duration>) -> PresignConfiguration {
+    let configuration = PresignConfiguration::new("AKIAEXAMPLE", "secret", Region::UsEast1);
+    match credentials_expire_in {
+      Some(expire_in) => {
+        configuration.with_session_token("token", Some(SystemTime::now() + expire_in))
+      }
+      None => configuration,
+    }
+  }
+
+  fn expires_param(url: &str) -> u64 {
+    url
+      .split(['?', '&'])
+      .find_map(|parameter| parameter.strip_prefix("X-Amz-Expires="))
+      .unwrap()
+      .parse()
+      .unwrap()
+  }
+
+  #[test]
+  fn urls_are_valid_for_the_expiration_of_the_options() {
+    let options = PresignOptions::default().with_expiration(Duration::from_secs(600));
+    assert_eq!(
+      configuration(None).expiration(&options),
+      Ok(Duration::from_secs(600))
+    );
+    assert_eq!(
+      configuration(None).expiration(&PresignOptions::default()),
+      Ok(DEFAULT_EXPIRATION)
+    );
+
+    let url = get_object(&configuration(None), "bucket", "a.txt", &options).unwrap();
+    assert_eq!(expires_param(&url), 600);
+  }
+
+  #[test]
+  fn urls_expire_with_the_credentials() {
+    let configuration = configuration(Some(Duration::from_secs(300)));
+    let expiration = configuration
+      .expiration(&PresignOptions::default())
+      .unwrap();
+    assert!(expiration <= Duration::from_secs(300), "{:?}", expiration);
+    assert!(expiration > Duration::from_secs(290), "{:?}", expiration);
+
+    // Credentials outliving the URL leave it untouched
+    let options = PresignOptions::default().with_expiration(Duration::from_secs(60));
+    assert_eq!(
+      configuration.expiration(&options),
+      Ok(Duration::from_secs(60))
+    );
+
+    let url = get_object(
+      &configuration,
+      "bucket",
+      "a.txt",
+      &PresignOptions::default(),
+    )
+    .unwrap();
+    assert!(expires_param(&url) <= 300, "{}", url);
+    assert!(url.contains("X-Amz-Security-Token=token"), "{}", url);
+  }
+
+  #[test]
+  fn expired_credentials_sign_no_url() {
+    let options = PresignOptions::default();
+    for expire_in in [Duration::ZERO, Duration::from_millis(500)] {
+      let configuration = configuration(Some(expire_in));
+      assert_eq!(configuration.expiration(&options), Err(CredentialsExpired));
+      assert_eq!(configuration.expires_at(&options), Err(CredentialsExpired));
+      assert_eq!(
+        get_object(&configuration, "bucket", "a.txt", &options),
+        Err(CredentialsExpired)
+      );
+    }
+
+    let expired = PresignConfiguration::new("AKIAEXAMPLE", "secret", Region::UsEast1)
+      .with_session_token("token", Some(SystemTime::now() - Duration::from_secs(60)));
+    assert_eq!(expired.expiration(&options), Err(CredentialsExpired));
+  }
+}
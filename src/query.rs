@@ -0,0 +1,41 @@
+use crate::Error;
+use serde::de::DeserializeOwned;
+use warp::{Filter, Rejection};
+
+/// Parameters which are rejected when empty, rather than reaching S3
+const REQUIRED_PARAMETERS: &[&str] = &["bucket", "path"];
+
+/// Deserializes the query string of the requests into `T`, like `warp::query`, but rejecting
+/// the invalid ones with an [`Error::InvalidParameter`] describing the missing, empty or malformed
+/// parameter.
+pub(crate) fn parameters<T: DeserializeOwned + Send + 'static>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+  warp::query::raw()
+    .or(warp::any().map(String::new))
+    .unify()
+    .and_then(|query: String| async move { parse(&query).map_err(warp::reject::custom) })
+}
+
+fn parse<T: DeserializeOwned>(query: &str) -> Result<T, Error> {
+  let pairs: Vec<(String, String)> = serde_urlencoded::from_str(query)
+    .map_err(|error| Error::InvalidParameter(format!("invalid query string: {}", error)))?;
+  for (name, value) in &pairs {
+    if REQUIRED_PARAMETERS.contains(&name.as_str()) {
+      required(name, value)?;
+    }
+  }
+
+  serde_urlencoded::from_str(query)
+    .map_err(|error| Error::InvalidParameter(format!("invalid query string: {}", error)))
+}
+
+/// Rejects an empty required parameter, e.g. a proto3 or GraphQL string left empty.
+pub(crate) fn required(name: &str, value: &str) -> Result<(), Error> {
+  if value.is_empty() {
+    return Err(Error::InvalidParameter(format!(
+      "{} must not be empty",
+      name
+    )));
+  }
+  Ok(())
+}
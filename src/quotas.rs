@@ -0,0 +1,179 @@
+use crate::{metrics, retry, s3_configuration::matches_pattern, telemetry, Error, S3Configuration};
+use rusoto_s3::{HeadObjectRequest, S3Client, S3};
+use std::{
+  collections::HashMap,
+  convert::TryFrom,
+  fmt::{Debug, Formatter},
+  sync::{Arc, Mutex},
+};
+use tracing::Instrument;
+
+/// Maximum number of bytes uploaded under a key prefix, e.g. the one of a tenant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quota {
+  /// Name or `*` pattern of the buckets
+  pub bucket: String,
+  /// Prefix of the keys, empty for the whole bucket
+  pub prefix: String,
+  pub max_bytes: u64,
+}
+
+/// Storage of the bytes uploaded under the prefixes with a quota, shared by the signers
+/// enforcing the same quotas.
+pub trait QuotaStore: Send + Sync {
+  /// Bytes uploaded so far under the prefix of the quota.
+  fn usage(&self, quota: &Quota) -> u64;
+  /// Adds the bytes of an upload to the usage of the quota.
+  fn add(&self, quota: &Quota, bytes: u64);
+}
+
+/// Store keeping the usage of the quotas in memory, lost on restart.
+#[derive(Default)]
+pub struct MemoryQuotaStore {
+  usage: Mutex<HashMap<(String, String), u64>>,
+}
+
+impl QuotaStore for MemoryQuotaStore {
+  fn usage(&self, quota: &Quota) -> u64 {
+    let usage = self.usage.lock().unwrap_or_else(|error| error.into_inner());
+    usage
+      .get(&(quota.bucket.clone(), quota.prefix.clone()))
+      .copied()
+      .unwrap_or_default()
+  }
+
+  fn add(&self, quota: &Quota, bytes: u64) {
+    let mut usage = self.usage.lock().unwrap_or_else(|error| error.into_inner());
+    let total = usage
+      .entry((quota.bucket.clone(), quota.prefix.clone()))
+      .or_default();
+    *total = total.saturating_add(bytes);
+  }
+}
+
+/// Quotas of bytes uploaded under key prefixes, rejecting the uploads signed once exceeded.
+///
+/// The bytes are accounted once the uploads went through the signer: multipart uploads on
+/// completion and objects uploaded through `/objects/content`, their size being read with
+/// HeadObject. The objects uploaded with single-request pre-signed URLs are checked when signed,
+/// but never accounted, the signer not seeing them complete. Overwritten objects count again, and
+/// deleted ones are not subtracted.
+#[derive(Clone)]
+pub struct Quotas {
+  quotas: Vec<Quota>,
+  store: Arc<dyn QuotaStore>,
+}
+
+impl Debug for Quotas {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Quotas")
+      .field("quotas", &self.quotas)
+      .finish()
+  }
+}
+
+impl Quotas {
+  /// Quotas accounted in memory.
+  pub fn new(quotas: Vec<Quota>) -> Self {
+    Self {
+      quotas,
+      store: Arc::new(MemoryQuotaStore::default()),
+    }
+  }
+
+  /// Accounts the uploads in another store, e.g. a database shared by several signers.
+  pub fn with_store(mut self, store: Arc<dyn QuotaStore>) -> Self {
+    self.store = store;
+    self
+  }
+
+  /// Returns the quotas of `other`, accounted in the store of these ones, with their usage.
+  pub(crate) fn reloaded(&self, other: Quotas) -> Quotas {
+    Quotas {
+      quotas: other.quotas,
+      store: self.store.clone(),
+    }
+  }
+
+  /// Quotas applying to the object `key` of `bucket`.
+  fn matching<'a>(&'a self, bucket: &'a str, key: &'a str) -> impl Iterator<Item = &'a Quota> {
+    self
+      .quotas
+      .iter()
+      .filter(move |quota| matches_pattern(&quota.bucket, bucket) && key.starts_with(&quota.prefix))
+  }
+
+  /// Fails when a quota of the object `key` of `bucket` is exceeded.
+  pub fn check(&self, bucket: &str, key: &str) -> Result<(), Error> {
+    match self
+      .matching(bucket, key)
+      .find(|quota| self.store.usage(quota) >= quota.max_bytes)
+    {
+      Some(quota) => Err(Error::QuotaExceeded(format!(
+        "the {} bytes quota of {}/{} is exceeded",
+        quota.max_bytes, bucket, quota.prefix
+      ))),
+      None => Ok(()),
+    }
+  }
+
+  /// Adds the `bytes` of the uploaded object `key` of `bucket` to the usage of its quotas.
+  pub fn add(&self, bucket: &str, key: &str, bytes: u64) {
+    for quota in self.matching(bucket, key) {
+      self.store.add(quota, bytes);
+    }
+  }
+}
+
+/// Rejects the upload of the object `key` of `bucket` once its quota is exceeded.
+pub(crate) fn check(
+  s3_configuration: &S3Configuration,
+  bucket: &str,
+  key: &str,
+) -> Result<(), Error> {
+  match s3_configuration.quotas() {
+    Some(quotas) => quotas.check(bucket, key),
+    None => Ok(()),
+  }
+}
+
+/// Accounts an object uploaded through the signer, of the size answered by HeadObject.
+///
+/// The upload succeeded already: failing to account it is only logged.
+pub(crate) async fn account_upload(s3_configuration: &S3Configuration, bucket: &str, key: &str) {
+  let quotas = match s3_configuration.quotas() {
+    Some(quotas) if quotas.matching(bucket, key).next().is_some() => quotas,
+    _ => return,
+  };
+  match object_size(s3_configuration, bucket, key).await {
+    Ok(size) => quotas.add(bucket, key, size),
+    Err(error) => log::error!(
+      "Cannot account upload: bucket={}, key={}, {}",
+      bucket,
+      key,
+      error
+    ),
+  }
+}
+
+async fn object_size(
+  s3_configuration: &S3Configuration,
+  bucket: &str,
+  key: &str,
+) -> Result<u64, Error> {
+  let client = S3Client::try_from(s3_configuration)?;
+  let request = HeadObjectRequest {
+    bucket: bucket.to_string(),
+    key: key.to_string(),
+    ..Default::default()
+  };
+  let output = retry::s3_call(s3_configuration, "HeadObject", || {
+    client
+      .head_object(request.clone())
+      .instrument(telemetry::s3_span("HeadObject"))
+  })
+  .await
+  .map_err(metrics::count_s3_error("HeadObject"))
+  .map_err(Error::HeadObjectError)?;
+  Ok(output.content_length.unwrap_or_default().max(0) as u64)
+}
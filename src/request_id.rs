@@ -0,0 +1,162 @@
+use std::{cell::RefCell, fmt};
+use tracing::{
+  field::{Field, Visit},
+  span::{Attributes, Id},
+  Subscriber,
+};
+use tracing_subscriber::{
+  layer::{Context, Layer},
+  registry::LookupSpan,
+};
+use warp::{
+  hyper::HeaderMap,
+  trace::{Info, Trace},
+  Filter, Reply,
+};
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+const REQUEST_ID_FIELD: &str = "request_id";
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+thread_local! {
+  static CURRENT_REQUEST_IDS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Identifies every request handled by the filter with an `X-Request-Id`, returned in the
+/// response headers and prefixed to the log lines.
+///
+/// The identifier of the request is kept if valid (at most 128 visible ASCII characters),
+/// otherwise a new one is generated. Nested filters share the identifier of the outer one.
+/// The identifier is tracked through its tracing span, so the subscriber must include the
+/// [`RequestIdLayer`] (see `telemetry::init_tracing`), otherwise the identifiers of the responses
+/// are generated and not logged.
+pub fn with_request_id<F, R>(
+  filter: F,
+) -> impl Filter<Extract = (impl Reply,), Error = F::Error> + Clone
+where
+  F: Filter<Extract = (R,)> + Clone + Send + Sync + 'static,
+  R: Reply,
+{
+  filter
+    .map(|reply: R| {
+      let request_id = current_request_id().unwrap_or_else(generate_request_id);
+      warp::reply::with_header(reply, X_REQUEST_ID, request_id)
+    })
+    .with(request_id_span())
+}
+
+/// Identifier of the request being handled on this thread, if any.
+pub fn current_request_id() -> Option<String> {
+  CURRENT_REQUEST_IDS.with(|request_ids| request_ids.borrow().last().cloned())
+}
+
+fn request_id_span() -> Trace<impl Fn(Info<'_>) -> tracing::Span + Clone> {
+  warp::trace(|info: Info<'_>| {
+    let request_id = current_request_id()
+      .or_else(|| incoming_request_id(info.request_headers()))
+      .unwrap_or_else(generate_request_id);
+    tracing::info_span!("request_id", request_id = request_id.as_str())
+  })
+}
+
+fn incoming_request_id(headers: &HeaderMap) -> Option<String> {
+  headers
+    .get(X_REQUEST_ID)
+    .and_then(|value| value.to_str().ok())
+    .filter(|value| {
+      !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LENGTH
+        && value.chars().all(|character| character.is_ascii_graphic())
+    })
+    .map(str::to_string)
+}
+
+fn generate_request_id() -> String {
+  format!("{:032x}", rand::random::<u128>())
+}
+
+/// Tracks the request identifier of the entered spans, for [`current_request_id`].
+pub struct RequestIdLayer;
+
+struct RequestId(String);
+
+impl<S> Layer<S> for RequestIdLayer
+where
+  S: Subscriber + for<'a> LookupSpan<'a>,
+{
+  fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+    let mut visitor = RequestIdVisitor(None);
+    attributes.record(&mut visitor);
+
+    if let (Some(request_id), Some(span)) = (visitor.0, context.span(id)) {
+      span.extensions_mut().insert(RequestId(request_id));
+    }
+  }
+
+  fn on_enter(&self, id: &Id, context: Context<'_, S>) {
+    if let Some(span) = context.span(id) {
+      if let Some(request_id) = span.extensions().get::<RequestId>() {
+        CURRENT_REQUEST_IDS.with(|request_ids| request_ids.borrow_mut().push(request_id.0.clone()));
+      }
+    }
+  }
+
+  fn on_exit(&self, id: &Id, context: Context<'_, S>) {
+    if let Some(span) = context.span(id) {
+      if span.extensions().get::<RequestId>().is_some() {
+        CURRENT_REQUEST_IDS.with(|request_ids| request_ids.borrow_mut().pop());
+      }
+    }
+  }
+}
+
+struct RequestIdVisitor(Option<String>);
+
+impl Visit for RequestIdVisitor {
+  fn record_str(&mut self, field: &Field, value: &str) {
+    if field.name() == REQUEST_ID_FIELD {
+      self.0 = Some(value.to_string());
+    }
+  }
+
+  fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+    if field.name() == REQUEST_ID_FIELD {
+      self.0 = Some(format!("{:?}", value));
+    }
+  }
+}
+
+/// Logger prefixing the log lines with the identifier of the current request.
+pub struct RequestIdLogger<L>(L);
+
+impl<L: log::Log> RequestIdLogger<L> {
+  pub fn new(logger: L) -> Self {
+    Self(logger)
+  }
+}
+
+impl<L: log::Log> log::Log for RequestIdLogger<L> {
+  fn enabled(&self, metadata: &log::Metadata) -> bool {
+    self.0.enabled(metadata)
+  }
+
+  fn log(&self, record: &log::Record) {
+    match current_request_id() {
+      Some(request_id) => self.0.log(
+        &log::Record::builder()
+          .args(format_args!("[{}] {}", request_id, record.args()))
+          .metadata(record.metadata().clone())
+          .module_path(record.module_path())
+          .file(record.file())
+          .line(record.line())
+          .build(),
+      ),
+      None => self.0.log(record),
+    }
+  }
+
+  fn flush(&self) {
+    self.0.flush()
+  }
+}
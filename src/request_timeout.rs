@@ -0,0 +1,98 @@
+use crate::error::error_response;
+use std::{convert::Infallible, future::Future, net::SocketAddr, time::Duration};
+use tower_service::Service;
+use warp::{
+  hyper::{service::service_fn, Body, Request, Response, StatusCode},
+  Filter, Rejection, Reply,
+};
+
+/// Remote address of the connection, for the requests served by [`with_request_timeout`], which
+/// `warp::addr::remote` only knows when served by `warp::serve`.
+#[derive(Clone, Copy, Debug)]
+struct RemoteAddress(SocketAddr);
+
+/// Serves the filter as a `tower::Service` for a connection from `remote_address`, answering
+/// `504 Gateway Timeout` with an [`ErrorBody`](crate::ErrorBody) to the requests not answered
+/// within `request_timeout`.
+///
+/// Only the responses are awaited within the timeout: their bodies, e.g. streamed archives, are
+/// not bounded.
+pub fn with_request_timeout<F, R>(
+  filter: F,
+  remote_address: Option<SocketAddr>,
+  request_timeout: Option<Duration>,
+) -> impl Service<
+  Request<Body>,
+  Response = Response<Body>,
+  Error = Infallible,
+  Future = impl Future<Output = Result<Response<Body>, Infallible>> + Send,
+> + Clone
+     + Send
+where
+  F: Filter<Extract = (R,), Error = Infallible> + Clone + Send + Sync + 'static,
+  R: Reply,
+{
+  let service = warp::service(filter);
+  service_fn(move |mut request: Request<Body>| {
+    let mut service = service.clone();
+    if let Some(remote_address) = remote_address {
+      request
+        .extensions_mut()
+        .insert(RemoteAddress(remote_address));
+    }
+
+    async move {
+      let response = service.call(request);
+      let request_timeout = match request_timeout {
+        Some(request_timeout) => request_timeout,
+        None => return response.await,
+      };
+
+      tokio::time::timeout(request_timeout, response)
+        .await
+        .unwrap_or_else(|_| {
+          let message = format!("no response after {}s", request_timeout.as_secs_f32());
+          log::warn!("Request timeout: {}", message);
+          Ok(error_response(
+            StatusCode::GATEWAY_TIMEOUT,
+            "GatewayTimeout",
+            &message,
+            None,
+          ))
+        })
+    }
+  })
+}
+
+/// Remote address of the request, whether served by `warp::serve` or [`with_request_timeout`].
+pub(crate) fn remote_address(
+) -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Clone {
+  warp::ext::optional::<RemoteAddress>()
+    .and(warp::addr::remote())
+    .map(
+      |remote_address: Option<RemoteAddress>, address: Option<SocketAddr>| {
+        remote_address
+          .map(|RemoteAddress(remote_address)| remote_address)
+          .or(address)
+      },
+    )
+}
+
+/// Address of the client of the request, e.g. for the logs: the first address of its
+/// `X-Forwarded-For` header, set by the proxies in front of the signer, else its remote address.
+pub(crate) fn client_address() -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Clone
+{
+  warp::header::optional::<String>("x-forwarded-for")
+    .and(remote_address())
+    .map(
+      |forwarded_for: Option<String>, remote_address: Option<SocketAddr>| {
+        forwarded_for
+          .as_deref()
+          .and_then(|forwarded_for| forwarded_for.split(',').next())
+          .map(str::trim)
+          .filter(|address| !address.is_empty())
+          .map(String::from)
+          .or_else(|| remote_address.map(|address| address.ip().to_string()))
+      },
+    )
+}
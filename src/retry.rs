@@ -0,0 +1,114 @@
+use crate::{batch::server::error_code, metrics, S3Configuration};
+use rand::Rng;
+use rusoto_core::{request::HttpDispatchError, RusotoError};
+use std::{fmt::Debug, future::Future, time::Duration};
+
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+const TIMEOUT_MESSAGE: &str = "no answer from S3 after";
+
+/// Retries of the S3 control-plane calls (listings, multipart upload creations, completions and
+/// abortions, ...) failing transiently: throttled (`SlowDown`), timed out, or answered with a server
+/// error.
+///
+/// Attempts are spaced by a jittered exponential backoff: a random delay up to `base_delay`
+/// doubled at each retry, capped to 5 seconds. Each attempt can be bounded by a `timeout`.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+  max_retries: u32,
+  base_delay: Duration,
+  timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_retries: DEFAULT_MAX_RETRIES,
+      base_delay: DEFAULT_RETRY_BASE_DELAY,
+      timeout: None,
+    }
+  }
+}
+
+impl RetryPolicy {
+  pub fn new(max_retries: u32, base_delay: Duration, timeout: Option<Duration>) -> Self {
+    Self {
+      max_retries,
+      base_delay,
+      timeout,
+    }
+  }
+
+  fn delay(&self, retry: u32) -> Duration {
+    let max_delay = self
+      .base_delay
+      .saturating_mul(2u32.saturating_pow(retry))
+      .min(MAX_RETRY_DELAY);
+    max_delay.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+  }
+}
+
+/// Runs the S3 call `operation` with the retry policy of the configuration, calling `call` for
+/// each attempt.
+pub(crate) async fn s3_call<T, E, F, Fut>(
+  s3_configuration: &S3Configuration,
+  operation: &'static str,
+  call: F,
+) -> Result<T, RusotoError<E>>
+where
+  E: Debug,
+  F: Fn() -> Fut,
+  Fut: Future<Output = Result<T, RusotoError<E>>>,
+{
+  let retry_policy = s3_configuration.retry_policy();
+  let mut retry = 0;
+  loop {
+    let result = match retry_policy.timeout {
+      Some(timeout) => tokio::time::timeout(timeout, call())
+        .await
+        .unwrap_or_else(|_| {
+          Err(RusotoError::HttpDispatch(HttpDispatchError::new(format!(
+            "{} {}s",
+            TIMEOUT_MESSAGE,
+            timeout.as_secs_f32()
+          ))))
+        }),
+      None => call().await,
+    };
+
+    match result {
+      Err(error) if retry < retry_policy.max_retries && is_transient(&error) => {
+        let delay = retry_policy.delay(retry);
+        retry += 1;
+        log::warn!(
+          "Retrying {} in {}ms ({}/{}): {}",
+          operation,
+          delay.as_millis(),
+          retry,
+          retry_policy.max_retries,
+          error_code(&error)
+        );
+        metrics::s3_retry(operation);
+        tokio::time::sleep(delay).await;
+      }
+      result => return result,
+    }
+  }
+}
+
+fn is_transient<E: Debug>(error: &RusotoError<E>) -> bool {
+  match error {
+    RusotoError::HttpDispatch(_) => true,
+    RusotoError::Unknown(response) if response.status.is_server_error() => true,
+    _ => matches!(
+      error_code(error).as_str(),
+      "SlowDown" | "RequestTimeout" | "InternalError" | "ServiceUnavailable" | "Throttling"
+    ),
+  }
+}
+
+/// Returns whether the S3 call failed for lack of an answer within the timeout of the policy.
+pub(crate) fn is_timeout<E>(error: &RusotoError<E>) -> bool {
+  matches!(error, RusotoError::HttpDispatch(error) if error.to_string().starts_with(TIMEOUT_MESSAGE))
+}
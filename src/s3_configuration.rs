@@ -1,14 +1,184 @@
-use rusoto_core::{request::TlsError, HttpClient};
+use crate::{
+  limits::DEFAULT_MAX_JSON_BODY_SIZE,
+  objects::{key::DEFAULT_MAX_KEY_LENGTH, AccessTagging, DEFAULT_SHARE_PRESETS},
+  presign::{self, PresignConfiguration, PresignOptions},
+  transfer_acceleration::AccelerationStatuses,
+  BucketRegions, ConcurrencyLimit, DownloadLinks, Error, Jobs, JwtValidator, KeyTemplate,
+  ListingCache, Policy, Quotas, RetryPolicy, Tenancy,
+};
+use hyper_tls::HttpsConnector;
+use native_tls::{Certificate, TlsConnector};
+use rusoto_core::{Client, HttpClient};
 use rusoto_credential::{AwsCredentials, StaticProvider};
 use rusoto_s3::S3Client;
 use rusoto_signature::{region::ParseRegionError, Region};
-use std::{convert::TryFrom, str::FromStr};
+use serde::Deserialize;
+use std::{
+  collections::BTreeMap,
+  convert::TryFrom,
+  fmt::{Debug, Display, Formatter},
+  str::FromStr,
+  sync::{Arc, OnceLock, RwLock},
+  time::{Duration, SystemTime},
+};
+use warp::http::Uri;
+use warp::hyper::client::HttpConnector;
+
+/// Duration after which calls to the primary storage fail over to the secondary one, if any
+const DEFAULT_SECONDARY_TIMEOUT: Duration = Duration::from_secs(10);
+
+const BEGIN_CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----";
+const END_CERTIFICATE: &str = "-----END CERTIFICATE-----";
+
+/// Names of the AWS regions known to the signer.
+pub const AWS_REGIONS: &[&str] = &[
+  "af-south-1",
+  "ap-east-1",
+  "ap-northeast-1",
+  "ap-northeast-2",
+  "ap-northeast-3",
+  "ap-south-1",
+  "ap-southeast-1",
+  "ap-southeast-2",
+  "ap-southeast-3",
+  "ca-central-1",
+  "cn-north-1",
+  "cn-northwest-1",
+  "eu-central-1",
+  "eu-north-1",
+  "eu-south-1",
+  "eu-west-1",
+  "eu-west-2",
+  "eu-west-3",
+  "me-south-1",
+  "sa-east-1",
+  "us-east-1",
+  "us-east-2",
+  "us-gov-east-1",
+  "us-gov-west-1",
+  "us-west-1",
+  "us-west-2",
+];
+
+#[derive(Debug)]
+pub enum S3ConfigurationError {
+  InvalidCredentialsExpiration(String),
+  InvalidEndpoint(String),
+  InvalidJobStore(String),
+  InvalidKeyTemplate(String),
+  InvalidQuota(String),
+  InvalidRegion(ParseRegionError),
+  InvalidScheduledTask(String),
+  InvalidSharePreset(String),
+  InvalidTenantPrefix(String),
+}
+
+impl Display for S3ConfigurationError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      S3ConfigurationError::InvalidCredentialsExpiration(error) => {
+        write!(f, "Invalid credentials expiration: {}", error)
+      }
+      S3ConfigurationError::InvalidEndpoint(error) => {
+        write!(f, "Invalid endpoint: {}", error)
+      }
+      S3ConfigurationError::InvalidJobStore(error) => write!(f, "Invalid job store: {}", error),
+      S3ConfigurationError::InvalidKeyTemplate(error) => {
+        write!(f, "Invalid key template: {}", error)
+      }
+      S3ConfigurationError::InvalidQuota(error) => write!(f, "Invalid quota: {}", error),
+      S3ConfigurationError::InvalidRegion(error) => write!(f, "Invalid region: {}", error),
+      S3ConfigurationError::InvalidScheduledTask(error) => {
+        write!(f, "Invalid scheduled task: {}", error)
+      }
+      S3ConfigurationError::InvalidSharePreset(error) => {
+        write!(f, "Invalid share preset: {}", error)
+      }
+      S3ConfigurationError::InvalidTenantPrefix(error) => {
+        write!(f, "Invalid tenant prefix: {}", error)
+      }
+    }
+  }
+}
+
+impl std::error::Error for S3ConfigurationError {}
 
 #[derive(Clone, Debug)]
 pub struct S3Configuration {
   access_key_id: String,
   secret_access_key: String,
+  session_token: Option<String>,
+  credentials_expiration: Option<SystemTime>,
   region: Region,
+  access_tagging: Option<AccessTagging>,
+  transfer_acceleration: bool,
+  acceleration_statuses: AccelerationStatuses,
+  ca_bundle: Option<Vec<u8>>,
+  insecure_skip_verify: bool,
+  endpoint_overrides: Vec<String>,
+  jwt_validator: Option<JwtValidator>,
+  policies: Option<Vec<Policy>>,
+  allowed_buckets: Option<Vec<String>>,
+  denied_buckets: Vec<String>,
+  max_key_length: usize,
+  max_json_body_size: u64,
+  hypermedia: bool,
+  presigned_url_expiration: Duration,
+  share_presets: BTreeMap<String, Duration>,
+  key_template: Option<KeyTemplate>,
+  dedup_prefix: Option<String>,
+  download_links: Option<DownloadLinks>,
+  health_check_bucket: Option<String>,
+  concurrency_limit: Option<ConcurrencyLimit>,
+  retry_policy: RetryPolicy,
+  listing_cache: Option<ListingCache>,
+  quotas: Option<Quotas>,
+  jobs: Jobs,
+  tenancy: Option<Tenancy>,
+  uppy_bucket: Option<String>,
+  bucket_regions: Option<BucketRegions>,
+  bucket_configurations: Vec<(String, S3Configuration)>,
+  s3_client: SharedS3Client,
+  secondary_region: Option<Region>,
+  secondary_timeout: Duration,
+  secondary_s3_client: SharedS3Client,
+  live: Option<Arc<RwLock<Arc<S3Configuration>>>>,
+}
+
+/// S3 client built on the first request served with a configuration, then shared by the next
+/// ones to reuse its pooled connections.
+#[derive(Clone, Default)]
+struct SharedS3Client(Arc<OnceLock<S3Client>>);
+
+impl Debug for SharedS3Client {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let state = if self.0.get().is_some() {
+      "built"
+    } else {
+      "not built"
+    };
+    write!(f, "SharedS3Client({})", state)
+  }
+}
+
+/// Overrides the S3 configuration for buckets matching a name or a pattern.
+///
+/// The `bucket` field accepts `*` wildcards (e.g. `media-*`). Fields left empty are inherited
+/// from the default configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BucketConfiguration {
+  pub bucket: String,
+  pub region: Option<String>,
+  pub hostname: Option<String>,
+  pub access_key_id: Option<String>,
+  pub secret_access_key: Option<String>,
+  pub transfer_acceleration: Option<bool>,
+  /// Hostname of the passive storage, when `hostname` is set
+  pub secondary_hostname: Option<String>,
+  /// Template of the keys generated on upload, e.g. `{date}/{uuid}/{filename}`
+  pub key_template: Option<String>,
+  /// Prefix of the objects searched for duplicates before uploads
+  pub dedup_prefix: Option<String>,
 }
 
 impl S3Configuration {
@@ -20,7 +190,42 @@ impl S3Configuration {
     Region::from_str(region).map(|region| Self {
       access_key_id: access_key_id.to_string(),
       secret_access_key: secret_access_key.to_string(),
+      session_token: None,
+      credentials_expiration: None,
       region,
+      access_tagging: None,
+      transfer_acceleration: false,
+      acceleration_statuses: AccelerationStatuses::default(),
+      ca_bundle: None,
+      insecure_skip_verify: false,
+      endpoint_overrides: vec![],
+      jwt_validator: None,
+      policies: None,
+      allowed_buckets: None,
+      denied_buckets: vec![],
+      max_key_length: DEFAULT_MAX_KEY_LENGTH,
+      max_json_body_size: DEFAULT_MAX_JSON_BODY_SIZE,
+      hypermedia: false,
+      presigned_url_expiration: presign::DEFAULT_EXPIRATION,
+      share_presets: default_share_presets(),
+      key_template: None,
+      dedup_prefix: None,
+      download_links: None,
+      health_check_bucket: None,
+      concurrency_limit: None,
+      retry_policy: RetryPolicy::default(),
+      listing_cache: None,
+      quotas: None,
+      jobs: Jobs::default(),
+      tenancy: None,
+      uppy_bucket: None,
+      bucket_regions: None,
+      bucket_configurations: vec![],
+      s3_client: SharedS3Client::default(),
+      secondary_region: None,
+      secondary_timeout: DEFAULT_SECONDARY_TIMEOUT,
+      secondary_s3_client: SharedS3Client::default(),
+      live: None,
     })
   }
 
@@ -29,19 +234,483 @@ impl S3Configuration {
     secret_access_key: &str,
     region: &str,
     hostname: &str,
-  ) -> Self {
+  ) -> Result<Self, S3ConfigurationError> {
     let region = Region::Custom {
       name: region.to_string(),
-      endpoint: hostname.to_string(),
+      endpoint: normalize_endpoint(hostname)?,
     };
 
-    Self {
+    Ok(Self {
       access_key_id: access_key_id.to_string(),
       secret_access_key: secret_access_key.to_string(),
+      session_token: None,
+      credentials_expiration: None,
       region,
+      access_tagging: None,
+      transfer_acceleration: false,
+      acceleration_statuses: AccelerationStatuses::default(),
+      ca_bundle: None,
+      insecure_skip_verify: false,
+      endpoint_overrides: vec![],
+      jwt_validator: None,
+      policies: None,
+      allowed_buckets: None,
+      denied_buckets: vec![],
+      max_key_length: DEFAULT_MAX_KEY_LENGTH,
+      max_json_body_size: DEFAULT_MAX_JSON_BODY_SIZE,
+      hypermedia: false,
+      presigned_url_expiration: presign::DEFAULT_EXPIRATION,
+      share_presets: default_share_presets(),
+      key_template: None,
+      dedup_prefix: None,
+      download_links: None,
+      health_check_bucket: None,
+      concurrency_limit: None,
+      retry_policy: RetryPolicy::default(),
+      listing_cache: None,
+      quotas: None,
+      jobs: Jobs::default(),
+      tenancy: None,
+      uppy_bucket: None,
+      bucket_regions: None,
+      bucket_configurations: vec![],
+      s3_client: SharedS3Client::default(),
+      secondary_region: None,
+      secondary_timeout: DEFAULT_SECONDARY_TIMEOUT,
+      secondary_s3_client: SharedS3Client::default(),
+      live: None,
+    })
+  }
+
+  pub fn with_access_tagging(mut self, access_tagging: AccessTagging) -> Self {
+    self.access_tagging = Some(access_tagging);
+    self
+  }
+
+  pub fn with_transfer_acceleration(mut self, transfer_acceleration: bool) -> Self {
+    self.transfer_acceleration = transfer_acceleration;
+    self
+  }
+
+  /// Trusts the certificates of a PEM bundle, in addition to the system ones.
+  pub fn with_ca_bundle(mut self, ca_bundle: Vec<u8>) -> Result<Self, native_tls::Error> {
+    parse_ca_bundle(&ca_bundle)?;
+    self.ca_bundle = Some(ca_bundle);
+    self.s3_client = SharedS3Client::default();
+    Ok(self)
+  }
+
+  /// Authenticates with temporary credentials, e.g. from STS, valid until `expiration` if known:
+  /// the pre-signed URLs then expire with them at the latest.
+  pub fn with_session_token(mut self, session_token: &str, expiration: Option<SystemTime>) -> Self {
+    self.session_token = Some(session_token.to_string());
+    self.credentials_expiration = expiration;
+    self.s3_client = SharedS3Client::default();
+    self
+  }
+
+  /// Disables the validation of the storage certificates and hostnames.
+  pub fn with_insecure_skip_verify(mut self, insecure_skip_verify: bool) -> Self {
+    self.insecure_skip_verify = insecure_skip_verify;
+    self.s3_client = SharedS3Client::default();
+    self
+  }
+
+  /// Requires requests to carry a bearer token validated by the given validator.
+  pub fn with_jwt_validator(mut self, jwt_validator: JwtValidator) -> Self {
+    self.jwt_validator = Some(jwt_validator);
+    self
+  }
+
+  /// Restricts the buckets, prefixes and operations allowed to each caller.
+  pub fn with_policies(mut self, policies: Vec<Policy>) -> Self {
+    self.policies = Some(policies);
+    self
+  }
+
+  /// Restricts requests to buckets (and prefixes) matching one of the `bucket[/prefix]` patterns.
+  pub fn with_allowed_buckets(mut self, allowed_buckets: Vec<String>) -> Self {
+    self.allowed_buckets = Some(allowed_buckets);
+    self
+  }
+
+  /// Rejects requests to buckets (and prefixes) matching one of the `bucket[/prefix]` patterns.
+  pub fn with_denied_buckets(mut self, denied_buckets: Vec<String>) -> Self {
+    self.denied_buckets = denied_buckets;
+    self
+  }
+
+  /// Sets the maximum length of the signed object keys, in bytes.
+  pub fn with_max_key_length(mut self, max_key_length: usize) -> Self {
+    self.max_key_length = max_key_length;
+    self
+  }
+
+  /// Sets the maximum size of the JSON request bodies, in bytes, larger ones being rejected before
+  /// they are read.
+  pub fn with_max_json_body_size(mut self, max_json_body_size: u64) -> Self {
+    self.max_json_body_size = max_json_body_size;
+    self
+  }
+
+  /// Answers the listings and the pre-signed URLs as HAL documents, with the links to the related
+  /// requests.
+  pub fn with_hypermedia(mut self, hypermedia: bool) -> Self {
+    self.hypermedia = hypermedia;
+    self
+  }
+
+  /// Lets the requests target another endpoint with their `X-S3-Endpoint` header, e.g.
+  /// to point the signer at ephemeral storages during test runs (for development only), provided
+  /// its host matches one of the patterns, e.g. `minio-*`.
+  pub fn with_endpoint_overrides(mut self, host_patterns: Vec<String>) -> Self {
+    self.endpoint_overrides = host_patterns;
+    self
+  }
+
+  /// Sets the lifetime of the pre-signed URLs, one hour by default.
+  pub fn with_presigned_url_expiration(mut self, presigned_url_expiration: Duration) -> Self {
+    self.presigned_url_expiration = presigned_url_expiration;
+    self
+  }
+
+  /// Replaces the named lifetimes of the share URLs, `preview`, `share` and `archive` by
+  /// default, which cannot exceed the 7 days accepted by S3.
+  pub fn with_share_presets(
+    mut self,
+    share_presets: BTreeMap<String, Duration>,
+  ) -> Result<Self, S3ConfigurationError> {
+    if let Some((name, _)) = share_presets
+      .iter()
+      .find(|(_, expiration)| expiration.is_zero() || **expiration > presign::MAX_EXPIRATION)
+    {
+      return Err(S3ConfigurationError::InvalidSharePreset(format!(
+        "{} must last between 1 second and 7 days",
+        name
+      )));
+    }
+    self.share_presets = share_presets;
+    Ok(self)
+  }
+
+  /// Generates the keys of the uploaded objects from a template instead of accepting the ones
+  /// requested by the clients.
+  pub fn with_key_template(mut self, key_template: KeyTemplate) -> Self {
+    self.key_template = Some(key_template);
+    self
+  }
+
+  /// Issues download links proxied by the signer instead of pre-signed GET URLs.
+  pub fn with_download_links(mut self, download_links: DownloadLinks) -> Self {
+    self.download_links = Some(download_links);
+    self
+  }
+
+  /// Checks that this bucket can be reached when probing the readiness of the signer.
+  pub fn with_health_check_bucket(mut self, health_check_bucket: String) -> Self {
+    self.health_check_bucket = Some(health_check_bucket);
+    self
+  }
+
+  /// Limits the number of concurrent S3 listings and multipart upload creations and completions.
+  pub fn with_concurrency_limit(mut self, concurrency_limit: ConcurrencyLimit) -> Self {
+    self.concurrency_limit = Some(concurrency_limit);
+    self
+  }
+
+  /// Retries the S3 calls failing transiently, see [`RetryPolicy`].
+  pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+    self.retry_policy = retry_policy;
+    self
+  }
+
+  /// Caches the object listings in memory, see [`ListingCache`].
+  pub fn with_listing_cache(mut self, listing_cache: ListingCache) -> Self {
+    self.listing_cache = Some(listing_cache);
+    self
+  }
+
+  /// Rejects the uploads under the prefixes whose quota is exceeded, see [`Quotas`].
+  pub fn with_quotas(mut self, quotas: Quotas) -> Self {
+    self.quotas = Some(quotas);
+    self
+  }
+
+  /// Keeps the background jobs in another store than memory, see [`Jobs`].
+  pub fn with_jobs(mut self, jobs: Jobs) -> Self {
+    self.jobs = jobs;
+    self
+  }
+
+  /// Scopes the keys of the requests under the prefix of their tenant, see [`Tenancy`].
+  pub fn with_tenancy(mut self, tenancy: Tenancy) -> Self {
+    self.tenancy = Some(tenancy);
+    self
+  }
+
+  /// Serves the multipart upload endpoints expected by Uppy, uploading to this bucket.
+  pub fn with_uppy_bucket(mut self, uppy_bucket: String) -> Self {
+    self.uppy_bucket = Some(uppy_bucket);
+    self
+  }
+
+  /// Serves the deduplication endpoint, searching the objects under this prefix for the content
+  /// about to be uploaded.
+  pub fn with_dedup_prefix(mut self, dedup_prefix: String) -> Self {
+    self.dedup_prefix = Some(dedup_prefix);
+    self
+  }
+
+  /// Signs the URLs for the region of their bucket, looked up with GetBucketLocation, see
+  /// [`BucketRegions`].
+  pub fn with_bucket_regions(mut self, bucket_regions: BucketRegions) -> Self {
+    self.bucket_regions = Some(bucket_regions);
+    self
+  }
+
+  /// Fails the multipart upload and listing calls over to the storage at this hostname (e.g. the
+  /// passive node of an active/passive pair), when the primary one cannot be reached, answers
+  /// with a server error or times out.
+  pub fn with_secondary_hostname(mut self, hostname: &str) -> Result<Self, S3ConfigurationError> {
+    self.secondary_region = Some(Region::Custom {
+      name: self.region.name().to_string(),
+      endpoint: normalize_endpoint(hostname)?,
+    });
+    Ok(self)
+  }
+
+  /// Sets the duration after which calls to the primary storage fail over to the secondary one,
+  /// 10 seconds by default.
+  pub fn with_secondary_timeout(mut self, secondary_timeout: Duration) -> Self {
+    self.secondary_timeout = secondary_timeout;
+    self
+  }
+
+  pub fn with_bucket_configurations(
+    mut self,
+    bucket_configurations: Vec<BucketConfiguration>,
+  ) -> Result<Self, S3ConfigurationError> {
+    for bucket_configuration in bucket_configurations {
+      let region = match (&bucket_configuration.hostname, &bucket_configuration.region) {
+        (Some(hostname), region) => Region::Custom {
+          name: region
+            .clone()
+            .unwrap_or_else(|| self.region.name().to_string()),
+          endpoint: normalize_endpoint(hostname)?,
+        },
+        (None, Some(region)) => {
+          Region::from_str(region).map_err(S3ConfigurationError::InvalidRegion)?
+        }
+        (None, None) => self.region.clone(),
+      };
+
+      // A secondary storage only makes sense along with the storage it mirrors.
+      let secondary_region = match (
+        &bucket_configuration.hostname,
+        &bucket_configuration.secondary_hostname,
+      ) {
+        (None, _) => self.secondary_region.clone(),
+        (Some(_), None) => None,
+        (Some(_), Some(secondary_hostname)) => Some(Region::Custom {
+          name: region.name().to_string(),
+          endpoint: normalize_endpoint(secondary_hostname)?,
+        }),
+      };
+
+      let key_template = match &bucket_configuration.key_template {
+        Some(key_template) => Some(
+          key_template
+            .parse()
+            .map_err(S3ConfigurationError::InvalidKeyTemplate)?,
+        ),
+        None => self.key_template.clone(),
+      };
+
+      // The temporary credentials only go with their own access key.
+      let (session_token, credentials_expiration) = match bucket_configuration.access_key_id {
+        Some(_) => (None, None),
+        None => (self.session_token.clone(), self.credentials_expiration),
+      };
+
+      let s3_configuration = Self {
+        access_key_id: bucket_configuration
+          .access_key_id
+          .unwrap_or_else(|| self.access_key_id.clone()),
+        secret_access_key: bucket_configuration
+          .secret_access_key
+          .unwrap_or_else(|| self.secret_access_key.clone()),
+        session_token,
+        credentials_expiration,
+        region,
+        access_tagging: self.access_tagging.clone(),
+        transfer_acceleration: bucket_configuration
+          .transfer_acceleration
+          .unwrap_or(self.transfer_acceleration),
+        ca_bundle: self.ca_bundle.clone(),
+        insecure_skip_verify: self.insecure_skip_verify,
+        endpoint_overrides: self.endpoint_overrides.clone(),
+        jwt_validator: self.jwt_validator.clone(),
+        policies: self.policies.clone(),
+        allowed_buckets: self.allowed_buckets.clone(),
+        denied_buckets: self.denied_buckets.clone(),
+        max_key_length: self.max_key_length,
+        max_json_body_size: self.max_json_body_size,
+        hypermedia: self.hypermedia,
+        presigned_url_expiration: self.presigned_url_expiration,
+        share_presets: self.share_presets.clone(),
+        key_template,
+        dedup_prefix: bucket_configuration
+          .dedup_prefix
+          .or_else(|| self.dedup_prefix.clone()),
+        download_links: self.download_links.clone(),
+        health_check_bucket: self.health_check_bucket.clone(),
+        concurrency_limit: self.concurrency_limit.clone(),
+        retry_policy: self.retry_policy,
+        listing_cache: self.listing_cache.clone(),
+        quotas: self.quotas.clone(),
+        jobs: self.jobs.clone(),
+        tenancy: self.tenancy.clone(),
+        uppy_bucket: self.uppy_bucket.clone(),
+        bucket_regions: self.bucket_regions.clone(),
+        acceleration_statuses: AccelerationStatuses::default(),
+        bucket_configurations: vec![],
+        s3_client: SharedS3Client::default(),
+        secondary_region,
+        secondary_timeout: self.secondary_timeout,
+        secondary_s3_client: SharedS3Client::default(),
+        live: None,
+      };
+
+      self
+        .bucket_configurations
+        .push((bucket_configuration.bucket, s3_configuration));
+    }
+
+    Ok(self)
+  }
+
+  /// Makes the configuration reloadable: routes built from it serve the configuration last set
+  /// with [`S3Configuration::reload`], without being rebuilt.
+  pub fn reloadable(mut self) -> Self {
+    self.live = Some(Arc::new(RwLock::new(Arc::new(self.clone()))));
+    self
+  }
+
+  /// Atomically replaces the configuration served by the routes built from this one, if reloadable.
+  ///
+  /// The jobs are kept, to answer the status of the ones submitted before. So are the concurrency
+  /// limit, with the calls in flight, and the listing cache, unless reconfigured, and the usage of
+  /// the quotas, whatever their limits.
+  pub fn reload(&self, s3_configuration: S3Configuration) {
+    if let Some(live) = &self.live {
+      let mut current = live.write().unwrap_or_else(|error| error.into_inner());
+      let concurrency_limit = match (
+        &current.concurrency_limit,
+        s3_configuration.concurrency_limit.clone(),
+      ) {
+        (Some(current), Some(reloaded)) => Some(current.reloaded(reloaded)),
+        (_, reloaded) => reloaded,
+      };
+      let listing_cache = match (
+        &current.listing_cache,
+        s3_configuration.listing_cache.clone(),
+      ) {
+        (Some(current), Some(reloaded)) => Some(current.reloaded(reloaded)),
+        (_, reloaded) => reloaded,
+      };
+      let quotas = match (&current.quotas, s3_configuration.quotas.clone()) {
+        (Some(current), Some(reloaded)) => Some(current.reloaded(reloaded)),
+        (_, reloaded) => reloaded,
+      };
+
+      // Shared by the per-bucket configurations, as when configured
+      let kept = |s3_configuration: S3Configuration| S3Configuration {
+        live: None,
+        jobs: current.jobs.clone(),
+        concurrency_limit: concurrency_limit.clone(),
+        listing_cache: listing_cache.clone(),
+        quotas: quotas.clone(),
+        ..s3_configuration
+      };
+      let bucket_configurations = s3_configuration
+        .bucket_configurations
+        .iter()
+        .map(|(bucket, bucket_configuration)| (bucket.clone(), kept(bucket_configuration.clone())))
+        .collect();
+      let reloaded = S3Configuration {
+        bucket_configurations,
+        ..kept(s3_configuration)
+      };
+      *current = Arc::new(reloaded);
     }
   }
 
+  /// Returns the configuration to serve a request with, i.e. the last reloaded one if reloadable,
+  /// shared rather than copied.
+  pub fn current(&self) -> Arc<S3Configuration> {
+    match &self.live {
+      Some(live) => live
+        .read()
+        .unwrap_or_else(|error| error.into_inner())
+        .clone(),
+      None => Arc::new(self.clone()),
+    }
+  }
+
+  /// Returns the configuration targeting another endpoint for every bucket, with the same
+  /// credentials and region name.
+  ///
+  /// The endpoint must be valid, and its host allowed by the [endpoint
+  /// overrides](S3Configuration::with_endpoint_overrides), for the signer not to send signed
+  /// requests to arbitrary hosts. The per-bucket configurations, the secondary storage, the
+  /// detection of the bucket regions and the listing cache, which would mix up the objects of both
+  /// storages, are left out.
+  pub(crate) fn with_endpoint(&self, hostname: &str) -> Result<S3Configuration, Error> {
+    let endpoint =
+      normalize_endpoint(hostname).map_err(|error| Error::InvalidParameter(error.to_string()))?;
+    let host = endpoint
+      .parse::<Uri>()
+      .ok()
+      .and_then(|uri| uri.host().map(str::to_string))
+      .unwrap_or_default();
+    if !self
+      .endpoint_overrides
+      .iter()
+      .any(|pattern| matches_pattern(pattern, &host))
+    {
+      return Err(Error::Forbidden(format!(
+        "the endpoint {} is not allowed by --allow-endpoint-override",
+        endpoint
+      )));
+    }
+
+    Ok(S3Configuration {
+      region: Region::Custom {
+        name: self.region.name().to_string(),
+        endpoint,
+      },
+      bucket_regions: None,
+      acceleration_statuses: AccelerationStatuses::default(),
+      listing_cache: None,
+      bucket_configurations: vec![],
+      s3_client: SharedS3Client::default(),
+      secondary_region: None,
+      secondary_s3_client: SharedS3Client::default(),
+      live: None,
+      ..self.clone()
+    })
+  }
+
+  /// Returns the configuration to use for the given bucket.
+  pub fn for_bucket(&self, bucket: &str) -> S3Configuration {
+    self
+      .bucket_configurations
+      .iter()
+      .find(|(pattern, _)| matches_pattern(pattern, bucket))
+      .map(|(_, s3_configuration)| s3_configuration.clone())
+      .unwrap_or_else(|| self.clone())
+  }
+
   pub fn access_key_id(&self) -> &String {
     &self.access_key_id
   }
@@ -50,9 +719,303 @@ impl S3Configuration {
     &self.secret_access_key
   }
 
+  pub fn session_token(&self) -> Option<&String> {
+    self.session_token.as_ref()
+  }
+
+  /// Expiration of the temporary credentials, if known.
+  pub fn credentials_expiration(&self) -> Option<SystemTime> {
+    self.credentials_expiration
+  }
+
   pub fn region(&self) -> &Region {
     &self.region
   }
+
+  pub fn access_tagging(&self) -> Option<&AccessTagging> {
+    self.access_tagging.as_ref()
+  }
+
+  pub fn transfer_acceleration(&self) -> bool {
+    self.transfer_acceleration
+  }
+
+  pub(crate) fn acceleration_statuses(&self) -> &AccelerationStatuses {
+    &self.acceleration_statuses
+  }
+
+  pub fn jwt_validator(&self) -> Option<&JwtValidator> {
+    self.jwt_validator.as_ref()
+  }
+
+  pub fn policies(&self) -> Option<&Vec<Policy>> {
+    self.policies.as_ref()
+  }
+
+  pub fn download_links(&self) -> Option<&DownloadLinks> {
+    self.download_links.as_ref()
+  }
+
+  pub fn health_check_bucket(&self) -> Option<&String> {
+    self.health_check_bucket.as_ref()
+  }
+
+  pub fn concurrency_limit(&self) -> Option<&ConcurrencyLimit> {
+    self.concurrency_limit.as_ref()
+  }
+
+  pub fn retry_policy(&self) -> RetryPolicy {
+    self.retry_policy
+  }
+
+  pub fn listing_cache(&self) -> Option<&ListingCache> {
+    self.listing_cache.as_ref()
+  }
+
+  pub fn quotas(&self) -> Option<&Quotas> {
+    self.quotas.as_ref()
+  }
+
+  pub fn jobs(&self) -> &Jobs {
+    &self.jobs
+  }
+
+  pub fn tenancy(&self) -> Option<&Tenancy> {
+    self.tenancy.as_ref()
+  }
+
+  pub fn uppy_bucket(&self) -> Option<&String> {
+    self.uppy_bucket.as_ref()
+  }
+
+  pub fn dedup_prefix(&self) -> Option<&String> {
+    self.dedup_prefix.as_ref()
+  }
+
+  pub fn bucket_regions(&self) -> Option<&BucketRegions> {
+    self.bucket_regions.as_ref()
+  }
+
+  /// Returns the credentials and region to pre-sign the URLs of a bucket with, given the
+  /// configuration of this bucket: its region is detected if enabled.
+  pub async fn presign_configuration(&self, bucket: &str) -> PresignConfiguration {
+    let presign_configuration = PresignConfiguration::from(self);
+    match &self.bucket_regions {
+      Some(bucket_regions) => {
+        presign_configuration.with_region(bucket_regions.region(self, bucket).await)
+      }
+      None => presign_configuration,
+    }
+  }
+
+  pub fn secondary_timeout(&self) -> Duration {
+    self.secondary_timeout
+  }
+
+  /// Returns the client of the secondary storage, if any, shared like the primary one.
+  pub(crate) fn secondary_s3_client(&self) -> Option<Result<S3Client, Error>> {
+    let region = self.secondary_region.as_ref()?;
+    if let Some(client) = self.secondary_s3_client.0.get() {
+      return Some(Ok(client.clone()));
+    }
+
+    Some(
+      new_s3_client(self, region.clone())
+        .map(|client| self.secondary_s3_client.0.get_or_init(|| client).clone()),
+    )
+  }
+
+  pub fn max_key_length(&self) -> usize {
+    self.max_key_length
+  }
+
+  pub fn max_json_body_size(&self) -> u64 {
+    self.max_json_body_size
+  }
+
+  /// Patterns of the hosts which the requests may target instead of the configured endpoint.
+  pub fn endpoint_overrides(&self) -> &[String] {
+    &self.endpoint_overrides
+  }
+
+  pub fn hypermedia(&self) -> bool {
+    self.hypermedia
+  }
+
+  pub fn presigned_url_expiration(&self) -> Duration {
+    self.presigned_url_expiration
+  }
+
+  /// Lifetimes of the share URLs, by preset name.
+  pub fn share_presets(&self) -> &BTreeMap<String, Duration> {
+    &self.share_presets
+  }
+
+  /// Template of the keys generated on upload, if any.
+  pub fn key_template(&self) -> Option<&KeyTemplate> {
+    self.key_template.as_ref()
+  }
+
+  pub fn allowed_buckets(&self) -> Option<&Vec<String>> {
+    self.allowed_buckets.as_ref()
+  }
+
+  /// Names of the buckets set by the configuration, patterns excluded: the health check and Uppy
+  /// buckets, then the allowed buckets and the buckets of the per-bucket configurations.
+  pub fn configured_buckets(&self) -> Vec<String> {
+    let allowed_buckets = self
+      .allowed_buckets
+      .iter()
+      .flatten()
+      .map(|pattern| pattern.split('/').next().unwrap_or_default());
+    let bucket_configurations = self
+      .bucket_configurations
+      .iter()
+      .map(|(pattern, _)| pattern.as_str());
+
+    let mut buckets: Vec<String> = vec![];
+    for bucket in self
+      .health_check_bucket
+      .iter()
+      .chain(&self.uppy_bucket)
+      .map(String::as_str)
+      .chain(allowed_buckets)
+      .chain(bucket_configurations)
+    {
+      if !bucket.is_empty() && !bucket.contains('*') && !buckets.iter().any(|known| known == bucket)
+      {
+        buckets.push(bucket.to_string());
+      }
+    }
+    buckets
+  }
+
+  /// Returns whether the key of the bucket is allowed and not denied.
+  pub fn is_bucket_allowed(&self, bucket: &str, key: &str) -> bool {
+    let allowed = self
+      .allowed_buckets
+      .as_ref()
+      .map(|allowed_buckets| {
+        allowed_buckets
+          .iter()
+          .any(|pattern| matches_bucket_pattern(pattern, bucket, key))
+      })
+      .unwrap_or(true);
+
+    allowed
+      && !self
+        .denied_buckets
+        .iter()
+        .any(|pattern| matches_bucket_pattern(pattern, bucket, key))
+  }
+}
+
+impl S3Configuration {
+  fn tls_connector(&self) -> Result<TlsConnector, native_tls::Error> {
+    let mut builder = TlsConnector::builder();
+
+    if let Some(ca_bundle) = &self.ca_bundle {
+      for certificate in parse_ca_bundle(ca_bundle)? {
+        builder.add_root_certificate(certificate);
+      }
+    }
+
+    if self.insecure_skip_verify {
+      builder
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true);
+    }
+
+    builder.build()
+  }
+}
+
+/// Validates a custom endpoint and normalizes it to a `scheme://host[:port]` URL.
+///
+/// Endpoints without scheme default to HTTPS, only `http` and `https` schemes are accepted. Paths
+/// are rejected: the signer addresses buckets from the root of the endpoint.
+fn normalize_endpoint(hostname: &str) -> Result<String, S3ConfigurationError> {
+  let hostname = hostname.trim();
+  let endpoint = if hostname.contains("://") {
+    hostname.to_string()
+  } else {
+    format!("https://{}", hostname)
+  };
+
+  let uri = endpoint
+    .parse::<Uri>()
+    .map_err(|error| S3ConfigurationError::InvalidEndpoint(format!("{}: {}", hostname, error)))?;
+
+  match uri.scheme_str() {
+    Some("http") | Some("https") => {}
+    _ => {
+      return Err(S3ConfigurationError::InvalidEndpoint(format!(
+        "{}: only http and https schemes are supported",
+        hostname
+      )))
+    }
+  }
+
+  if uri.host().map(str::is_empty).unwrap_or(true) {
+    return Err(S3ConfigurationError::InvalidEndpoint(format!(
+      "{}: missing host name",
+      hostname
+    )));
+  }
+
+  if uri.query().is_some() {
+    return Err(S3ConfigurationError::InvalidEndpoint(format!(
+      "{}: query strings are not supported",
+      hostname
+    )));
+  }
+
+  if !matches!(uri.path(), "" | "/") {
+    return Err(S3ConfigurationError::InvalidEndpoint(format!(
+      "{}: paths are not supported",
+      hostname
+    )));
+  }
+
+  Ok(format!(
+    "{}://{}",
+    uri.scheme_str().unwrap_or_default().to_lowercase(),
+    uri
+      .authority()
+      .map(|authority| authority.as_str())
+      .unwrap_or_default()
+      .to_lowercase(),
+  ))
+}
+
+fn parse_ca_bundle(ca_bundle: &[u8]) -> Result<Vec<Certificate>, native_tls::Error> {
+  String::from_utf8_lossy(ca_bundle)
+    .split_inclusive(END_CERTIFICATE)
+    .filter(|block| block.contains(BEGIN_CERTIFICATE))
+    .map(|block| Certificate::from_pem(block.as_bytes()))
+    .collect()
+}
+
+pub(crate) fn matches_pattern(pattern: &str, bucket: &str) -> bool {
+  match pattern.split_once('*') {
+    None => pattern == bucket,
+    Some((prefix, remaining_pattern)) => {
+      bucket.starts_with(prefix)
+        && (prefix.len()..=bucket.len())
+          .filter(|index| bucket.is_char_boundary(*index))
+          .any(|index| matches_pattern(remaining_pattern, &bucket[index..]))
+    }
+  }
+}
+
+fn matches_bucket_pattern(pattern: &str, bucket: &str, key: &str) -> bool {
+  match pattern.split_once('/') {
+    None => matches_pattern(pattern, bucket),
+    Some((bucket_pattern, prefix_pattern)) => {
+      matches_pattern(bucket_pattern, bucket)
+        && matches_pattern(&format!("{}*", prefix_pattern), key)
+    }
+  }
 }
 
 impl From<&S3Configuration> for AwsCredentials {
@@ -66,20 +1029,265 @@ impl From<&S3Configuration> for AwsCredentials {
   }
 }
 
+impl From<&S3Configuration> for PresignConfiguration {
+  fn from(s3_configuration: &S3Configuration) -> Self {
+    let presign_configuration = Self::new(
+      &s3_configuration.access_key_id,
+      &s3_configuration.secret_access_key,
+      s3_configuration.region.clone(),
+    );
+    match &s3_configuration.session_token {
+      Some(session_token) => presign_configuration
+        .with_session_token(session_token, s3_configuration.credentials_expiration),
+      None => presign_configuration,
+    }
+  }
+}
+
+fn default_share_presets() -> BTreeMap<String, Duration> {
+  DEFAULT_SHARE_PRESETS
+    .iter()
+    .map(|(name, seconds)| (name.to_string(), Duration::from_secs(*seconds)))
+    .collect()
+}
+
+impl From<&S3Configuration> for PresignOptions {
+  fn from(s3_configuration: &S3Configuration) -> Self {
+    PresignOptions::default().with_expiration(s3_configuration.presigned_url_expiration)
+  }
+}
+
 impl TryFrom<&S3Configuration> for S3Client {
-  type Error = TlsError;
+  type Error = Error;
 
+  /// Returns the client shared by the requests served with this configuration, built on the first
+  /// call.
   fn try_from(s3_configuration: &S3Configuration) -> Result<Self, Self::Error> {
-    let http_client = HttpClient::new()?;
-    let client = S3Client::new_with(
-      http_client,
-      StaticProvider::new_minimal(
-        s3_configuration.access_key_id.clone(),
-        s3_configuration.secret_access_key.clone(),
-      ),
-      s3_configuration.region.clone(),
+    if let Some(client) = s3_configuration.s3_client.0.get() {
+      return Ok(client.clone());
+    }
+
+    let client = new_s3_client(s3_configuration, s3_configuration.region.clone())?;
+    Ok(s3_configuration.s3_client.0.get_or_init(|| client).clone())
+  }
+}
+
+impl TryFrom<&S3Configuration> for Client {
+  type Error = Error;
+
+  /// Returns a client signing and dispatching raw requests, for the S3 operations which the
+  /// `S3Client` does not implement, e.g. SelectObjectContent.
+  fn try_from(s3_configuration: &S3Configuration) -> Result<Self, Self::Error> {
+    new_client(s3_configuration)
+  }
+}
+
+fn new_s3_client(s3_configuration: &S3Configuration, region: Region) -> Result<S3Client, Error> {
+  Ok(S3Client::new_with_client(
+    new_client(s3_configuration)?,
+    region,
+  ))
+}
+
+fn new_client(s3_configuration: &S3Configuration) -> Result<Client, Error> {
+  let tls_connector = s3_configuration
+    .tls_connector()
+    .map_err(Error::S3ConnectionError)?;
+
+  let mut http_connector = HttpConnector::new();
+  http_connector.enforce_http(false);
+
+  let http_client =
+    HttpClient::from_connector(HttpsConnector::from((http_connector, tls_connector.into())));
+  let client = Client::new_with(
+    StaticProvider::new(
+      s3_configuration.access_key_id.clone(),
+      s3_configuration.secret_access_key.clone(),
+      s3_configuration.session_token.clone(),
+      None,
+    ),
+    http_client,
+  );
+
+  Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn configuration() -> S3Configuration {
+    S3Configuration::new_with_hostname("access", "secret", "us-east-1", "https://s3.example.com")
+      .unwrap()
+  }
+
+  #[test]
+  fn current_configuration_is_shared() {
+    let s3_configuration = configuration().reloadable();
+    assert!(Arc::ptr_eq(
+      &s3_configuration.current(),
+      &s3_configuration.current()
+    ));
+
+    s3_configuration.reload(configuration().with_uppy_bucket("media".to_string()));
+    assert_eq!(
+      s3_configuration.current().uppy_bucket().map(String::as_str),
+      Some("media")
+    );
+  }
+
+  #[test]
+  fn reload_keeps_the_calls_in_flight_and_the_cached_listings() {
+    let limited = |max_concurrent_calls| {
+      configuration()
+        .with_concurrency_limit(ConcurrencyLimit::new(
+          max_concurrent_calls,
+          Duration::from_secs(1),
+        ))
+        .with_listing_cache(ListingCache::new(Duration::from_secs(60), 10))
+    };
+    let s3_configuration = limited(1).reloadable();
+    let _permit = crate::concurrency_limit::acquire(&s3_configuration.current()).unwrap();
+    s3_configuration
+      .current()
+      .listing_cache()
+      .unwrap()
+      .insert("bucket", "site/", vec![]);
+
+    s3_configuration.reload(limited(1));
+    let current = s3_configuration.current();
+    assert!(crate::concurrency_limit::acquire(&current).is_err());
+    assert!(current
+      .listing_cache()
+      .unwrap()
+      .get("bucket", "site/")
+      .is_some());
+
+    // Reconfigured, they start over
+    s3_configuration
+      .reload(limited(2).with_listing_cache(ListingCache::new(Duration::from_secs(30), 10)));
+    let current = s3_configuration.current();
+    assert!(crate::concurrency_limit::acquire(&current).is_ok());
+    assert!(current
+      .listing_cache()
+      .unwrap()
+      .get("bucket", "site/")
+      .is_none());
+  }
+
+  #[test]
+  fn endpoint_keeps_its_scheme_and_port() {
+    assert_eq!(
+      normalize_endpoint("http://MinIO:9000/").unwrap(),
+      "http://minio:9000"
     );
+    assert_eq!(
+      normalize_endpoint(" https://s3.example.com ").unwrap(),
+      "https://s3.example.com"
+    );
+  }
+
+  #[test]
+  fn endpoint_defaults_to_https() {
+    assert_eq!(
+      normalize_endpoint("minio:9000").unwrap(),
+      "https://minio:9000"
+    );
+    assert_eq!(
+      normalize_endpoint("s3.example.com").unwrap(),
+      "https://s3.example.com"
+    );
+  }
+
+  #[test]
+  fn invalid_endpoints_are_rejected() {
+    for hostname in [
+      "ftp://minio:9000",
+      "s3://bucket",
+      "http://minio:9000/storage",
+      "http://minio:9000?region=eu",
+      "http://",
+      "",
+    ]
+    .iter()
+    {
+      assert!(
+        matches!(
+          normalize_endpoint(hostname),
+          Err(S3ConfigurationError::InvalidEndpoint(_))
+        ),
+        "{}",
+        hostname
+      );
+    }
+  }
+
+  #[test]
+  fn presigned_urls_keep_the_scheme_and_port() {
+    let s3_configuration =
+      S3Configuration::new_with_hostname("access", "secret", "us-east-1", "http://minio:9000")
+        .unwrap();
+    let url = presign::get_object(
+      &PresignConfiguration::from(&s3_configuration),
+      "bucket",
+      "folder/file.txt",
+      &PresignOptions::default(),
+    )
+    .unwrap();
+    assert!(
+      url.starts_with("http://minio:9000/bucket/folder/file.txt?"),
+      "{}",
+      url
+    );
+  }
+
+  #[test]
+  fn reload_keeps_the_quota_usage() {
+    let with_quotas = |max_bytes| {
+      configuration()
+        .with_quotas(Quotas::new(vec![crate::quotas::Quota {
+          bucket: "media".to_string(),
+          prefix: String::new(),
+          max_bytes,
+        }]))
+        .with_bucket_configurations(vec![BucketConfiguration {
+          bucket: "media".to_string(),
+          region: None,
+          hostname: Some("https://media.example.com".to_string()),
+          access_key_id: None,
+          secret_access_key: None,
+          transfer_acceleration: None,
+          secondary_hostname: None,
+          key_template: None,
+          dedup_prefix: None,
+        }])
+        .unwrap()
+    };
+    let s3_configuration = with_quotas(10).reloadable();
+    s3_configuration
+      .current()
+      .quotas()
+      .unwrap()
+      .add("media", "a.txt", 10);
+
+    s3_configuration.reload(with_quotas(10));
+    let current = s3_configuration.current();
+    assert!(current.quotas().unwrap().check("media", "b.txt").is_err());
+    let bucket_configuration = current.for_bucket("media");
+    assert!(bucket_configuration
+      .quotas()
+      .unwrap()
+      .check("media", "b.txt")
+      .is_err());
 
-    Ok(client)
+    // Raised, the usage is checked against the new limit
+    s3_configuration.reload(with_quotas(20));
+    assert!(s3_configuration
+      .current()
+      .for_bucket("media")
+      .quotas()
+      .unwrap()
+      .check("media", "b.txt")
+      .is_ok());
   }
 }
@@ -0,0 +1,602 @@
+//! Built-in maintenance tasks run by the signer on cron-like schedules, e.g. aborting the stale
+//! multipart uploads every night, with the outcome of their last run served at
+//! `GET /admin/tasks` to its administrators.
+//!
+//! The tasks run on behalf of the signer itself: the policies do not apply to them.
+
+use crate::{
+  multipart_upload::{
+    abort_batch::server::{abort_upload, list_stale_uploads},
+    lifecycle::DEFAULT_ABORT_INCOMPLETE_UPLOADS_DAYS,
+  },
+  objects::list::server::warm_listing,
+  policy::{self, Identity},
+  to_ok_json_response, Error, S3Configuration,
+};
+use chrono::{DateTime, Datelike, Duration, DurationRound, SecondsFormat, Timelike, Utc};
+use serde::Serialize;
+use std::{
+  fmt::{Debug, Display, Formatter},
+  str::FromStr,
+  sync::{Arc, Mutex},
+};
+use warp::{Filter, Rejection, Reply};
+
+/// Years searched for the next run of a schedule, long enough to reach a 29th of February
+const MAX_SCHEDULE_YEARS: i64 = 5;
+
+/// Five fields cron expression, `minute hour day-of-month month day-of-week`, in UTC.
+///
+/// Fields accept `*`, values, ranges (`1-5`), lists (`1,15`) and steps (`*/15`, `0-30/10`).
+/// Sunday is 0 or 7. As in cron, a day matches either of the day fields when both are restricted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+  expression: String,
+  minutes: u64,
+  hours: u64,
+  days: u64,
+  months: u64,
+  weekdays: u64,
+  days_restricted: bool,
+  weekdays_restricted: bool,
+}
+
+impl FromStr for Schedule {
+  type Err = String;
+
+  fn from_str(expression: &str) -> Result<Self, Self::Err> {
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    let [minutes, hours, days, months, weekdays] = fields[..] else {
+      return Err(format!(
+        "{:?} is not a schedule of 5 fields: minute hour day-of-month month day-of-week",
+        expression
+      ));
+    };
+
+    let mut weekdays_bits = parse_field(weekdays, 0, 7)?;
+    // Sunday is both 0 and 7
+    if weekdays_bits & (1 << 7) != 0 {
+      weekdays_bits = (weekdays_bits | 1) & !(1 << 7);
+    }
+    let schedule = Self {
+      expression: fields.join(" "),
+      minutes: parse_field(minutes, 0, 59)?,
+      hours: parse_field(hours, 0, 23)?,
+      days: parse_field(days, 1, 31)?,
+      months: parse_field(months, 1, 12)?,
+      weekdays: weekdays_bits,
+      days_restricted: !days.starts_with('*'),
+      weekdays_restricted: !weekdays.starts_with('*'),
+    };
+    if schedule.next_after(Utc::now()).is_none() {
+      return Err(format!("{:?} never runs", expression));
+    }
+    Ok(schedule)
+  }
+}
+
+impl Display for Schedule {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.expression)
+  }
+}
+
+impl Schedule {
+  /// First minute matching the schedule strictly after `time`, if any within a few years.
+  pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let mut next = time.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+    let limit = next + Duration::days(366 * MAX_SCHEDULE_YEARS);
+    while next < limit {
+      let midnight = next.duration_trunc(Duration::days(1)).ok()?;
+      if !has(self.months, next.month()) {
+        next = midnight + Duration::days(i64::from(days_left_in_month(next)));
+      } else if !self.matches_day(next) {
+        next = midnight + Duration::days(1);
+      } else if !has(self.hours, next.hour()) {
+        next = next.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+      } else if !has(self.minutes, next.minute()) {
+        next += Duration::minutes(1);
+      } else {
+        return Some(next);
+      }
+    }
+    None
+  }
+
+  fn matches_day(&self, time: DateTime<Utc>) -> bool {
+    let day = has(self.days, time.day());
+    let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+    if self.days_restricted && self.weekdays_restricted {
+      day || weekday
+    } else {
+      day && weekday
+    }
+  }
+}
+
+fn has(bits: u64, value: u32) -> bool {
+  bits & (1 << value) != 0
+}
+
+/// Days from `time` to the first day of the next month.
+fn days_left_in_month(time: DateTime<Utc>) -> u32 {
+  let (year, month) = match time.month() {
+    12 => (time.year() + 1, 1),
+    month => (time.year(), month + 1),
+  };
+  let first_of_next_month = chrono::NaiveDate::from_ymd_opt(year, month, 1).unwrap_or_default();
+  (first_of_next_month - time.date_naive()).num_days() as u32
+}
+
+/// Values of a cron field between `min` and `max`, as bits.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+  let invalid = || format!("invalid schedule field {:?}", field);
+  let value = |value: &str| {
+    value
+      .parse::<u32>()
+      .ok()
+      .filter(|value| (min..=max).contains(value))
+      .ok_or_else(invalid)
+  };
+
+  let mut bits = 0;
+  for part in field.split(',') {
+    let (range, step) = match part.split_once('/') {
+      Some((range, step)) => (range, Some(step.parse::<u32>().map_err(|_| invalid())?)),
+      None => (part, None),
+    };
+    let (start, end) = match range.split_once('-') {
+      _ if range == "*" => (min, max),
+      Some((start, end)) => (value(start)?, value(end)?),
+      // `5/10` runs from 5 on
+      None if step.is_some() => (value(range)?, max),
+      None => (value(range)?, value(range)?),
+    };
+    let step = step.unwrap_or(1);
+    if start > end || step == 0 {
+      return Err(invalid());
+    }
+    for value in (start..=end).step_by(step as usize) {
+      bits |= 1 << value;
+    }
+  }
+  Ok(bits)
+}
+
+/// Built-in task of the scheduler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Task {
+  /// Aborts the multipart uploads under a prefix initiated that many days ago
+  CleanupUploads {
+    bucket: String,
+    prefix: String,
+    days: i64,
+  },
+  /// Reloads the configuration, with its S3 credentials, and fetches the JWKS again
+  RefreshCredentials,
+  /// Lists a prefix into the listing cache
+  WarmListingCache { bucket: String, prefix: String },
+}
+
+impl Display for Task {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Task::CleanupUploads {
+        bucket,
+        prefix,
+        days,
+      } => write!(f, "cleanup-uploads {}/{} {}", bucket, prefix, days),
+      Task::RefreshCredentials => write!(f, "refresh-credentials"),
+      Task::WarmListingCache { bucket, prefix } => {
+        write!(f, "warm-listing-cache {}/{}", bucket, prefix)
+      }
+    }
+  }
+}
+
+impl FromStr for Task {
+  type Err = String;
+
+  /// Parses a task and its arguments, e.g. `cleanup-uploads media/uploads/ 3`.
+  fn from_str(task: &str) -> Result<Self, Self::Err> {
+    let arguments: Vec<&str> = task.split_whitespace().collect();
+    let location = |location: &str| match location.split_once('/') {
+      Some((bucket, prefix)) => (bucket.to_string(), prefix.to_string()),
+      None => (location.to_string(), String::new()),
+    };
+    match arguments[..] {
+      ["cleanup-uploads", bucket_prefix] | ["cleanup-uploads", bucket_prefix, _] => {
+        let (bucket, prefix) = location(bucket_prefix);
+        let days = match arguments.get(2) {
+          Some(days) => days
+            .parse()
+            .ok()
+            .filter(|days| *days >= 0)
+            .ok_or_else(|| format!("invalid number of days {:?}", days))?,
+          None => DEFAULT_ABORT_INCOMPLETE_UPLOADS_DAYS,
+        };
+        Ok(Task::CleanupUploads {
+          bucket,
+          prefix,
+          days,
+        })
+      }
+      ["refresh-credentials"] => Ok(Task::RefreshCredentials),
+      ["warm-listing-cache", bucket_prefix] => {
+        let (bucket, prefix) = location(bucket_prefix);
+        Ok(Task::WarmListingCache { bucket, prefix })
+      }
+      _ => Err(format!(
+        "unknown task {:?}, expected cleanup-uploads <bucket>[/<prefix>] [<days>], refresh-credentials or warm-listing-cache <bucket>[/<prefix>]",
+        task
+      )),
+    }
+  }
+}
+
+/// Task run on a schedule, e.g. `0 3 * * * cleanup-uploads media/uploads/`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScheduledTask {
+  pub schedule: Schedule,
+  pub task: Task,
+}
+
+impl FromStr for ScheduledTask {
+  type Err = String;
+
+  /// Parses the five fields of the schedule, followed by the task.
+  fn from_str(scheduled_task: &str) -> Result<Self, Self::Err> {
+    let fields: Vec<&str> = scheduled_task.split_whitespace().collect();
+    if fields.len() < 6 {
+      return Err(format!(
+        "{:?} is not a schedule followed by a task",
+        scheduled_task
+      ));
+    }
+    Ok(Self {
+      schedule: fields[..5].join(" ").parse()?,
+      task: fields[5..].join(" ").parse()?,
+    })
+  }
+}
+
+/// Status of a scheduled task, as answered by `GET /admin/tasks`.
+#[derive(Clone, Debug, Serialize, utoipa::ToSchema)]
+pub struct ScheduledTaskStatus {
+  /// Cron expression of the schedule, in UTC
+  pub schedule: String,
+  /// Task and its arguments, e.g. `cleanup-uploads media/uploads/ 7`
+  pub task: String,
+  /// Date of the next run, RFC 3339
+  pub next_run_at: Option<String>,
+  /// Outcome of the last run, if the task ran already
+  pub last_run: Option<TaskRun>,
+}
+
+#[derive(Clone, Debug, Serialize, utoipa::ToSchema)]
+pub struct TaskRun {
+  /// Start date, RFC 3339
+  pub started_at: String,
+  /// End date, RFC 3339
+  pub finished_at: String,
+  pub succeeded: bool,
+  /// What the task did, or why it failed
+  pub message: String,
+}
+
+type CredentialsRefresh = Arc<dyn Fn() -> Result<(), String> + Send + Sync>;
+
+/// Runs the scheduled tasks against the current configuration, each one after the other: the
+/// runs due while a task still runs are skipped.
+#[derive(Clone)]
+pub struct Scheduler {
+  tasks: Vec<ScheduledTask>,
+  statuses: Arc<Mutex<Vec<ScheduledTaskStatus>>>,
+  credentials_refresh: Option<CredentialsRefresh>,
+}
+
+impl Debug for Scheduler {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Scheduler")
+      .field("tasks", &self.tasks)
+      .finish()
+  }
+}
+
+impl Scheduler {
+  pub fn new(tasks: Vec<ScheduledTask>) -> Self {
+    let statuses = tasks
+      .iter()
+      .map(|scheduled_task| ScheduledTaskStatus {
+        schedule: scheduled_task.schedule.to_string(),
+        task: scheduled_task.task.to_string(),
+        next_run_at: None,
+        last_run: None,
+      })
+      .collect();
+    Self {
+      tasks,
+      statuses: Arc::new(Mutex::new(statuses)),
+      credentials_refresh: None,
+    }
+  }
+
+  /// Reloads the configuration with `refresh` on `refresh-credentials`, e.g. to read rotated
+  /// credentials again. Without it, only the JWKS is fetched again.
+  pub fn with_credentials_refresh(
+    mut self,
+    refresh: impl Fn() -> Result<(), String> + Send + Sync + 'static,
+  ) -> Self {
+    self.credentials_refresh = Some(Arc::new(refresh));
+    self
+  }
+
+  /// Spawns the runs of the tasks, with the last reloaded configuration of `s3_configuration`.
+  pub fn start(&self, s3_configuration: &S3Configuration) {
+    for (index, scheduled_task) in self.tasks.iter().enumerate() {
+      let scheduler = self.clone();
+      let scheduled_task = scheduled_task.clone();
+      let s3_configuration = s3_configuration.clone();
+      tokio::spawn(async move {
+        while let Some(next_run_at) = scheduled_task.schedule.next_after(Utc::now()) {
+          scheduler.update(index, |status| {
+            status.next_run_at = Some(rfc3339(next_run_at))
+          });
+          let delay = (next_run_at - Utc::now()).to_std().unwrap_or_default();
+          tokio::time::sleep(delay).await;
+
+          let started_at = Utc::now();
+          let outcome = scheduler.run(&s3_configuration, &scheduled_task.task).await;
+          let (succeeded, message) = match outcome {
+            Ok(message) => {
+              log::info!("Task succeeded: {}, {}", scheduled_task.task, message);
+              (true, message)
+            }
+            Err(error) => {
+              log::error!("Task failed: {}, {}", scheduled_task.task, error);
+              (false, error.public_message())
+            }
+          };
+          scheduler.update(index, |status| {
+            status.last_run = Some(TaskRun {
+              started_at: rfc3339(started_at),
+              finished_at: rfc3339(Utc::now()),
+              succeeded,
+              message,
+            })
+          });
+        }
+      });
+    }
+  }
+
+  /// Status of every task, in the order of the configuration.
+  pub fn statuses(&self) -> Vec<ScheduledTaskStatus> {
+    self
+      .statuses
+      .lock()
+      .unwrap_or_else(|error| error.into_inner())
+      .clone()
+  }
+
+  fn update(&self, index: usize, update: impl FnOnce(&mut ScheduledTaskStatus)) {
+    let mut statuses = self
+      .statuses
+      .lock()
+      .unwrap_or_else(|error| error.into_inner());
+    update(&mut statuses[index]);
+  }
+
+  /// Runs a task with the last reloaded configuration, describing what it did.
+  async fn run(&self, s3_configuration: &S3Configuration, task: &Task) -> Result<String, Error> {
+    let current = s3_configuration.current();
+    match task {
+      Task::CleanupUploads {
+        bucket,
+        prefix,
+        days,
+      } => {
+        let initiated_before = Utc::now() - Duration::days(*days);
+        let uploads = list_stale_uploads(&current, bucket, prefix, initiated_before).await?;
+        let count = uploads.len();
+        for upload in uploads {
+          abort_upload(&current, &upload).await?;
+        }
+        Ok(format!("aborted {} uploads", count))
+      }
+      Task::RefreshCredentials => {
+        if let Some(refresh) = &self.credentials_refresh {
+          refresh().map_err(Error::InvalidParameter)?;
+        }
+        // The reloaded configuration validates the tokens from then on.
+        if let Some(jwt_validator) = s3_configuration.current().jwt_validator() {
+          jwt_validator.refresh_jwks().await?;
+        }
+        Ok("credentials refreshed".to_string())
+      }
+      Task::WarmListingCache { bucket, prefix } => {
+        let count = warm_listing(&current, bucket, prefix).await?;
+        Ok(format!("cached {} objects and folders", count))
+      }
+    }
+  }
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+  time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Scheduled tasks
+#[utoipa::path(
+  get,
+  path = "/admin/tasks",
+  tag = "Health",
+  responses(
+    (status = 200, description = "Returns the scheduled tasks, with the outcome of their last run", content_type = "application/json", body = [ScheduledTaskStatus]),
+    (status = 401, description = "The caller is not authenticated"),
+    (status = 403, description = "The caller does not administer the signer"),
+  ),
+  security(("bearer_token" = []), ("api_key" = [])),
+)]
+pub(crate) fn route(
+  scheduler: &Scheduler,
+  s3_configuration: &S3Configuration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  let scheduler = scheduler.clone();
+  let s3_configuration = s3_configuration.clone();
+  warp::path!("admin" / "tasks")
+    .and(warp::get())
+    .and(policy::identity(&s3_configuration))
+    .and_then(move |identity: Identity| {
+      let s3_configuration = s3_configuration.current();
+      let statuses = scheduler.statuses();
+      async move {
+        if !policy::is_authenticated(&s3_configuration, &identity) {
+          return Err(warp::reject::custom(Error::Unauthorized(
+            "the scheduled tasks require a bearer token or an API key".to_string(),
+          )));
+        }
+        if !policy::is_admin(&s3_configuration, &identity) {
+          return Err(warp::reject::custom(Error::Forbidden(format!(
+            "{:?} does not administer the signer",
+            identity
+          ))));
+        }
+        to_ok_json_response(&statuses).map_err(warp::reject::custom)
+      }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn at(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time)
+      .unwrap()
+      .with_timezone(&Utc)
+  }
+
+  fn next_after(expression: &str, time: &str) -> String {
+    let schedule = expression.parse::<Schedule>().unwrap();
+    rfc3339(schedule.next_after(at(time)).unwrap())
+  }
+
+  fn bits(values: &[u32]) -> u64 {
+    values.iter().fold(0, |bits, value| bits | 1 << value)
+  }
+
+  #[test]
+  fn parses_values_ranges_lists_and_steps() {
+    assert_eq!(
+      parse_field("*", 1, 12),
+      Ok(bits(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]))
+    );
+    assert_eq!(parse_field("5", 0, 59), Ok(bits(&[5])));
+    assert_eq!(parse_field("1-5", 0, 6), Ok(bits(&[1, 2, 3, 4, 5])));
+    assert_eq!(parse_field("1,15", 1, 31), Ok(bits(&[1, 15])));
+    assert_eq!(parse_field("*/15", 0, 59), Ok(bits(&[0, 15, 30, 45])));
+    assert_eq!(parse_field("0-30/10", 0, 59), Ok(bits(&[0, 10, 20, 30])));
+    assert_eq!(parse_field("5/20", 0, 59), Ok(bits(&[5, 25, 45])));
+  }
+
+  #[test]
+  fn rejects_invalid_fields() {
+    for field in ["", "a", "60", "5-1", "*/0", "*/a", "1-", "-1"] {
+      assert!(parse_field(field, 0, 59).is_err(), "{:?}", field);
+    }
+    assert!("0 0 * *".parse::<Schedule>().is_err());
+    assert!("0 0 * * * *".parse::<Schedule>().is_err());
+    assert!("0 0 0 * *".parse::<Schedule>().is_err());
+  }
+
+  #[test]
+  fn sunday_is_0_or_7() {
+    let sunday = "0 0 * * 7".parse::<Schedule>().unwrap();
+    assert_eq!(sunday.weekdays, bits(&[0]));
+    assert_eq!(
+      sunday.weekdays,
+      "0 0 * * 0".parse::<Schedule>().unwrap().weekdays
+    );
+    assert_eq!(
+      "0 0 * * 5-7".parse::<Schedule>().unwrap().weekdays,
+      bits(&[0, 5, 6])
+    );
+    // 2024-01-06 is a Saturday
+    assert_eq!(
+      next_after("0 0 * * 7", "2024-01-06T12:00:00Z"),
+      "2024-01-07T00:00:00Z"
+    );
+  }
+
+  #[test]
+  fn runs_strictly_after_the_given_time() {
+    assert_eq!(
+      next_after("*/15 * * * *", "2024-01-01T10:07:30Z"),
+      "2024-01-01T10:15:00Z"
+    );
+    assert_eq!(
+      next_after("*/15 * * * *", "2024-01-01T10:15:00Z"),
+      "2024-01-01T10:30:00Z"
+    );
+    assert_eq!(
+      next_after("0 3 * * *", "2024-01-01T03:00:00Z"),
+      "2024-01-02T03:00:00Z"
+    );
+    assert_eq!(
+      next_after("*/5 8-18 * * 1-5", "2024-01-05T18:56:00Z"),
+      "2024-01-08T08:00:00Z"
+    );
+  }
+
+  #[test]
+  fn matches_either_day_field_when_both_are_restricted() {
+    // 2024-01-05 is a Friday, before the 13th
+    assert_eq!(
+      next_after("0 0 13 * 5", "2024-01-01T00:00:00Z"),
+      "2024-01-05T00:00:00Z"
+    );
+    assert_eq!(
+      next_after("0 0 13 * 5", "2024-01-12T00:00:00Z"),
+      "2024-01-13T00:00:00Z"
+    );
+    // Either field alone must match when the other one is `*`
+    assert_eq!(
+      next_after("0 0 13 * *", "2024-01-01T00:00:00Z"),
+      "2024-01-13T00:00:00Z"
+    );
+    assert_eq!(
+      next_after("0 0 * * 5", "2024-01-06T00:00:00Z"),
+      "2024-01-12T00:00:00Z"
+    );
+  }
+
+  #[test]
+  fn rolls_over_months_and_years() {
+    // February has no 31st
+    assert_eq!(
+      next_after("30 0 31 * *", "2024-01-31T01:00:00Z"),
+      "2024-03-31T00:30:00Z"
+    );
+    assert_eq!(
+      next_after("0 0 1 1 *", "2024-06-01T00:00:00Z"),
+      "2025-01-01T00:00:00Z"
+    );
+    assert_eq!(
+      next_after("59 23 31 12 *", "2024-12-31T23:59:00Z"),
+      "2025-12-31T23:59:00Z"
+    );
+    assert_eq!(
+      next_after("0 0 29 2 *", "2025-03-01T00:00:00Z"),
+      "2028-02-29T00:00:00Z"
+    );
+  }
+
+  #[test]
+  fn rejects_schedules_which_never_run() {
+    for expression in ["0 0 30 2 *", "0 0 31 4 *", "0 0 31 2,4,6,9,11 *"] {
+      let error = expression.parse::<Schedule>().unwrap_err();
+      assert!(error.contains("never runs"), "{}", error);
+    }
+  }
+}
@@ -0,0 +1,915 @@
+use crate::{
+  objects::AccessTagging, retry, BucketConfiguration, BucketRegions, ConcurrencyLimit, Cors,
+  DownloadLinks, JwtValidator, ListingCache, Policy, Quota, Quotas, RetryPolicy, S3Configuration,
+  S3ConfigurationError, ScheduledTask, Scheduler, Tenancy, AWS_REGIONS,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use std::{
+  collections::BTreeMap,
+  fmt::{Display, Formatter},
+  path::Path,
+  time::{Duration, SystemTime},
+};
+
+pub const DEFAULT_PORT: u16 = 8000;
+pub const DEFAULT_BASE_PATH: &str = "/api";
+pub const DEFAULT_DOWNLOAD_LINK_MAX_DOWNLOADS: u32 = 1;
+pub const DEFAULT_DOWNLOAD_LINK_EXPIRATION: u64 = 3600;
+pub const DEFAULT_RETRY_AFTER: u64 = 1;
+pub const DEFAULT_LISTING_CACHE_MAX_ENTRIES: usize = 10000;
+
+const DEFAULT_AWS_REGION: &str = "us-east-1";
+
+/// Settings of the signer, from the command line, the environment and a configuration file.
+///
+/// Command line arguments take precedence over environment variables, which take precedence over
+/// the configuration file. The keys of the configuration file are the names of the long options,
+/// e.g. `aws-region` or `allowed-buckets`. Flags enabled by a layer cannot be disabled by another.
+#[derive(Parser, Debug, Default, Deserialize)]
+#[clap(author, version, about, long_about = None)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+  /// Reads the settings missing from the command line and the environment from this TOML or YAML file
+  #[clap(long, value_parser, env = "CONFIG")]
+  #[serde(skip)]
+  pub config: Option<String>,
+
+  /// Sets the AWS Access Key ID
+  #[clap(long, value_parser, env = "AWS_ACCESS_KEY_ID")]
+  pub aws_access_key_id: Option<String>,
+
+  /// Sets the AWS Secret Access Key
+  #[clap(long, value_parser, env = "AWS_SECRET_ACCESS_KEY")]
+  pub aws_secret_access_key: Option<String>,
+
+  /// Sets the AWS Session Token of temporary credentials, e.g. from STS
+  #[clap(long, value_parser, env = "AWS_SESSION_TOKEN")]
+  pub aws_session_token: Option<String>,
+
+  /// Sets the expiration of the temporary credentials in RFC 3339, e.g. 2024-01-01T12:00:00Z, which the pre-signed URLs do not outlive
+  #[clap(long, value_parser, env = "AWS_CREDENTIAL_EXPIRATION")]
+  pub aws_credential_expiration: Option<String>,
+
+  /// Sets the AWS Region (falls back on AWS_DEFAULT_REGION, then us-east-1)
+  #[clap(long, value_parser, env = "AWS_REGION")]
+  pub aws_region: Option<String>,
+
+  /// Sets the AWS Hostname (required for non-AWS S3 endpoint), e.g. http://minio:9000 (defaults to HTTPS, without path)
+  #[clap(short, long, value_parser, env = "AWS_HOSTNAME")]
+  pub aws_hostname: Option<String>,
+
+  /// Sets the hostname of a secondary storage (e.g. the passive node of a pair), which multipart upload and listing calls fail over to
+  #[clap(long, value_parser, env = "AWS_SECONDARY_HOSTNAME")]
+  pub aws_secondary_hostname: Option<String>,
+
+  /// Sets the duration after which calls fail over to the secondary storage (in seconds) [default: 10]
+  #[clap(long, value_parser, env = "AWS_SECONDARY_TIMEOUT")]
+  pub aws_secondary_timeout: Option<u64>,
+
+  /// Runs the server against an in-memory S3 storage instead of the configured one, for local development
+  #[cfg(feature = "mock-s3")]
+  #[clap(long, value_parser, env = "MOCK_S3")]
+  pub mock: bool,
+
+  /// Sets the port number to server the signer [default: 8000]
+  #[clap(short, long, value_parser, env = "PORT")]
+  pub port: Option<u16>,
+
+  /// Serves the gRPC interface of the signer on this port, over HTTP/2
+  #[cfg(feature = "grpc")]
+  #[clap(long, value_parser, env = "GRPC_PORT")]
+  pub grpc_port: Option<u16>,
+
+  /// Mounts the API at this base path, e.g. /storage/v1 [default: /api]
+  #[clap(long, value_parser, env = "BASE_PATH")]
+  pub base_path: Option<String>,
+
+  /// Rejects the requests with query parameters missing from the OpenAPI documentation, and logs the responses with undocumented status codes (bodies and headers are not validated)
+  #[clap(long, value_parser, env = "STRICT_OPEN_API")]
+  pub strict_open_api: bool,
+
+  /// Serves the OpenAPI documents and the Swagger UI [default: true]
+  #[clap(long, value_parser, env = "ENABLE_DOCS")]
+  pub enable_docs: Option<bool>,
+
+  /// Requires a bearer token or an API key of the policies to get the OpenAPI documents and the Swagger UI
+  #[clap(long, value_parser, env = "DOCS_AUTH")]
+  pub docs_auth: bool,
+
+  /// Answers 504 Gateway Timeout to the requests not answered within this duration (in seconds)
+  #[clap(long, value_parser, env = "REQUEST_TIMEOUT")]
+  pub request_timeout: Option<u64>,
+
+  /// Stamps access tags on objects when GET URLs are pre-signed, at most once per interval (in seconds)
+  #[clap(long, value_parser, env = "ACCESS_TAGGING_INTERVAL")]
+  pub access_tagging_interval: Option<u64>,
+
+  /// Pre-signs URLs against the S3 Transfer Acceleration endpoint by default
+  #[clap(long, value_parser, env = "AWS_TRANSFER_ACCELERATION")]
+  pub aws_transfer_acceleration: bool,
+
+  /// Signs URLs for the region of their bucket, looked up with GetBucketLocation, instead of the configured one
+  #[clap(long, value_parser, env = "DETECT_BUCKET_REGIONS")]
+  pub detect_bucket_regions: bool,
+
+  /// Sets a PEM bundle of additional certificate authorities trusted for the S3 endpoint
+  #[clap(long, value_parser, env = "S3_CA_BUNDLE")]
+  pub s3_ca_bundle: Option<String>,
+
+  /// Disables the validation of the S3 endpoint certificate (for development only)
+  #[clap(long, value_parser, env = "S3_INSECURE_SKIP_VERIFY")]
+  pub s3_insecure_skip_verify: bool,
+
+  /// Lets the requests target another S3 endpoint with their `X-S3-Endpoint` header, provided its host matches one of these patterns, e.g. `minio-*` (for development only)
+  #[clap(
+    long,
+    value_parser,
+    env = "ALLOW_ENDPOINT_OVERRIDE",
+    use_value_delimiter = true
+  )]
+  pub allow_endpoint_override: Option<Vec<String>>,
+
+  /// Sets the JSON file mapping buckets (or patterns) to their region, hostname and credentials
+  #[clap(long, value_parser, env = "BUCKET_CONFIGURATION")]
+  pub bucket_configuration: Option<String>,
+
+  /// Allows browsers to cache CORS preflight responses for this duration (in seconds)
+  #[clap(long, value_parser, env = "CORS_MAX_AGE")]
+  pub cors_max_age: Option<u64>,
+
+  /// Requires bearer tokens signed by the keys published at this JWKS URL
+  #[clap(long, value_parser, env = "JWKS_URL")]
+  pub jwks_url: Option<String>,
+
+  /// Sets the issuer expected in bearer tokens
+  #[clap(long, value_parser, env = "JWT_ISSUER")]
+  pub jwt_issuer: Option<String>,
+
+  /// Sets the audience expected in bearer tokens
+  #[clap(long, value_parser, env = "JWT_AUDIENCE")]
+  pub jwt_audience: Option<String>,
+
+  /// Sets the algorithm of the JWKS keys published without `alg`, e.g. ES256 (defaults to RS256)
+  #[clap(long, value_parser, env = "JWT_ALGORITHM")]
+  pub jwt_algorithm: Option<String>,
+
+  /// Sets the JSON file of policies granting buckets, prefixes and operations to callers
+  #[clap(long, value_parser, env = "POLICY_FILE")]
+  pub policy_file: Option<String>,
+
+  /// Restricts requests to these buckets, as comma-separated `bucket[/prefix]` patterns
+  #[clap(
+    long,
+    value_parser,
+    env = "ALLOWED_BUCKETS",
+    use_value_delimiter = true
+  )]
+  pub allowed_buckets: Option<Vec<String>>,
+
+  /// Rejects requests to these buckets, as comma-separated `bucket[/prefix]` patterns
+  #[clap(long, value_parser, env = "DENIED_BUCKETS", use_value_delimiter = true)]
+  pub denied_buckets: Option<Vec<String>>,
+
+  /// Sets the maximum length of the signed object keys, in bytes
+  #[clap(long, value_parser, env = "MAX_KEY_LENGTH")]
+  pub max_key_length: Option<usize>,
+
+  /// Sets the maximum size of the JSON request bodies, in bytes (1 MiB by default)
+  #[clap(long, value_parser, env = "MAX_JSON_BODY_SIZE")]
+  pub max_json_body_size: Option<u64>,
+
+  /// Answers the listings and the pre-signed URLs as HAL documents, with the `_links` to the related requests
+  #[clap(long, value_parser, env = "HYPERMEDIA")]
+  pub hypermedia: bool,
+
+  /// Redirects GET requests to revocable download links proxied by the signer, instead of pre-signed URLs
+  #[clap(long, value_parser, env = "DOWNLOAD_LINKS")]
+  pub download_links: bool,
+
+  /// Sets the lifetimes of the share URLs, as comma-separated `name=seconds` presets (up to 7 days) [default: preview=900,share=86400,archive=604800]
+  #[clap(long, value_parser, env = "SHARE_PRESETS", use_value_delimiter = true)]
+  pub share_presets: Option<Vec<String>>,
+
+  /// Generates the keys of the uploaded objects from a template of `{date}`, `{uuid}` and `{filename}` placeholders, e.g. `uploads/{date}/{uuid}/{filename}`
+  #[clap(long, value_parser, env = "KEY_TEMPLATE")]
+  pub key_template: Option<String>,
+
+  /// Sets the number of times a download link can be used (0 for unlimited until revoked) [default: 1]
+  #[clap(long, value_parser, env = "DOWNLOAD_LINK_MAX_DOWNLOADS")]
+  pub download_link_max_downloads: Option<u32>,
+
+  /// Sets the duration after which download links expire (in seconds) [default: 3600]
+  #[clap(long, value_parser, env = "DOWNLOAD_LINK_EXPIRATION")]
+  pub download_link_expiration: Option<u64>,
+
+  /// Serves HTTPS with this PEM certificate chain (requires --tls-key)
+  #[clap(long, value_parser, env = "TLS_CERT")]
+  pub tls_cert: Option<String>,
+
+  /// Sets the PKCS #8 PEM private key of the TLS certificate
+  #[clap(long, value_parser, env = "TLS_KEY")]
+  pub tls_key: Option<String>,
+
+  /// Sets the bucket checked with HeadBucket by the readiness probe
+  #[clap(long, value_parser, env = "HEALTH_CHECK_BUCKET")]
+  pub health_check_bucket: Option<String>,
+
+  /// Limits the number of concurrent S3 listings and multipart upload creations and completions
+  #[clap(long, value_parser, env = "MAX_CONCURRENT_S3_CALLS")]
+  pub max_concurrent_s3_calls: Option<usize>,
+
+  /// Sets the delay suggested to clients rejected by the concurrency limit (in seconds) [default: 1]
+  #[clap(long, value_parser, env = "RETRY_AFTER")]
+  pub retry_after: Option<u64>,
+
+  /// Sets the number of retries of the S3 calls failing transiently (e.g. SlowDown) [default: 3]
+  #[clap(long, value_parser, env = "S3_MAX_RETRIES")]
+  pub s3_max_retries: Option<u32>,
+
+  /// Sets the base delay of the jittered exponential backoff between retries (in milliseconds) [default: 100]
+  #[clap(long, value_parser, env = "S3_RETRY_BASE_DELAY")]
+  pub s3_retry_base_delay: Option<u64>,
+
+  /// Sets the duration after which an attempt of an S3 call times out, to be retried (in seconds)
+  #[clap(long, value_parser, env = "S3_TIMEOUT")]
+  pub s3_timeout: Option<u64>,
+
+  /// Caches the object listings in memory for this duration (in seconds)
+  #[clap(long, value_parser, env = "LISTING_CACHE_TTL")]
+  pub listing_cache_ttl: Option<u64>,
+
+  /// Sets the maximum number of cached object listings [default: 10000]
+  #[clap(long, value_parser, env = "LISTING_CACHE_MAX_ENTRIES")]
+  pub listing_cache_max_entries: Option<usize>,
+
+  /// Rejects new uploads under the prefixes which received this many bytes, as comma-separated `bucket/prefix=bytes` quotas accounted in memory (multipart and /objects/content uploads only)
+  #[clap(long, value_parser, env = "QUOTAS", use_value_delimiter = true)]
+  pub quotas: Option<Vec<String>>,
+
+  /// Keeps the background jobs in this Redis server, e.g. redis://localhost:6379/0, for every signer to answer their status
+  #[cfg(feature = "redis-jobs")]
+  #[clap(long, value_parser, env = "JOBS_REDIS_URL")]
+  pub jobs_redis_url: Option<String>,
+
+  /// Scopes the keys of the requests under the prefix of their tenant, read from this claim of the bearer tokens
+  #[clap(long, value_parser, env = "TENANT_CLAIM")]
+  pub tenant_claim: Option<String>,
+
+  /// Scopes the keys of the requests under the prefix of their tenant, read from their X-Tenant header (to be set by a trusted gateway)
+  #[clap(long, value_parser, env = "TENANT_HEADER")]
+  pub tenant_header: bool,
+
+  /// Sets the prefix of the keys of the tenants [default: tenants/{tenant}/]
+  #[clap(long, value_parser, env = "TENANT_PREFIX")]
+  pub tenant_prefix: Option<String>,
+
+  /// Serves the multipart upload endpoints of Uppy (under /s3/multipart), uploading to this bucket
+  #[clap(long, value_parser, env = "UPPY_BUCKET")]
+  pub uppy_bucket: Option<String>,
+
+  /// Serves the deduplication endpoint (/objects/dedup), searching the objects under this prefix for the content about to be uploaded
+  #[clap(long, value_parser, env = "DEDUP_PREFIX")]
+  pub dedup_prefix: Option<String>,
+
+  /// Installs a lifecycle rule aborting the multipart uploads left incomplete for this number of days, at startup, on the buckets of --allowed-buckets and --uppy-bucket
+  #[clap(long, value_parser, env = "ENSURE_LIFECYCLE")]
+  pub ensure_lifecycle: Option<i64>,
+
+  /// Runs built-in tasks on cron schedules in UTC, as semicolon-separated `<minute> <hour> <day> <month> <weekday> <task>` entries, e.g. `0 3 * * * cleanup-uploads media/uploads/ 7`
+  #[clap(long, value_parser, env = "SCHEDULED_TASKS", value_delimiter = ';')]
+  pub scheduled_tasks: Option<Vec<String>>,
+
+  /// Exports traces to this OTLP/HTTP collector, e.g. http://otel-collector:4318
+  #[clap(long, value_parser, env = "OTEL_ENDPOINT")]
+  pub otel_endpoint: Option<String>,
+
+  /// Sets the level of verbosity
+  #[clap(short, long, parse(from_occurrences))]
+  pub verbose: usize,
+
+  #[clap(subcommand)]
+  #[serde(skip)]
+  pub command: Option<Command>,
+}
+
+/// Commands run instead of the server.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Prints a pre-signed URL and exits, without running the server
+  Presign(PresignCommand),
+  /// Lists the objects under an s3://bucket/prefix location and exits, without running the server
+  Ls(LsCommand),
+  /// Checks the configuration and that the configured buckets can be reached, then exits
+  Check,
+  /// Prints the OpenAPI document of the server and exits, without running it
+  #[clap(name = "openapi")]
+  OpenApi(OpenApiCommand),
+}
+
+#[derive(Args, Debug)]
+pub struct PresignCommand {
+  /// Sets the operation allowed by the URL
+  #[clap(value_enum)]
+  pub method: PresignMethod,
+
+  /// Sets the name of the bucket
+  #[clap(long, value_parser)]
+  pub bucket: String,
+
+  /// Sets the key of the object
+  #[clap(long, value_parser)]
+  pub key: String,
+
+  /// Sets the validity of the URL (in seconds) [default: 3600]
+  #[clap(long, value_parser)]
+  pub expires_in: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct LsCommand {
+  /// Sets the location to list, e.g. s3://bucket/prefix/
+  #[clap(value_parser)]
+  pub location: String,
+
+  /// Lists the objects of the sub-folders too
+  #[clap(short, long, value_parser)]
+  pub recursive: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct OpenApiCommand {
+  /// Sets the format of the document
+  #[clap(long, value_enum, default_value = "json")]
+  pub format: OpenApiFormat,
+
+  /// Sets the path the API is documented under [default: the --base-path of the server]
+  #[clap(long, value_parser)]
+  pub base_path: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum OpenApiFormat {
+  Json,
+  Yaml,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum PresignMethod {
+  Get,
+  Put,
+  Delete,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+  Io(std::io::Error),
+  Toml(toml::de::Error),
+  UnsupportedFormat(String),
+  Yaml(serde_yaml::Error),
+}
+
+impl Display for SettingsError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SettingsError::Io(error) => write!(f, "Cannot read configuration file: {}", error),
+      SettingsError::Toml(error) => write!(f, "Invalid TOML configuration file: {}", error),
+      SettingsError::UnsupportedFormat(path) => write!(
+        f,
+        "Unsupported configuration file {}, expected a .toml, .yaml or .yml file",
+        path
+      ),
+      SettingsError::Yaml(error) => write!(f, "Invalid YAML configuration file: {}", error),
+    }
+  }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// Reasons for the settings not to make a valid S3 configuration, or for a `Signer` not to be
+/// built.
+#[derive(Debug)]
+pub enum ConfigurationError {
+  Io(std::io::Error),
+  Missing(&'static str),
+  MissingS3Configuration,
+  S3Configuration(S3ConfigurationError),
+  Settings(SettingsError),
+}
+
+impl Display for ConfigurationError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ConfigurationError::Io(error) => write!(f, "{}", error),
+      ConfigurationError::Missing(name) => write!(
+        f,
+        "Missing --{} (or its environment variable, or configuration file key)",
+        name
+      ),
+      ConfigurationError::MissingS3Configuration => {
+        write!(f, "Missing S3 configuration of the signer")
+      }
+      ConfigurationError::S3Configuration(S3ConfigurationError::InvalidRegion(error)) => write!(
+        f,
+        "{}\nValid AWS regions are: {}\nCustom region names require an AWS hostname to be set.",
+        error,
+        AWS_REGIONS.join(", ")
+      ),
+      ConfigurationError::S3Configuration(error) => write!(f, "{}", error),
+      ConfigurationError::Settings(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+impl From<std::io::Error> for ConfigurationError {
+  fn from(error: std::io::Error) -> Self {
+    ConfigurationError::Io(error)
+  }
+}
+
+impl From<serde_json::Error> for ConfigurationError {
+  fn from(error: serde_json::Error) -> Self {
+    ConfigurationError::Io(error.into())
+  }
+}
+
+impl From<S3ConfigurationError> for ConfigurationError {
+  fn from(error: S3ConfigurationError) -> Self {
+    ConfigurationError::S3Configuration(error)
+  }
+}
+
+impl std::error::Error for ConfigurationError {}
+
+impl Settings {
+  /// Parses the command line and the environment, completed by the configuration file if any.
+  pub fn load() -> Result<Self, SettingsError> {
+    let settings = Self::parse();
+
+    match &settings.config {
+      Some(path) => {
+        let file_settings = Self::from_file(path)?;
+        Ok(settings.or(file_settings))
+      }
+      None => Ok(settings),
+    }
+  }
+
+  /// Reads a TOML or YAML configuration file, depending on its extension.
+  pub fn from_file(path: &str) -> Result<Self, SettingsError> {
+    let content = std::fs::read_to_string(path).map_err(SettingsError::Io)?;
+
+    match Path::new(path)
+      .extension()
+      .and_then(|extension| extension.to_str())
+    {
+      Some("toml") => toml::from_str(&content).map_err(SettingsError::Toml),
+      Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(SettingsError::Yaml),
+      _ => Err(SettingsError::UnsupportedFormat(path.to_string())),
+    }
+  }
+
+  /// Completes the settings with the ones of a lower precedence layer.
+  pub fn or(self, other: Self) -> Self {
+    Self {
+      config: self.config.or(other.config),
+      aws_access_key_id: self.aws_access_key_id.or(other.aws_access_key_id),
+      aws_secret_access_key: self.aws_secret_access_key.or(other.aws_secret_access_key),
+      aws_session_token: self.aws_session_token.or(other.aws_session_token),
+      aws_credential_expiration: self
+        .aws_credential_expiration
+        .or(other.aws_credential_expiration),
+      aws_region: self.aws_region.or(other.aws_region),
+      aws_hostname: self.aws_hostname.or(other.aws_hostname),
+      aws_secondary_hostname: self.aws_secondary_hostname.or(other.aws_secondary_hostname),
+      aws_secondary_timeout: self.aws_secondary_timeout.or(other.aws_secondary_timeout),
+      #[cfg(feature = "mock-s3")]
+      mock: self.mock || other.mock,
+      port: self.port.or(other.port),
+      #[cfg(feature = "grpc")]
+      grpc_port: self.grpc_port.or(other.grpc_port),
+      base_path: self.base_path.or(other.base_path),
+      strict_open_api: self.strict_open_api || other.strict_open_api,
+      enable_docs: self.enable_docs.or(other.enable_docs),
+      docs_auth: self.docs_auth || other.docs_auth,
+      request_timeout: self.request_timeout.or(other.request_timeout),
+      access_tagging_interval: self
+        .access_tagging_interval
+        .or(other.access_tagging_interval),
+      aws_transfer_acceleration: self.aws_transfer_acceleration || other.aws_transfer_acceleration,
+      detect_bucket_regions: self.detect_bucket_regions || other.detect_bucket_regions,
+      s3_ca_bundle: self.s3_ca_bundle.or(other.s3_ca_bundle),
+      s3_insecure_skip_verify: self.s3_insecure_skip_verify || other.s3_insecure_skip_verify,
+      allow_endpoint_override: self
+        .allow_endpoint_override
+        .or(other.allow_endpoint_override),
+      bucket_configuration: self.bucket_configuration.or(other.bucket_configuration),
+      cors_max_age: self.cors_max_age.or(other.cors_max_age),
+      jwks_url: self.jwks_url.or(other.jwks_url),
+      jwt_issuer: self.jwt_issuer.or(other.jwt_issuer),
+      jwt_audience: self.jwt_audience.or(other.jwt_audience),
+      jwt_algorithm: self.jwt_algorithm.or(other.jwt_algorithm),
+      policy_file: self.policy_file.or(other.policy_file),
+      allowed_buckets: self.allowed_buckets.or(other.allowed_buckets),
+      denied_buckets: self.denied_buckets.or(other.denied_buckets),
+      max_key_length: self.max_key_length.or(other.max_key_length),
+      max_json_body_size: self.max_json_body_size.or(other.max_json_body_size),
+      hypermedia: self.hypermedia || other.hypermedia,
+      share_presets: self.share_presets.or(other.share_presets),
+      key_template: self.key_template.or(other.key_template),
+      download_links: self.download_links || other.download_links,
+      download_link_max_downloads: self
+        .download_link_max_downloads
+        .or(other.download_link_max_downloads),
+      download_link_expiration: self
+        .download_link_expiration
+        .or(other.download_link_expiration),
+      tls_cert: self.tls_cert.or(other.tls_cert),
+      tls_key: self.tls_key.or(other.tls_key),
+      health_check_bucket: self.health_check_bucket.or(other.health_check_bucket),
+      max_concurrent_s3_calls: self
+        .max_concurrent_s3_calls
+        .or(other.max_concurrent_s3_calls),
+      retry_after: self.retry_after.or(other.retry_after),
+      s3_max_retries: self.s3_max_retries.or(other.s3_max_retries),
+      s3_retry_base_delay: self.s3_retry_base_delay.or(other.s3_retry_base_delay),
+      s3_timeout: self.s3_timeout.or(other.s3_timeout),
+      listing_cache_ttl: self.listing_cache_ttl.or(other.listing_cache_ttl),
+      listing_cache_max_entries: self
+        .listing_cache_max_entries
+        .or(other.listing_cache_max_entries),
+      quotas: self.quotas.or(other.quotas),
+      #[cfg(feature = "redis-jobs")]
+      jobs_redis_url: self.jobs_redis_url.or(other.jobs_redis_url),
+      tenant_claim: self.tenant_claim.or(other.tenant_claim),
+      tenant_header: self.tenant_header || other.tenant_header,
+      tenant_prefix: self.tenant_prefix.or(other.tenant_prefix),
+      uppy_bucket: self.uppy_bucket.or(other.uppy_bucket),
+      dedup_prefix: self.dedup_prefix.or(other.dedup_prefix),
+      ensure_lifecycle: self.ensure_lifecycle.or(other.ensure_lifecycle),
+      scheduled_tasks: self.scheduled_tasks.or(other.scheduled_tasks),
+      otel_endpoint: self.otel_endpoint.or(other.otel_endpoint),
+      verbose: self.verbose.max(other.verbose),
+      command: self.command.or(other.command),
+    }
+  }
+
+  /// Points the settings at the in-memory storage served at this hostname, with placeholder
+  /// credentials unless set.
+  pub fn with_mock_storage(self, hostname: &str) -> Self {
+    Self {
+      aws_access_key_id: self.aws_access_key_id.or_else(|| Some("mock".to_string())),
+      aws_secret_access_key: self
+        .aws_secret_access_key
+        .or_else(|| Some("mock".to_string())),
+      aws_hostname: Some(hostname.to_string()),
+      aws_secondary_hostname: None,
+      bucket_configuration: None,
+      detect_bucket_regions: false,
+      aws_transfer_acceleration: false,
+      ..self
+    }
+  }
+
+  /// Buckets of `--ensure-lifecycle`: the ones of `--allowed-buckets` without wildcards, and the
+  /// Uppy bucket.
+  pub fn lifecycle_buckets(&self) -> Vec<String> {
+    let mut buckets: Vec<String> = self
+      .allowed_buckets
+      .iter()
+      .flatten()
+      .map(|pattern| pattern.split('/').next().unwrap_or_default().to_string())
+      .filter(|bucket| !bucket.is_empty() && !bucket.contains('*'))
+      .chain(self.uppy_bucket.clone())
+      .collect();
+    buckets.sort();
+    buckets.dedup();
+    buckets
+  }
+
+  /// Scheduler of the `--scheduled-tasks`, read at startup only.
+  pub fn scheduler(&self) -> Result<Scheduler, ConfigurationError> {
+    let tasks = self
+      .scheduled_tasks
+      .iter()
+      .flatten()
+      .map(|scheduled_task| scheduled_task.parse())
+      .collect::<Result<Vec<ScheduledTask>, _>>()
+      .map_err(S3ConfigurationError::InvalidScheduledTask)?;
+    Ok(Scheduler::new(tasks))
+  }
+
+  /// Cross-origin policy of the routes, allowing any origin.
+  pub fn cors(&self) -> Cors {
+    match self.cors_max_age {
+      Some(cors_max_age) => Cors::default().with_max_age(cors_max_age),
+      None => Cors::default(),
+    }
+  }
+
+  /// Builds the S3 configuration from the settings, keeping the given download links if enabled.
+  pub fn s3_configuration(
+    &self,
+    download_links: Option<DownloadLinks>,
+  ) -> Result<S3Configuration, ConfigurationError> {
+    let aws_access_key_id = self
+      .aws_access_key_id
+      .as_deref()
+      .ok_or(ConfigurationError::Missing("aws-access-key-id"))?;
+    let aws_secret_access_key = self
+      .aws_secret_access_key
+      .as_deref()
+      .ok_or(ConfigurationError::Missing("aws-secret-access-key"))?;
+
+    let aws_region = self
+      .aws_region
+      .clone()
+      .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+      .unwrap_or_else(|| DEFAULT_AWS_REGION.to_string());
+
+    let s3_configuration = if let Some(aws_hostname) = &self.aws_hostname {
+      S3Configuration::new_with_hostname(
+        aws_access_key_id,
+        aws_secret_access_key,
+        &aws_region,
+        aws_hostname,
+      )?
+    } else {
+      S3Configuration::new(aws_access_key_id, aws_secret_access_key, &aws_region)
+        .map_err(S3ConfigurationError::InvalidRegion)?
+    };
+
+    let s3_configuration = if let Some(aws_session_token) = &self.aws_session_token {
+      let expiration = self
+        .aws_credential_expiration
+        .as_deref()
+        .map(|expiration| {
+          chrono::DateTime::parse_from_rfc3339(expiration)
+            .map(SystemTime::from)
+            .map_err(|error| {
+              S3ConfigurationError::InvalidCredentialsExpiration(format!(
+                "{}: {}",
+                expiration, error
+              ))
+            })
+        })
+        .transpose()?;
+      s3_configuration.with_session_token(aws_session_token, expiration)
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(aws_secondary_hostname) = &self.aws_secondary_hostname {
+      s3_configuration.with_secondary_hostname(aws_secondary_hostname)?
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(aws_secondary_timeout) = self.aws_secondary_timeout {
+      s3_configuration.with_secondary_timeout(Duration::from_secs(aws_secondary_timeout))
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = s3_configuration
+      .with_transfer_acceleration(self.aws_transfer_acceleration)
+      .with_insecure_skip_verify(self.s3_insecure_skip_verify)
+      .with_endpoint_overrides(self.allow_endpoint_override.clone().unwrap_or_default());
+
+    let s3_configuration = if let Some(s3_ca_bundle) = &self.s3_ca_bundle {
+      let ca_bundle = std::fs::read(s3_ca_bundle)?;
+      s3_configuration
+        .with_ca_bundle(ca_bundle)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(interval) = self.access_tagging_interval {
+      s3_configuration.with_access_tagging(AccessTagging::new(Duration::from_secs(interval)))
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(jwks_url) = &self.jwks_url {
+      let jwt_validator =
+        JwtValidator::new(jwks_url, self.jwt_issuer.clone(), self.jwt_audience.clone())
+          .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+      let jwt_validator = match &self.jwt_algorithm {
+        Some(algorithm) => jwt_validator.with_algorithm(
+          algorithm
+            .parse()
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?,
+        ),
+        None => jwt_validator,
+      };
+      s3_configuration.with_jwt_validator(jwt_validator)
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration =
+      s3_configuration.with_denied_buckets(self.denied_buckets.clone().unwrap_or_default());
+
+    let s3_configuration = if let Some(share_presets) = &self.share_presets {
+      s3_configuration.with_share_presets(parse_share_presets(share_presets)?)?
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(key_template) = &self.key_template {
+      s3_configuration.with_key_template(
+        key_template
+          .parse()
+          .map_err(S3ConfigurationError::InvalidKeyTemplate)?,
+      )
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if self.download_links {
+      s3_configuration.with_download_links(download_links.unwrap_or_else(|| {
+        DownloadLinks::new(
+          self
+            .download_link_max_downloads
+            .unwrap_or(DEFAULT_DOWNLOAD_LINK_MAX_DOWNLOADS),
+          Duration::from_secs(
+            self
+              .download_link_expiration
+              .unwrap_or(DEFAULT_DOWNLOAD_LINK_EXPIRATION),
+          ),
+        )
+      }))
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(health_check_bucket) = &self.health_check_bucket {
+      s3_configuration.with_health_check_bucket(health_check_bucket.clone())
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(max_concurrent_s3_calls) = self.max_concurrent_s3_calls {
+      s3_configuration.with_concurrency_limit(ConcurrencyLimit::new(
+        max_concurrent_s3_calls,
+        Duration::from_secs(self.retry_after.unwrap_or(DEFAULT_RETRY_AFTER)),
+      ))
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = s3_configuration.with_retry_policy(RetryPolicy::new(
+      self.s3_max_retries.unwrap_or(retry::DEFAULT_MAX_RETRIES),
+      self
+        .s3_retry_base_delay
+        .map(Duration::from_millis)
+        .unwrap_or(retry::DEFAULT_RETRY_BASE_DELAY),
+      self.s3_timeout.map(Duration::from_secs),
+    ));
+
+    let s3_configuration = if let Some(listing_cache_ttl) = self.listing_cache_ttl {
+      s3_configuration.with_listing_cache(ListingCache::new(
+        Duration::from_secs(listing_cache_ttl),
+        self
+          .listing_cache_max_entries
+          .unwrap_or(DEFAULT_LISTING_CACHE_MAX_ENTRIES),
+      ))
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(quotas) = &self.quotas {
+      s3_configuration.with_quotas(Quotas::new(parse_quotas(quotas)?))
+    } else {
+      s3_configuration
+    };
+
+    #[cfg(feature = "redis-jobs")]
+    let s3_configuration = if let Some(jobs_redis_url) = &self.jobs_redis_url {
+      let store = crate::jobs::RedisJobStore::new(jobs_redis_url)
+        .map_err(|error| S3ConfigurationError::InvalidJobStore(error.to_string()))?;
+      s3_configuration.with_jobs(crate::Jobs::new(std::sync::Arc::new(store)))
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if self.tenant_claim.is_some() || self.tenant_header {
+      let tenancy = Tenancy::new(self.tenant_claim.clone(), self.tenant_header);
+      s3_configuration.with_tenancy(match &self.tenant_prefix {
+        Some(tenant_prefix) => tenancy.with_prefix(tenant_prefix)?,
+        None => tenancy,
+      })
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(uppy_bucket) = &self.uppy_bucket {
+      s3_configuration.with_uppy_bucket(uppy_bucket.clone())
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(dedup_prefix) = &self.dedup_prefix {
+      s3_configuration.with_dedup_prefix(dedup_prefix.clone())
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if self.detect_bucket_regions {
+      s3_configuration.with_bucket_regions(BucketRegions::new())
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(max_key_length) = self.max_key_length {
+      s3_configuration.with_max_key_length(max_key_length)
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(max_json_body_size) = self.max_json_body_size {
+      s3_configuration.with_max_json_body_size(max_json_body_size)
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = s3_configuration.with_hypermedia(self.hypermedia);
+
+    let s3_configuration = if let Some(allowed_buckets) = &self.allowed_buckets {
+      s3_configuration.with_allowed_buckets(allowed_buckets.clone())
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(policy_file) = &self.policy_file {
+      let content = std::fs::read_to_string(policy_file)?;
+      let policies: Vec<Policy> = serde_json::from_str(&content)?;
+      s3_configuration.with_policies(policies)
+    } else {
+      s3_configuration
+    };
+
+    let s3_configuration = if let Some(bucket_configuration) = &self.bucket_configuration {
+      let content = std::fs::read_to_string(bucket_configuration)?;
+      let bucket_configurations: Vec<BucketConfiguration> = serde_json::from_str(&content)?;
+      s3_configuration.with_bucket_configurations(bucket_configurations)?
+    } else {
+      s3_configuration
+    };
+
+    Ok(s3_configuration)
+  }
+}
+
+/// Parses the `name=seconds` share presets.
+fn parse_share_presets(
+  share_presets: &[String],
+) -> Result<BTreeMap<String, Duration>, S3ConfigurationError> {
+  share_presets
+    .iter()
+    .map(|share_preset| {
+      share_preset
+        .split_once('=')
+        .and_then(|(name, seconds)| {
+          let seconds = seconds.trim().parse().ok()?;
+          Some((name.trim().to_string(), Duration::from_secs(seconds)))
+        })
+        .ok_or_else(|| {
+          S3ConfigurationError::InvalidSharePreset(format!(
+            "{:?} is not a name=seconds preset",
+            share_preset
+          ))
+        })
+    })
+    .collect()
+}
+
+/// Parses the `bucket/prefix=bytes` quotas.
+fn parse_quotas(quotas: &[String]) -> Result<Vec<Quota>, S3ConfigurationError> {
+  quotas
+    .iter()
+    .map(|quota| {
+      quota
+        .split_once('=')
+        .and_then(|(location, max_bytes)| {
+          let (bucket, prefix) = location
+            .trim()
+            .split_once('/')
+            .unwrap_or((location.trim(), ""));
+          Some(Quota {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            max_bytes: max_bytes.trim().parse().ok()?,
+          })
+        })
+        .filter(|quota| !quota.bucket.is_empty())
+        .ok_or_else(|| {
+          S3ConfigurationError::InvalidQuota(format!(
+            "{:?} is not a bucket/prefix=bytes quota",
+            quota
+          ))
+        })
+    })
+    .collect()
+}
@@ -0,0 +1,148 @@
+use crate::{
+  base_path, insert_open_api_at, preflight_route, routes, settings::ConfigurationError,
+  JwtValidator, S3Configuration,
+};
+use std::time::Duration;
+use utoipa::openapi::OpenApiBuilder;
+use warp::{hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN, Filter, Rejection, Reply};
+
+const ANY_ORIGIN: &str = "*";
+
+/// Cross-origin resource sharing policy of the signer routes.
+#[derive(Clone, Debug)]
+pub struct Cors {
+  allowed_origin: String,
+  max_age: Option<u64>,
+}
+
+impl Default for Cors {
+  /// Allows any origin, without caching of the preflight responses.
+  fn default() -> Self {
+    Self::new(ANY_ORIGIN)
+  }
+}
+
+impl Cors {
+  /// Allows requests from a single origin, e.g. `https://app.example.com`, or any origin with `*`.
+  pub fn new(allowed_origin: &str) -> Self {
+    Self {
+      allowed_origin: allowed_origin.to_string(),
+      max_age: None,
+    }
+  }
+
+  /// Allows browsers to cache the preflight responses for this duration (in seconds).
+  pub fn with_max_age(mut self, max_age: u64) -> Self {
+    self.max_age = Some(max_age);
+    self
+  }
+}
+
+/// Signer routes mounted at a base path, built with [`Signer::builder`].
+#[derive(Clone, Debug)]
+pub struct Signer {
+  s3_configuration: S3Configuration,
+  base_path: String,
+  cors: Cors,
+}
+
+impl Signer {
+  pub fn builder() -> SignerBuilder {
+    SignerBuilder::default()
+  }
+
+  pub fn s3_configuration(&self) -> &S3Configuration {
+    &self.s3_configuration
+  }
+
+  pub fn base_path(&self) -> &str {
+    &self.base_path
+  }
+
+  /// Routes of the API mounted at the base path, along with the CORS preflight responses.
+  pub fn routes(&self) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let open_api_doc = insert_open_api_at(OpenApiBuilder::new().build(), &self.base_path);
+    #[cfg(feature = "graphql")]
+    let open_api_doc = crate::open_api::insert_graphql_path(open_api_doc, &self.base_path);
+    let allowed_origin = self.cors.allowed_origin.clone();
+
+    preflight_route(&open_api_doc, self.cors.max_age)
+      .or(base_path(&self.base_path).and(routes(&self.s3_configuration)))
+      .map(move |reply| {
+        warp::reply::with_header(reply, ACCESS_CONTROL_ALLOW_ORIGIN, allowed_origin.as_str())
+      })
+  }
+}
+
+/// Builds a [`Signer`], e.g.
+/// `Signer::builder().configuration(s3_configuration).base_path("/storage").build_routes()`.
+///
+/// The options apply to the given configuration: they are lost when a reloadable configuration
+/// is reloaded with another one.
+#[derive(Clone, Debug, Default)]
+pub struct SignerBuilder {
+  s3_configuration: Option<S3Configuration>,
+  base_path: Option<String>,
+  default_expiry: Option<Duration>,
+  cors: Cors,
+  jwt_validator: Option<JwtValidator>,
+}
+
+impl SignerBuilder {
+  pub fn configuration(mut self, s3_configuration: S3Configuration) -> Self {
+    self.s3_configuration = Some(s3_configuration);
+    self
+  }
+
+  /// Mounts the routes at this path, `/api` by default.
+  pub fn base_path(mut self, base_path: &str) -> Self {
+    self.base_path = Some(base_path.to_string());
+    self
+  }
+
+  /// Sets the lifetime of the pre-signed URLs, one hour by default.
+  pub fn default_expiry(mut self, default_expiry: Duration) -> Self {
+    self.default_expiry = Some(default_expiry);
+    self
+  }
+
+  pub fn cors(mut self, cors: Cors) -> Self {
+    self.cors = cors;
+    self
+  }
+
+  /// Requires requests to carry a bearer token validated by the given validator.
+  pub fn auth(mut self, jwt_validator: JwtValidator) -> Self {
+    self.jwt_validator = Some(jwt_validator);
+    self
+  }
+
+  pub fn build(self) -> Result<Signer, ConfigurationError> {
+    let mut s3_configuration = self
+      .s3_configuration
+      .ok_or(ConfigurationError::MissingS3Configuration)?;
+    if let Some(default_expiry) = self.default_expiry {
+      s3_configuration = s3_configuration.with_presigned_url_expiration(default_expiry);
+    }
+    if let Some(jwt_validator) = self.jwt_validator {
+      s3_configuration = s3_configuration.with_jwt_validator(jwt_validator);
+    }
+    // Serves the options from now on when the configuration is reloadable.
+    s3_configuration.reload(s3_configuration.clone());
+
+    Ok(Signer {
+      s3_configuration,
+      base_path: self
+        .base_path
+        .unwrap_or_else(|| crate::settings::DEFAULT_BASE_PATH.to_string()),
+      cors: self.cors,
+    })
+  }
+
+  pub fn build_routes(
+    self,
+  ) -> Result<impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone, ConfigurationError>
+  {
+    Ok(self.build()?.routes())
+  }
+}
@@ -0,0 +1,89 @@
+use crate::request_id::RequestIdLayer;
+use opentelemetry::{global, propagation::Extractor, trace::TraceError, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use warp::{
+  hyper::HeaderMap,
+  trace::{Info, Trace},
+};
+
+const SERVICE_NAME: &str = "s3-signer";
+
+/// Installs the tracing subscriber, tracking request identifiers and exporting the spans to the
+/// OTLP/HTTP collector at `otel_endpoint` if any, e.g. `http://collector:4318`.
+///
+/// Must be called within a Tokio runtime when exporting spans.
+pub fn init_tracing(otel_endpoint: Option<&str>) -> Result<(), TraceError> {
+  let otel_layer = match otel_endpoint {
+    Some(endpoint) => {
+      global::set_text_map_propagator(TraceContextPropagator::new());
+
+      let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_endpoint(endpoint);
+      let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(
+          trace::config().with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            SERVICE_NAME,
+          )])),
+        )
+        .install_batch(runtime::Tokio)?;
+
+      Some(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+    None => None,
+  };
+
+  // Logs keep going through the `log` logger, only spans are exported.
+  let subscriber = tracing_subscriber::registry()
+    .with(RequestIdLayer)
+    .with(otel_layer);
+  tracing::subscriber::set_global_default(subscriber)
+    .map_err(|error| TraceError::Other(error.into()))
+}
+
+/// Flushes the pending spans to the collector.
+pub fn shutdown_tracing() {
+  global::shutdown_tracer_provider();
+}
+
+/// Wraps every request in a span, continuing the trace of its `traceparent` header if any.
+pub fn trace_requests() -> Trace<impl Fn(Info<'_>) -> Span + Clone> {
+  warp::trace(|info: Info<'_>| {
+    let span = tracing::info_span!(
+      "request",
+      http.method = %info.method(),
+      http.target = %info.path(),
+    );
+
+    let parent = global::get_text_map_propagator(|propagator| {
+      propagator.extract(&HeaderExtractor(info.request_headers()))
+    });
+    span.set_parent(parent);
+
+    span
+  })
+}
+
+/// Span of an S3 API call, to be used with `Instrument::instrument`.
+pub(crate) fn s3_span(operation: &'static str) -> Span {
+  tracing::info_span!("s3", rpc.system = "aws-api", rpc.method = operation)
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+  fn get(&self, key: &str) -> Option<&str> {
+    self.0.get(key).and_then(|value| value.to_str().ok())
+  }
+
+  fn keys(&self) -> Vec<&str> {
+    self.0.keys().map(|name| name.as_str()).collect()
+  }
+}
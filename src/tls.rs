@@ -0,0 +1,47 @@
+use futures::{future::ready, Stream, StreamExt};
+use native_tls::Identity;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_native_tls::{TlsAcceptor, TlsStream};
+
+const MAX_PENDING_HANDSHAKES: usize = 64;
+
+/// Builds a TLS acceptor from a PEM certificate chain and its PKCS #8 PEM private key.
+pub fn tls_acceptor(certificate: &[u8], key: &[u8]) -> Result<TlsAcceptor, native_tls::Error> {
+  let identity = Identity::from_pkcs8(certificate, key)?;
+  native_tls::TlsAcceptor::new(identity).map(TlsAcceptor::from)
+}
+
+/// Accepts the connections of the listener and performs their TLS handshakes, to be served with
+/// `warp::serve(...).run_incoming(...)`.
+///
+/// Failed connections and handshakes are logged and skipped. The remote address of TLS
+/// connections is not available to the routes.
+pub fn tls_incoming(
+  listener: TcpListener,
+  tls_acceptor: TlsAcceptor,
+) -> impl Stream<Item = Result<TlsStream<TcpStream>, std::io::Error>> {
+  futures::stream::unfold(listener, |listener| async move {
+    let connection = listener.accept().await;
+    Some((connection, listener))
+  })
+  .filter_map(|connection| {
+    ready(
+      connection
+        .map_err(|error| log::warn!("TCP connection: {}", error))
+        .ok(),
+    )
+  })
+  .map(move |(stream, _address)| {
+    let tls_acceptor = tls_acceptor.clone();
+    async move { tls_acceptor.accept(stream).await }
+  })
+  .buffer_unordered(MAX_PENDING_HANDSHAKES)
+  .filter_map(|stream| {
+    ready(
+      stream
+        .map_err(|error| log::warn!("TLS handshake: {}", error))
+        .ok()
+        .map(Ok),
+    )
+  })
+}
@@ -0,0 +1,123 @@
+use crate::{metrics, presign::Partition, telemetry, Error, S3Configuration};
+use rusoto_s3::{GetBucketAccelerateConfigurationRequest, S3Client, S3};
+use std::{
+  collections::HashMap,
+  convert::TryFrom,
+  sync::{Arc, RwLock},
+  time::{Duration, Instant},
+};
+use tracing::Instrument;
+
+/// Duration for which the acceleration status of a bucket is trusted before being looked up again
+const DEFAULT_STATUS_TTL: Duration = Duration::from_secs(300);
+
+/// Acceleration statuses of the buckets, looked up with GetBucketAccelerateConfiguration the first
+/// time an accelerated URL is requested for them, rather than on every URL.
+///
+/// Statuses expire after a while, so that enabling or suspending the acceleration of a bucket is
+/// picked up without restart. Failed lookups are not kept.
+#[derive(Clone, Debug)]
+pub(crate) struct AccelerationStatuses {
+  ttl: Duration,
+  statuses: Arc<RwLock<HashMap<String, (bool, Instant)>>>,
+}
+
+impl Default for AccelerationStatuses {
+  fn default() -> Self {
+    Self::new(DEFAULT_STATUS_TTL)
+  }
+}
+
+impl AccelerationStatuses {
+  fn new(ttl: Duration) -> Self {
+    Self {
+      ttl,
+      statuses: Arc::default(),
+    }
+  }
+
+  /// Returns whether the acceleration of the bucket is enabled, unless unknown or expired.
+  fn get(&self, bucket: &str) -> Option<bool> {
+    self
+      .statuses
+      .read()
+      .unwrap_or_else(|error| error.into_inner())
+      .get(bucket)
+      .filter(|(_, looked_up_at)| looked_up_at.elapsed() < self.ttl)
+      .map(|(enabled, _)| *enabled)
+  }
+
+  fn insert(&self, bucket: &str, enabled: bool) {
+    self
+      .statuses
+      .write()
+      .unwrap_or_else(|error| error.into_inner())
+      .insert(bucket.to_string(), (enabled, Instant::now()));
+  }
+}
+
+/// Checks that S3 Transfer Acceleration is enabled on the bucket, and available in its partition.
+pub(crate) async fn check_enabled(
+  s3_configuration: &S3Configuration,
+  bucket: &str,
+) -> Result<(), Error> {
+  let partition = Partition::of(s3_configuration.region());
+  if !partition.supports_transfer_acceleration() {
+    return Err(Error::InvalidParameter(format!(
+      "S3 Transfer Acceleration is not available in the {} partition",
+      partition.name()
+    )));
+  }
+
+  let statuses = s3_configuration.acceleration_statuses();
+  let enabled = match statuses.get(bucket) {
+    Some(enabled) => enabled,
+    None => {
+      let enabled = lookup(s3_configuration, bucket).await?;
+      statuses.insert(bucket, enabled);
+      enabled
+    }
+  };
+
+  if !enabled {
+    return Err(Error::TransferAccelerationDisabled(bucket.to_string()));
+  }
+
+  Ok(())
+}
+
+async fn lookup(s3_configuration: &S3Configuration, bucket: &str) -> Result<bool, Error> {
+  let client = S3Client::try_from(s3_configuration)?;
+
+  let output = client
+    .get_bucket_accelerate_configuration(GetBucketAccelerateConfigurationRequest {
+      bucket: bucket.to_string(),
+      ..Default::default()
+    })
+    .instrument(telemetry::s3_span("GetBucketAccelerateConfiguration"))
+    .await
+    .map_err(metrics::count_s3_error("GetBucketAccelerateConfiguration"))
+    .map_err(Error::TransferAccelerationError)?;
+
+  Ok(output.status.as_deref() == Some("Enabled"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn statuses_are_kept_until_they_expire() {
+    let statuses = AccelerationStatuses::default();
+    assert_eq!(statuses.get("media"), None);
+
+    statuses.insert("media", true);
+    statuses.insert("archives", false);
+    assert_eq!(statuses.get("media"), Some(true));
+    assert_eq!(statuses.get("archives"), Some(false));
+
+    let statuses = AccelerationStatuses::new(Duration::ZERO);
+    statuses.insert("media", true);
+    assert_eq!(statuses.get("media"), None);
+  }
+}
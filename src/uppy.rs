@@ -0,0 +1,556 @@
+//! Multipart upload endpoints in the shape expected by the AwsS3Multipart plugin of
+//! [Uppy](https://uppy.io/docs/aws-s3-multipart/) (as served by Companion), so that Uppy can
+//! upload through the signer with `companionUrl` pointing at the API.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UppyQueryParameters {
+  pub key: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct UppyCreateBody {
+  /// Name of the uploaded file
+  pub filename: String,
+  /// Content type of the uploaded file
+  #[serde(rename = "type")]
+  pub content_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct UppyCreateResponse {
+  pub key: String,
+  pub upload_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct UppyPart {
+  #[serde(rename = "PartNumber")]
+  pub part_number: i64,
+  #[serde(rename = "ETag")]
+  pub etag: String,
+  /// Size of the part, in bytes (listed parts only)
+  #[serde(rename = "Size", skip_serializing_if = "Option::is_none")]
+  pub size: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct UppyPartUrlResponse {
+  pub url: String,
+  /// Lifetime of the URL, in seconds
+  pub expires: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct UppyCompleteBody {
+  pub parts: Vec<UppyPart>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct UppyCompleteResponse {
+  /// Pre-signed URL of the uploaded object
+  pub location: String,
+}
+
+#[cfg(feature = "server")]
+pub(crate) mod server {
+  use super::*;
+  use crate::{
+    body, json_reply, metrics,
+    multipart_upload::{
+      abort_or_complete::server::abort_or_complete_multipart_upload,
+      create::server::create_multipart_upload, normalize_etag,
+      part_upload_url::server::part_upload_url, AbortOrCompleteUploadBody,
+      AbortOrCompleteUploadQueryParameters, CompletedUploadPart, CreateUploadQueryParameters,
+      CreateUploadResponse, PartUploadQueryParameters, PartUploadResponse,
+    },
+    objects::key,
+    policy::{self, Identity, Operation},
+    presign::{self, PresignOptions},
+    query, retry, telemetry, Error, S3Configuration,
+  };
+  use rand::{distributions::Alphanumeric, Rng};
+  use rusoto_s3::{ListPartsRequest, S3Client, S3};
+  use std::{convert::TryFrom, sync::Arc};
+  use tracing::Instrument;
+  use warp::{
+    filters::BoxedFilter,
+    hyper::{Body, Response},
+    Filter, Rejection, Reply,
+  };
+
+  const KEY_ID_LENGTH: usize = 16;
+
+  pub(crate) fn routes(s3_configuration: &S3Configuration) -> BoxedFilter<(Response<Body>,)> {
+    // Boxed, like the routes they are mounted with, to keep the stack of their futures shallow.
+    create_route(s3_configuration)
+      .or(list_parts_route(s3_configuration))
+      .or(part_url_route(s3_configuration))
+      .or(complete_route(s3_configuration))
+      .or(abort_route(s3_configuration))
+      .map(Reply::into_response)
+      .boxed()
+  }
+
+  /// Create multipart upload (Uppy)
+  #[utoipa::path(
+    post,
+    path = "/s3/multipart",
+    tag = "Uppy",
+    request_body(content = UppyCreateBody, content_type = "application/json"),
+    responses(
+      (status = 200, description = "Returns the key and the ID of the upload", content_type = "application/json", body = UppyCreateResponse),
+      (status = 404, description = "Uppy endpoints are not enabled"),
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn create_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("s3" / "multipart")
+      .and(warp::post())
+      .and(body::json::<UppyCreateBody>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |body: UppyCreateBody, identity: Identity, s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(uppy_create_multipart_upload(&s3_configuration, &identity, body).await)
+        },
+      )
+  }
+
+  /// List uploaded parts (Uppy)
+  #[utoipa::path(
+    get,
+    path = "/s3/multipart/{upload_id}",
+    tag = "Uppy",
+    responses(
+      (status = 200, description = "Returns the parts uploaded so far", content_type = "application/json", body = [UppyPart]),
+      (status = 404, description = "Uppy endpoints are not enabled, or unknown upload"),
+    ),
+    params(
+      ("upload_id" = String, Path, description = "ID of the upload"),
+      ("key" = String, Query, description = "Key of the uploaded object")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn list_parts_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("s3" / "multipart" / String)
+      .and(warp::get())
+      .and(query::parameters::<UppyQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |upload_id: String,
+         parameters: UppyQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(uppy_list_parts(&s3_configuration, &identity, upload_id, parameters).await)
+        },
+      )
+  }
+
+  /// Pre-sign part upload URL (Uppy)
+  #[utoipa::path(
+    get,
+    path = "/s3/multipart/{upload_id}/{part_number}",
+    tag = "Uppy",
+    responses(
+      (status = 200, description = "Returns the pre-signed URL to upload the part", content_type = "application/json", body = UppyPartUrlResponse),
+      (status = 404, description = "Uppy endpoints are not enabled"),
+    ),
+    params(
+      ("upload_id" = String, Path, description = "ID of the upload"),
+      ("part_number" = i64, Path, description = "Index number of the part to upload"),
+      ("key" = String, Query, description = "Key of the uploaded object")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn part_url_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("s3" / "multipart" / String / i64)
+      .and(warp::get())
+      .and(query::parameters::<UppyQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |upload_id: String,
+         part_number: i64,
+         parameters: UppyQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(
+            uppy_part_url(
+              &s3_configuration,
+              &identity,
+              upload_id,
+              part_number,
+              parameters,
+            )
+            .await,
+          )
+        },
+      )
+  }
+
+  /// Complete multipart upload (Uppy)
+  #[utoipa::path(
+    post,
+    path = "/s3/multipart/{upload_id}/complete",
+    tag = "Uppy",
+    request_body(content = UppyCompleteBody, content_type = "application/json"),
+    responses(
+      (status = 200, description = "Returns the location of the uploaded object", content_type = "application/json", body = UppyCompleteResponse),
+      (status = 404, description = "Uppy endpoints are not enabled, or unknown upload"),
+    ),
+    params(
+      ("upload_id" = String, Path, description = "ID of the upload"),
+      ("key" = String, Query, description = "Key of the uploaded object")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn complete_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("s3" / "multipart" / String / "complete")
+      .and(warp::post())
+      .and(query::parameters::<UppyQueryParameters>())
+      .and(body::json::<UppyCompleteBody>(&s3_configuration))
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |upload_id: String,
+         parameters: UppyQueryParameters,
+         body: UppyCompleteBody,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(
+            uppy_complete_multipart_upload(
+              &s3_configuration,
+              &identity,
+              upload_id,
+              parameters,
+              body,
+            )
+            .await,
+          )
+        },
+      )
+  }
+
+  /// Abort multipart upload (Uppy)
+  #[utoipa::path(
+    delete,
+    path = "/s3/multipart/{upload_id}",
+    tag = "Uppy",
+    responses(
+      (status = 200, description = "Successfully aborted the upload"),
+      (status = 404, description = "Uppy endpoints are not enabled, or unknown upload"),
+    ),
+    params(
+      ("upload_id" = String, Path, description = "ID of the upload"),
+      ("key" = String, Query, description = "Key of the uploaded object")
+    ),
+    security(("bearer_token" = []), ("api_key" = [])),
+  )]
+  pub(crate) fn abort_route(
+    s3_configuration: &S3Configuration,
+  ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let s3_configuration = s3_configuration.clone();
+    warp::path!("s3" / "multipart" / String)
+      .and(warp::delete())
+      .and(query::parameters::<UppyQueryParameters>())
+      .and(policy::identity(&s3_configuration))
+      .and(crate::endpoint_override::current(&s3_configuration))
+      .and_then(
+        |upload_id: String,
+         parameters: UppyQueryParameters,
+         identity: Identity,
+         s3_configuration: Arc<S3Configuration>| async move {
+          json_reply(
+            uppy_abort_multipart_upload(&s3_configuration, &identity, upload_id, parameters).await,
+          )
+        },
+      )
+  }
+
+  /// Creates a multipart upload for Uppy, on behalf of `identity`.
+  ///
+  /// The key of the object is the file name, prefixed with a random ID like Companion does, unless
+  /// generated from the key template of the bucket.
+  pub async fn uppy_create_multipart_upload(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    body: UppyCreateBody,
+  ) -> Result<UppyCreateResponse, Error> {
+    let bucket = uppy_bucket(s3_configuration)?;
+    let key = if s3_configuration
+      .for_bucket(&bucket)
+      .key_template()
+      .is_some()
+    {
+      body.filename
+    } else {
+      let key_id: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(KEY_ID_LENGTH)
+        .map(char::from)
+        .collect();
+      format!("{}-{}", key_id, body.filename)
+    };
+
+    let CreateUploadResponse {
+      upload_id,
+      key: generated_key,
+    } = create_multipart_upload(
+      s3_configuration,
+      identity,
+      CreateUploadQueryParameters {
+        bucket,
+        path: key.clone(),
+        sse: None,
+        kms_key_id: None,
+        content_type: body.content_type,
+        cache_control: None,
+        expires: None,
+      },
+    )
+    .await?
+    .body;
+
+    Ok(UppyCreateResponse {
+      key: generated_key.unwrap_or(key),
+      upload_id,
+    })
+  }
+
+  /// Lists the parts uploaded so far, for Uppy to resume an upload, on behalf of `identity`.
+  pub async fn uppy_list_parts(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    upload_id: String,
+    mut parameters: UppyQueryParameters,
+  ) -> Result<Vec<UppyPart>, Error> {
+    let bucket = uppy_bucket(s3_configuration)?;
+    identity.scope(&mut parameters.key);
+    key::server::validate_key(s3_configuration, &parameters.key)?;
+    policy::authorize(
+      s3_configuration,
+      identity,
+      Operation::Multipart,
+      &bucket,
+      &parameters.key,
+    )?;
+    handle_uppy_list_parts(s3_configuration, bucket, parameters.key, upload_id).await
+  }
+
+  /// Pre-signs the URL to upload a part for Uppy, on behalf of `identity`.
+  pub async fn uppy_part_url(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    upload_id: String,
+    part_number: i64,
+    parameters: UppyQueryParameters,
+  ) -> Result<UppyPartUrlResponse, Error> {
+    let bucket = uppy_bucket(s3_configuration)?;
+    let PartUploadResponse { presigned_url, .. } = part_upload_url(
+      s3_configuration,
+      identity,
+      upload_id,
+      part_number,
+      PartUploadQueryParameters {
+        bucket: bucket.clone(),
+        path: parameters.key,
+        accelerate: None,
+      },
+    )
+    .await?;
+
+    Ok(UppyPartUrlResponse {
+      url: presigned_url,
+      expires: s3_configuration
+        .for_bucket(&bucket)
+        .presigned_url_expiration()
+        .as_secs(),
+    })
+  }
+
+  /// Completes a multipart upload for Uppy, on behalf of `identity`.
+  pub async fn uppy_complete_multipart_upload(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    upload_id: String,
+    parameters: UppyQueryParameters,
+    body: UppyCompleteBody,
+  ) -> Result<UppyCompleteResponse, Error> {
+    let bucket = uppy_bucket(s3_configuration)?;
+    // The location reads the object, which the caller must be allowed to, like a download
+    let mut key = parameters.key.clone();
+    identity.scope(&mut key);
+    key::server::validate_key(s3_configuration, &key)?;
+    policy::authorize(s3_configuration, identity, Operation::Read, &bucket, &key)?;
+
+    let parts = body
+      .parts
+      .into_iter()
+      .map(|part| CompletedUploadPart {
+        number: part.part_number,
+        etag: normalize_etag(&part.etag),
+      })
+      .collect();
+    abort_or_complete_multipart_upload(
+      s3_configuration,
+      identity,
+      upload_id,
+      AbortOrCompleteUploadQueryParameters {
+        bucket: bucket.clone(),
+        path: parameters.key.clone(),
+      },
+      AbortOrCompleteUploadBody::Complete { parts },
+    )
+    .await?;
+
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    let location = presign::get_object(
+      &s3_configuration.presign_configuration(&bucket).await,
+      &bucket,
+      &key,
+      &PresignOptions::from(&s3_configuration),
+    )?;
+    metrics::presigned_url("GetObject");
+    Ok(UppyCompleteResponse { location })
+  }
+
+  /// Aborts a multipart upload for Uppy, on behalf of `identity`.
+  pub async fn uppy_abort_multipart_upload(
+    s3_configuration: &S3Configuration,
+    identity: &Identity,
+    upload_id: String,
+    parameters: UppyQueryParameters,
+  ) -> Result<(), Error> {
+    let bucket = uppy_bucket(s3_configuration)?;
+    abort_or_complete_multipart_upload(
+      s3_configuration,
+      identity,
+      upload_id,
+      AbortOrCompleteUploadQueryParameters {
+        bucket,
+        path: parameters.key,
+      },
+      AbortOrCompleteUploadBody::Abort,
+    )
+    .await?;
+    Ok(())
+  }
+
+  #[tracing::instrument(skip_all)]
+  async fn handle_uppy_list_parts(
+    s3_configuration: &S3Configuration,
+    bucket: String,
+    key: String,
+    upload_id: String,
+  ) -> Result<Vec<UppyPart>, Error> {
+    let s3_configuration = s3_configuration.for_bucket(&bucket);
+    log::info!("List parts: upload_id={}", upload_id);
+    let client = S3Client::try_from(&s3_configuration)?;
+
+    let mut parts = vec![];
+    let mut part_number_marker = None;
+    loop {
+      let request = ListPartsRequest {
+        bucket: bucket.clone(),
+        key: key.clone(),
+        upload_id: upload_id.clone(),
+        part_number_marker: part_number_marker.take(),
+        ..Default::default()
+      };
+      let response = retry::s3_call(&s3_configuration, "ListParts", || {
+        client
+          .list_parts(request.clone())
+          .instrument(telemetry::s3_span("ListParts"))
+      })
+      .await
+      .map_err(metrics::count_s3_error("ListParts"))
+      .map_err(Error::ListPartsError)?;
+
+      parts.extend(
+        response
+          .parts
+          .unwrap_or_default()
+          .into_iter()
+          .filter_map(|part| {
+            Some(UppyPart {
+              part_number: part.part_number?,
+              etag: part.e_tag?,
+              size: part.size,
+            })
+          }),
+      );
+
+      part_number_marker = response.next_part_number_marker;
+      if !response.is_truncated.unwrap_or_default() || part_number_marker.is_none() {
+        break;
+      }
+    }
+
+    Ok(parts)
+  }
+
+  /// Bucket of the Uppy uploads, the endpoints not being found unless it is configured.
+  fn uppy_bucket(s3_configuration: &S3Configuration) -> Result<String, Error> {
+    s3_configuration
+      .uppy_bucket()
+      .cloned()
+      .ok_or_else(|| Error::NotFound("Uppy endpoints are not enabled".to_string()))
+  }
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn completion_requires_reading_the_object() {
+      let policies = serde_json::from_value(json!([
+        { "api_key": "uploader", "buckets": ["uploads"], "operations": ["multipart"] },
+      ]))
+      .unwrap();
+      let s3_configuration =
+        S3Configuration::new_with_hostname("access", "secret", "us-east-1", "http://127.0.0.1:9")
+          .unwrap()
+          .with_uppy_bucket("uploads".to_string())
+          .with_policies(policies);
+      let mut headers = warp::http::HeaderMap::new();
+      headers.insert("x-api-key", "uploader".parse().unwrap());
+      let uploader = Identity::from_headers(&s3_configuration, &headers)
+        .await
+        .unwrap();
+
+      let result = uppy_complete_multipart_upload(
+        &s3_configuration,
+        &uploader,
+        "upload".to_string(),
+        UppyQueryParameters {
+          key: "a.txt".to_string(),
+        },
+        UppyCompleteBody { parts: vec![] },
+      )
+      .await;
+      assert!(matches!(result, Err(Error::Forbidden(_))), "{:?}", result);
+    }
+  }
+}
@@ -0,0 +1,79 @@
+//! Build of the signer, `GET /api/version`: its version, commit and build time, and the features
+//! it was compiled with, e.g. for deployment dashboards to tell which build each instance runs.
+
+use crate::to_ok_json_response;
+use serde::Serialize;
+use utoipa::ToSchema;
+use warp::{Filter, Rejection, Reply};
+
+mod built_info {
+  include!(concat!(env!("OUT_DIR"), "/built.rs"));
+}
+
+/// Features of the crate, along with whether the build enables them.
+const FEATURES: &[(&str, bool)] = &[
+  ("client", cfg!(feature = "client")),
+  ("graphql", cfg!(feature = "graphql")),
+  ("grpc", cfg!(feature = "grpc")),
+  ("lambda", cfg!(feature = "lambda")),
+  ("mock-s3", cfg!(feature = "mock-s3")),
+  ("presign", cfg!(feature = "presign")),
+  ("redis-jobs", cfg!(feature = "redis-jobs")),
+  ("server", cfg!(feature = "server")),
+];
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VersionResponse {
+  /// Version of the crate, e.g. `0.3.2`
+  pub version: String,
+  /// Commit the signer was built from, unless built outside of a git repository
+  pub git_sha: Option<String>,
+  /// Build time, in RFC 3339
+  pub built_at: String,
+  /// Features the signer was compiled with, e.g. `grpc`
+  pub features: Vec<String>,
+  /// Version of the Rust compiler
+  pub rustc_version: String,
+  /// Cargo profile of the build, e.g. `release`
+  pub profile: String,
+}
+
+impl VersionResponse {
+  fn current() -> Self {
+    let built_at = chrono::DateTime::parse_from_rfc2822(built_info::BUILT_TIME_UTC)
+      .map(|built_at| built_at.to_rfc3339())
+      .unwrap_or_else(|_| built_info::BUILT_TIME_UTC.to_string());
+    VersionResponse {
+      version: built_info::PKG_VERSION.to_string(),
+      git_sha: option_env!("S3_SIGNER_GIT_SHA").map(str::to_string),
+      built_at,
+      features: FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| feature.to_string())
+        .collect(),
+      rustc_version: built_info::RUSTC_VERSION.to_string(),
+      profile: built_info::PROFILE.to_string(),
+    }
+  }
+}
+
+/// Build information
+#[utoipa::path(
+  get,
+  path = "/version",
+  tag = "Version",
+  responses(
+    (
+      status = 200,
+      description = "Version, commit, build time and features of the signer",
+      content_type = "application/json",
+      body = VersionResponse
+    ),
+  ),
+)]
+pub(crate) fn route() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+  warp::path!("version").and(warp::get()).and_then(|| async {
+    to_ok_json_response(&VersionResponse::current()).map_err(warp::reject::custom)
+  })
+}
@@ -0,0 +1,416 @@
+//! Streaming ZIP encoding, without compression (store method).
+//!
+//! Entries are written as their content is read: their CRC-32 and size follow their content in a
+//! data descriptor. ZIP64 records are only written when the sizes or offsets require them.
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+
+const VERSION: u16 = 20;
+const ZIP64_VERSION: u16 = 45;
+/// Sizes and CRC-32 in the data descriptor, UTF-8 names
+const FLAGS: u16 = 0x0008 | 0x0800;
+const STORE_METHOD: u16 = 0;
+const ZIP64_EXTRA_FIELD_TAG: u16 = 0x0001;
+const MAX_U16: u64 = 0xffff;
+const MAX_U32: u64 = 0xffff_ffff;
+/// 1980-01-01, the earliest MS-DOS date
+const DEFAULT_DOS_DATE: u16 = 0x0021;
+
+/// ZIP archive being written, keeping track of its entries for the central directory.
+#[derive(Default)]
+pub(crate) struct ZipArchive {
+  offset: u64,
+  entries: Vec<ZipEntry>,
+  current_entry: Option<ZipEntry>,
+}
+
+struct ZipEntry {
+  name: String,
+  dos_time: u16,
+  dos_date: u16,
+  offset: u64,
+  crc32: crc32fast::Hasher,
+  size: u64,
+}
+
+impl ZipEntry {
+  fn is_zip64(&self) -> bool {
+    self.size >= MAX_U32 || self.offset >= MAX_U32
+  }
+}
+
+impl ZipArchive {
+  /// Starts an entry, returning its local file header.
+  pub(crate) fn start_entry(&mut self, name: &str, modified: Option<DateTime<Utc>>) -> Vec<u8> {
+    let (dos_time, dos_date) = modified.map(dos_date_time).unwrap_or((0, DEFAULT_DOS_DATE));
+
+    let mut header = Vec::with_capacity(30 + name.len());
+    put_u32(&mut header, LOCAL_FILE_HEADER_SIGNATURE);
+    put_u16(&mut header, VERSION);
+    put_u16(&mut header, FLAGS);
+    put_u16(&mut header, STORE_METHOD);
+    put_u16(&mut header, dos_time);
+    put_u16(&mut header, dos_date);
+    // CRC-32 and sizes, in the data descriptor
+    put_u32(&mut header, 0);
+    put_u32(&mut header, 0);
+    put_u32(&mut header, 0);
+    put_u16(&mut header, name.len() as u16);
+    put_u16(&mut header, 0);
+    header.extend_from_slice(name.as_bytes());
+
+    self.current_entry = Some(ZipEntry {
+      name: name.to_string(),
+      dos_time,
+      dos_date,
+      offset: self.offset,
+      crc32: crc32fast::Hasher::new(),
+      size: 0,
+    });
+    self.offset += header.len() as u64;
+    header
+  }
+
+  /// Accounts for a chunk of the content of the current entry, written as is.
+  pub(crate) fn write(&mut self, chunk: &[u8]) {
+    if let Some(entry) = &mut self.current_entry {
+      entry.crc32.update(chunk);
+      entry.size += chunk.len() as u64;
+    }
+    self.offset += chunk.len() as u64;
+  }
+
+  /// Ends the current entry, returning its data descriptor.
+  pub(crate) fn end_entry(&mut self) -> Vec<u8> {
+    let entry = match self.current_entry.take() {
+      Some(entry) => entry,
+      None => return vec![],
+    };
+
+    let mut descriptor = Vec::with_capacity(24);
+    put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+    put_u32(&mut descriptor, entry.crc32.clone().finalize());
+    if entry.is_zip64() {
+      put_u64(&mut descriptor, entry.size);
+      put_u64(&mut descriptor, entry.size);
+    } else {
+      put_u32(&mut descriptor, entry.size as u32);
+      put_u32(&mut descriptor, entry.size as u32);
+    }
+
+    self.offset += descriptor.len() as u64;
+    self.entries.push(entry);
+    descriptor
+  }
+
+  /// Ends the archive, returning its central directory.
+  pub(crate) fn finish(mut self) -> Vec<u8> {
+    let mut directory = self.end_entry();
+    let directory_offset = self.offset + directory.len() as u64;
+
+    for entry in &self.entries {
+      let mut extra_field = vec![];
+      if entry.size >= MAX_U32 {
+        put_u64(&mut extra_field, entry.size);
+        put_u64(&mut extra_field, entry.size);
+      }
+      if entry.offset >= MAX_U32 {
+        put_u64(&mut extra_field, entry.offset);
+      }
+
+      let version = if entry.is_zip64() {
+        ZIP64_VERSION
+      } else {
+        VERSION
+      };
+      put_u32(&mut directory, CENTRAL_DIRECTORY_HEADER_SIGNATURE);
+      put_u16(&mut directory, version);
+      put_u16(&mut directory, version);
+      put_u16(&mut directory, FLAGS);
+      put_u16(&mut directory, STORE_METHOD);
+      put_u16(&mut directory, entry.dos_time);
+      put_u16(&mut directory, entry.dos_date);
+      put_u32(&mut directory, entry.crc32.clone().finalize());
+      put_u32(&mut directory, entry.size.min(MAX_U32) as u32);
+      put_u32(&mut directory, entry.size.min(MAX_U32) as u32);
+      put_u16(&mut directory, entry.name.len() as u16);
+      put_u16(
+        &mut directory,
+        if extra_field.is_empty() {
+          0
+        } else {
+          extra_field.len() as u16 + 4
+        },
+      );
+      // Comment length, disk number, internal and external attributes
+      put_u16(&mut directory, 0);
+      put_u16(&mut directory, 0);
+      put_u16(&mut directory, 0);
+      put_u32(&mut directory, 0);
+      put_u32(&mut directory, entry.offset.min(MAX_U32) as u32);
+      directory.extend_from_slice(entry.name.as_bytes());
+      if !extra_field.is_empty() {
+        put_u16(&mut directory, ZIP64_EXTRA_FIELD_TAG);
+        put_u16(&mut directory, extra_field.len() as u16);
+        directory.extend_from_slice(&extra_field);
+      }
+    }
+
+    let entries = self.entries.len() as u64;
+    let directory_end = self.offset + directory.len() as u64;
+    let directory_size = directory_end - directory_offset;
+
+    if entries >= MAX_U16 || directory_size >= MAX_U32 || directory_offset >= MAX_U32 {
+      put_u32(&mut directory, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+      put_u64(&mut directory, 44);
+      put_u16(&mut directory, ZIP64_VERSION);
+      put_u16(&mut directory, ZIP64_VERSION);
+      put_u32(&mut directory, 0);
+      put_u32(&mut directory, 0);
+      put_u64(&mut directory, entries);
+      put_u64(&mut directory, entries);
+      put_u64(&mut directory, directory_size);
+      put_u64(&mut directory, directory_offset);
+
+      put_u32(
+        &mut directory,
+        ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE,
+      );
+      put_u32(&mut directory, 0);
+      put_u64(&mut directory, directory_end);
+      put_u32(&mut directory, 1);
+    }
+
+    put_u32(&mut directory, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+    put_u16(&mut directory, 0);
+    put_u16(&mut directory, 0);
+    put_u16(&mut directory, entries.min(MAX_U16) as u16);
+    put_u16(&mut directory, entries.min(MAX_U16) as u16);
+    put_u32(&mut directory, directory_size.min(MAX_U32) as u32);
+    put_u32(&mut directory, directory_offset.min(MAX_U32) as u32);
+    put_u16(&mut directory, 0);
+
+    directory
+  }
+}
+
+/// MS-DOS time and date, with a two-second precision.
+fn dos_date_time(date_time: DateTime<Utc>) -> (u16, u16) {
+  if date_time.year() < 1980 {
+    return (0, DEFAULT_DOS_DATE);
+  }
+
+  let time = (date_time.hour() << 11) | (date_time.minute() << 5) | (date_time.second() / 2);
+  let date = (((date_time.year() - 1980) as u32) << 9) | (date_time.month() << 5) | date_time.day();
+  (time as u16, date as u16)
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+  buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+  buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+  buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+  use std::convert::TryInto;
+  use std::io::{Cursor, Read};
+
+  fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = ZipArchive::default();
+    let mut bytes = vec![];
+    let modified = Utc.with_ymd_and_hms(2023, 4, 5, 6, 7, 8).unwrap();
+    for (name, content) in entries {
+      bytes.extend(archive.start_entry(name, Some(modified)));
+      for chunk in content.chunks(3) {
+        archive.write(chunk);
+        bytes.extend_from_slice(chunk);
+      }
+      bytes.extend(archive.end_entry());
+    }
+    bytes.extend(archive.finish());
+    bytes
+  }
+
+  #[test]
+  fn entries_read_back() {
+    let bytes = archive(&[
+      ("a.txt", b"hello world"),
+      ("dir/empty", b""),
+      ("données/é.bin", &[0, 1, 2, 255]),
+    ]);
+
+    let mut reader = ::zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.len(), 3);
+    for (index, &(name, content)) in [
+      ("a.txt", &b"hello world"[..]),
+      ("dir/empty", &b""[..]),
+      ("données/é.bin", &[0, 1, 2, 255][..]),
+    ]
+    .iter()
+    .enumerate()
+    {
+      let mut file = reader.by_index(index).unwrap();
+      assert_eq!(file.name(), name);
+      assert_eq!(file.size(), content.len() as u64);
+      assert_eq!(file.compressed_size(), content.len() as u64);
+      assert_eq!(file.crc32(), crc32fast::hash(content));
+      assert_eq!(file.compression(), ::zip::CompressionMethod::Stored);
+      let modified = file.last_modified();
+      assert_eq!(
+        (modified.year(), modified.month(), modified.day()),
+        (2023, 4, 5)
+      );
+      assert_eq!(
+        (modified.hour(), modified.minute(), modified.second()),
+        (6, 7, 8)
+      );
+
+      let mut read = vec![];
+      file.read_to_end(&mut read).unwrap();
+      assert_eq!(read, content);
+    }
+  }
+
+  #[test]
+  fn empty_archive_reads_back() {
+    let reader = ::zip::ZipArchive::new(Cursor::new(archive(&[]))).unwrap();
+    assert_eq!(reader.len(), 0);
+  }
+
+  #[test]
+  fn too_many_entries_use_zip64_end_of_central_directory() {
+    let names: Vec<String> = (0..MAX_U16 + 1).map(|index| format!("{index}")).collect();
+    let entries: Vec<(&str, &[u8])> = names
+      .iter()
+      .map(|name| (name.as_str(), name.as_bytes()))
+      .collect();
+    let bytes = archive(&entries);
+
+    let end = bytes.len() - 22;
+    assert_eq!(
+      u32::from_le_bytes(bytes[end..end + 4].try_into().unwrap()),
+      END_OF_CENTRAL_DIRECTORY_SIGNATURE
+    );
+    assert_eq!(
+      u16::from_le_bytes(bytes[end + 8..end + 10].try_into().unwrap()),
+      0xffff
+    );
+    let locator = end - 20;
+    assert_eq!(
+      u32::from_le_bytes(bytes[locator..locator + 4].try_into().unwrap()),
+      ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE
+    );
+
+    let mut reader = ::zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.len() as u64, MAX_U16 + 1);
+    let mut file = reader.by_name("65535").unwrap();
+    assert_eq!(file.crc32(), crc32fast::hash(b"65535"));
+    let mut read = String::new();
+    file.read_to_string(&mut read).unwrap();
+    assert_eq!(read, "65535");
+  }
+
+  #[test]
+  fn large_offsets_and_sizes_use_zip64_records() {
+    // Sizes and offsets past 4 GiB, without writing as much
+    let mut archive = ZipArchive {
+      offset: MAX_U32 + 10,
+      ..Default::default()
+    };
+    archive.start_entry("large", None);
+    archive.write(b"content");
+    archive.current_entry.as_mut().unwrap().size = MAX_U32 + 1;
+
+    let descriptor = archive.end_entry();
+    assert_eq!(descriptor.len(), 24);
+    assert_eq!(
+      u64::from_le_bytes(descriptor[8..16].try_into().unwrap()),
+      MAX_U32 + 1
+    );
+    assert_eq!(
+      u64::from_le_bytes(descriptor[16..24].try_into().unwrap()),
+      MAX_U32 + 1
+    );
+
+    let directory = archive.finish();
+    assert_eq!(
+      u32::from_le_bytes(directory[0..4].try_into().unwrap()),
+      CENTRAL_DIRECTORY_HEADER_SIGNATURE
+    );
+    assert_eq!(
+      u16::from_le_bytes(directory[6..8].try_into().unwrap()),
+      ZIP64_VERSION
+    );
+    assert_eq!(
+      u32::from_le_bytes(directory[16..20].try_into().unwrap()),
+      crc32fast::hash(b"content")
+    );
+    // Sizes and offset in the ZIP64 extra field
+    assert_eq!(&directory[20..28], &[0xff; 8]);
+    assert_eq!(&directory[42..46], &[0xff; 4]);
+    assert_eq!(
+      u16::from_le_bytes(directory[30..32].try_into().unwrap()),
+      28
+    );
+    let extra_field = &directory[46 + 5..46 + 5 + 28];
+    assert_eq!(
+      u16::from_le_bytes(extra_field[0..2].try_into().unwrap()),
+      ZIP64_EXTRA_FIELD_TAG
+    );
+    assert_eq!(
+      u16::from_le_bytes(extra_field[2..4].try_into().unwrap()),
+      24
+    );
+    assert_eq!(
+      u64::from_le_bytes(extra_field[4..12].try_into().unwrap()),
+      MAX_U32 + 1
+    );
+    assert_eq!(
+      u64::from_le_bytes(extra_field[12..20].try_into().unwrap()),
+      MAX_U32 + 1
+    );
+    assert_eq!(
+      u64::from_le_bytes(extra_field[20..28].try_into().unwrap()),
+      MAX_U32 + 10
+    );
+
+    // ZIP64 end of central directory, its locator, then the end of central directory
+    let records = &directory[46 + 5 + 28..];
+    assert_eq!(records.len(), 56 + 20 + 22);
+    assert_eq!(
+      u32::from_le_bytes(records[0..4].try_into().unwrap()),
+      ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE
+    );
+    assert_eq!(
+      u64::from_le_bytes(records[48..56].try_into().unwrap()),
+      MAX_U32 + 10 + 30 + 5 + 7 + 24
+    );
+    assert_eq!(
+      u32::from_le_bytes(records[56..60].try_into().unwrap()),
+      ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE
+    );
+    assert_eq!(&records[76 + 16..76 + 20], &[0xff; 4]);
+  }
+
+  #[test]
+  fn dos_date_time_before_1980_defaults() {
+    let date_time = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    assert_eq!(dos_date_time(date_time), (0, DEFAULT_DOS_DATE));
+  }
+}
@@ -0,0 +1,100 @@
+//! Points requests at another storage with the `X-S3-Endpoint` header, which must be
+//! allowed by `--allow-endpoint-override` and name a valid endpoint.
+
+use s3_signer::S3Configuration;
+use serde_json::Value;
+use warp::{Filter, Reply};
+
+const BASE_PATH: &str = "/api";
+const OBJECT_URI: &str = "/api/v1/object?bucket=bucket&path=a.txt";
+
+/// Status of the pre-signing of an object URL through `endpoint`, and its JSON body.
+async fn get_object_url(host_patterns: &[&str], endpoint: &str) -> (u16, Value) {
+  let s3_configuration =
+    S3Configuration::new_with_hostname("access", "secret", "us-east-1", "https://s3.example.com")
+      .unwrap()
+      .with_endpoint_overrides(
+        host_patterns
+          .iter()
+          .map(|pattern| pattern.to_string())
+          .collect(),
+      );
+  let routes = s3_signer::recover_errors(
+    s3_signer::base_path(BASE_PATH).and(s3_signer::routes(&s3_configuration)),
+  );
+
+  let response = warp::test::request()
+    .method("GET")
+    .path(OBJECT_URI)
+    .header("accept", "application/json")
+    .header("x-s3-endpoint", endpoint)
+    .filter(&routes)
+    .await
+    .unwrap()
+    .into_response();
+  let status = response.status().as_u16();
+  let body = warp::hyper::body::to_bytes(response.into_body())
+    .await
+    .unwrap();
+  (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn rejects_overrides_unless_allowed() {
+  let (status, _) = get_object_url(&[], "http://minio-42:9000").await;
+  assert_eq!(status, 403);
+}
+
+#[tokio::test]
+async fn rejects_hosts_matching_no_pattern() {
+  let (status, _) = get_object_url(&["minio-*"], "http://169.254.169.254").await;
+  assert_eq!(status, 403);
+}
+
+#[tokio::test]
+async fn rejects_invalid_endpoints() {
+  let (status, _) = get_object_url(&["*"], "ftp://minio-42").await;
+  assert_eq!(status, 400);
+  let (status, _) = get_object_url(&["*"], "http://minio-42:9000/path?query").await;
+  assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn signs_urls_for_the_overriding_endpoint() {
+  let (status, body) = get_object_url(&["minio-*"], "http://minio-42:9000").await;
+  assert_eq!(status, 200);
+  let url = body["url"].as_str().unwrap();
+  assert!(
+    url.starts_with("http://minio-42:9000/bucket/a.txt?"),
+    "{}",
+    url
+  );
+}
+
+#[test]
+fn documents_the_header_when_allowed() {
+  let documents_header = |host_patterns: &[&str]| {
+    let s3_configuration =
+      S3Configuration::new_with_hostname("access", "secret", "us-east-1", "https://s3.example.com")
+        .unwrap()
+        .with_endpoint_overrides(
+          host_patterns
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect(),
+        );
+    let open_api = s3_signer::insert_security_schemes(
+      s3_signer::insert_open_api_at(utoipa::openapi::OpenApiBuilder::new().build(), BASE_PATH),
+      &s3_configuration,
+    );
+    let open_api = serde_json::to_value(open_api).unwrap();
+    open_api["paths"]["/api/v1/object"]["get"]["parameters"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .any(|parameter| parameter["in"] == "header" && parameter["name"] == "X-S3-Endpoint")
+  };
+
+  assert!(!documents_header(&[]));
+  assert!(documents_header(&["minio-*"]));
+}
@@ -0,0 +1,196 @@
+//! End-to-end flows against MinIO, run with `cargo test --features it` (requires Docker): the
+//! URLs pre-signed by the signer must be accepted by the storage, and rejected once tampered with.
+
+mod minio;
+
+use reqwest::{Client, StatusCode};
+use s3_signer::{
+  multipart_upload::{
+    AbortOrCompleteUploadQueryParameters, CompletedUploadPart, CreateUploadQueryParameters,
+    PartUploadQueryParameters,
+  },
+  objects::{CreateQueryParameters, PresignedUrlResponse, SignQueryParameters},
+  S3SignerClient,
+};
+use testcontainers::clients::Cli;
+
+const BUCKET: &str = "it";
+/// Minimum size of the parts of a multipart upload, except the last one
+const PART_SIZE: usize = 5 * 1024 * 1024;
+
+fn sign_query_parameters(key: &str) -> SignQueryParameters {
+  SignQueryParameters {
+    bucket: BUCKET.to_string(),
+    path: key.to_string(),
+    accelerate: None,
+    proxy: None,
+  }
+}
+
+/// Pre-signed URL to create the object, answered as JSON by `POST /objects`.
+async fn put_object_url(base_url: &str, key: &str) -> String {
+  Client::new()
+    .post(format!("{}/v1/objects", base_url))
+    .query(&CreateQueryParameters::from(sign_query_parameters(key)))
+    .header("accept", "application/json")
+    .send()
+    .await
+    .unwrap()
+    .json::<PresignedUrlResponse>()
+    .await
+    .unwrap()
+    .url
+}
+
+async fn download(url: &str) -> (StatusCode, Vec<u8>) {
+  let response = Client::new().get(url).send().await.unwrap();
+  (response.status(), response.bytes().await.unwrap().to_vec())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn presigned_put_then_get() {
+  let docker = Cli::default();
+  let container = minio::start(&docker);
+  let s3_configuration = minio::s3_configuration(&container);
+  minio::create_bucket(&s3_configuration, BUCKET).await;
+  let base_url = minio::start_signer(&s3_configuration);
+  let client = S3SignerClient::new(&base_url).unwrap();
+
+  // Keys needing to be encoded, whose canonical form is signed
+  for key in [
+    "hello.txt",
+    "some folder/hello world.txt",
+    "accents/été+1 (2).txt",
+  ] {
+    let content = format!("content of {}", key);
+    let response = Client::new()
+      .put(put_object_url(&base_url, key).await)
+      .body(content.clone())
+      .send()
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK, "PUT {}", key);
+
+    let url = client
+      .get_object_url(&sign_query_parameters(key))
+      .await
+      .unwrap();
+    assert_eq!(
+      download(url.as_str()).await,
+      (StatusCode::OK, content.into_bytes()),
+      "GET {}",
+      key
+    );
+  }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn multipart_upload_then_get() {
+  let docker = Cli::default();
+  let container = minio::start(&docker);
+  let s3_configuration = minio::s3_configuration(&container);
+  minio::create_bucket(&s3_configuration, BUCKET).await;
+  let client = S3SignerClient::new(&minio::start_signer(&s3_configuration)).unwrap();
+  let key = "uploads/large file.bin";
+
+  let upload = client
+    .create_upload(&CreateUploadQueryParameters {
+      bucket: BUCKET.to_string(),
+      path: key.to_string(),
+      sse: None,
+      kms_key_id: None,
+      content_type: Some("application/octet-stream".to_string()),
+      cache_control: None,
+      expires: None,
+    })
+    .await
+    .unwrap();
+
+  let contents = [vec![1u8; PART_SIZE], vec![2u8; 1024]];
+  let mut parts = vec![];
+  for (part_number, content) in (1..).zip(&contents) {
+    let part_url = client
+      .part_url(
+        &upload.upload_id,
+        part_number,
+        &PartUploadQueryParameters {
+          bucket: BUCKET.to_string(),
+          path: key.to_string(),
+          accelerate: None,
+        },
+      )
+      .await
+      .unwrap();
+
+    let response = Client::new()
+      .put(&part_url.presigned_url)
+      .body(content.clone())
+      .send()
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK, "part {}", part_number);
+    parts.push(CompletedUploadPart {
+      number: part_number,
+      etag: response.headers()["etag"].to_str().unwrap().to_string(),
+    });
+  }
+
+  client
+    .complete_upload(
+      &upload.upload_id,
+      &AbortOrCompleteUploadQueryParameters {
+        bucket: BUCKET.to_string(),
+        path: key.to_string(),
+      },
+      parts,
+    )
+    .await
+    .unwrap();
+
+  let url = client
+    .get_object_url(&sign_query_parameters(key))
+    .await
+    .unwrap();
+  assert_eq!(
+    download(url.as_str()).await,
+    (StatusCode::OK, contents.concat())
+  );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tampered_urls_are_rejected() {
+  let docker = Cli::default();
+  let container = minio::start(&docker);
+  let s3_configuration = minio::s3_configuration(&container);
+  minio::create_bucket(&s3_configuration, BUCKET).await;
+  let base_url = minio::start_signer(&s3_configuration);
+  let client = S3SignerClient::new(&base_url).unwrap();
+
+  let response = Client::new()
+    .put(put_object_url(&base_url, "a.txt").await)
+    .body("a")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let url = client
+    .get_object_url(&sign_query_parameters("a.txt"))
+    .await
+    .unwrap()
+    .to_string();
+
+  let tampered_urls = [
+    url.replace("/a.txt?", "/b.txt?"),
+    url.replace("X-Amz-Expires=", "X-Amz-Expires=1"),
+    url.replace("X-Amz-Signature=", "X-Amz-Signature=0"),
+  ];
+  for tampered_url in &tampered_urls {
+    assert_ne!(&url, tampered_url);
+    assert_eq!(
+      download(tampered_url).await.0,
+      StatusCode::FORBIDDEN,
+      "GET {}",
+      tampered_url
+    );
+  }
+}
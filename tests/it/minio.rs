@@ -0,0 +1,46 @@
+//! Helpers running MinIO in a container, and the signer in front of it.
+
+use rusoto_s3::{CreateBucketRequest, S3Client, S3};
+use s3_signer::S3Configuration;
+use std::convert::TryFrom;
+use testcontainers::{clients::Cli, Container};
+use testcontainers_modules::minio::MinIO;
+use warp::Filter;
+
+const ACCESS_KEY_ID: &str = "minioadmin";
+const SECRET_ACCESS_KEY: &str = "minioadmin";
+const REGION: &str = "us-east-1";
+const API_PORT: u16 = 9000;
+pub const BASE_PATH: &str = "/api";
+
+/// Starts MinIO, removed when the returned container is dropped.
+pub fn start(docker: &Cli) -> Container<'_, MinIO> {
+  docker.run(MinIO::default())
+}
+
+/// Configuration of the signer for the MinIO container, with its default credentials.
+pub fn s3_configuration(minio: &Container<'_, MinIO>) -> S3Configuration {
+  let hostname = format!("http://127.0.0.1:{}", minio.get_host_port_ipv4(API_PORT));
+  S3Configuration::new_with_hostname(ACCESS_KEY_ID, SECRET_ACCESS_KEY, REGION, &hostname).unwrap()
+}
+
+pub async fn create_bucket(s3_configuration: &S3Configuration, bucket: &str) {
+  S3Client::try_from(s3_configuration)
+    .unwrap()
+    .create_bucket(CreateBucketRequest {
+      bucket: bucket.to_string(),
+      ..Default::default()
+    })
+    .await
+    .unwrap();
+}
+
+/// Serves the signer on a local port, returning the base URL of its API.
+pub fn start_signer(s3_configuration: &S3Configuration) -> String {
+  let routes = s3_signer::recover_errors(
+    s3_signer::base_path(BASE_PATH).and(s3_signer::routes(s3_configuration)),
+  );
+  let (address, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+  tokio::spawn(server);
+  format!("http://{}{}", address, BASE_PATH)
+}
@@ -0,0 +1,660 @@
+//! Checks the handlers against their OpenAPI documentation: every documented operation is called
+//! with a mocked storage, and its response must have a documented status code, content type and
+//! JSON schema.
+
+use s3_signer::{ContractValidator, DownloadLinks, S3Configuration};
+use serde_json::{json, Value};
+use std::{collections::BTreeSet, net::SocketAddr, time::Duration};
+use warp::{
+  http::Method,
+  hyper::{body::Bytes, header::CONTENT_TYPE, Body, Response},
+  path::FullPath,
+  Filter, Reply,
+};
+
+const BASE_PATH: &str = "/api";
+const VERSIONED_PATH: &str = "/api/v1";
+const BUCKET: &str = "bucket";
+
+/// Request to the signer, and the status code it must be answered with.
+struct Case {
+  method: Method,
+  uri: String,
+  headers: Vec<(&'static str, &'static str)>,
+  body: Option<String>,
+  status: u16,
+}
+
+impl Case {
+  fn new(method: Method, uri: &str, status: u16) -> Self {
+    Self {
+      method,
+      uri: format!("{}{}", VERSIONED_PATH, uri),
+      headers: vec![],
+      body: None,
+      status,
+    }
+  }
+
+  fn header(mut self, name: &'static str, value: &'static str) -> Self {
+    self.headers.push((name, value));
+    self
+  }
+
+  fn json(mut self, body: Value) -> Self {
+    self.headers.push(("content-type", "application/json"));
+    self.body = Some(body.to_string());
+    self
+  }
+
+  fn xml(mut self, body: &str) -> Self {
+    self.headers.push(("content-type", "application/xml"));
+    self.body = Some(body.to_string());
+    self
+  }
+
+  fn path(&self) -> &str {
+    self.uri.split('?').next().unwrap_or_default()
+  }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn documented_operations_conform() {
+  let storage = start_storage().await;
+  let download_links = DownloadLinks::new(1, Duration::from_secs(60));
+  let s3_configuration = S3Configuration::new_with_hostname(
+    "access",
+    "secret",
+    "us-east-1",
+    &format!("http://{}", storage),
+  )
+  .unwrap()
+  .with_uppy_bucket(BUCKET.to_string())
+  .with_dedup_prefix("media/".to_string())
+  .with_download_links(download_links.clone());
+
+  let open_api =
+    s3_signer::insert_open_api_at(utoipa::openapi::OpenApiBuilder::new().build(), BASE_PATH);
+  let contract_validator = ContractValidator::new(open_api.clone(), true);
+  let routes = s3_signer::recover_errors(
+    contract_validator
+      .requests()
+      .and(s3_signer::base_path(BASE_PATH))
+      .and(s3_signer::routes(&s3_configuration)),
+  );
+  let open_api = serde_json::to_value(&open_api).unwrap();
+
+  let download_token = download_links.issue(BUCKET, "a.txt");
+  let revoked_token = download_links.issue(BUCKET, "a.txt");
+
+  let cases = vec![
+    Case::new(Method::GET, "/limits", 200),
+    Case::new(Method::GET, "/objects?bucket=bucket&prefix=site/", 200),
+    Case::new(Method::GET, "/objects?bucket=bucket&prefix=site/", 200)
+      .header("accept", "text/csv"),
+    Case::new(
+      Method::GET,
+      "/objects/stream?bucket=bucket&prefix=site/",
+      200,
+    ),
+    Case::new(Method::GET, "/objects/site?bucket=bucket&prefix=site", 200),
+    Case::new(Method::POST, "/objects/archive", 200)
+      .json(json!({ "bucket": BUCKET, "keys": ["a.txt"] })),
+    Case::new(
+      Method::GET,
+      "/objects/attributes?bucket=bucket&path=a.txt",
+      200,
+    ),
+    Case::new(
+      Method::GET,
+      "/objects/share?bucket=bucket&path=a.txt&preset=preview",
+      200,
+    ),
+    Case::new(Method::POST, "/objects/select", 200).json(json!({
+      "bucket": BUCKET,
+      "path": "a.csv",
+      "expression": "SELECT * FROM S3Object",
+      "input_format": "csv",
+    })),
+    Case::new(Method::POST, "/objects/verify", 200).json(json!({
+      "bucket": BUCKET,
+      "path": "a.txt",
+      "md5": "5d41402abc4b2a76b9719d911017c592",
+    })),
+    Case::new(Method::POST, "/objects/dedup", 200).json(json!({
+      "bucket": BUCKET,
+      "md5": "5d41402abc4b2a76b9719d911017c592",
+      "size": 5,
+    })),
+    Case::new(Method::POST, "/objects/upload-manifest", 200).json(json!({
+      "bucket": BUCKET,
+      "prefix": "holidays",
+      "part_size": 5242880,
+      "files": [
+        { "path": "a.txt", "size": 5 },
+        { "path": "2024/beach.mp4", "size": 12582912, "content_type": "video/mp4" },
+      ],
+    })),
+    Case::new(
+      Method::PUT,
+      "/objects/content?bucket=bucket&path=a.txt",
+      200,
+    )
+    .header("content-type", "application/octet-stream"),
+    Case::new(
+      Method::GET,
+      "/objects/retention?bucket=bucket&path=a.txt",
+      200,
+    ),
+    Case::new(
+      Method::PUT,
+      "/objects/retention?bucket=bucket&path=a.txt",
+      200,
+    )
+    .json(json!({ "mode": "GOVERNANCE", "retain_until_date": "2030-01-01T00:00:00Z" })),
+    Case::new(
+      Method::GET,
+      "/objects/legal-hold?bucket=bucket&path=a.txt",
+      200,
+    ),
+    Case::new(
+      Method::PUT,
+      "/objects/legal-hold?bucket=bucket&path=a.txt",
+      200,
+    )
+    .json(json!({ "status": "ON" })),
+    Case::new(Method::GET, "/object?bucket=bucket&path=a.txt", 302),
+    Case::new(Method::GET, "/object?bucket=bucket&path=a.txt", 200)
+      .header("accept", "application/json"),
+    Case::new(
+      Method::GET,
+      "/object?bucket=bucket&path=a.txt&proxy=true",
+      206,
+    )
+    .header("range", "bytes=0-1"),
+    Case::new(
+      Method::GET,
+      "/object?bucket=bucket&path=a.txt&path=b.txt",
+      400,
+    ),
+    Case::new(Method::GET, "/object?b%75cket=bucket&path=a.txt", 302),
+    Case::new(
+      Method::GET,
+      "/object?bucket=bucket&path=a.txt&sse=AES256",
+      400,
+    ),
+    Case::new(Method::POST, "/objects?bucket=bucket&path=a.txt", 302),
+    Case::new(Method::POST, "/objects?bucket=bucket&path=a.txt", 200)
+      .header("accept", "application/json"),
+    Case::new(
+      Method::POST,
+      "/multipart-upload?bucket=bucket&path=a.txt",
+      200,
+    ),
+    Case::new(
+      Method::POST,
+      "/multipart-upload/plan?bucket=bucket&path=a.txt&size=12582912",
+      200,
+    ),
+    Case::new(
+      Method::GET,
+      "/multipart-upload/upload/part/1?bucket=bucket&path=a.txt",
+      200,
+    ),
+    Case::new(
+      Method::GET,
+      "/multipart-upload/upload/parts?bucket=bucket&path=a.txt&from=1&to=3",
+      200,
+    ),
+    Case::new(
+      Method::POST,
+      "/multipart-upload/upload?bucket=bucket&path=a.txt",
+      200,
+    )
+    .json(json!({ "action": "Complete", "parts": [{ "number": 1, "etag": "\"5d41402abc4b2a76b9719d911017c592\"" }] })),
+    Case::new(
+      Method::POST,
+      "/multipart-upload/upload?bucket=bucket&path=a.txt",
+      200,
+    )
+    .json(json!({ "action": "Abort" })),
+    Case::new(
+      Method::POST,
+      "/multipart-upload/upload?bucket=bucket&path=a.txt",
+      200,
+    )
+    .xml(
+      "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>\"5d41402abc4b2a76b9719d911017c592\"</ETag></Part></CompleteMultipartUpload>",
+    ),
+    Case::new(
+      Method::DELETE,
+      "/multipart-upload?bucket=bucket&path=a.txt",
+      200,
+    ),
+    Case::new(Method::POST, "/multipart-upload/abort", 200).json(json!({
+      "uploads": [{ "bucket": BUCKET, "path": "a.txt", "upload_id": "upload" }]
+    })),
+    Case::new(
+      Method::PUT,
+      "/multipart-upload/lifecycle?bucket=bucket&days=3",
+      200,
+    ),
+    Case::new(Method::GET, "/events?bucket=bucket", 200),
+    Case::new(Method::POST, "/jobs/prefix-delete", 202)
+      .json(json!({ "bucket": BUCKET, "prefix": "trash/" })),
+    Case::new(Method::POST, "/jobs/inventory", 202)
+      .json(json!({ "bucket": BUCKET, "prefix": "site/" })),
+    Case::new(Method::POST, "/jobs/archive", 202).json(json!({
+      "bucket": BUCKET,
+      "keys": ["a.txt"],
+      "destination": "archives/a.zip"
+    })),
+    Case::new(Method::POST, "/jobs/upload-cleanup", 202)
+      .json(json!({ "bucket": BUCKET, "older_than_days": 1 })),
+    Case::new(Method::GET, "/jobs/unknown", 404),
+    Case::new(
+      Method::GET,
+      "/media/hls?bucket=bucket&path=video/index.m3u8",
+      200,
+    ),
+    Case::new(
+      Method::GET,
+      "/media/dash?bucket=bucket&path=video/manifest.mpd",
+      200,
+    ),
+    Case::new(Method::GET, &format!("/d/{}", download_token), 200),
+    Case::new(Method::GET, "/d/unknown", 404),
+    Case::new(Method::DELETE, &format!("/d/{}", revoked_token), 200),
+    Case::new(Method::POST, "/s3/multipart", 200)
+      .json(json!({ "filename": "a.txt", "type": "text/plain" })),
+    Case::new(Method::GET, "/s3/multipart/upload?key=a.txt", 200),
+    Case::new(Method::GET, "/s3/multipart/upload/1?key=a.txt", 200),
+    Case::new(Method::POST, "/s3/multipart/upload/complete?key=a.txt", 200)
+      .json(json!({ "parts": [{ "PartNumber": 1, "ETag": "5d41402abc4b2a76b9719d911017c592" }] })),
+    Case::new(Method::DELETE, "/s3/multipart/upload?key=a.txt", 200),
+  ];
+
+  let mut failures = vec![];
+  let mut covered = BTreeSet::new();
+  for case in &cases {
+    let mut request = warp::test::request()
+      .method(case.method.as_str())
+      .path(&case.uri);
+    for (name, value) in &case.headers {
+      request = request.header(*name, *value);
+    }
+    request = match &case.body {
+      Some(body) => request.body(body),
+      None => request.body("hello"),
+    };
+
+    let response = request.filter(&routes).await.unwrap().into_response();
+    let operation = find_operation(&open_api, &case.method, case.path());
+    if let Some((template, _)) = &operation {
+      covered.insert(format!("{} {}", case.method, template));
+    }
+
+    if let Err(failure) = check_response(&open_api, operation, case, response).await {
+      failures.push(format!("{} {}: {}", case.method, case.uri, failure));
+    }
+  }
+
+  let uncovered = documented_operations(&open_api)
+    .into_iter()
+    .filter(|operation| !covered.contains(operation))
+    .collect::<Vec<_>>();
+  if !uncovered.is_empty() {
+    failures.push(format!(
+      "documented operations without a case: {}",
+      uncovered.join(", ")
+    ));
+  }
+
+  assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+/// Checks that the status code, content type and JSON body of the response are documented.
+///
+/// Error responses documented without content are described by the `default` response, as an
+/// `ErrorBody`, and responses documented without content can answer a JSON `null`.
+async fn check_response(
+  open_api: &Value,
+  operation: Option<(String, &Value)>,
+  case: &Case,
+  response: Response<Body>,
+) -> Result<(), String> {
+  let status = response.status();
+  if status.as_u16() != case.status {
+    let body = warp::hyper::body::to_bytes(response.into_body())
+      .await
+      .unwrap_or_default();
+    return Err(format!(
+      "answered {} instead of {}: {}",
+      status,
+      case.status,
+      String::from_utf8_lossy(&body)
+    ));
+  }
+
+  let (_template, operation) = operation.ok_or("undocumented operation")?;
+  let is_error = status.is_client_error() || status.is_server_error();
+  let documented_response = match operation["responses"].get(status.as_str()) {
+    Some(documented_response) if !is_error || documented_response.get("content").is_some() => {
+      documented_response
+    }
+    _ if is_error => &operation["responses"]["default"],
+    _ => return Err(format!("undocumented status {}", status)),
+  };
+
+  let content_type = response
+    .headers()
+    .get(CONTENT_TYPE)
+    .and_then(|content_type| content_type.to_str().ok())
+    .map(|content_type| {
+      content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+    });
+  let content = match (documented_response.get("content"), &content_type) {
+    (Some(content), Some(content_type)) => Some(
+      content
+        .get(content_type)
+        .ok_or_else(|| format!("undocumented content type {}", content_type))?,
+    ),
+    _ => None,
+  };
+
+  if content_type.as_deref() != Some("application/json") {
+    return Ok(());
+  }
+
+  let body = warp::hyper::body::to_bytes(response.into_body())
+    .await
+    .map_err(|error| error.to_string())?;
+  let body: Value = serde_json::from_slice(&body).map_err(|error| error.to_string())?;
+  match content {
+    Some(content) => validate(open_api, &content["schema"], &body, "body"),
+    None if body.is_null() => Ok(()),
+    None => Err(format!("undocumented JSON body {}", body)),
+  }
+}
+
+/// Validates a JSON value against an OpenAPI schema, resolving the references to the components.
+fn validate(open_api: &Value, schema: &Value, value: &Value, at: &str) -> Result<(), String> {
+  if let Some(reference) = schema["$ref"].as_str() {
+    let name = reference.trim_start_matches("#/components/schemas/");
+    let schema = open_api["components"]["schemas"]
+      .get(name)
+      .ok_or_else(|| format!("{}: unresolved reference {}", at, reference))?;
+    return validate(open_api, schema, value, at);
+  }
+
+  if value.is_null() && schema["nullable"] == json!(true) {
+    return Ok(());
+  }
+
+  for all_of in schema["allOf"].as_array().into_iter().flatten() {
+    validate(open_api, all_of, value, at)?;
+  }
+
+  if let Some(one_of) = schema["oneOf"].as_array() {
+    if !one_of
+      .iter()
+      .any(|one_of| validate(open_api, one_of, value, at).is_ok())
+    {
+      return Err(format!("{}: {} matches none of the schemas", at, value));
+    }
+  }
+
+  if let Some(values) = schema["enum"].as_array() {
+    if !values.contains(value) {
+      return Err(format!("{}: {} is not one of {:?}", at, value, values));
+    }
+  }
+
+  let matches_type = match schema["type"].as_str() {
+    Some("object") => value.is_object(),
+    Some("array") => value.is_array(),
+    Some("string") => value.is_string(),
+    Some("integer") => value.is_i64() || value.is_u64(),
+    Some("number") => value.is_number(),
+    Some("boolean") => value.is_boolean(),
+    _ => true,
+  };
+  if !matches_type {
+    return Err(format!(
+      "{}: {} is not of type {}",
+      at, value, schema["type"]
+    ));
+  }
+
+  if let Some(object) = value.as_object() {
+    for required in schema["required"].as_array().into_iter().flatten() {
+      let required = required.as_str().unwrap_or_default();
+      if !object.contains_key(required) {
+        return Err(format!("{}: missing required property {}", at, required));
+      }
+    }
+
+    for (name, property) in object {
+      let at = format!("{}.{}", at, name);
+      match schema["properties"].get(name) {
+        Some(property_schema) => validate(open_api, property_schema, property, &at)?,
+        None if schema["additionalProperties"].is_object() => {
+          validate(open_api, &schema["additionalProperties"], property, &at)?
+        }
+        None if schema["properties"].is_object() => {
+          return Err(format!("{}: undocumented property", at))
+        }
+        None => {}
+      }
+    }
+  }
+
+  if let Some(items) = value.as_array() {
+    for (index, item) in items.iter().enumerate() {
+      validate(
+        open_api,
+        &schema["items"],
+        item,
+        &format!("{}[{}]", at, index),
+      )?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Operation documented for the method and path, with its path template.
+fn find_operation<'a>(
+  open_api: &'a Value,
+  method: &Method,
+  path: &str,
+) -> Option<(String, &'a Value)> {
+  open_api["paths"]
+    .as_object()?
+    .iter()
+    .filter(|(template, _)| matches_template(template, path))
+    .find_map(|(template, path_item)| {
+      path_item
+        .get(method.as_str().to_lowercase())
+        .map(|operation| (template.clone(), operation))
+    })
+}
+
+/// Operations of the versioned API, as `METHOD /path/{template}`.
+fn documented_operations(open_api: &Value) -> Vec<String> {
+  open_api["paths"]
+    .as_object()
+    .into_iter()
+    .flatten()
+    .filter(|(template, _)| template.starts_with(VERSIONED_PATH))
+    .flat_map(|(template, path_item)| {
+      path_item
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(move |(method, _)| format!("{} {}", method.to_uppercase(), template))
+    })
+    .collect()
+}
+
+fn matches_template(template: &str, path: &str) -> bool {
+  let template_segments = template.trim_matches('/').split('/');
+  let path_segments = path.trim_matches('/').split('/');
+
+  template_segments.clone().count() == path_segments.clone().count()
+    && template_segments
+      .zip(path_segments)
+      .all(|(expected, actual)| {
+        (expected.starts_with('{') && expected.ends_with('}')) || expected == actual
+      })
+}
+
+/// Local storage answering the S3 calls of the signer with canned responses.
+async fn start_storage() -> SocketAddr {
+  let storage = warp::method()
+    .and(warp::path::full())
+    .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+    .and(warp::header::optional::<String>("range"))
+    .and(warp::body::bytes())
+    .map(storage_response);
+  let (address, server) = warp::serve(storage).bind_ephemeral(([127, 0, 0, 1], 0));
+  tokio::spawn(server);
+  address
+}
+
+fn storage_response(
+  method: Method,
+  path: FullPath,
+  query: String,
+  range: Option<String>,
+  _body: Bytes,
+) -> Response<Body> {
+  let has = |parameter: &str| {
+    query
+      .split('&')
+      .any(|pair| pair.split('=').next() == Some(parameter))
+  };
+  let xml = |body: String| {
+    Response::builder()
+      .header(CONTENT_TYPE, "application/xml")
+      .body(Body::from(body))
+      .unwrap()
+  };
+
+  match method {
+    Method::GET if has("list-type") => {
+      let prefix = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("prefix="))
+        .unwrap_or_default()
+        .replace("%2F", "/");
+      let contents = ["index.html", "a.txt"]
+        .iter()
+        .map(|name| {
+          format!(
+            "<Contents><Key>{}{}</Key><Size>5</Size><LastModified>2024-01-01T00:00:00.000Z</LastModified></Contents>",
+            prefix, name
+          )
+        })
+        .collect::<String>();
+      xml(format!(
+        "<ListBucketResult><Name>{}</Name><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+        BUCKET, contents
+      ))
+    }
+    Method::GET if has("uploads") => xml(format!(
+      "<ListMultipartUploadsResult><Bucket>{}</Bucket><IsTruncated>false</IsTruncated><Upload><Key>a.txt</Key><UploadId>upload</UploadId></Upload><Upload><Key>a.txt.bak</Key><UploadId>other</UploadId></Upload></ListMultipartUploadsResult>",
+      BUCKET
+    )),
+    Method::GET if has("uploadId") => xml(
+      "<ListPartsResult><Part><PartNumber>1</PartNumber><ETag>\"etag\"</ETag><Size>5</Size></Part><IsTruncated>false</IsTruncated></ListPartsResult>"
+        .to_string(),
+    ),
+    Method::GET if has("retention") => xml(
+      "<Retention><Mode>GOVERNANCE</Mode><RetainUntilDate>2030-01-01T00:00:00Z</RetainUntilDate></Retention>"
+        .to_string(),
+    ),
+    Method::GET if has("lifecycle") => xml(
+      "<LifecycleConfiguration><Rule><ID>expire</ID><Status>Enabled</Status><Expiration><Days>30</Days></Expiration></Rule></LifecycleConfiguration>"
+        .to_string(),
+    ),
+    Method::GET if has("attributes") => xml(
+      "<GetObjectAttributesResponse><ETag>5d41402abc4b2a76b9719d911017c592-2</ETag><ObjectParts><TotalPartsCount>2</TotalPartsCount><IsTruncated>false</IsTruncated><Part><PartNumber>1</PartNumber><Size>3</Size><ChecksumSHA256>c2hh</ChecksumSHA256></Part></ObjectParts><StorageClass>STANDARD</StorageClass><ObjectSize>5</ObjectSize></GetObjectAttributesResponse>"
+        .to_string(),
+    ),
+    Method::GET if has("legal-hold") => {
+      xml("<LegalHold><Status>ON</Status></LegalHold>".to_string())
+    }
+    Method::GET if range.is_some() => Response::builder()
+      .status(206)
+      .header("content-range", "bytes 0-1/5")
+      .body(Body::from("he"))
+      .unwrap(),
+    Method::GET => {
+      let body = match path.as_str() {
+        path if path.ends_with(".m3u8") => "#EXTM3U\n#EXTINF:10,\nsegment0.ts\n#EXT-X-ENDLIST\n",
+        path if path.ends_with(".mpd") => {
+          "<?xml version=\"1.0\"?><MPD xmlns=\"urn:mpeg:dash:schema:mpd:2011\"><Period><AdaptationSet><Representation id=\"1\"><BaseURL>video.mp4</BaseURL></Representation></AdaptationSet></Period></MPD>"
+        }
+        path if path.ends_with(".html") => "<html><script src=\"a.txt\"></script></html>",
+        _ => "hello",
+      };
+      Response::new(Body::from(body))
+    }
+    Method::POST if has("uploads") => xml(format!(
+      "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>a.txt</Key><UploadId>upload</UploadId></InitiateMultipartUploadResult>",
+      BUCKET
+    )),
+    Method::POST if has("select") => Response::new(Body::from(
+      [
+        event_message("Records", b"{\"a\":1}\n"),
+        event_message("End", b""),
+      ]
+      .concat(),
+    )),
+    Method::POST if has("uploadId") => xml(format!(
+      "<CompleteMultipartUploadResult><Location>http://storage/{0}/a.txt</Location><Bucket>{0}</Bucket><Key>a.txt</Key><ETag>\"etag\"</ETag></CompleteMultipartUploadResult>",
+      BUCKET
+    )),
+    Method::HEAD => Response::builder()
+      .header("etag", "\"5d41402abc4b2a76b9719d911017c592\"")
+      .header("content-length", "5")
+      .body(Body::empty())
+      .unwrap(),
+    Method::PUT => Response::builder()
+      .header("etag", "\"etag\"")
+      .body(Body::empty())
+      .unwrap(),
+    Method::DELETE => Response::builder().status(204).body(Body::empty()).unwrap(),
+    _ => Response::new(Body::empty()),
+  }
+}
+
+/// Message of an event stream, as S3 Select answers its records with.
+fn event_message(event_type: &str, payload: &[u8]) -> Vec<u8> {
+  let mut headers = Vec::new();
+  for (name, value) in [(":message-type", "event"), (":event-type", event_type)].iter() {
+    headers.push(name.len() as u8);
+    headers.extend_from_slice(name.as_bytes());
+    // String value
+    headers.push(7);
+    headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    headers.extend_from_slice(value.as_bytes());
+  }
+
+  let total_length = 12 + headers.len() + payload.len() + 4;
+  let mut message = Vec::with_capacity(total_length);
+  message.extend_from_slice(&(total_length as u32).to_be_bytes());
+  message.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+  let prelude_crc = crc32fast::hash(&message);
+  message.extend_from_slice(&prelude_crc.to_be_bytes());
+  message.extend_from_slice(&headers);
+  message.extend_from_slice(payload);
+  let message_crc = crc32fast::hash(&message);
+  message.extend_from_slice(&message_crc.to_be_bytes());
+  message
+}
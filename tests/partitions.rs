@@ -0,0 +1,92 @@
+//! Pre-signs URLs for the regions of the China and AWS GovCloud (US) partitions, which must target
+//! the endpoints of their partition and be signed for their region.
+
+use s3_signer::presign::{self, Partition, PresignConfiguration, PresignOptions, Region};
+
+const BUCKET: &str = "media";
+const KEY: &str = "videos/intro.mp4";
+
+fn get_object_url(region: Region, options: &PresignOptions) -> String {
+  let configuration = PresignConfiguration::new("AKIAEXAMPLE", "secret", region);
+  presign::get_object(&configuration, BUCKET, KEY, options).unwrap()
+}
+
+/// Host of the URL, and the credential scope of its signature, e.g.
+/// `20240101/cn-north-1/s3/aws4_request`.
+fn host_and_scope(url: &str) -> (String, String) {
+  let (host, query) = url
+    .strip_prefix("https://")
+    .and_then(|url| url.split_once('/'))
+    .map(|(host, path)| (host, path.split_once('?').unwrap().1))
+    .unwrap();
+  let credential = query
+    .split('&')
+    .find_map(|parameter| parameter.strip_prefix("X-Amz-Credential="))
+    .unwrap()
+    .replace("%2F", "/");
+  let scope = credential.split_once('/').unwrap().1.to_string();
+  (host.to_string(), scope)
+}
+
+#[test]
+fn regions_belong_to_their_partition() {
+  let partitions = [
+    ("us-east-1", Partition::Aws),
+    ("eu-west-3", Partition::Aws),
+    ("cn-north-1", Partition::AwsCn),
+    ("cn-northwest-1", Partition::AwsCn),
+    ("us-gov-east-1", Partition::AwsUsGov),
+    ("us-gov-west-1", Partition::AwsUsGov),
+  ];
+  for (name, partition) in partitions {
+    let region: Region = name.parse().unwrap();
+    assert_eq!(Partition::of(&region), partition, "{}", name);
+  }
+
+  let custom = Region::Custom {
+    name: "cn-north-1".to_string(),
+    endpoint: "https://s3.example.cn".to_string(),
+  };
+  assert_eq!(Partition::of(&custom), Partition::AwsCn);
+}
+
+#[test]
+fn china_urls_target_the_china_domain() {
+  for name in ["cn-north-1", "cn-northwest-1"] {
+    let url = get_object_url(name.parse().unwrap(), &PresignOptions::default());
+    let (host, scope) = host_and_scope(&url);
+    assert_eq!(host, format!("s3.{}.amazonaws.com.cn", name));
+    assert!(scope.contains(&format!("/{}/s3/", name)), "{}", scope);
+  }
+}
+
+#[test]
+fn govcloud_urls_target_the_govcloud_regions() {
+  for name in ["us-gov-east-1", "us-gov-west-1"] {
+    let url = get_object_url(name.parse().unwrap(), &PresignOptions::default());
+    let (host, scope) = host_and_scope(&url);
+    assert_eq!(host, format!("s3.{}.amazonaws.com", name));
+    assert!(scope.contains(&format!("/{}/s3/", name)), "{}", scope);
+  }
+}
+
+#[test]
+fn transfer_acceleration_only_applies_to_the_aws_partition() {
+  let options = PresignOptions::default().with_transfer_acceleration(true);
+
+  let (host, _) = host_and_scope(&get_object_url(Region::EuWest1, &options));
+  assert_eq!(host, format!("{}.s3-accelerate.amazonaws.com", BUCKET));
+
+  let (host, _) = host_and_scope(&get_object_url(Region::CnNorth1, &options));
+  assert_eq!(host, "s3.cn-north-1.amazonaws.com.cn");
+
+  let (host, _) = host_and_scope(&get_object_url(Region::UsGovWest1, &options));
+  assert_eq!(host, "s3.us-gov-west-1.amazonaws.com");
+}
+
+#[test]
+fn buckets_without_location_constraint_are_in_the_default_region_of_their_partition() {
+  assert_eq!(Partition::Aws.default_region(), Region::UsEast1);
+  assert_eq!(Partition::AwsCn.default_region(), Region::CnNorth1);
+  assert_eq!(Partition::AwsUsGov.default_region(), Region::UsGovWest1);
+}